@@ -9,7 +9,7 @@ fn main() {
     let dest = env::var("OUT_DIR").unwrap();    
     let mut file = File::create(&Path::new(&dest).join("gl_bindings.rs")).unwrap();
 
-    Registry::new(Api::Gl, (4, 3), Profile::Core, Fallbacks::All, ["GL_EXT_texture_filter_anisotropic"])
+    Registry::new(Api::Gl, (4, 3), Profile::Core, Fallbacks::All, ["GL_EXT_texture_filter_anisotropic", "GL_ARB_clip_control"])
         .write_bindings(GlobalGenerator, &mut file)
         .unwrap();
 
@@ -24,12 +24,24 @@ pub fn clear(mask: u32) {
     }
 }
 
+pub fn get_error() -> types::GLenum {
+    unsafe {
+        GetError()
+    }
+}
+
 pub fn viewport(x: i32, y: i32, width: i32, height: i32) {
     unsafe {
         Viewport(x, y, width, height);
     }
 }
 
+pub fn scissor(x: i32, y: i32, width: i32, height: i32) {
+    unsafe {
+        Scissor(x, y, width, height);
+    }
+}
+
 pub fn draw_arrays(draw_type: types::GLenum, first_idx: usize, num_to_draw: usize) {
     unsafe {
         DrawArrays(draw_type, first_idx as i32, num_to_draw as i32);
@@ -49,6 +61,13 @@ pub fn draw_elements(draw_mode: types::GLenum, index_cnt: usize, draw_type: type
     }
 }
 
+pub fn draw_elements_instanced(draw_mode: types::GLenum, index_cnt: usize, draw_type: types::GLenum, instancecount: usize) {
+    unsafe {
+        let offset = ptr::null() as *const _; // offset to start of data in buffer
+        DrawElementsInstanced(draw_mode, index_cnt as i32, draw_type, offset, instancecount as i32);
+    }
+}
+
 pub fn enable(capability: types::GLenum) {
     unsafe {
         Enable(capability);
@@ -73,6 +92,12 @@ pub fn blend_func(sfactor: types::GLenum, dfactor: types::GLenum) {
     }
 }
 
+pub fn blend_func_separate(src_rgb: types::GLenum, dst_rgb: types::GLenum, src_alpha: types::GLenum, dst_alpha: types::GLenum) {
+    unsafe {
+        BlendFuncSeparate(src_rgb, dst_rgb, src_alpha, dst_alpha);
+    }
+}
+
 ///////////
 // gl 1.3
 ///////////
@@ -466,6 +491,56 @@ pub fn depth_mask(flag: bool) {
     }
 }
 
+pub fn depth_func(func: types::GLenum) {
+    unsafe {
+        DepthFunc(func);
+    }
+}
+
+pub fn polygon_offset(factor: f32, units: f32) {
+    unsafe {
+        PolygonOffset(factor, units);
+    }
+}
+
+pub fn clear_depth(depth: f64) {
+    unsafe {
+        ClearDepth(depth);
+    }
+}
+
+// needs gl 4.5 or ARB_clip_control
+pub fn clip_control(origin: types::GLenum, depth: types::GLenum) {
+    unsafe {
+        ClipControl(origin, depth);
+    }
+}
+
+pub fn color_mask(flag: bool) {
+    let flag = if flag { TRUE } else { FALSE };
+    unsafe {
+        ColorMask(flag, flag, flag, flag);
+    }
+}
+
+pub fn stencil_func(func: types::GLenum, reference: i32, mask: u32) {
+    unsafe {
+        StencilFunc(func, reference, mask);
+    }
+}
+
+pub fn stencil_op(stencil_fail: types::GLenum, depth_fail: types::GLenum, depth_pass: types::GLenum) {
+    unsafe {
+        StencilOp(stencil_fail, depth_fail, depth_pass);
+    }
+}
+
+pub fn stencil_mask(mask: u32) {
+    unsafe {
+        StencilMask(mask);
+    }
+}
+
 pub fn get_floatv(name: types::GLenum) -> f32 {
     unsafe {
         let mut result: f32 = 0.0;
@@ -474,6 +549,24 @@ pub fn get_floatv(name: types::GLenum) -> f32 {
     }
 }
 
+pub fn get_stringi(name: types::GLenum, index: u32) -> String {
+    unsafe {
+        let result = GetStringi(name, index);
+        if result.is_null() {
+            return String::new();
+        }
+        std::ffi::CStr::from_ptr(result as *const c_char).to_string_lossy().into_owned()
+    }
+}
+
+pub fn get_integerv(name: types::GLenum) -> i32 {
+    unsafe {
+        let mut result: i32 = 0;
+        GetIntegerv(name, &mut result as *mut i32);
+        result
+    }
+}
+
 ///////////
 // gl 3.0
 ///////////
@@ -615,6 +708,46 @@ pub fn framebuffer_texture(target: types::GLenum, attachment: types::GLenum, tex
     }
 }
 
+///////////
+// gl 3.3
+///////////
+pub fn gen_sampler() -> u32 {
+    unsafe {
+        let mut samplers = [0u32; 1];
+        let samplers_ptr = samplers.as_mut_ptr();
+        GenSamplers(1, samplers_ptr);
+        samplers[0]
+    }
+}
+
+pub fn delete_sampler(sampler_id: u32) {
+    unsafe {
+        DeleteSamplers(1, &sampler_id as *const u32);
+    }
+}
+
+// the unit is the index of the texture unit i.e. 1 for TEXTURE1, a sampler of 0 goes back to the parameters of the bound texture
+pub fn bind_sampler(unit: u32, sampler_id: u32) {
+    unsafe {
+        BindSampler(unit, sampler_id);
+    }
+}
+
+pub fn sampler_parameteri(sampler_id: u32, pname: types::GLenum, value: u32) {
+    unsafe {
+        SamplerParameteri(sampler_id, pname, value as i32);
+    }
+}
+
+///////////
+// gl 4.0
+///////////
+pub fn patch_parameter_i(param_name: types::GLenum, value: i32) {
+    unsafe {
+        PatchParameteri(param_name, value);
+    }
+}
+
 pub mod helper {
     use super::*;
 
@@ -637,6 +770,82 @@ pub mod helper {
         disable(CULL_FACE);        
     }
 
+    // (major, minor) of the current context
+    pub fn gl_version() -> (i32, i32) {
+        (get_integerv(MAJOR_VERSION), get_integerv(MINOR_VERSION))
+    }
+
+    // tessellation control and evaluation stages are core since gl 4.0
+    pub fn supports_tessellation() -> bool {
+        gl_version() >= (4, 0)
+    }
+
+    pub fn supports_extension(extension_name: &str) -> bool {
+        (0..get_integerv(NUM_EXTENSIONS)).any(|i| get_stringi(EXTENSIONS, i as u32) == extension_name)
+    }
+
+    // anisotropic filtering is core since gl 4.6, before that it needs the extension
+    pub fn supports_anisotropic_filtering() -> bool {
+        gl_version() >= (4, 6) || supports_extension("GL_EXT_texture_filter_anisotropic")
+    }
+
+    // clip control is core since gl 4.5, before that it needs the extension
+    pub fn supports_clip_control() -> bool {
+        gl_version() >= (4, 5) || supports_extension("GL_ARB_clip_control")
+    }
+
+    // without a current context get_error keeps returning an error so we give up after this many
+    const MAX_QUEUED_ERRORS: usize = 32;
+
+    pub fn error_name(error_code: types::GLenum) -> &'static str {
+        match error_code {
+            NO_ERROR => "GL_NO_ERROR",
+            INVALID_ENUM => "GL_INVALID_ENUM",
+            INVALID_VALUE => "GL_INVALID_VALUE",
+            INVALID_OPERATION => "GL_INVALID_OPERATION",
+            INVALID_FRAMEBUFFER_OPERATION => "GL_INVALID_FRAMEBUFFER_OPERATION",
+            OUT_OF_MEMORY => "GL_OUT_OF_MEMORY",
+            STACK_UNDERFLOW => "GL_STACK_UNDERFLOW",
+            STACK_OVERFLOW => "GL_STACK_OVERFLOW",
+            _ => "unknown GL error",
+        }
+    }
+
+    pub fn format_error(error_code: types::GLenum, context: &str) -> String {
+        format!("{} (0x{:04x}) after {}", error_name(error_code), error_code, context)
+    }
+
+    // drains all errors gl has queued up since the last check, the context should say what was done before the check
+    // debug builds panic so that the failing call is found right away, release builds only report the errors (set_error_handler)
+    pub fn check_error(context: &str) {
+        let errors: Vec<String> = (0..MAX_QUEUED_ERRORS)
+            .map(|_| get_error())
+            .take_while(|&error_code| error_code != NO_ERROR)
+            .map(|error_code| format_error(error_code, context))
+            .collect();
+        if errors.is_empty() {
+            return;
+        }
+        if cfg!(debug_assertions) {
+            panic!("GL errors:\n{}", errors.join("\n"));
+        }
+        let handler = *ERROR_HANDLER.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        for error in errors.iter() {
+            match handler {
+                Some(handler) => handler(error),
+                None => println!("GL error: {}", error),
+            }
+        }
+    }
+
+    // get_error stalls the pipeline so key state changes only get checked in debug builds
+    // the context is only built in debug builds so hot paths like binding a framebuffer don't format it every call
+    pub fn debug_check_error(context: impl FnOnce() -> String) {
+        if cfg!(debug_assertions) {
+            check_error(&context());
+        }
+    }
+
     ///////////
     // gl 4.3
     ///////////
@@ -649,6 +858,26 @@ pub mod helper {
 
     use std::os::raw;
     use std::ffi::CStr;
+    use std::sync::RwLock;
+
+    // this crate can't use the logging of the crates built on it so they can hand it a handler for the debug output
+    // it gets the severity, the type and the message, without a handler the messages are printed
+    pub type DebugMessageHandler = fn(types::GLenum, types::GLenum, &str);
+
+    static DEBUG_MESSAGE_HANDLER: RwLock<Option<DebugMessageHandler>> = RwLock::new(None);
+
+    pub fn set_debug_message_handler(handler: Option<DebugMessageHandler>) {
+        *DEBUG_MESSAGE_HANDLER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = handler;
+    }
+
+    // same for the errors check_error finds in release builds, without a handler they are printed
+    pub type ErrorHandler = fn(&str);
+
+    static ERROR_HANDLER: RwLock<Option<ErrorHandler>> = RwLock::new(None);
+
+    pub fn set_error_handler(handler: Option<ErrorHandler>) {
+        *ERROR_HANDLER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = handler;
+    }
 
     extern "system" fn error_callback(
         _source: u32,
@@ -664,12 +893,16 @@ pub mod helper {
         }
         let msg = unsafe { CStr::from_ptr(message) };
         let msg: &str = msg.to_str().unwrap();
-            
-        println!("GL Callback: {} type: 0x{:x}, severity: 0x{:x}, message: {}", 
-            if gltype == DEBUG_TYPE_ERROR { "** GL ERROR **" } else { "" },
-            gltype,
-            severity,
-            msg);
+
+        let handler = *DEBUG_MESSAGE_HANDLER.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+        match handler {
+            Some(handler) => handler(severity, gltype, msg),
+            None => println!("GL Callback: {} type: 0x{:x}, severity: 0x{:x}, message: {}", 
+                if gltype == DEBUG_TYPE_ERROR { "** GL ERROR **" } else { "" },
+                gltype,
+                severity,
+                msg),
+        }
     }
 
     pub fn push_debug_group(id: u32, group_label: &str) {
@@ -687,3 +920,15 @@ pub mod helper {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn error_is_formatted_with_name_code_and_context() {
+        assert_eq!(helper::format_error(INVALID_ENUM, "binding fbo 3"), "GL_INVALID_ENUM (0x0500) after binding fbo 3");
+        assert_eq!(helper::format_error(OUT_OF_MEMORY, "uploading texture"), "GL_OUT_OF_MEMORY (0x0505) after uploading texture");
+        assert_eq!(helper::format_error(0x1234, "linking"), "unknown GL error (0x1234) after linking");
+    }
+}
@@ -0,0 +1,13 @@
+#version 400 core
+
+in vec2 pass_tex_coord;
+
+uniform sampler2D model_texture;
+// textures with transparency like foliage only write depth where they are opaque
+uniform bool uses_alpha_test;
+
+void main(void) {
+    if (uses_alpha_test && texture(model_texture, pass_tex_coord).a < 0.5) {
+        discard;
+    }
+}
@@ -0,0 +1,25 @@
+#version 400 core
+
+in vec3 pos;
+in vec2 tex_coord;
+
+out vec2 pass_tex_coord;
+
+uniform mat4 transform;
+uniform mat4 vp_matrix;
+
+// clipping plane for water rendering
+uniform vec4 clip_plane;
+
+// same texture coordinates as the entity shader so the alpha test matches what is drawn
+uniform float number_of_rows;
+uniform vec2 texture_offset;
+uniform vec2 uv_scale;
+uniform vec2 uv_offset;
+
+void main(void) {
+    vec4 world_position = transform * vec4(pos, 1.0);
+    gl_ClipDistance[0] = dot(world_position, clip_plane);
+    gl_Position = vp_matrix * world_position;
+    pass_tex_coord = ((tex_coord * uv_scale + uv_offset) / number_of_rows) + texture_offset;
+}
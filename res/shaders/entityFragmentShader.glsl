@@ -1,8 +1,11 @@
 #version 400 core
 
 const int NUM_LIGHTS = 4;
+// lower bound on the diffuse light so a scene without (or with unused) lights is still visible
+const float min_ambient = 0.2;
 
 in vec2 pass_tex_coord;
+in vec3 pass_vertex_color;
 in vec3 surface_normal;
 in vec3 light_direction[NUM_LIGHTS];
 // specular lighting stuff
@@ -16,7 +19,6 @@ layout(location = 0) out vec4 out_Color;
 layout(location = 1) out vec4 out_brightness_Color;
 
 uniform sampler2D texture_sampler;
-uniform sampler2D shadow_map;
 uniform sampler2D extra_info_map;
 
 uniform vec3 light_color[NUM_LIGHTS];
@@ -29,15 +31,30 @@ uniform vec3 sky_color;
 uniform vec3 attenuation[NUM_LIGHTS];
 // for turning off/on extra info
 uniform float has_extra_info;
+// the color attribute is only enabled for models that have it, otherwise it would read the default (0, 0, 0)
+uniform float has_vertex_color;
+// screen door cross fade between lod levels, the incoming level keeps exactly the pixels the outgoing one discards
+uniform float lod_fade;
+uniform float lod_fade_in;
+
+// 4x4 bayer matrix so neighbouring pixels get spread out thresholds
+float dither_threshold() {
+    const float bayer[16] = float[16](
+        0.0, 8.0, 2.0, 10.0,
+        12.0, 4.0, 14.0, 6.0,
+        3.0, 11.0, 1.0, 9.0,
+        15.0, 7.0, 13.0, 5.0
+    );
+    ivec2 pixel = ivec2(gl_FragCoord.xy) % 4;
+    return (bayer[pixel.y * 4 + pixel.x] + 0.5) / 16.0;
+}
 
 const bool uses_cell_shading = false;
 const float brightness_levels = 3.0;
 
-// how many pixels to sample on each side of center pixel (so 2 means 3x3 box) 
+// how many pixels to sample on each side of center pixel (so 2 means 5x5 box) 
 const int pcf_count = 2;
-// texture pixels we will be sampling
-const float texel_count = (pcf_count*2.0 + 1.0)*(pcf_count*2.0 + 1.0);
-uniform float shadow_map_size;
+#include "shadowPcf.glsl"
 
 void adjust_brightness(inout float diffuse_brightness, inout float specular_brightness) {
     if (!uses_cell_shading) {
@@ -50,28 +67,23 @@ void adjust_brightness(inout float diffuse_brightness, inout float specular_brig
 }
 
 void main(void) {
-    // size of a pixel in texture coords space
-    float texel_size = 1.0 / shadow_map_size;
-    float total_in_shadow = 0.0;
-
-    for (int x=-pcf_count; x<=pcf_count; x++) {
-        for (int y=-pcf_count; y <= pcf_count; y++) {
-            // compare depth with shadowmap depth to figure out if this piece of terrain is in shadow or not (absence of light due to something blocking it)
-            float obj_depth_nearest_light = texture(shadow_map, shadow_coords.xy + vec2(x, y) * texel_size).r;
-            // add slight offset to prevent shadow acne
-            // note that unlike in terrain shader here we use a very aggressive bias
-            // this due to self-shadow casting of complex objects causes a lot of acne
-            // a better approach would be to calculate more precise near/far planes like described in the msdn shadows article
-            total_in_shadow += step(obj_depth_nearest_light + 0.01, shadow_coords.z);
-        }
+    if (lod_fade > 0.0 && (dither_threshold() < lod_fade) != (lod_fade_in > 0.5)) {
+        discard;
     }
-    total_in_shadow /= texel_count;     
-    float light_factor = 1.0 - total_in_shadow*shadow_coords.w;    
+    // we have to normalize after interpolation
+    vec3 unit_normal = normalize(surface_normal);
+    // the first light is the sun which is the one casting the shadows
+    // self-shadow casting of complex objects causes a lot of acne so surfaces at grazing angles to the sun get a larger bias
+    // a better approach would be to calculate more precise near/far planes like described in the msdn shadows article
+    float light_factor = shadow_light_factor(shadow_coords, unit_normal, normalize(light_direction[0]), pcf_count);
     
     vec4 texture_color = texture(texture_sampler, pass_tex_coord);
     if (texture_color.a < 0.5) {
         discard; // do not render transparency (hack)
     }    
+    if (has_vertex_color > 0.5) {
+        texture_color.rgb *= pass_vertex_color;
+    }
     float shininess_fac = 1.0;
     float glow_fac = 0.0;
     if (has_extra_info > 0.5) {
@@ -80,8 +92,6 @@ void main(void) {
         glow_fac = extra_info.g;
     }
 
-    // we have to normalize after interpolation
-    vec3 unit_normal = normalize(surface_normal);
     vec3 unit_camera = normalize(to_camera_dir);
 
     vec3 total_diffuse = vec3(0.0);
@@ -106,7 +116,7 @@ void main(void) {
         total_diffuse += (brightness * light_color[i]) / attenuation_factor;
         total_specular = (pow(spec_brightness, shine_damper) * reflectivity * light_color[i]) / attenuation_factor;
     }
-    total_diffuse = max(total_diffuse * light_factor, 0.2); // clamp to 0.2 so nothing totally dark -> ambient light
+    total_diffuse = max(total_diffuse * light_factor, min_ambient); // clamp so nothing totally dark -> ambient light
 
     // apply extra info factors
     // adjust shininess (lowering it) based on specular map
@@ -114,7 +124,8 @@ void main(void) {
     // when glow_fac > 0.5 this will subtract old total_diffuse and add vec3(1) making the pixel glow since very bright    
     total_diffuse += step(0.5, glow_fac) * (vec3(1) - total_diffuse);
     
-    vec4 light_based_out_color = vec4(total_diffuse, 1.0) * texture_color + vec4(total_specular, 1.0);
+    // alpha comes from the texture only so that premultiplied alpha textures blend correctly
+    vec4 light_based_out_color = vec4(total_diffuse, 1.0) * texture_color + vec4(total_specular, 0.0);
     out_Color = mix(vec4(sky_color, 1.0), light_based_out_color, visibility);
     // make pixels brightness black unless in glow map
     out_brightness_Color = step(0.5, glow_fac) * (light_based_out_color);
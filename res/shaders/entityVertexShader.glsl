@@ -5,8 +5,13 @@ const int NUM_LIGHTS = 4;
 in vec3 pos;
 in vec2 tex_coord;
 in vec3 normal;
+in vec3 vertex_color;
+// only read when is_instanced is set, otherwise the transform and texture offset uniforms are used
+in mat4 instance_transform;
+in vec2 instance_texture_offset;
 
 out vec2 pass_tex_coord;
+out vec3 pass_vertex_color;
 out vec3 surface_normal;
 out vec3 light_direction[NUM_LIGHTS];
 out vec3 to_camera_dir;
@@ -18,17 +23,20 @@ uniform mat4 projection_matrix;
 uniform mat4 view_matrix;
 uniform mat4 to_shadowmap_space;
 uniform float shadow_distance;
+uniform float is_instanced;
 
-uniform vec3 light_pos[NUM_LIGHTS];
+// w is 0 for directional lights whose xyz is then the direction towards the light
+uniform vec4 light_pos[NUM_LIGHTS];
 uniform float uses_fake_lighting;
 
 // atlas scaling stuff
 uniform float number_of_rows;
 uniform vec2 texture_offset;
+// material uv transform for tiling or using a sub rect of the texture, applied before the atlas
+uniform vec2 uv_scale;
+uniform vec2 uv_offset;
 
-// fog stuff
-const float fog_density = 0.007;
-const float fog_gradient = 1.5;
+#include "fog.glsl"
 
 // shadow stuff
 const float shadow_transition_distance = 10.0;
@@ -37,7 +45,9 @@ const float shadow_transition_distance = 10.0;
 uniform vec4 clip_plane;
 
 void main(void) {
-    vec4 world_position = transform * vec4(pos, 1.0);
+    mat4 model_transform = is_instanced > 0.5 ? instance_transform : transform;
+    vec2 atlas_offset = is_instanced > 0.5 ? instance_texture_offset : texture_offset;
+    vec4 world_position = model_transform * vec4(pos, 1.0);
     shadow_coords = to_shadowmap_space * world_position;
     // set what the distance to clipping plane 0 is from this vertex (negative will get culled, positive won't)
     // to compute distance of point from plane we substitute the point (or it's vec4 with w=1) into plane equation -> this is the same as taking dot product
@@ -47,8 +57,9 @@ void main(void) {
     
     vec4 eye_space_position = view_matrix * world_position;
     gl_Position = projection_matrix * eye_space_position;
-    pass_tex_coord = (tex_coord / number_of_rows) + texture_offset; // rescale original tex_coords down to section of atlas where texture is located
+    pass_tex_coord = ((tex_coord * uv_scale + uv_offset) / number_of_rows) + atlas_offset; // rescale original tex_coords down to section of atlas where texture is located
     // tex coords will get linearly interpolated as we pass them to frag shader
+    pass_vertex_color = vertex_color;
 
     vec3 actual_normal = normal;
     if (uses_fake_lighting > 0.5) {
@@ -56,10 +67,10 @@ void main(void) {
     }
 
     // this i think is correct: you need to transform normals by the transpose of the inverse of the transformation matrix
-    mat4 normal_transform = transpose(inverse(transform));
+    mat4 normal_transform = transpose(inverse(model_transform));
     surface_normal = (normal_transform * vec4(actual_normal, 0.0)).xyz;
     for (int i=0; i<NUM_LIGHTS; i++) {
-        light_direction[i] = light_pos[i] - world_position.xyz;
+        light_direction[i] = light_pos[i].xyz - world_position.xyz * light_pos[i].w;
     }
     // extract camera position from view matrix
     vec3 camera_position = (inverse(view_matrix) * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
@@ -67,8 +78,7 @@ void main(void) {
     
     // compute visibility
     float distance_to_eye = length(eye_space_position.xyz);
-    float fog_vis_coef = exp(-pow(distance_to_eye * fog_density, fog_gradient));
-    visibility = clamp(fog_vis_coef, 0.0, 1.0);
+    visibility = fog_visibility(distance_to_eye);
 
     float to_shadow_box_edge_dist = distance_to_eye - (shadow_distance - shadow_transition_distance);
     float excess_of_transition = to_shadow_box_edge_dist / shadow_transition_distance;
@@ -0,0 +1,16 @@
+// exponential distance fog shared by the scene shaders, the constants match FogCutoff on the cpu side
+
+const float fog_density = 0.007;
+const float fog_gradient = 1.5;
+// the fog reaches the fog color at fog_end no matter how thin it is so nothing pops in at the far plane, disabled when fog_end is 0
+uniform float fog_start;
+uniform float fog_end;
+
+// 1 shows the surface color and 0 the fog color
+float fog_visibility(float distance_to_eye) {
+    float fog_vis_coef = exp(-pow(distance_to_eye * fog_density, fog_gradient));
+    if (fog_end > 0.0) {
+        fog_vis_coef = min(fog_vis_coef, clamp((fog_end - distance_to_eye) / max(fog_end - fog_start, 0.0001), 0.0, 1.0));
+    }
+    return clamp(fog_vis_coef, 0.0, 1.0);
+}
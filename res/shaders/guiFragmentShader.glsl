@@ -5,7 +5,8 @@ in vec2 textureCoords;
 out vec4 out_Color;
 
 uniform sampler2D guiTexture;
+uniform vec4 tint;
 
 void main(void){
-	out_Color = texture(guiTexture, textureCoords);
+	out_Color = texture(guiTexture, textureCoords) * tint;
 }
\ No newline at end of file
@@ -1,6 +1,8 @@
 #version 400 core
 
 const int NUM_LIGHTS = 4;
+// lower bound on the diffuse light so a scene without (or with unused) lights is still visible
+const float min_ambient = 0.2;
 
 in vec2 pass_tex_coord;
 in vec3 surface_normal;
@@ -20,6 +22,8 @@ uniform vec3 light_color[NUM_LIGHTS];
 // specular lighting
 uniform float shine_damper;
 uniform float reflectivity;
+// 0 = flat geometric normal, 1 = full normal map
+uniform float normal_strength;
 // fog
 uniform vec3 sky_color;
 // point light attenuation
@@ -47,9 +51,10 @@ void main(void) {
 
     vec3 normal_from_map = (2.0 * texture(normal_map_sampler, pass_tex_coord) - 1.0).xyz;
 
+    // in tangent space the geometric normal is just +z
+    vec3 blended_normal = mix(vec3(0.0, 0.0, 1.0), normalize(normal_from_map), normal_strength);
     // we have to normalize after interpolation
-    vec3 unit_normal = normalize(normal_from_map);
-    // vec3 unit_normal = mix(normalize(surface_normal_eye), normal_from_map, 0.001); UNCOMMENT to see non bump mapped
+    vec3 unit_normal = normalize(blended_normal);
     vec3 unit_camera = normalize(to_camera_dir_tgs);
 
     vec3 total_diffuse = vec3(0.0);
@@ -74,7 +79,7 @@ void main(void) {
         total_diffuse += (brightness * light_color[i]) / attenuation_factor;
         total_specular = (pow(spec_brightness, shine_damper) * reflectivity * light_color[i]) / attenuation_factor;
     }
-    total_diffuse = max(total_diffuse, 0.2); // clamp to 0.2 so nothing totally dark -> ambient light
+    total_diffuse = max(total_diffuse, min_ambient); // clamp so nothing totally dark -> ambient light
 
     vec4 light_based_out_color = vec4(total_diffuse, 1.0) * texture_color + vec4(total_specular, 1.0);
     out_Color = mix(vec4(sky_color, 1.0), light_based_out_color, visibility); 
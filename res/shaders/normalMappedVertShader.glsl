@@ -16,16 +16,18 @@ uniform mat4 transform;
 uniform mat4 projection_matrix;
 uniform mat4 view_matrix;
 
-uniform vec3 light_pos[NUM_LIGHTS];
+// w is 0 for directional lights whose xyz is then the direction towards the light
+uniform vec4 light_pos[NUM_LIGHTS];
 uniform float uses_fake_lighting;
 
 // atlas scaling stuff
 uniform float number_of_rows;
 uniform vec2 texture_offset;
+// material uv transform for tiling or using a sub rect of the texture, applied before the atlas
+uniform vec2 uv_scale;
+uniform vec2 uv_offset;
 
-// fog stuff
-const float fog_density = 0.007;
-const float fog_gradient = 1.5;
+#include "fog.glsl"
 
 // clipping plane for water rendering
 uniform vec4 clip_plane;
@@ -42,7 +44,7 @@ void main(void) {
 
     vec4 eye_space_position = view_matrix * world_position;
     gl_Position = projection_matrix * eye_space_position;
-    pass_tex_coord = (tex_coord / number_of_rows) + texture_offset; // rescale original tex_coords down to section of atlas where texture is located
+    pass_tex_coord = ((tex_coord * uv_scale + uv_offset) / number_of_rows) + texture_offset; // rescale original tex_coords down to section of atlas where texture is located
     // tex coords will get linearly interpolated as we pass them to frag shader
 
     vec3 actual_normal = normal;
@@ -76,12 +78,11 @@ void main(void) {
     );
 
     for (int i=0; i<NUM_LIGHTS; i++) {
-        light_direction_tgs[i] = eye_to_tangent_space * (view_matrix * (vec4(light_pos[i], 1.0) - world_position)).xyz;  
+        light_direction_tgs[i] = eye_to_tangent_space * (view_matrix * vec4(light_pos[i].xyz - world_position.xyz * light_pos[i].w, 0.0)).xyz;  
     }    
     to_camera_dir_tgs = eye_to_tangent_space * (-eye_space_position.xyz);
         
     // compute visibility
     float distance_to_eye = length(eye_space_position.xyz);
-    float fog_vis_coef = exp(-pow(distance_to_eye * fog_density, fog_gradient));
-    visibility = clamp(fog_vis_coef, 0.0, 1.0);
+    visibility = fog_visibility(distance_to_eye);
 }
\ No newline at end of file
@@ -0,0 +1,9 @@
+#version 400 core
+
+out vec4 out_Color;
+
+uniform vec3 outline_color;
+
+void main(void) {
+    out_Color = vec4(outline_color, 1.0);
+}
@@ -0,0 +1,13 @@
+#version 400 core
+
+in vec3 pos;
+in vec3 normal;
+
+uniform mat4 mvp_matrix;
+// how far to push the vertices out along their normals in model space, 0 draws the model as is
+uniform float outline_offset;
+
+void main(void) {
+    vec3 extruded_pos = pos + normalize(normal) * outline_offset;
+    gl_Position = mvp_matrix * vec4(extruded_pos, 1.0);
+}
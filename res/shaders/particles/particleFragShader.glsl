@@ -8,11 +8,30 @@ layout(location = 0) out vec4 out_color;
 layout(location = 1) out vec4 out_brightness_Color;
 
 uniform sampler2D particle_texture;
+// set for mixed blending, then the color is premultiplied by alpha and the weight fades from alpha (0) to additive (1) blending
+uniform float premultiply;
+uniform float additive_weight;
+// set for ParticleDepthTest::ResolvedSceneDepth, the camera depth resolved into a texture of the same size replaces the depth test
+uniform bool tests_scene_depth;
+uniform sampler2D scene_depth;
+// reversed z stores the near plane at 1 so what is behind the scene has the smaller depth
+uniform bool reversed_depth;
 
 void main(void) {
+    if (tests_scene_depth) {
+        float depth = texelFetch(scene_depth, ivec2(gl_FragCoord.xy), 0).r;
+        bool is_behind_scene = reversed_depth ? gl_FragCoord.z < depth : gl_FragCoord.z > depth;
+        if (is_behind_scene) {
+            discard;
+        }
+    }
     vec4 color1 = texture(particle_texture, tex_coords1);
     vec4 color2 = texture(particle_texture, tex_coords2);
 
     out_color = mix(color1, color2, blend);
+    if (premultiply > 0.5) {
+        out_color.rgb *= out_color.a;
+        out_color.a *= 1.0 - additive_weight;
+    }
     out_brightness_Color = vec4(0.0);
 }
\ No newline at end of file
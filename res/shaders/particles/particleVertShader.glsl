@@ -11,6 +11,8 @@ out float blend;
 
 uniform mat4 projection_matrix;
 uniform float number_of_rows;
+// clip plane in view space since the particles only have a model view matrix
+uniform vec4 view_clip_plane;
 
 void main(void) {
     // the quad we use for particles has extent (-0.5, 0.5) -> (0.5, -0.5)
@@ -23,6 +25,7 @@ void main(void) {
     tex_coords2 = tex_coords + tex_offsets.zw;
     blend = blend_factor;
 
-    vec4 world_pos = projection_matrix * model_view_matrix * vec4(position, 0, 1);
-    gl_Position = world_pos;
+    vec4 view_pos = model_view_matrix * vec4(position, 0, 1);
+    gl_ClipDistance[0] = dot(view_pos, view_clip_plane);
+    gl_Position = projection_matrix * view_pos;
 }
\ No newline at end of file
@@ -14,6 +14,8 @@ in vec3 pass_colour[];
 out vec3 final_colour;
 
 uniform mat4 projectionViewMatrix;
+// world space plane, CLIP_DISTANCE0 stays enabled while particles are drawn so it has to be written
+uniform vec4 clip_plane;
 
 const float size = 0.1;
 // direction from light source to our point
@@ -23,6 +25,7 @@ void createVertex(vec3 offset, float brightness) {
     vec4 actualOffset = vec4(offset * size, 0.0);
     // access built in gl_Position that comes from vertex shader
     vec4 worldPosition = gl_in[0].gl_Position + actualOffset;
+    gl_ClipDistance[0] = dot(worldPosition, clip_plane);
     gl_Position = projectionViewMatrix * worldPosition;
     final_colour = pass_colour[0] * brightness;
     EmitVertex();
@@ -0,0 +1,38 @@
+// percentage closer filtered shadow lookup shared by the entity and terrain fragment shaders
+
+// compares the depth for us, with linear filtering the hardware also averages 2x2 comparisons
+uniform sampler2DShadow shadow_map;
+uniform float shadow_map_size;
+// depth bias against shadow acne, the slope part grows with the angle between the surface and the sun
+uniform float shadow_constant_bias;
+uniform float shadow_slope_bias;
+uniform float shadow_max_bias;
+
+// same as ShadowParams::slope_scaled_bias on the cpu side
+float shadow_bias(vec3 unit_normal, vec3 unit_to_sun) {
+    float cos_angle = clamp(dot(unit_normal, unit_to_sun), 0.001, 1.0);
+    float tan_angle = sqrt(1.0 - cos_angle*cos_angle) / cos_angle;
+    return min(shadow_constant_bias + shadow_slope_bias * tan_angle, shadow_max_bias);
+}
+
+// pcf_count is how many pixels to sample on each side of the center pixel (so 1 means a 3x3 box)
+// 1 is fully lit, towards the edge of the shadow box shadow_coords.w fades the shadow out
+float shadow_light_factor(vec4 shadow_coords, vec3 unit_normal, vec3 unit_to_sun, int pcf_count) {
+    // size of a pixel in texture coords space
+    float texel_size = 1.0 / shadow_map_size;
+    // texture pixels we will be sampling
+    float texel_count = (pcf_count*2.0 + 1.0)*(pcf_count*2.0 + 1.0);
+    float bias = shadow_bias(unit_normal, unit_to_sun);
+    float total_in_shadow = 0.0;
+
+    for (int x=-pcf_count; x<=pcf_count; x++) {
+        for (int y=-pcf_count; y <= pcf_count; y++) {
+            // compare depth with shadowmap depth to figure out if this fragment is in shadow or not (absence of light due to something blocking it)
+            // the bias is a slight offset to prevent shadow acne
+            // the comparison returns 1 where the fragment is lit i.e. not further from the light than the shadow map depth
+            total_in_shadow += 1.0 - texture(shadow_map, vec3(shadow_coords.xy + vec2(x, y) * texel_size, shadow_coords.z - bias));
+        }
+    }
+    total_in_shadow /= texel_count;
+    return 1.0 - total_in_shadow*shadow_coords.w;
+}
@@ -7,7 +7,13 @@ out vec2 texture_coords;
 
 uniform mat4 mvp_matrix;
 
+// same texture coordinates as the entity shader so the alpha test matches what is drawn
+uniform float number_of_rows;
+uniform vec2 texture_offset;
+uniform vec2 uv_scale;
+uniform vec2 uv_offset;
+
 void main(void) {
-    texture_coords = in_texture_coords;
+    texture_coords = ((in_texture_coords * uv_scale + uv_offset) / number_of_rows) + texture_offset;
     gl_Position = mvp_matrix * vec4(pos, 1.0);
 }
\ No newline at end of file
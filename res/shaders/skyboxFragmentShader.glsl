@@ -9,6 +9,14 @@ uniform samplerCube cube_map_sampler2;
 uniform float blend_factor;
 uniform float uses_fog;
 
+// analytic sky (simplified Preetham) which replaces the cubemaps when enabled
+uniform float uses_atmosphere;
+uniform vec3 sun_direction;
+// angle between straight up and the sun in radians, pi/2 is the sun at the horizon
+uniform float sun_zenith;
+uniform float turbidity;
+uniform float exposure;
+
 // lower limit is up to where the skybox should have the color of the fog
 const float lower_limit = 0.0;
 // uper limit is from where we dont mix with fog color at all
@@ -29,10 +37,52 @@ void adjust_brightness(inout vec4 skybox_color) {
     }
 }
 
+// perez distribution of the sky luminance for the view zenith angle theta and the angle gamma between view and sun
+float perez(float theta, float gamma, float A, float B, float C, float D, float E) {
+    return (1.0 + A * exp(B / max(cos(theta), 0.01))) * (1.0 + C * exp(D * gamma) + E * cos(gamma) * cos(gamma));
+}
+
+vec3 atmosphere_color(vec3 view_dir) {
+    float T = turbidity;
+    // the model breaks down for the sun below the horizon so we keep it just above and fade out instead
+    float theta_s = min(sun_zenith, 1.55);
+    float theta = acos(clamp(view_dir.y, 0.0, 1.0));
+    float gamma = acos(clamp(dot(view_dir, normalize(sun_direction)), -1.0, 1.0));
+
+    float chi = (4.0 / 9.0 - T / 120.0) * (3.14159265 - 2.0 * theta_s);
+    float zenith_Y = (4.0453 * T - 4.9710) * tan(chi) - 0.2155 * T + 2.4192;
+    float t3 = theta_s * theta_s * theta_s;
+    float t2 = theta_s * theta_s;
+    float zenith_x = T * T * (0.00166 * t3 - 0.00375 * t2 + 0.00209 * theta_s)
+        + T * (-0.02903 * t3 + 0.06377 * t2 - 0.03202 * theta_s + 0.00394)
+        + (0.11693 * t3 - 0.21196 * t2 + 0.06052 * theta_s + 0.25886);
+    float zenith_y = T * T * (0.00275 * t3 - 0.00610 * t2 + 0.00317 * theta_s)
+        + T * (-0.04214 * t3 + 0.08970 * t2 - 0.04153 * theta_s + 0.00516)
+        + (0.15346 * t3 - 0.26756 * t2 + 0.06670 * theta_s + 0.26688);
+
+    float Y = zenith_Y * perez(theta, gamma, 0.1787 * T - 1.4630, -0.3554 * T + 0.4275, -0.0227 * T + 5.3251, 0.1206 * T - 2.5771, -0.0670 * T + 0.3703)
+        / perez(0.0, theta_s, 0.1787 * T - 1.4630, -0.3554 * T + 0.4275, -0.0227 * T + 5.3251, 0.1206 * T - 2.5771, -0.0670 * T + 0.3703);
+    float x = zenith_x * perez(theta, gamma, -0.0193 * T - 0.2592, -0.0665 * T + 0.0008, -0.0004 * T + 0.2125, -0.0641 * T - 0.8989, -0.0033 * T + 0.0452)
+        / perez(0.0, theta_s, -0.0193 * T - 0.2592, -0.0665 * T + 0.0008, -0.0004 * T + 0.2125, -0.0641 * T - 0.8989, -0.0033 * T + 0.0452);
+    float y = zenith_y * perez(theta, gamma, -0.0167 * T - 0.2608, -0.0950 * T + 0.0092, -0.0079 * T + 0.2102, -0.0441 * T - 1.6537, -0.0109 * T + 0.0529)
+        / perez(0.0, theta_s, -0.0167 * T - 0.2608, -0.0950 * T + 0.0092, -0.0079 * T + 0.2102, -0.0441 * T - 1.6537, -0.0109 * T + 0.0529);
+
+    // xyY -> XYZ -> linear rgb
+    Y = 1.0 - exp(-exposure * Y);
+    vec3 XYZ = vec3(x * Y / y, Y, (1.0 - x - y) * Y / y);
+    vec3 rgb = mat3(3.2406, -0.9689, 0.0557, -1.5372, 1.8758, -0.2040, -0.4986, 0.0415, 1.0570) * XYZ;
+    // fade to black once the sun sets
+    float day_factor = smoothstep(-0.1, 0.05, cos(sun_zenith));
+    return max(rgb, vec3(0.0)) * day_factor;
+}
+
 void main(void) {
     vec4 day_color = texture(cube_map_sampler1, pass_tex_coords);
     vec4 night_color = texture(cube_map_sampler2, pass_tex_coords);
     vec4 final_color = mix(day_color, night_color, blend_factor);
+    if (uses_atmosphere > 0.5) {
+        final_color = vec4(atmosphere_color(normalize(pass_tex_coords)), 1.0);
+    }
 
     // tex coord y is pixel y coord in case of skybox
     float factor = (pass_tex_coords.y - lower_limit) / (upper_limit - lower_limit);
@@ -9,11 +9,18 @@ uniform mat4 view_matrix;
 
 // clipping plane for water rendering
 uniform vec4 clip_plane;
+// puts the cube on the far plane so it only fills what no geometry was drawn into
+uniform float projects_to_far_plane;
+uniform float far_plane_depth;
 
 void main(void) {
     vec4 world_pos = vec4(position, 1.0);
     gl_ClipDistance[0] = dot(world_pos, clip_plane);
 
     gl_Position = projection_matrix * view_matrix * world_pos;
+    if (projects_to_far_plane > 0.5) {
+        // z/w ends up exactly on the far plane after the perspective divide
+        gl_Position.z = far_plane_depth * gl_Position.w;
+    }
     pass_tex_coords = position;
 }
\ No newline at end of file
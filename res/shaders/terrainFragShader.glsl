@@ -1,6 +1,8 @@
 #version 400 core
 
 const int NUM_LIGHTS = 4;
+// lower bound on the diffuse light so a scene without (or with unused) lights is still visible
+const float min_ambient = 0.2;
 
 in vec2 pass_tex_coord;
 in vec3 surface_normal;
@@ -19,7 +21,6 @@ uniform sampler2D r_sampler;
 uniform sampler2D g_sampler;
 uniform sampler2D b_sampler;
 uniform sampler2D blend_map_sampler;
-uniform sampler2D shadow_map;
 
 uniform vec3 light_color[NUM_LIGHTS];
 
@@ -33,11 +34,9 @@ uniform vec3 attenuation[NUM_LIGHTS];
 const bool uses_cell_shading = false;
 const float brightness_levels = 2.0;
 
-// how many pixels to sample on each side of center pixel (so 2 means 3x3 box) 
+// how many pixels to sample on each side of center pixel (so 1 means 3x3 box) 
 const int pcf_count = 1;
-// texture pixels we will be sampling
-const float texel_count = (pcf_count*2.0 + 1.0)*(pcf_count*2.0 + 1.0);
-uniform float shadow_map_size;
+#include "shadowPcf.glsl"
 
 void adjust_brightness(inout float diffuse_brightness, inout float specular_brightness) {
     if (!uses_cell_shading) {
@@ -50,20 +49,10 @@ void adjust_brightness(inout float diffuse_brightness, inout float specular_brig
 }
 
 void main(void) {
-    // size of a pixel in texture coords space
-    float texel_size = 1.0 / shadow_map_size;
-    float total_in_shadow = 0.0;
-
-    for (int x=-pcf_count; x<=pcf_count; x++) {
-        for (int y=-pcf_count; y <= pcf_count; y++) {
-            // compare depth with shadowmap depth to figure out if this piece of terrain is in shadow or not (absence of light due to something blocking it)
-            float obj_depth_nearest_light = texture(shadow_map, shadow_coords.xy + vec2(x, y) * texel_size).r;
-            // add slight offset to prevent shadow acne
-            total_in_shadow += step(obj_depth_nearest_light + 0.005, shadow_coords.z);
-        }
-    }
-    total_in_shadow /= texel_count;     
-    float light_factor = 1.0 - total_in_shadow*shadow_coords.w;
+    // we have to normalize after interpolation
+    vec3 unit_normal = normalize(surface_normal);
+    // the first light is the sun which is the one casting the shadows
+    float light_factor = shadow_light_factor(shadow_coords, unit_normal, normalize(light_direction[0]), pcf_count);
 
     // sample untiled (by untiled i mean before coordinates are scaled by 40.0 which exploits REPEAT to tile the texture onto the object)
     vec4 blend_map_col = texture(blend_map_sampler, pass_tex_coord);
@@ -79,8 +68,6 @@ void main(void) {
     vec4 b_col = texture(b_sampler, tiled_coords) * blend_map_col.b;
     vec4 blended_texture_color = background_col + r_col + g_col + b_col;
 
-    // we have to normalize after interpolation
-    vec3 unit_normal = normalize(surface_normal);
     vec3 unit_camera = normalize(to_camera_dir);
 
     vec3 total_diffuse = vec3(0.0);
@@ -103,7 +90,7 @@ void main(void) {
         total_diffuse += (brightness * light_color[i]) / attenuation_factor; // add alpha of 1
         total_specular += (pow(spec_brightness, shine_damper) * reflectivity * light_color[i]) / attenuation_factor;
     }
-    total_diffuse = max(total_diffuse * light_factor, 0.2); // clamp to [0.2, 1], the 0.2 means everything is given a little bit of color -> ambient
+    total_diffuse = max(total_diffuse * light_factor, min_ambient); // clamp to [min_ambient, 1], everything is given a little bit of color -> ambient
     
     vec4 light_based_out_color = vec4(total_diffuse, 1.0) * blended_texture_color + vec4(total_specular, 1.0);
     out_Color = mix(vec4(sky_color, 1.0), light_based_out_color, visibility);
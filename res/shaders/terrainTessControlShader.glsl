@@ -0,0 +1,46 @@
+#version 400 core
+
+layout (vertices = 3) out;
+
+in vec3 tcs_world_position[];
+in vec2 tcs_tex_coord[];
+in vec3 tcs_normal[];
+
+out vec3 tes_world_position[];
+out vec2 tes_tex_coord[];
+out vec3 tes_normal[];
+
+uniform mat4 view_matrix;
+
+uniform float tess_min_level;
+uniform float tess_max_level;
+uniform float tess_near_distance;
+uniform float tess_far_distance;
+
+// must match TerrainTessellationParams::tessellation_level
+float tessellation_level(float distance_to_camera) {
+    float distance_range = max(tess_far_distance - tess_near_distance, 0.0001);
+    float far_factor = clamp((distance_to_camera - tess_near_distance) / distance_range, 0.0, 1.0);
+    return mix(tess_max_level, tess_min_level, far_factor);
+}
+
+float edge_level(vec3 camera_position, int from, int to) {
+    vec3 edge_center = 0.5 * (tcs_world_position[from] + tcs_world_position[to]);
+    return tessellation_level(distance(camera_position, edge_center));
+}
+
+void main(void) {
+    tes_world_position[gl_InvocationID] = tcs_world_position[gl_InvocationID];
+    tes_tex_coord[gl_InvocationID] = tcs_tex_coord[gl_InvocationID];
+    tes_normal[gl_InvocationID] = tcs_normal[gl_InvocationID];
+
+    if (gl_InvocationID == 0) {
+        vec3 camera_position = (inverse(view_matrix) * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
+        // the level of an edge only depends on the edge itself so neighbouring patches agree on it and no cracks open up
+        // outer level i is for the edge opposite to vertex i
+        gl_TessLevelOuter[0] = edge_level(camera_position, 1, 2);
+        gl_TessLevelOuter[1] = edge_level(camera_position, 2, 0);
+        gl_TessLevelOuter[2] = edge_level(camera_position, 0, 1);
+        gl_TessLevelInner[0] = max(gl_TessLevelOuter[0], max(gl_TessLevelOuter[1], gl_TessLevelOuter[2]));
+    }
+}
@@ -0,0 +1,59 @@
+#version 400 core
+
+layout (triangles, fractional_odd_spacing, ccw) in;
+
+in vec3 tes_world_position[];
+in vec2 tes_tex_coord[];
+in vec3 tes_normal[];
+
+#include "terrainVertexOutputs.glsl"
+
+uniform float tess_near_distance;
+uniform float tess_far_distance;
+// height of the detail relief in world units and how many noise cells there are per world unit
+uniform float detail_amplitude;
+uniform float detail_frequency;
+
+float hash(vec2 cell) {
+    return fract(sin(dot(cell, vec2(127.1, 311.7))) * 43758.5453);
+}
+
+// smoothly interpolated value noise in [0,1]
+float value_noise(vec2 p) {
+    vec2 cell = floor(p);
+    vec2 f = fract(p);
+    vec2 u = f * f * (3.0 - 2.0 * f);
+    float a = hash(cell);
+    float b = hash(cell + vec2(1.0, 0.0));
+    float c = hash(cell + vec2(0.0, 1.0));
+    float d = hash(cell + vec2(1.0, 1.0));
+    return mix(mix(a, b, u.x), mix(c, d, u.x), u.y);
+}
+
+float detail_height(vec2 world_xz) {
+    vec2 p = world_xz * detail_frequency;
+    // two octaves are enough for small bumps
+    return detail_amplitude * (value_noise(p) + 0.5 * value_noise(2.0 * p) - 0.75);
+}
+
+void main(void) {
+    vec3 position = gl_TessCoord.x * tes_world_position[0] + gl_TessCoord.y * tes_world_position[1] + gl_TessCoord.z * tes_world_position[2];
+    vec2 tex_coord = gl_TessCoord.x * tes_tex_coord[0] + gl_TessCoord.y * tes_tex_coord[1] + gl_TessCoord.z * tes_tex_coord[2];
+    vec3 normal = normalize(gl_TessCoord.x * tes_normal[0] + gl_TessCoord.y * tes_normal[1] + gl_TessCoord.z * tes_normal[2]);
+
+    vec3 camera_position = camera_world_position();
+    // the relief fades out together with the tessellation level so distant coarse patches stay flat and don't swim
+    float distance_range = max(tess_far_distance - tess_near_distance, 0.0001);
+    float detail_weight = 1.0 - clamp((distance(camera_position, position) - tess_near_distance) / distance_range, 0.0, 1.0);
+
+    float step = 0.1;
+    float height = detail_weight * detail_height(position.xz);
+    float height_dx = detail_weight * detail_height(position.xz + vec2(step, 0.0));
+    float height_dz = detail_weight * detail_height(position.xz + vec2(0.0, step));
+    vec3 tangent = normalize(cross(normal, vec3(0.0, 0.0, 1.0)));
+    vec3 bitangent = cross(tangent, normal);
+    normal = normalize(normal - (tangent * (height_dx - height) + bitangent * (height_dz - height)) / step);
+
+    vec4 world_position = vec4(position + normal * height, 1.0);
+    write_vertex_outputs(world_position, normal, tex_coord);
+}
@@ -0,0 +1,23 @@
+#version 400 core
+
+// vertex stage of the tessellated terrain pipeline, everything view dependent is done in the evaluation shader after subdividing
+
+in vec3 pos;
+in vec2 tex_coord;
+in vec3 normal;
+
+out vec3 tcs_world_position;
+out vec2 tcs_tex_coord;
+out vec3 tcs_normal;
+
+uniform mat4 transform;
+
+void main(void) {
+    vec4 world_position = transform * vec4(pos, 1.0);
+    tcs_world_position = world_position.xyz;
+    tcs_tex_coord = tex_coord;
+
+    mat4 normal_transform = transpose(inverse(transform));
+    tcs_normal = (normal_transform * vec4(normal, 0.0)).xyz;
+    gl_Position = world_position;
+}
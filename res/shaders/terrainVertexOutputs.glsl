@@ -0,0 +1,56 @@
+// view dependent outputs of the terrain vertex stage, shared by terrainVertexShader and terrainTessEvalShader
+// so that they feed the same fragment shader
+
+const int NUM_LIGHTS = 4;
+
+out vec2 pass_tex_coord;
+out vec3 surface_normal;
+out vec3 light_direction[NUM_LIGHTS];
+out vec3 to_camera_dir;
+out float visibility;
+out vec4 shadow_coords;
+
+uniform mat4 projection_matrix;
+uniform mat4 view_matrix;
+uniform mat4 to_shadowmap_space;
+uniform float shadow_distance;
+
+// w is 0 for directional lights whose xyz is then the direction towards the light
+uniform vec4 light_pos[NUM_LIGHTS];
+
+#include "fog.glsl"
+
+// shadow stuff
+const float shadow_transition_distance = 10.0;
+
+// clipping plane for water rendering
+uniform vec4 clip_plane;
+
+vec3 camera_world_position() {
+    // extract camera position from view matrix
+    return (inverse(view_matrix) * vec4(0.0, 0.0, 0.0, 1.0)).xyz;
+}
+
+void write_vertex_outputs(vec4 world_position, vec3 normal, vec2 tex_coord) {
+    shadow_coords = to_shadowmap_space * world_position;
+
+    gl_ClipDistance[0] = dot(world_position, clip_plane);
+
+    vec4 eye_space_position = view_matrix * world_position;
+    gl_Position = projection_matrix * eye_space_position;
+    pass_tex_coord = tex_coord;
+
+    surface_normal = normal;
+    for (int i=0; i<NUM_LIGHTS; i++) {
+        light_direction[i] = light_pos[i].xyz - world_position.xyz * light_pos[i].w;
+    }
+    to_camera_dir = camera_world_position() - world_position.xyz;
+
+    // compute visibility
+    float distance_to_eye = length(eye_space_position.xyz);
+    visibility = fog_visibility(distance_to_eye);
+
+    float to_shadow_box_edge_dist = distance_to_eye - (shadow_distance - shadow_transition_distance);
+    float excess_of_transition = to_shadow_box_edge_dist / shadow_transition_distance;
+    shadow_coords.w = 1 - clamp(excess_of_transition, 0, 1);
+}
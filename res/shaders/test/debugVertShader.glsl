@@ -6,9 +6,17 @@ out vec4 color;
 const vec4 colors[8] = vec4[8](vec4(1,0,0,1), vec4(1,1,1,1), vec4(1,1,1,1), vec4(1,1,1,1),
 vec4(0,0,0,1), vec4(0,0,0,1), vec4(0,0,0,1), vec4(0,0,0,1));
 uniform mat4 mvp_matrix;
+// lines use one color for all vertices instead of the per corner colors of the cube
+uniform bool uses_line_color;
+uniform vec4 line_color;
 
 void main(void) {
     gl_Position = mvp_matrix * vec4(pos, 1);
 
-    color = colors[gl_VertexID];
+    if (uses_line_color) {
+        color = line_color;
+    } else {
+        color = colors[gl_VertexID];
+        color.a = 0.2;
+    }
 }
\ No newline at end of file
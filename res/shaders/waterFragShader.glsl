@@ -25,6 +25,11 @@ uniform vec3 attenuation[LIGHT_NUM];
 // the depth buffer in fact has the value in the range [0,1]
 uniform float depth_calc_A;
 uniform float depth_calc_B;
+// with reversed z the depth buffer value is already the ndc z
+uniform bool zero_to_one_depth;
+
+// water depth with the full distortion, always above 0 (WaterProps::shader_falloff_depth)
+uniform float distortion_falloff_depth;
 
 // fog
 uniform vec3 sky_color;
@@ -35,8 +40,11 @@ const float water_reflectivity = 1.5;
 const float shine_damper = 20.0;
 const float shine_reflectivity = 0.5;
 
-const float fog_density = 0.007;
-const float fog_gradient = 1.5;
+#include "fog.glsl"
+
+float depth_to_ndc(float depth) {
+    return zero_to_one_depth ? depth : 2.0*depth - 1.0;
+}
 
 void main() {
     vec2 ndc_coords = clip_coords.xy / clip_coords.w;
@@ -47,16 +55,16 @@ void main() {
 
     float bottom_to_camera = texture(depth_map, texture_coords).x;
     // use depth buffer reversal formula 
-    float bottom_to_camera_real_z = -depth_calc_B / (depth_calc_A + 2.0*bottom_to_camera - 1.0);
+    float bottom_to_camera_real_z = -depth_calc_B / (depth_calc_A + depth_to_ndc(bottom_to_camera));
     bottom_to_camera_real_z = -bottom_to_camera_real_z; // we want depths to be positive unlike z
 
     float water_surface_depth = gl_FragCoord.z; // find frags depth buffer z
-    float water_surface_depth_real_z = -(-depth_calc_B / (depth_calc_A + 2.0*water_surface_depth - 1.0)); // the minus is from wanting positive like above
+    float water_surface_depth_real_z = -(-depth_calc_B / (depth_calc_A + depth_to_ndc(water_surface_depth))); // the minus is from wanting positive like above
     float water_depth = bottom_to_camera_real_z - water_surface_depth_real_z;
     // alpha blending linearly until distance of 5 into water depth -> after that opaque
     float water_blend_factor = clamp(water_depth / 2.0, 0.0, 1.0);
-    // distortion factor
-    float water_depth_anti_distort_factor = clamp(water_depth / 20.0, 0.0, 1.0);
+    // less distortion in shallow water so the refraction doesn't pull in what is above the water line at the shore
+    float water_depth_anti_distort_factor = smoothstep(0.0, distortion_falloff_depth, water_depth);
 
     // this seems like a fancy way to distort the water
     vec2 distorted_tex_coords = texture(dudv_map, vec2(tex_coords.x + wave_factor, tex_coords.y)).rg * 0.1;
@@ -96,8 +104,7 @@ void main() {
     }
         
     // compute visibility    
-    float fog_vis_coef = exp(-pow(water_surface_depth_real_z * fog_density, fog_gradient));
-    float visibility = clamp(fog_vis_coef, 0.0, 1.0);
+    float visibility = fog_visibility(water_surface_depth_real_z);
 
     final_color = mix(reflection_color, refraction_color, refraction_factor);
     // mix with a bit of blue/gree
@@ -13,7 +13,8 @@ uniform mat4 projection_matrix;
 uniform mat4 view_matrix;
 uniform mat4 transform_matrix;
 uniform vec3 camera_world_pos;
-uniform vec3 light_pos[LIGHT_NUM];
+// w is 0 for directional lights whose xyz is then the direction towards the light
+uniform vec4 light_pos[LIGHT_NUM];
 
 const float tiling = 4.0;
 
@@ -27,6 +28,6 @@ void main() {
 
     to_camera_vec = camera_world_pos - world_pos.xyz;
     for (int i=0; i < LIGHT_NUM; ++i) {
-        from_light[i] = world_pos.xyz - light_pos[i];
+        from_light[i] = world_pos.xyz * light_pos[i].w - light_pos[i].xyz;
     }
 }
\ No newline at end of file
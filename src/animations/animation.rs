@@ -12,20 +12,88 @@ impl Default for AnimationState {
     }
 }
 
-#[derive(Clone, Default)]
+// a named point in the clip, e.g. a footstep or the hit frame of an attack
+#[derive(Clone, Debug)]
+pub struct AnimationEvent {
+    pub name: String,
+    pub time: f32,
+}
+
+#[derive(Clone)]
 pub struct Animation {
     pub length_seconds: f32,
     pub joint_animations: Vec<JointAnimation>,
+    pub events: Vec<AnimationEvent>,
     animation_state: AnimationState,
+    playback_speed: f32,
+    // time into the clip used to detect event crossings, the joint animations keep their own times
+    current_time: f32,
+    fired_events: Vec<String>,
+}
+
+impl Default for Animation {
+    fn default() -> Self {
+        Animation {
+            length_seconds: 0.0,
+            joint_animations: Vec::new(),
+            events: Vec::new(),
+            animation_state: AnimationState::default(),
+            playback_speed: 1.0,
+            current_time: 0.0,
+            fired_events: Vec::new(),
+        }
+    }
 }
 
 impl Animation {
+    pub fn add_event(&mut self, name: &str, time: f32) {
+        self.events.push(AnimationEvent { name: name.to_string(), time });
+    }
+
+    pub fn playback_speed(&self) -> f32 {
+        self.playback_speed
+    }
+
+    // playing backwards is not supported
+    pub fn set_playback_speed(&mut self, playback_speed: f32) {
+        self.playback_speed = playback_speed.max(0.0);
+    }
+
+    // events crossed since the last call in the order they were crossed
+    pub fn drain_fired_events(&mut self) -> Vec<String> {
+        std::mem::replace(&mut self.fired_events, Vec::new())
+    }
+
+    // moves the clip time forward and queues every event crossed on the way, once per loop
+    pub fn advance_events(&mut self, clip_time: f32) {
+        if self.length_seconds <= 0.0 {
+            return;
+        }
+        let start = self.current_time;
+        let end = start + clip_time;
+        let mut crossed: Vec<(f32, &AnimationEvent)> = Vec::new();
+        for event in self.events.iter() {
+            // number of times t + k * length lies in (start, end]
+            let first_loop = ((start - event.time) / self.length_seconds).floor() + 1.0;
+            let last_loop = ((end - event.time) / self.length_seconds).floor();
+            let mut k = first_loop;
+            while k <= last_loop {
+                crossed.push((event.time + k * self.length_seconds, event));
+                k += 1.0;
+            }
+        }
+        crossed.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap());
+        self.fired_events.extend(crossed.into_iter().map(|(_, event)| event.name.clone()));
+        self.current_time = end % self.length_seconds;
+    }
+
     pub fn play(&mut self) {
         self.animation_state = AnimationState::Playing;
     }
 
     pub fn stop(&mut self) {
         self.animation_state = AnimationState::Stopped;
+        self.current_time = 0.0;
         for anim in self.joint_animations.iter_mut() {
             anim.current_animation_time = 0.0;
         }
@@ -1,23 +1,54 @@
 use crate::entities::AnimatedEntity;
-use crate::display::Display;
 use super::animation::*;
 use super::joint::*;
 use std::collections::HashMap;
 
+pub type AnimationEventCallback = Box<dyn Fn(&str)>;
+
 #[derive(Default)]
-pub struct Animator;
+pub struct Animator {
+    event_callbacks: HashMap<String, Vec<AnimationEventCallback>>,
+}
 
 impl Animator {
-    pub fn update_animation(&self, animated_entity: &mut AnimatedEntity, display: &Display) {
-        let animation = &mut animated_entity.model.animation;
+    // called every time update_animation crosses an event with this name
+    pub fn on_event(&mut self, event_name: &str, callback: AnimationEventCallback) {
+        self.event_callbacks.entry(event_name.to_string()).or_insert_with(Vec::new).push(callback);
+    }
+
+    // hands the queued events of the animation to the registered callbacks
+    pub fn dispatch_events(&self, animation: &mut Animation) {
+        for event_name in animation.drain_fired_events() {
+            if let Some(callbacks) = self.event_callbacks.get(&event_name) {
+                for callback in callbacks.iter() {
+                    callback(&event_name);
+                }
+            }
+        }
+    }
+
+    // the main loop passes the display frame time, tests can step with a fixed delta
+    pub fn update_animation(&self, animated_entity: &mut AnimatedEntity, frame_time_sec: f32) {
+        let joint_poses = match Self::advance_animation(&mut animated_entity.model.animation, frame_time_sec) {
+            Some(joint_poses) => joint_poses,
+            None => return,
+        };
+        self.dispatch_events(&mut animated_entity.model.animation);
+        animated_entity.model.root_joint.apply_new_joint_poses(&crate::math::Matrix4f::identity(), &joint_poses);
+    }
+
+    // moves every joint animation forward by the frame time scaled by the playback speed, queues the crossed events and returns the interpolated joint poses, None when the animation is stopped
+    pub fn advance_animation(animation: &mut Animation, frame_time: f32) -> Option<HashMap<String, JointTransform>> {
         if !animation.is_playing() {
-            return;
+            return None;
         }
 
+        let clip_time = frame_time * animation.playback_speed();
+        animation.advance_events(clip_time);
+
         let mut joint_poses = HashMap::new();
-        let frame_time = display.frame_time_sec;
         for joint_animation in animation.joint_animations.iter_mut() {
-            joint_animation.current_animation_time = (joint_animation.current_animation_time + frame_time) % joint_animation.length_seconds;
+            joint_animation.current_animation_time = (joint_animation.current_animation_time + clip_time) % joint_animation.length_seconds;
             let progress = joint_animation.get_keyframe_progress();            
             match progress {
                 AnimationProgress::InProgress(k1, k2) | AnimationProgress::LastFrame(k1, k2) => {                    
@@ -28,11 +59,99 @@ impl Animator {
                 AnimationProgress::NotStarted => {},
             }
         }
-
-        animated_entity.model.root_joint.apply_new_joint_poses(&crate::math::Matrix4f::identity(), &joint_poses);
+        Some(joint_poses)
     }
 
     fn calculate_progress_time(cur_time: f32, t1: f32, t2: f32) -> f32 {
         (cur_time - t1) / (t2 - t1)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use super::super::keyframe::Keyframe;
+    use crate::math::Vector3f;
+
+    fn walk_animation() -> Animation {
+        let mut end_pose = JointTransform::identity();
+        end_pose.position = Vector3f::new(0.0, 2.0, 0.0);
+        let joint_animation = JointAnimation {
+            name: String::from("walk"),
+            joint_name: String::from("hip"),
+            current_animation_time: 0.0,
+            length_seconds: 2.0,
+            keyframes: vec![
+                Keyframe { timestamp: 0.0, pose: JointTransform::identity() },
+                Keyframe { timestamp: 2.0, pose: end_pose },
+            ],
+        };
+        let mut animation = Animation::default();
+        animation.length_seconds = 2.0;
+        animation.joint_animations.push(joint_animation);
+        animation
+    }
+
+    #[test]
+    fn fixed_steps_advance_the_clip_deterministically() {
+        let mut animation = walk_animation();
+        assert!(Animator::advance_animation(&mut animation, 0.25).is_none());
+        assert_eq!(animation.joint_animations[0].current_animation_time, 0.0);
+
+        animation.play();
+        let mut poses = HashMap::new();
+        for _ in 0..4 {
+            poses = Animator::advance_animation(&mut animation, 0.25).unwrap();
+        }
+        assert_eq!(animation.joint_animations[0].current_animation_time, 1.0);
+        assert!((poses["hip"].position.y - 1.0).abs() < 1e-6);
+
+        // wraps around at the end of the clip
+        for _ in 0..3 {
+            Animator::advance_animation(&mut animation, 0.5);
+        }
+        assert_eq!(animation.joint_animations[0].current_animation_time, 0.5);
+    }
+
+    #[test]
+    fn crossed_events_fire_once_per_loop() {
+        let mut animation = walk_animation();
+        animation.add_event("footstep", 0.5);
+        animation.play();
+
+        Animator::advance_animation(&mut animation, 0.25);
+        assert!(animation.drain_fired_events().is_empty());
+        Animator::advance_animation(&mut animation, 0.5);
+        assert_eq!(animation.drain_fired_events(), vec!["footstep"]);
+        Animator::advance_animation(&mut animation, 0.5);
+        assert!(animation.drain_fired_events().is_empty());
+
+        // a step spanning two full loops crosses the event twice
+        Animator::advance_animation(&mut animation, 4.0);
+        assert_eq!(animation.drain_fired_events(), vec!["footstep", "footstep"]);
+    }
+
+    #[test]
+    fn playback_speed_scales_event_timing() {
+        use std::rc::Rc;
+        use std::cell::Cell;
+
+        let mut animation = walk_animation();
+        animation.add_event("hit", 1.0);
+        animation.set_playback_speed(2.0);
+        animation.play();
+
+        let hits = Rc::new(Cell::new(0));
+        let mut animator = Animator::default();
+        let counter = hits.clone();
+        animator.on_event("hit", Box::new(move |_| counter.set(counter.get() + 1)));
+
+        Animator::advance_animation(&mut animation, 0.4);
+        animator.dispatch_events(&mut animation);
+        assert_eq!(hits.get(), 0);
+        Animator::advance_animation(&mut animation, 0.1);
+        animator.dispatch_events(&mut animation);
+        assert_eq!(hits.get(), 1);
+        assert_eq!(animation.joint_animations[0].current_animation_time, 1.0);
+    }
+}
@@ -4,5 +4,8 @@ pub mod joint;
 pub mod keyframe;
 pub mod animation;
 
-pub use animation::Animation;
+pub use animation::{
+    Animation,
+    AnimationEvent,
+};
 pub use animated_model::AnimatedModel;
@@ -1,10 +1,6 @@
 extern crate copper;
 
 use copper::animations::animator::Animator;
-use copper::entities::player::{
-    Player,
-    PlayerEntityType,
-};
 use copper::display::{
     Display,
     framebuffers::FboMap,
@@ -13,6 +9,7 @@ use copper::renderers::{
     master_renderer::MasterRenderer,
     master_renderer::RenderGroup,
     gui_renderer::GuiRenderer,
+    particle_renderer::ParticleDepthTest,
 };
 use copper::models::{
     ResourceManager,
@@ -23,7 +20,6 @@ use copper::particles::{
 use copper::post_processing::post_processing::PostProcessing;
 use copper::mouse_picker::MousePicker;
 use copper::scenes::{
-    scene::Scene,
     all_scene::*,
     //test_scene::*,
     //environment_map_scene::*,
@@ -31,9 +27,6 @@ use copper::scenes::{
 };
 use copper::gl;
 
-use std::thread;
-use std::time::Duration;
-
 fn main() {
     let mut display = Display::create();
     let mut framebuffers = FboMap::new_rendering_fbos(&display);
@@ -41,22 +34,25 @@ fn main() {
     let mut gui_renderer = GuiRenderer::new();
     
     init_resourced_for_load_screen(&mut resource_manager);
-    while resource_manager.are_textures_loading() && !display.is_close_requested() {
-        thread::sleep(Duration::from_millis(10));
-    }
-    if display.is_close_requested() {
-        return;
-    }
-    let load_screen = create_load_screen(&mut resource_manager);
-
-    let mut resource_init_started = false;
-    while (!resource_init_started || resource_manager.are_textures_loading()) && !display.is_close_requested() {        
-        gui_renderer.render(&load_screen.guis, &load_screen.gui_model.raw_model, &load_screen.texts);
+    // every frame uploads some of the loaded textures and updates the display so that the window stays responsive while loading
+    let mut load_stage = LoadStage::default();
+    let mut load_screen = None;
+    while load_stage != LoadStage::Done && !display.is_close_requested() {
+        let next_stage = load_stage.next(resource_manager.are_textures_loading());
+        let scene_load_starts = load_stage == LoadStage::LoadScreen && next_stage == LoadStage::Scene;
+        if scene_load_starts {
+            load_screen = Some(create_load_screen(&mut resource_manager));
+        }
+        if let Some(load_screen) = &mut load_screen {
+            resource_manager.resolve_gui_panels(&mut load_screen.guis);
+            gui_renderer.render(&load_screen.guis, &load_screen.gui_model.raw_model, &load_screen.texts, &display);
+        }
         display.update_display();
-        if !resource_init_started {
+        // the load screen is shown before we start loading the models which blocks for a while
+        if scene_load_starts {
             init_scene_resources(&mut resource_manager);
-            resource_init_started = true;
         }
+        load_stage = next_stage;
     }
     if display.is_close_requested() {
         return;
@@ -65,6 +61,10 @@ fn main() {
     let mut scene = create_scene(&mut resource_manager, &framebuffers);
     
     let mut master_renderer = MasterRenderer::new(&display.projection_matrix, display.get_aspect_ratio());    
+    master_renderer.set_sky_colors(&scene.sky_colors);
+    // the smoke behind the foliage of the scene is tested against the resolved scene depth
+    master_renderer.set_particle_depth_test(ParticleDepthTest::ResolvedSceneDepth);
+    gui_renderer.set_target(scene.gui_target);
     
     let mut mouse_picker = MousePicker::new();
 
@@ -76,54 +76,39 @@ fn main() {
         
     while !display.is_close_requested() {
 
-        update_animations(&animator, &mut scene.player, &display);
-
-        scene.camera.move_camera(&display, &scene.player);
-        
-        update_mouse_picker_and_move_lamp(&mut mouse_picker, &display, &mut scene);
-
-        spin_around_normal_mapped_entities(&mut scene, &display);
+        scene.update(&display, &animator, &mut mouse_picker);
+        if let Some((tile_manager, generator)) = &scene.terrain_streaming {
+            let position = scene.player.position().clone();
+            resource_manager.stream_terrain_tiles(tile_manager, generator, &mut scene.ground, position.x, position.z);
+        }
         
-        particle_master.emit_particles(&scene.particle_systems, &display);
+        particle_master.emit_particles(&scene.particle_systems, display.frame_time_sec, &scene.camera);
         
-        particle_master.update(&display, &scene.camera);
+        particle_master.update(display.frame_time_sec, &scene.camera, Some(&scene.ground));
 
-        scene.player.move_player(&display, &scene.ground);
+        let mut snapshot = scene.create_render_snapshot();
+        master_renderer.begin_frame();
+        master_renderer.render(&snapshot.lights, &mut snapshot.camera, &snapshot.entities, &snapshot.normal_mapped_entities, &scene.ground.terrains, 
+            &scene.player, &scene.water, &scene.skybox, &display, &mut framebuffers, &mut particle_master, &snapshot.entities_with_env_map, &mut snapshot.debug_entity);
 
-        scene.skybox.increase_rotation(&display);
+        let glow_layer_texture = master_renderer.render_layer_texture(&framebuffers);
+        do_post_processing(scene.uses_post_processing, &mut post_processing, &mut framebuffers, &display, glow_layer_texture);
 
-        master_renderer.render(&scene.lights, &mut scene.camera, &scene.entities, &scene.normal_mapped_entities, &scene.ground.terrains, 
-            &scene.player, &scene.water, &scene.skybox, &display, &mut framebuffers, &mut particle_master, &mut scene.entities_with_env_map, &mut scene.debug_entity);
-
-        do_post_processing(scene.uses_post_processing, &mut post_processing, &mut framebuffers, &display);
-
-        gui_renderer.render(&scene.guis, &scene.quad_model.raw_model, &scene.texts);
+        resource_manager.resolve_gui_panels(&mut scene.guis);
+        framebuffers.resolve_gui_panels(&mut scene.guis);
+        gui_renderer.render(&scene.guis, &scene.quad_model.raw_model, &scene.texts, &display);
 
         display.update_display();
     }
 }
 
-fn update_animations(animator: &Animator, player: &mut Player, display: &Display) {
-    let moving = player.is_moving();
-    if let PlayerEntityType::AnimatedModelEntity(animated_model) = &mut player.entity {
-        
-        if moving {
-            animated_model.model.animation.play();
-        } else {
-            animated_model.model.animation.stop();
-        }
-
-        animator.update_animation(animated_model, display);
-    }
-}
-
-fn do_post_processing(uses_post_processing: bool, post_processing: &mut PostProcessing, framebuffers: &mut FboMap, display: &Display) {    
+fn do_post_processing(uses_post_processing: bool, post_processing: &mut PostProcessing, framebuffers: &mut FboMap, display: &Display, glow_layer_texture: Option<u32>) {    
     
     gl::helper::push_debug_group(RenderGroup::POST_PROCESSING.id, RenderGroup::POST_PROCESSING.name);
 
     if uses_post_processing {
         do_anti_aliasing_for_fbo(post_processing, framebuffers, display);
-        post_processing.do_post_processing(display);
+        post_processing.do_post_processing(display, glow_layer_texture);
     } else {
         do_anti_aliasing_to_screen(framebuffers, display);
     }
@@ -146,19 +131,3 @@ fn do_anti_aliasing_to_screen(framebuffers: &mut FboMap, display: &Display) {
     let camera_multisampled_fbo = framebuffers.fbos.get_mut(FboMap::CAMERA_TEXTURE_FBO_MULTI).expect("A multisampled fbo must be present MSAA processing of camera output");
     camera_multisampled_fbo.resolve_to_screen(&display);
 }
-
-fn update_mouse_picker_and_move_lamp(mouse_picker: &mut MousePicker, display: &Display, scene: &mut Scene) {
-    if let Some(selected_pos) = mouse_picker.update(&display, &display.projection_matrix, &scene.camera, &scene.ground) {            
-        let last_pos = scene.entities.len()-1;
-        scene.entities[last_pos].set_position(&selected_pos);
-        scene.lights[3].position = selected_pos;
-        scene.lights[3].position.y += 14.0; 
-    }
-}
-
-fn spin_around_normal_mapped_entities(scene: &mut Scene, display: &Display) {
-    const SPEED: f32 = 20.0;
-    for idx in 0..scene.normal_mapped_entities.len() {
-        scene.normal_mapped_entities[idx].increase_rotation(0.0, 0.0, SPEED * display.frame_time_sec);
-    }
-}
\ No newline at end of file
@@ -0,0 +1,143 @@
+use std::sync::{
+    atomic::{
+        AtomicU32,
+        Ordering,
+    },
+    OnceLock,
+};
+use crate::gl;
+use crate::math::{
+    Matrix4f,
+    Vector4f,
+};
+use super::Display;
+
+// reversed z maps the near plane to depth 1 and the far plane to 0 which together with the float precision distribution
+// gives nearly uniform depth precision across the whole frustum, it needs clip control so that ndc depth is in [0, 1]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DepthMode {
+    Standard,
+    ReversedZ,
+}
+
+impl Default for DepthMode {
+    fn default() -> Self {
+        DepthMode::Standard
+    }
+}
+
+// the clip depth last set on the context, 0 before the first apply
+static APPLIED_CLIP_DEPTH: AtomicU32 = AtomicU32::new(0);
+
+// enumerating the extensions is slow so it is only done once
+fn clip_control_supported() -> bool {
+    static SUPPORTED: OnceLock<bool> = OnceLock::new();
+    *SUPPORTED.get_or_init(gl::helper::supports_clip_control)
+}
+
+impl DepthMode {
+    // reversed z without clip control would clip away half of the depth range so it falls back to standard depth
+    pub fn supported_mode(&self, clip_control_supported: bool) -> DepthMode {
+        match self {
+            DepthMode::ReversedZ if !clip_control_supported => DepthMode::Standard,
+            _ => *self,
+        }
+    }
+
+    // the mode that is actually used on the current context
+    pub fn or_supported(&self) -> DepthMode {
+        self.supported_mode(clip_control_supported())
+    }
+
+    pub fn projection_matrix(&self, aspect_ratio: f32) -> Matrix4f {
+        match self {
+            DepthMode::Standard => Matrix4f::create_projection_matrix(Display::NEAR, Display::FAR, Display::FOV_HORIZONTAL, aspect_ratio),
+            DepthMode::ReversedZ => Matrix4f::create_reversed_z_projection_matrix(Display::NEAR, Display::FAR, Display::FOV_HORIZONTAL, aspect_ratio),
+        }
+    }
+
+    // keeps the field of view and aspect of the projection but moves its far plane, the distance is positive
+    pub fn with_far_distance(&self, projection_matrix: &Matrix4f, far_distance: f32) -> Matrix4f {
+        // only the depth row depends on near and far
+        let depth_template = match self {
+            DepthMode::Standard => Matrix4f::create_projection_matrix(Display::NEAR, -far_distance, Display::FOV_HORIZONTAL, 1.0),
+            DepthMode::ReversedZ => Matrix4f::create_reversed_z_projection_matrix(Display::NEAR, -far_distance, Display::FOV_HORIZONTAL, 1.0),
+        };
+        let mut projection = projection_matrix.clone();
+        projection[2] = depth_template[2];
+        projection
+    }
+
+    // moves the near plane of the projection onto the view space clip plane (see Matrix4f::make_oblique_clip)
+    pub fn oblique_clip(&self, projection_matrix: &Matrix4f, view_clip_plane: &Vector4f) -> Matrix4f {
+        match self {
+            DepthMode::Standard => Matrix4f::make_oblique_clip(projection_matrix, view_clip_plane),
+            DepthMode::ReversedZ => Matrix4f::make_reversed_z_oblique_clip(projection_matrix, view_clip_plane),
+        }
+    }
+
+    pub fn clear_depth(&self) -> f64 {
+        match self {
+            DepthMode::Standard => 1.0,
+            DepthMode::ReversedZ => 0.0,
+        }
+    }
+
+    pub fn depth_func(&self) -> gl::types::GLenum {
+        match self {
+            DepthMode::Standard => gl::LESS,
+            DepthMode::ReversedZ => gl::GREATER,
+        }
+    }
+
+    // for drawing surfaces again over a depth buffer that already contains them
+    pub fn depth_func_or_equal(&self) -> gl::types::GLenum {
+        match self {
+            DepthMode::Standard => gl::LEQUAL,
+            DepthMode::ReversedZ => gl::GEQUAL,
+        }
+    }
+
+    // whether ndc depth is in [0, 1] instead of [-1, 1], needed when converting depth buffer values back to view space
+    pub fn is_zero_to_one(&self) -> bool {
+        *self == DepthMode::ReversedZ
+    }
+
+    // sets the global depth state, the depth buffer has to be cleared afterwards for the new clear value to take effect
+    // the mode should come from or_supported, clip control is only touched when it actually changes
+    pub fn apply(&self) {
+        let clip_depth = if self.is_zero_to_one() { gl::ZERO_TO_ONE } else { gl::NEGATIVE_ONE_TO_ONE };
+        if APPLIED_CLIP_DEPTH.swap(clip_depth, Ordering::Relaxed) != clip_depth && clip_control_supported() {
+            gl::clip_control(gl::LOWER_LEFT, clip_depth);
+        }
+        gl::depth_func(self.depth_func());
+        gl::clear_depth(self.clear_depth());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector4f;
+
+    fn ndc_depth(proj: &Matrix4f, view_z: f32) -> f32 {
+        let clip = proj.transform(&Vector4f::new(0.0, 0.0, view_z, 1.0));
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn reversed_projection_maps_near_to_one_and_far_to_zero() {
+        let proj = DepthMode::ReversedZ.projection_matrix(16.0 / 9.0);
+        assert!((ndc_depth(&proj, Display::NEAR) - 1.0).abs() < 1e-5);
+        assert!(ndc_depth(&proj, Display::FAR).abs() < 1e-5);
+        // depth decreases monotonically with distance
+        assert!(ndc_depth(&proj, -10.0) > ndc_depth(&proj, -100.0));
+    }
+
+    #[test]
+    fn reversed_z_falls_back_to_standard_without_clip_control() {
+        assert_eq!(DepthMode::ReversedZ.supported_mode(false), DepthMode::Standard);
+        assert_eq!(DepthMode::ReversedZ.supported_mode(true), DepthMode::ReversedZ);
+        assert_eq!(DepthMode::Standard.supported_mode(false), DepthMode::Standard);
+    }
+}
@@ -21,6 +21,10 @@ use std::sync::mpsc::Receiver;
 use std::fmt;
 use crate::gl;
 use crate::math::Matrix4f;
+use super::{
+    DepthMode,
+    Viewport,
+};
 
 pub use gl::glfw::Key;
 
@@ -83,27 +87,53 @@ impl fmt::Display for MousePosData {
     }
 }
 
-#[derive(Default)]
 pub struct WallClock {
     pub time_of_day: f32,
+    // longest step the simulation takes in one frame, after a stall (loading, breakpoint, window drag) the game slows down for a frame instead of jumping ahead
+    pub max_frame_time_sec: f32,
+    // how long the last frame really took, unclamped
+    pub real_frame_time_sec: f32,
+}
+
+impl Default for WallClock {
+    fn default() -> Self {
+        WallClock {
+            time_of_day: 0.0,
+            max_frame_time_sec: WallClock::DEFAULT_MAX_FRAME_TIME,
+            real_frame_time_sec: 0.0,
+        }
+    }
 }
 
 impl WallClock {
     pub const DAY_LENGTH: f32 = 240.0;
+    pub const DEFAULT_MAX_FRAME_TIME: f32 = 0.1;
+
+    pub fn simulation_frame_time(&self, real_frame_time_sec: f32) -> f32 {
+        real_frame_time_sec.min(self.max_frame_time_sec)
+    }
 
-    pub fn update(&mut self, frame_time_sec: f32) {
+    // returns the clamped frame time the simulation should step by
+    pub fn update(&mut self, real_frame_time_sec: f32) -> f32 {
+        self.real_frame_time_sec = real_frame_time_sec;
+        let frame_time_sec = self.simulation_frame_time(real_frame_time_sec);
         self.time_of_day += frame_time_sec;
         if self.time_of_day >= WallClock::DAY_LENGTH {
             self.time_of_day %= WallClock::DAY_LENGTH;
         }
+        frame_time_sec
     }
 }
 
 pub struct Display {
+    // clamped to wall_clock.max_frame_time_sec, the real frame time is wall_clock.real_frame_time_sec
     pub frame_time_sec: f32,
     pub mouse_pos: MousePosData,
     pub wall_clock: WallClock,
     pub projection_matrix: Matrix4f,
+    depth_mode: DepthMode,
+    // when set the scene is drawn into a centered sub-viewport with this aspect and the rest of the window is covered by black bars
+    target_aspect_ratio: Option<f32>,
     glfw: Glfw,
     window: Window,
     events: Receiver<(f64, WindowEvent)>,
@@ -153,12 +183,14 @@ impl Display {
 
         gl::load_with(|s| window.get_proc_address(s) as *const _);
 
+        crate::logging::connect_library_logging();
         gl::helper::register_error_callback();
 
         // turn on multisampling in opengl after enabling the window hint to have fbo use multisampling
         gl::enable(gl::MULTISAMPLE);
 
-        let projection_matrix = Matrix4f::create_projection_matrix(Display::NEAR, Display::FAR, Display::FOV_HORIZONTAL, Display::get_aspect_ratio_internal(&window));
+        let depth_mode = DepthMode::default();
+        let projection_matrix = depth_mode.projection_matrix(Display::get_aspect_ratio_internal(&window));
 
         Display {
             glfw,
@@ -170,6 +202,8 @@ impl Display {
             wall_clock: WallClock::default(),
             mouse_select_active: false,
             projection_matrix,
+            depth_mode,
+            target_aspect_ratio: None,
         }
     }
 
@@ -183,8 +217,56 @@ impl Display {
         }
     }
 
+    // renderers need to be told about the new projection matrix and depth mode as well (MasterRenderer::set_depth_mode)
+    // reversed z falls back to standard depth when the context has no clip control, depth_mode returns what is used
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode) {
+        let depth_mode = depth_mode.or_supported();
+        self.depth_mode = depth_mode;
+        self.projection_matrix = depth_mode.projection_matrix(self.get_aspect_ratio());
+        depth_mode.apply();
+    }
+
+    pub fn depth_mode(&self) -> DepthMode {
+        self.depth_mode
+    }
+
+    // renderers need to be told about the new projection matrix and aspect ratio as well (MasterRenderer::set_aspect_ratio)
+    pub fn set_target_aspect_ratio(&mut self, target_aspect_ratio: Option<f32>) {
+        self.target_aspect_ratio = target_aspect_ratio;
+        self.projection_matrix = self.depth_mode.projection_matrix(self.get_aspect_ratio());
+        self.update_viewport();
+    }
+
+    pub fn target_aspect_ratio(&self) -> Option<f32> {
+        self.target_aspect_ratio
+    }
+
+    // aspect ratio of the image the scene is rendered into, the target aspect when letterboxing
     pub fn get_aspect_ratio(&self) -> f32 {
-        Display::get_aspect_ratio_internal(&self.window)
+        match self.target_aspect_ratio {
+            Some(target_aspect_ratio) => target_aspect_ratio,
+            None => Display::get_aspect_ratio_internal(&self.window),
+        }
+    }
+
+    // the part of the default framebuffer (in pixels) the scene and gui are drawn to
+    pub fn scene_viewport(&self) -> Viewport {
+        let (width, height) = self.window.get_framebuffer_size();
+        self.letterbox(width as usize, height as usize)
+    }
+
+    // size of scene_viewport in screen coords like get_size, the gui is laid out in it so it lines up with the letterboxed scene
+    pub fn scene_size(&self) -> WindowSize {
+        let window_size = self.get_size();
+        self.letterbox(window_size.width, window_size.height).size()
+    }
+
+    // same as scene_viewport for any screen size e.g. the window size in screen coords for the mouse position
+    pub fn letterbox(&self, width: usize, height: usize) -> Viewport {
+        match self.target_aspect_ratio {
+            Some(target_aspect_ratio) => Viewport::letterboxed(width, height, target_aspect_ratio),
+            None => Viewport::full(width, height),
+        }
     }
 
     fn get_aspect_ratio_internal(window: &Window) -> f32 {
@@ -197,10 +279,8 @@ impl Display {
         let gl_version = window.get_context_version();    
         let is_core_profile = window.get_opengl_profile() == OpenGlProfileHint::Core as i32;
         let is_forward_compat = window.is_opengl_forward_compat();
-        println!("{}", "*".repeat(10));
-        println!("OpenGL version: {}", gl_version);    
-        println!("Core profile: {}, Forward compatibility: {}", is_core_profile, is_forward_compat);    
-        println!("{}", "*".repeat(10));
+        log_info!("OpenGL version: {}", gl_version);    
+        log_info!("Core profile: {}, Forward compatibility: {}", is_core_profile, is_forward_compat);
     }
 
     pub fn restore_default_framebuffer(&self) {
@@ -210,8 +290,22 @@ impl Display {
 
     fn update_viewport(&self) {
         // set the viewport size (measured in pixels unlike the window size which is in screen coordinates)
-        let (width, height) = self.window.get_framebuffer_size();               
-        gl::viewport(0, 0, width, height);
+        let viewport = self.scene_viewport();
+        gl::viewport(viewport.x, viewport.y, viewport.width, viewport.height);
+    }
+
+    // nothing is drawn outside the scene viewport so the bars are cleared once per frame
+    fn clear_letterbox_bars(&self) {
+        let scene_viewport = self.scene_viewport();
+        let (width, height) = self.window.get_framebuffer_size();
+        if scene_viewport == Viewport::full(width as usize, height as usize) {
+            return;
+        }
+        // keep the scissor test from limiting the clear
+        Viewport::full(width as usize, height as usize).apply();
+        gl::clear_color(0.0, 0.0, 0.0, 1.0);
+        gl::clear(gl::COLOR_BUFFER_BIT);
+        Viewport::reset_scissor();
     }
 
     pub fn update_display(&mut self) {        
@@ -219,10 +313,12 @@ impl Display {
 
         self.window.swap_buffers();
 
+        self.clear_letterbox_bars();
+
         self.glfw.poll_events();
 
-        self.update_frame_time_measurement();
-        self.wall_clock.update(self.frame_time_sec);
+        let real_frame_time_sec = self.update_frame_time_measurement();
+        self.frame_time_sec = self.wall_clock.update(real_frame_time_sec);
 
         self.mouse_pos.set_prev_to_cur();
 
@@ -235,14 +331,15 @@ impl Display {
         self.window.should_close()
     }
 
-    fn update_frame_time_measurement(&mut self) {
+    fn update_frame_time_measurement(&mut self) -> f32 {
         let current_time = SystemTime::now();
         let elapsed = current_time.duration_since(self.last_frame_sys_time);
-        self.frame_time_sec = match elapsed {
+        let real_frame_time_sec = match elapsed {
             Ok(elapsed) => (elapsed.as_secs() as f32 + elapsed.subsec_micros() as f32 / 1_000_000.0),
-            Err(_) => self.frame_time_sec,
+            Err(_) => self.wall_clock.real_frame_time_sec,
         };
         self.last_frame_sys_time = current_time;
+        real_frame_time_sec
     }
 
     fn handle_window_event(mouse_pos: &mut MousePosData, mouse_select_active: &mut bool, event: WindowEvent) {
@@ -270,7 +367,7 @@ impl Display {
             WindowEvent::Key(key, _, action, _) => {
                 if key == Key::M && action == Action::Press {
                     *mouse_select_active = !*mouse_select_active;
-                    println!("Toggled mouse select: {}", mouse_select_active);
+                    log_info!("Toggled mouse select: {}", mouse_select_active);
                 }
             },
             _ => {}
@@ -278,3 +375,17 @@ impl Display {
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn long_frame_is_clamped_for_the_simulation() {
+        let mut wall_clock = WallClock { max_frame_time_sec: 0.1, ..WallClock::default() };
+        let frame_time_sec = wall_clock.update(2.0);
+        assert_eq!(frame_time_sec, 0.1);
+        assert_eq!(wall_clock.real_frame_time_sec, 2.0);
+        assert!((wall_clock.time_of_day - 0.1).abs() < 1e-6);
+        assert_eq!(wall_clock.update(0.016), 0.016);
+    }
+}
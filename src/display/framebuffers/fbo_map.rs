@@ -4,6 +4,8 @@ use super::framebuffer_object::{
 };
 
 use crate::display::Display;
+use crate::guis::GuiPanel;
+use crate::models::TextureId;
 
 use std::collections::HashMap;
 
@@ -11,6 +13,13 @@ pub struct FboMap {
     pub fbos: HashMap<&'static str, FramebufferObject>,
 }
 
+// a texture attached to one of the fbos, its id changes whenever the fbo is recreated (FboMap::replace)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum FboTexture {
+    Color(usize),
+    Depth,
+}
+
 impl FboMap {
     pub const REFLECTION_FBO: &'static str = "ReflectionFBO";
     pub const REFRACTION_FBO: &'static str = "RefractionFBO";
@@ -19,6 +28,15 @@ impl FboMap {
     // used for rendering the scene to a texture that can later be operated on with post processing
     pub const CAMERA_TEXTURE_FBO: &'static str = "CameraTexture";
     pub const CAMERA_BRIGHTNESS_FBO: &'static str = "CameraBrightnessTexture";
+    // entities on the selected render layer get drawn into this one so post processing can treat them differently
+    pub const RENDER_LAYER_FBO: &'static str = "RenderLayerFBO";
+    // the depth of the camera fbo resolved into a texture e.g. for the particles to test against (ParticleDepthTest::ResolvedSceneDepth)
+    pub const SCENE_DEPTH_FBO: &'static str = "SceneDepthFBO";
+    // the gui is drawn into this one when it is composited over the scene instead of drawn directly
+    pub const GUI_FBO: &'static str = "GuiFBO";
+    // post processing stages alternate between reading one of these and writing the other
+    pub const POST_PROCESS_PING_FBO: &'static str = "PostProcessPingFBO";
+    pub const POST_PROCESS_PONG_FBO: &'static str = "PostProcessPongFBO";
 
     const REFLECTION_FBO_WIDTH: usize = 1280;
     const REFLECTION_FBO_HEIGHT: usize = 720;
@@ -28,10 +46,15 @@ impl FboMap {
 
     pub const SHADOW_MAP_SIZE: usize = 4096;
 
+    // the water shader samples the depth texture to know how deep the water is
+    pub fn refraction_fbo_flags() -> FboFlags {
+        FboFlags::COLOR_TEX | FboFlags::DEPTH_TEX
+    }
+
     pub fn new_postprocessing_fbos(display: &Display) -> Self {
         let mut fbos = HashMap::new();
         let display_size = display.get_size();
-        let camera_texture_fbo = FramebufferObject::new(display_size.width, display_size.height, FboFlags::COLOR_TEX | FboFlags::DEPTH_TEX, 1);
+        let camera_texture_fbo = FramebufferObject::new(display_size.width, display_size.height, FboFlags::COLOR_TEX | FboFlags::DEPTH_STENCIL_RENDERBUF, 1);
         let camera_brightness_fbo = FramebufferObject::new(display_size.width, display_size.height, FboFlags::COLOR_TEX, 1);                
         display.restore_default_framebuffer();
 
@@ -46,11 +69,12 @@ impl FboMap {
     pub fn new_rendering_fbos(display: &Display) -> Self {
         let mut fbos = HashMap::new();
         fbos.insert(Self::REFLECTION_FBO, FramebufferObject::new(Self::REFLECTION_FBO_WIDTH, Self::REFLECTION_FBO_HEIGHT, FboFlags::COLOR_TEX, 1));
-        fbos.insert(Self::REFRACTION_FBO, FramebufferObject::new(Self::REFRACTION_FBO_WIDTH, Self::REFRACTION_FBO_HEIGHT, FboFlags::COLOR_TEX | FboFlags::DEPTH_TEX, 1));
-        fbos.insert(Self::SHADOW_MAP_FBO, FramebufferObject::new(Self::SHADOW_MAP_SIZE, Self::SHADOW_MAP_SIZE, FboFlags::SHADOW_DEPTH, 0));
-        // TODO: what if screen size changes 
+        fbos.insert(Self::REFRACTION_FBO, FramebufferObject::new(Self::REFRACTION_FBO_WIDTH, Self::REFRACTION_FBO_HEIGHT, Self::refraction_fbo_flags(), 1));
+        fbos.insert(Self::SHADOW_MAP_FBO, Self::new_shadow_map_fbo(Self::SHADOW_MAP_SIZE));
         let display_size = display.get_size();
-        fbos.insert(Self::CAMERA_TEXTURE_FBO_MULTI, FramebufferObject::new(display_size.width, display_size.height, FboFlags::MULTISAMPLED | FboFlags::COLOR_RENDERBUF | FboFlags::DEPTH_RENDERBUF, 2));
+        fbos.insert(Self::CAMERA_TEXTURE_FBO_MULTI, Self::new_camera_fbo(display_size.width, display_size.height));
+        fbos.insert(Self::RENDER_LAYER_FBO, Self::new_render_layer_fbo(display_size.width, display_size.height));
+        fbos.insert(Self::SCENE_DEPTH_FBO, Self::new_scene_depth_fbo(display_size.width, display_size.height));
                 
         display.restore_default_framebuffer();
         FboMap {
@@ -58,7 +82,95 @@ impl FboMap {
         }
     }
 
+    // the stencil is used to outline selected entities, its depth stencil format has to match the camera texture fbo for the depth to be resolvable
+    pub fn new_camera_fbo(width: usize, height: usize) -> FramebufferObject {
+        FramebufferObject::new(width, height, FboFlags::MULTISAMPLED | FboFlags::COLOR_RENDERBUF | FboFlags::DEPTH_STENCIL_RENDERBUF, 2)
+    }
+
+    // same depth format as the camera fbo so the camera depth can be copied into it
+    pub fn new_render_layer_fbo(width: usize, height: usize) -> FramebufferObject {
+        FramebufferObject::new(width, height, FboFlags::COLOR_TEX | FboFlags::DEPTH_STENCIL_RENDERBUF, 1)
+    }
+
+    // the camera depth can only be blitted into the same depth stencil format
+    pub fn new_scene_depth_fbo(width: usize, height: usize) -> FramebufferObject {
+        FramebufferObject::new(width, height, FboFlags::DEPTH_STENCIL_TEX, 0)
+    }
+
+    // the rendering fbos that cover the screen follow the window size, the ones that are already the right size are kept
+    pub fn resize_screen_fbos(&mut self, width: usize, height: usize) {
+        let screen_fbos: [(&'static str, fn(usize, usize) -> FramebufferObject); 3] = [
+            (Self::CAMERA_TEXTURE_FBO_MULTI, Self::new_camera_fbo),
+            (Self::RENDER_LAYER_FBO, Self::new_render_layer_fbo),
+            (Self::SCENE_DEPTH_FBO, Self::new_scene_depth_fbo),
+        ];
+        for (name, create_fbo) in screen_fbos.iter() {
+            let has_size = self.fbos.get(name).map_or(true, |fbo| (fbo.viewport_width, fbo.viewport_height) == (width, height));
+            if !has_size {
+                self.replace(name, || create_fbo(width, height));
+            }
+        }
+    }
+
+    pub fn new_shadow_map_fbo(shadow_map_size: usize) -> FramebufferObject {
+        FramebufferObject::new(shadow_map_size, shadow_map_size, FboFlags::SHADOW_DEPTH | FboFlags::SHADOW_HARDWARE_PCF, 0)
+    }
+
+    pub fn new_gui_fbo(width: usize, height: usize) -> FramebufferObject {
+        FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1)
+    }
+
+    pub fn texture_id(&self, name: &'static str, texture: FboTexture) -> TextureId {
+        let tex_id = self.fbos.get(name).and_then(|fbo| match texture {
+            FboTexture::Color(attachment_id) => fbo.color_texture(attachment_id),
+            FboTexture::Depth => fbo.depth_texture,
+        });
+        tex_id.map_or(TextureId::Empty, TextureId::FboTexture)
+    }
+
+    // has to run every frame after the fbos may have been recreated e.g. when the shadow map resolution changes
+    pub fn resolve_gui_panels(&self, guis: &mut [GuiPanel]) {
+        for gui in guis.iter_mut() {
+            if let Some((name, texture)) = gui.fbo_texture {
+                gui.texture_id = self.texture_id(name, texture);
+            }
+        }
+    }
+
+    // an fbo previously stored under the name is dropped here which deletes its gl objects
     pub fn insert(&mut self, name: &'static str, fbo: FramebufferObject) {
         self.fbos.insert(name, fbo);
     }
-}
\ No newline at end of file
+
+    // for recreating an fbo (e.g. after a resize), the old one is dropped before the new one is created so both never exist at the same time
+    pub fn replace<F>(&mut self, name: &'static str, create_fbo: F) where F: FnOnce() -> FramebufferObject {
+        self.fbos.remove(name);
+        self.fbos.insert(name, create_fbo());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector2f;
+
+    #[test]
+    fn gui_panels_follow_a_recreated_shadow_map_fbo() {
+        let mut framebuffers = FboMap { fbos: HashMap::new() };
+        framebuffers.insert(FboMap::SHADOW_MAP_FBO, FramebufferObject::with_textures_for_test(1, None, Some(2)));
+        let mut guis = vec![GuiPanel::for_fbo_texture(FboMap::SHADOW_MAP_FBO, FboTexture::Depth, Vector2f::zero(), Vector2f::new(0.4, 0.4))];
+        framebuffers.resolve_gui_panels(&mut guis);
+        assert_eq!(guis[0].texture_id, TextureId::FboTexture(2));
+
+        // like a shadow map resolution change in the master renderer, the old fbo is forgotten first
+        // since dropping it would call into gl which isn't loaded in tests
+        std::mem::forget(framebuffers.fbos.remove(FboMap::SHADOW_MAP_FBO));
+        framebuffers.replace(FboMap::SHADOW_MAP_FBO, || FramebufferObject::with_textures_for_test(3, None, Some(4)));
+        framebuffers.resolve_gui_panels(&mut guis);
+        assert_eq!(guis[0].texture_id, TextureId::FboTexture(4));
+
+        std::mem::forget(framebuffers.fbos.remove(FboMap::SHADOW_MAP_FBO));
+        framebuffers.resolve_gui_panels(&mut guis);
+        assert_eq!(guis[0].texture_id, TextureId::Empty);
+    }
+}
@@ -1,4 +1,7 @@
-use super::super::Display;
+use super::super::{
+    Display,
+    Viewport,
+};
 use crate::gl;
 
 bitflags! {
@@ -9,15 +12,32 @@ bitflags! {
         const DEPTH_RENDERBUF   = 0b0001000;
         const SHADOW_DEPTH      = 0b0010000;
         const MULTISAMPLED      = 0b0100000;
+        // packed 24 bit depth and 8 bit stencil, used instead of DEPTH_RENDERBUF when a stencil buffer is needed
+        const DEPTH_STENCIL_RENDERBUF = 0b1000000;
+        // linear filtering of the SHADOW_DEPTH texture so that the hardware does 2x2 pcf on each depth comparison
+        const SHADOW_HARDWARE_PCF = 0b10000000;
+        // a depth texture in the packed depth stencil format of DEPTH_STENCIL_RENDERBUF so that depth can be blitted into it and sampled
+        const DEPTH_STENCIL_TEX = 0b100000000;
     }
 }
 
+// a gl object owned by a FramebufferObject, they all get deleted when it is dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FboGlObject {
+    Framebuffer(u32),
+    Texture(u32),
+    Renderbuffer(u32),
+    Sampler(u32),
+}
+
 pub struct FramebufferObject {
     fbo_id: u32,
     pub viewport_width: usize,
     pub viewport_height: usize,
     color_textures: Option<Vec<u32>>,
     pub depth_texture: Option<u32>,
+    // compares the SHADOW_DEPTH texture when bound to its texture unit, the texture itself returns the plain depth e.g. for a gui panel
+    pub shadow_sampler: Option<u32>,
     pub depth_renderbuffer_id: Option<u32>,
     color_renderbuffer_ids: Option<Vec<u32>>,
 }
@@ -43,12 +63,21 @@ impl FramebufferObject {
             Some(Self::create_depth_texture_attachment(viewport_width, viewport_height))
         } else if flags.contains(FboFlags::SHADOW_DEPTH) {
             // can this be simplified into just one depth attachment?
-            Some(Self::create_depth_texture_attachment_for_shadows(viewport_width, viewport_height))
+            Some(Self::create_depth_texture_attachment_for_shadows(viewport_width, viewport_height, flags.contains(FboFlags::SHADOW_HARDWARE_PCF)))
+        } else if flags.contains(FboFlags::DEPTH_STENCIL_TEX) {
+            Some(Self::create_depth_stencil_texture_attachment(viewport_width, viewport_height))
+        } else {
+            None
+        };
+        let shadow_sampler = if flags.contains(FboFlags::SHADOW_DEPTH) {
+            Some(Self::create_shadow_compare_sampler(flags.contains(FboFlags::SHADOW_HARDWARE_PCF)))
         } else {
             None
         };
         let depth_renderbuffer_id = if flags.contains(FboFlags::DEPTH_RENDERBUF) {
             Some(Self::create_depth_renderbuffer_attachment(viewport_width, viewport_height, flags.contains(FboFlags::MULTISAMPLED)))
+        } else if flags.contains(FboFlags::DEPTH_STENCIL_RENDERBUF) {
+            Some(Self::create_depth_stencil_renderbuffer_attachment(viewport_width, viewport_height, flags.contains(FboFlags::MULTISAMPLED)))
         } else {
             None
         };
@@ -68,6 +97,7 @@ impl FramebufferObject {
             viewport_height,
             color_textures,
             depth_texture,
+            shadow_sampler,
             depth_renderbuffer_id,
             color_renderbuffer_ids,
         }
@@ -108,19 +138,63 @@ impl FramebufferObject {
         tex_id
     }
 
-    pub fn create_depth_texture_attachment_for_shadows(width: usize, height: usize) -> u32 {
+    // sampling it returns the depth, the stencil is only there so the format matches the camera fbo
+    pub fn create_depth_stencil_texture_attachment(width: usize, height: usize) -> u32 {
+        let tex_id = gl::gen_texture();
+        gl::bind_texture(gl::TEXTURE_2D, tex_id);
+        gl::tex_image_2d_uninitialized(gl::TEXTURE_2D, 0, gl::DEPTH_STENCIL, gl::DEPTH24_STENCIL8, width, height, gl::UNSIGNED_INT_24_8);
+        gl::tex_parameter_iv(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST);
+        gl::tex_parameter_iv(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST);
+        gl::framebuffer_texture(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, tex_id, 0);
+        tex_id
+    }
+
+    pub fn create_depth_texture_attachment_for_shadows(width: usize, height: usize, uses_hardware_pcf: bool) -> u32 {
         let tex_id = gl::gen_texture();
         gl::bind_texture(gl::TEXTURE_2D, tex_id);
         gl::tex_image_2d_uninitialized(gl::TEXTURE_2D, 0, gl::DEPTH_COMPONENT, gl::DEPTH_COMPONENT32, width, height, gl::FLOAT);
-        gl::tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST);
-        gl::tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST);
-        gl::tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE);
-        gl::tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE);
+        for &(param_name, value) in Self::shadow_depth_texture_params(uses_hardware_pcf).iter() {
+            gl::tex_parameteri(gl::TEXTURE_2D, param_name, value);
+        }
         // attach mipmap level 0 of texture (tex_id -> unitialized) to the color attach0 of current framebuffer 
         gl::framebuffer_texture(gl::FRAMEBUFFER, gl::DEPTH_ATTACHMENT, tex_id, 0);
         tex_id
     }
 
+    // the texture returns the stored depth so it can also be shown with a plain sampler2D
+    pub fn shadow_depth_texture_params(uses_hardware_pcf: bool) -> [(gl::types::GLenum, gl::types::GLenum); 4] {
+        let filter = if uses_hardware_pcf { gl::LINEAR } else { gl::NEAREST };
+        [
+            (gl::TEXTURE_MIN_FILTER, filter),
+            (gl::TEXTURE_MAG_FILTER, filter),
+            (gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE),
+            (gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE),
+        ]
+    }
+
+    pub fn create_shadow_compare_sampler(uses_hardware_pcf: bool) -> u32 {
+        let sampler_id = gl::gen_sampler();
+        for &(param_name, value) in Self::shadow_compare_sampler_params(uses_hardware_pcf).iter() {
+            gl::sampler_parameteri(sampler_id, param_name, value);
+        }
+        sampler_id
+    }
+
+    // the shaders sample the shadow map with sampler2DShadow so the sampler has to compare the depth instead of returning it
+    // passing is when the reference depth is less or equal to the stored one i.e. the fragment is lit
+    // a bound sampler replaces all parameters of the texture so it repeats the filtering and wrapping
+    pub fn shadow_compare_sampler_params(uses_hardware_pcf: bool) -> [(gl::types::GLenum, gl::types::GLenum); 6] {
+        let [min_filter, mag_filter, wrap_s, wrap_t] = Self::shadow_depth_texture_params(uses_hardware_pcf);
+        [
+            min_filter,
+            mag_filter,
+            wrap_s,
+            wrap_t,
+            (gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE),
+            (gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL),
+        ]
+    }
+
     pub fn create_depth_renderbuffer_attachment(width: usize, height: usize, multisampled: bool) -> u32 {
         let render_buffer_id = gl::gen_renderbuffer();
         gl::bind_renderbuffer(gl::RENDERBUFFER, render_buffer_id);
@@ -133,6 +207,18 @@ impl FramebufferObject {
         render_buffer_id
     }
 
+    pub fn create_depth_stencil_renderbuffer_attachment(width: usize, height: usize, multisampled: bool) -> u32 {
+        let render_buffer_id = gl::gen_renderbuffer();
+        gl::bind_renderbuffer(gl::RENDERBUFFER, render_buffer_id);
+        if multisampled {
+            gl::renderbuffer_storage_multisampled(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height, Self::SAMPLE_NUM);
+        } else {
+            gl::renderbuffer_storage(gl::RENDERBUFFER, gl::DEPTH24_STENCIL8, width, height);
+        }
+        gl::framebuffer_renderbuffer(gl::FRAMEBUFFER, gl::DEPTH_STENCIL_ATTACHMENT, gl::RENDERBUFFER, render_buffer_id);
+        render_buffer_id
+    }
+
     pub fn create_color_renderbuffer_attachment(color_attach_id: gl::types::GLenum, width: usize, height: usize, multisampled: bool) -> u32 {
         let render_buffer_id = gl::gen_renderbuffer();
         gl::bind_renderbuffer(gl::RENDERBUFFER, render_buffer_id);
@@ -148,12 +234,28 @@ impl FramebufferObject {
     pub fn bind_framebuffer(fbo_id: u32, width: usize, height: usize) {
         gl::bind_framebuffer(gl::FRAMEBUFFER, fbo_id); // bind the frame buffer 
         gl::viewport(0, 0, width as i32, height as i32);
+        gl::helper::debug_check_error(|| format!("binding framebuffer {}", fbo_id));
     }
 
     pub fn check_framebuffer() {
         gl::check_framebuffer_status(gl::FRAMEBUFFER);
     }
 
+    // stands in for an fbo in tests where gl isn't loaded, it must not be dropped since that deletes its gl objects
+    #[cfg(test)]
+    pub fn with_textures_for_test(fbo_id: u32, color_textures: Option<Vec<u32>>, depth_texture: Option<u32>) -> Self {
+        FramebufferObject {
+            fbo_id,
+            viewport_width: 64,
+            viewport_height: 64,
+            color_textures,
+            depth_texture,
+            shadow_sampler: None,
+            depth_renderbuffer_id: None,
+            color_renderbuffer_ids: None,
+        }
+    }
+
     pub fn color_texture(&self, attachment_id: usize) -> Option<u32> {
         match &self.color_textures {
             Some(attachments) => {
@@ -189,37 +291,119 @@ impl FramebufferObject {
         display.restore_default_framebuffer();
     }
 
+    // so that a later pass into the target is depth tested against what was drawn here, both need the same depth format and size
+    // the target stays bound for drawing
+    pub fn blit_depth_to(&self, target_fbo: &FramebufferObject) {
+        gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, target_fbo.fbo_id);
+        gl::bind_framebuffer(gl::READ_FRAMEBUFFER, self.fbo_id);
+        gl::blit_framebuffer(0, 0, self.viewport_width, self.viewport_height, 0, 0, target_fbo.viewport_width, target_fbo.viewport_height, gl::DEPTH_BUFFER_BIT, gl::NEAREST);
+    }
+
     pub fn resolve_to_screen(&mut self, display: &Display) {
         // draw to default fbo
         gl::bind_framebuffer(gl::DRAW_FRAMEBUFFER, 0);
         // read from us
         gl::bind_framebuffer(gl::READ_FRAMEBUFFER, self.fbo_id);
-        let size = display.get_size();
-        let width = size.width;
-        let height = size.height;
-        gl::blit_framebuffer(0, 0, self.viewport_width, self.viewport_height, 0, 0, width, height, gl::COLOR_BUFFER_BIT, gl::NEAREST);
+        let target = display.scene_viewport();
+        let (x0, y0) = (target.x as usize, target.y as usize);
+        let filter = FramebufferObject::blit_filter(self.viewport_width, self.viewport_height, &target);
+        gl::blit_framebuffer(0, 0, self.viewport_width, self.viewport_height, x0, y0, x0 + target.width as usize, y0 + target.height as usize, gl::COLOR_BUFFER_BIT, filter);
         display.restore_default_framebuffer();
     }
 }
 
+impl FramebufferObject {
+    // a letterboxed or high dpi target is a different size than the fbo, nearest would drop or double whole pixel rows when scaling
+    fn blit_filter(width: usize, height: usize, target: &Viewport) -> u32 {
+        if (width, height) == (target.width as usize, target.height as usize) {
+            gl::NEAREST
+        } else {
+            gl::LINEAR
+        }
+    }
+
+    // everything this fbo allocated, attachments that were not requested through the flags are not included
+    pub fn owned_gl_objects(&self) -> Vec<FboGlObject> {
+        let mut gl_objects = vec![FboGlObject::Framebuffer(self.fbo_id)];
+        gl_objects.extend(self.color_textures.iter().flatten().map(|&color_tex| FboGlObject::Texture(color_tex)));
+        gl_objects.extend(self.depth_texture.map(FboGlObject::Texture));
+        gl_objects.extend(self.shadow_sampler.map(FboGlObject::Sampler));
+        gl_objects.extend(self.depth_renderbuffer_id.map(FboGlObject::Renderbuffer));
+        gl_objects.extend(self.color_renderbuffer_ids.iter().flatten().map(|&renderbuf| FboGlObject::Renderbuffer(renderbuf)));
+        gl_objects
+    }
+}
+
 impl Drop for FramebufferObject {
     fn drop(&mut self) {
-        gl::delete_framebuffer(self.fbo_id);
-        if let Some(color_textures) = &self.color_textures {
-            for color_tex in color_textures {
-                gl::delete_texture(*color_tex);
-            }
-        }
-        if let Some(depth_tex) = self.depth_texture {
-            gl::delete_texture(depth_tex);
-        }
-        if let Some(depth_renderbuf) = self.depth_renderbuffer_id {
-            gl::delete_renderbuffer(depth_renderbuf);
-        }
-        if let Some(color_renderbufs) = &self.color_renderbuffer_ids {
-            for renderbuf in color_renderbufs {
-                gl::delete_renderbuffer(*renderbuf);
+        for gl_object in self.owned_gl_objects() {
+            match gl_object {
+                FboGlObject::Framebuffer(fbo_id) => gl::delete_framebuffer(fbo_id),
+                FboGlObject::Texture(tex_id) => gl::delete_texture(tex_id),
+                FboGlObject::Renderbuffer(renderbuf_id) => gl::delete_renderbuffer(renderbuf_id),
+                FboGlObject::Sampler(sampler_id) => gl::delete_sampler(sampler_id),
             }
         }
     }    
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn shadow_sampler_compares_depth_but_the_texture_does_not() {
+        let params = FramebufferObject::shadow_compare_sampler_params(true);
+        assert!(params.contains(&(gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE)));
+        assert!(params.contains(&(gl::TEXTURE_COMPARE_FUNC, gl::LEQUAL)));
+        assert!(params.contains(&(gl::TEXTURE_MIN_FILTER, gl::LINEAR)));
+        assert!(params.contains(&(gl::TEXTURE_MAG_FILTER, gl::LINEAR)));
+
+        let params = FramebufferObject::shadow_compare_sampler_params(false);
+        assert!(params.contains(&(gl::TEXTURE_COMPARE_MODE, gl::COMPARE_REF_TO_TEXTURE)));
+        assert!(params.contains(&(gl::TEXTURE_MIN_FILTER, gl::NEAREST)));
+
+        // the gui panels show the shadow map with a plain sampler2D
+        for &uses_hardware_pcf in [true, false].iter() {
+            let texture_params = FramebufferObject::shadow_depth_texture_params(uses_hardware_pcf);
+            assert!(texture_params.iter().all(|&(param_name, _)| param_name != gl::TEXTURE_COMPARE_MODE && param_name != gl::TEXTURE_COMPARE_FUNC));
+        }
+    }
+
+    #[test]
+    fn owns_exactly_the_allocated_attachments() {
+        // ManuallyDrop because dropping would call into gl which isn't loaded in tests
+        let fbo = std::mem::ManuallyDrop::new(FramebufferObject {
+            fbo_id: 1,
+            viewport_width: 64,
+            viewport_height: 64,
+            color_textures: Some(vec![2, 3]),
+            depth_texture: None,
+            shadow_sampler: None,
+            depth_renderbuffer_id: Some(4),
+            color_renderbuffer_ids: None,
+        });
+        assert_eq!(fbo.owned_gl_objects(), vec![
+            FboGlObject::Framebuffer(1), FboGlObject::Texture(2), FboGlObject::Texture(3), FboGlObject::Renderbuffer(4),
+        ]);
+
+        let shadow_fbo = std::mem::ManuallyDrop::new(FramebufferObject {
+            fbo_id: 5,
+            viewport_width: 64,
+            viewport_height: 64,
+            color_textures: None,
+            depth_texture: Some(6),
+            shadow_sampler: Some(7),
+            depth_renderbuffer_id: None,
+            color_renderbuffer_ids: None,
+        });
+        assert_eq!(shadow_fbo.owned_gl_objects(), vec![FboGlObject::Framebuffer(5), FboGlObject::Texture(6), FboGlObject::Sampler(7)]);
+    }
+
+    #[test]
+    fn blit_to_the_screen_only_filters_when_it_scales() {
+        assert_eq!(FramebufferObject::blit_filter(1280, 720, &Viewport::full(1280, 720)), gl::NEAREST);
+        assert_eq!(FramebufferObject::blit_filter(1920, 1080, &Viewport::letterboxed(1920, 1080, 4.0 / 3.0)), gl::LINEAR);
+        assert_eq!(FramebufferObject::blit_filter(1280, 720, &Viewport::full(2560, 1440)), gl::LINEAR);
+    }
+}
@@ -2,5 +2,7 @@ pub mod fbo_map;
 pub mod framebuffer_object;
 
 pub use fbo_map::FboMap;
+pub use fbo_map::FboTexture;
 pub use framebuffer_object::FramebufferObject;
 pub use framebuffer_object::FboFlags;
+pub use framebuffer_object::FboGlObject;
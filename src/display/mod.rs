@@ -1,5 +1,9 @@
+pub mod depth_mode;
 pub mod display;
 pub mod framebuffers;
+pub mod viewport;
 
+pub use self::depth_mode::*;
 pub use self::display::*;
-pub use self::framebuffers::*;
\ No newline at end of file
+pub use self::framebuffers::*;
+pub use self::viewport::*;
\ No newline at end of file
@@ -0,0 +1,145 @@
+use crate::gl;
+use crate::math::Matrix4f;
+use super::{
+    DepthMode,
+    WindowSize,
+};
+
+// a rectangle of the framebuffer in pixels with the origin in the lower left corner (same as glViewport)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Viewport {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl Viewport {
+    pub fn new(x: i32, y: i32, width: i32, height: i32) -> Viewport {
+        Viewport {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn full(width: usize, height: usize) -> Viewport {
+        Viewport::new(0, 0, width as i32, height as i32)
+    }
+
+    // the largest rectangle with the target aspect centered in the screen, the rest is covered by letterbox (top/bottom) or pillarbox (left/right) bars
+    pub fn letterboxed(screen_width: usize, screen_height: usize, target_aspect_ratio: f32) -> Viewport {
+        let screen_width = screen_width as f32;
+        let screen_height = screen_height as f32;
+        let (width, height) = if screen_width / screen_height > target_aspect_ratio {
+            ((screen_height * target_aspect_ratio).round(), screen_height)
+        } else {
+            (screen_width, (screen_width / target_aspect_ratio).round())
+        };
+        let x = ((screen_width - width) / 2.0).floor();
+        let y = ((screen_height - height) / 2.0).floor();
+        Viewport::new(x as i32, y as i32, width as i32, height as i32)
+    }
+
+    // maps a point in window coords (origin in the upper left corner like the mouse position) to [-1,1] normalized device coords of this viewport
+    pub fn to_normalized_device_coords(&self, window_height: f32, x: f32, y: f32) -> (f32, f32) {
+        let x = x - self.x as f32;
+        let y = (window_height - y) - self.y as f32;
+        (2.0 * x / self.width as f32 - 1.0, 2.0 * y / self.height as f32 - 1.0)
+    }
+
+    // the gui is laid out in the letterboxed region instead of the whole window (Display::scene_size)
+    pub fn size(&self) -> WindowSize {
+        WindowSize {
+            width: self.width as usize,
+            height: self.height as usize,
+            width_f32: self.width as f32,
+            height_f32: self.height as f32,
+        }
+    }
+
+    pub fn aspect_ratio(&self) -> f32 {
+        self.width as f32 / self.height as f32
+    }
+
+    // each camera gets its own projection so the image isn't stretched when the viewport is only a part of the screen
+    pub fn projection_matrix(&self, depth_mode: DepthMode) -> Matrix4f {
+        depth_mode.projection_matrix(self.aspect_ratio())
+    }
+
+    // split screen: left and right half, the right half gets the odd pixel if the width isn't even
+    pub fn split_side_by_side(&self) -> (Viewport, Viewport) {
+        let left_width = self.width / 2;
+        let left = Viewport::new(self.x, self.y, left_width, self.height);
+        let right = Viewport::new(self.x + left_width, self.y, self.width - left_width, self.height);
+        (left, right)
+    }
+
+    // sets the viewport and restricts clears to it
+    pub fn apply(&self) {
+        gl::viewport(self.x, self.y, self.width, self.height);
+        gl::enable(gl::SCISSOR_TEST);
+        gl::scissor(self.x, self.y, self.width, self.height);
+    }
+
+    pub fn reset_scissor() {
+        gl::disable(gl::SCISSOR_TEST);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn split_viewports_cover_screen_with_own_aspect() {
+        let screen = Viewport::full(1281, 720);
+        let (left, right) = screen.split_side_by_side();
+        assert_eq!(left, Viewport::new(0, 0, 640, 720));
+        assert_eq!(right, Viewport::new(640, 0, 641, 720));
+        assert_eq!(left.width + right.width, screen.width);
+        assert!((left.aspect_ratio() - 640.0 / 720.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn projection_is_recomputed_for_viewport_aspect() {
+        let screen = Viewport::full(1280, 720);
+        let (left, _) = screen.split_side_by_side();
+        let full_proj = screen.projection_matrix(DepthMode::Standard);
+        let half_proj = left.projection_matrix(DepthMode::Standard);
+        // horizontal fov is fixed so the x scale stays and the y scale follows the aspect ratio
+        assert_eq!(full_proj[0][0], half_proj[0][0]);
+        assert!((half_proj[1][1] / half_proj[0][0] - left.aspect_ratio()).abs() < 1e-5);
+        assert!((full_proj[1][1] / full_proj[0][0] - screen.aspect_ratio()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn letterbox_keeps_target_aspect_centered() {
+        // window wider than the target -> pillarbox bars left and right
+        let pillarboxed = Viewport::letterboxed(1920, 1080, 4.0 / 3.0);
+        assert_eq!(pillarboxed, Viewport::new(240, 0, 1440, 1080));
+
+        // window taller than the target -> letterbox bars top and bottom
+        let letterboxed = Viewport::letterboxed(1280, 1024, 16.0 / 9.0);
+        assert_eq!(letterboxed, Viewport::new(0, 152, 1280, 720));
+        assert!((letterboxed.aspect_ratio() - 16.0 / 9.0).abs() < 1e-3);
+
+        assert_eq!(Viewport::letterboxed(1280, 720, 16.0 / 9.0), Viewport::full(1280, 720));
+    }
+
+    #[test]
+    fn window_coords_map_into_letterboxed_region() {
+        let viewport = Viewport::letterboxed(1280, 1024, 16.0 / 9.0);
+        assert_eq!(viewport.to_normalized_device_coords(1024.0, 640.0, 512.0), (0.0, 0.0));
+        // upper left corner of the scene is below the top bar
+        assert_eq!(viewport.to_normalized_device_coords(1024.0, 0.0, 152.0), (-1.0, 1.0));
+    }
+
+    #[test]
+    fn gui_is_laid_out_in_the_letterboxed_size() {
+        let size = Viewport::letterboxed(1920, 1080, 4.0 / 3.0).size();
+        assert_eq!((size.width, size.height), (1440, 1080));
+        assert_eq!((size.width_f32, size.height_f32), (1440.0, 1080.0));
+    }
+}
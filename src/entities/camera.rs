@@ -1,13 +1,79 @@
 use std::f32;
 use crate::math::{
+    Matrix4f,
     Quaternion,
     Vector3f,
 };
 use crate::display::{
     Display,
+    Key,
+    Keyboard,
 };
-use crate::entities::Player;
+use crate::entities::{
+    Ground,
+    Player,
+};
+
+#[derive(Clone, Debug)]
+pub struct CameraTerrainCollision {
+    // how far above the terrain the camera has to stay
+    pub margin: f32,
+    // move the camera in front of hills that are between it and the player
+    pub pull_in_towards_player: bool,
+    // how fast the camera gets lifted to the margin, higher is snappier and lower is smoother on bumpy terrain
+    pub smoothing_rate: f32,
+}
+
+impl Default for CameraTerrainCollision {
+    fn default() -> Self {
+        CameraTerrainCollision {
+            margin: 2.0,
+            pull_in_towards_player: true,
+            smoothing_rate: 10.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CameraMode {
+    // orbits the player, see move_camera
+    Follow,
+    // flies around on its own for debugging and inspecting the level, the player gets no input meanwhile
+    FreeFly,
+}
+
+impl Default for CameraMode {
+    fn default() -> Self {
+        CameraMode::Follow
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct FreeFlyParams {
+    // switches between following the player and free flying
+    pub toggle_key: Key,
+    // units per second
+    pub speed: f32,
+    // speed multipliers while shift/alt are held
+    pub fast_multiplier: f32,
+    pub slow_multiplier: f32,
+    // degrees per pixel of mouse movement while the right button is held
+    pub mouse_sensitivity: f32,
+}
 
+impl Default for FreeFlyParams {
+    fn default() -> Self {
+        FreeFlyParams {
+            toggle_key: Key::F,
+            speed: 30.0,
+            fast_multiplier: 4.0,
+            slow_multiplier: 0.25,
+            mouse_sensitivity: 0.1,
+        }
+    }
+}
+
+#[derive(Clone)]
 pub struct Camera {
     pub position: Vector3f,
     pub roll: f32,
@@ -17,6 +83,14 @@ pub struct Camera {
     pub up: Vector3f,
     distance_to_player: f32,
     angle_around_player: f32,
+    // None lets the camera go through the terrain
+    pub terrain_collision: Option<CameraTerrainCollision>,
+    // smoothed amount the camera is currently lifted by to stay above the terrain
+    terrain_lift: f32,
+    pub mode: CameraMode,
+    pub free_fly: FreeFlyParams,
+    // the mode is toggled when the key goes down, not while it is held
+    toggle_key_was_pressed: bool,
 }
 
 impl Camera {
@@ -31,14 +105,119 @@ impl Camera {
             angle_around_player: 0.0,
             looking_at: Vector3f::zero(),
             up: Vector3f::new(0.0, 1.0, 0.0),
+            terrain_collision: Some(CameraTerrainCollision::default()),
+            terrain_lift: 0.0,
+            mode: CameraMode::default(),
+            free_fly: FreeFlyParams::default(),
+            toggle_key_was_pressed: false,
         }    
     }
 
-    pub fn move_camera(&mut self, display: &Display, player: &Player) {
+    // free fly never goes past looking straight up or down so the fps view matrix stays valid
+    const MAX_FREE_FLY_PITCH: f32 = 89.0;
+
+    // how many points between the player and the camera are checked for terrain that blocks the view
+    const PULL_IN_SAMPLES: usize = 16;
+    // the camera is never pulled closer than this fraction of its distance to the player
+    const MIN_PULL_IN_FRACTION: f32 = 0.2;
+
+    pub fn move_camera(&mut self, display: &Display, player: &Player, ground: &Ground) {
+        self.update_mode(display);
+        if self.mode == CameraMode::FreeFly {
+            let mouse_delta = if display.mouse_pos.is_right_pressed {
+                (display.mouse_pos.dx() as f32, display.mouse_pos.dy() as f32)
+            } else {
+                (0.0, 0.0)
+            };
+            self.fly(display, mouse_delta, display.frame_time_sec);
+            return;
+        }
         self.calc_zoom(display);
         self.calc_pitch(display);
         self.calc_angle_around_player(display);        
         self.update_camera_pos(player);
+        if let Some(collision) = self.terrain_collision.clone() {
+            self.resolve_terrain_collision(&collision, player.position(), |x, z| ground.height_at_xz(x, z), display.frame_time_sec);
+        }
+    }
+
+    pub fn update_mode(&mut self, keyboard: &dyn Keyboard) {
+        let is_pressed = keyboard.is_pressed(self.free_fly.toggle_key);
+        if is_pressed && !self.toggle_key_was_pressed {
+            self.mode = match self.mode {
+                CameraMode::Follow => CameraMode::FreeFly,
+                CameraMode::FreeFly => CameraMode::Follow,
+            };
+        }
+        self.toggle_key_was_pressed = is_pressed;
+    }
+
+    // wasd moves along the view direction and sideways, e/q up and down
+    pub fn fly(&mut self, keyboard: &dyn Keyboard, mouse_delta: (f32, f32), frame_time_sec: f32) {
+        let params = &self.free_fly;
+        self.yaw -= mouse_delta.0 * params.mouse_sensitivity;
+        self.pitch = (self.pitch + mouse_delta.1 * params.mouse_sensitivity).max(-Camera::MAX_FREE_FLY_PITCH).min(Camera::MAX_FREE_FLY_PITCH);
+        self.roll = 0.0;
+
+        let axis = |positive: Key, negative: Key| {
+            if keyboard.is_pressed(positive) { 1.0 } else if keyboard.is_pressed(negative) { -1.0 } else { 0.0 }
+        };
+        let (forward, right) = self.view_directions();
+        let mut step = &forward * axis(Key::W, Key::S) + right * axis(Key::D, Key::A) + Vector3f::POS_Y_AXIS * axis(Key::E, Key::Q);
+        let mut speed = params.speed;
+        if keyboard.is_pressed(Key::LeftShift) {
+            speed *= params.fast_multiplier;
+        } else if keyboard.is_pressed(Key::LeftAlt) {
+            speed *= params.slow_multiplier;
+        }
+        step *= speed * frame_time_sec;
+        self.position += &step;
+
+        self.looking_at = &self.position + &forward;
+        self.up = Vector3f::POS_Y_AXIS;
+    }
+
+    // forward and right in world space taken from the rows of the view matrix
+    pub fn view_directions(&self) -> (Vector3f, Vector3f) {
+        let view_matrix = Matrix4f::create_view_matrix(self);
+        let right = Vector3f::new(view_matrix[0][0], view_matrix[0][1], view_matrix[0][2]);
+        let forward = Vector3f::new(-view_matrix[2][0], -view_matrix[2][1], -view_matrix[2][2]);
+        (forward, right)
+    }
+
+    fn resolve_terrain_collision(&mut self, collision: &CameraTerrainCollision, player_pos: &Vector3f, height_at: impl Fn(f32, f32) -> f32, frame_time_sec: f32) {
+        if collision.pull_in_towards_player {
+            self.position = Camera::pull_in_before_terrain(player_pos, &self.position, &height_at);
+        }
+        let terrain_height = height_at(self.position.x, self.position.z);
+        let target_lift = Camera::lift_above_terrain(self.position.y, terrain_height, collision.margin) - self.position.y;
+        let blend = f32::min(1.0, collision.smoothing_rate * frame_time_sec);
+        self.terrain_lift += (target_lift - self.terrain_lift) * blend;
+        // while smoothing the camera may be inside the margin but never below the terrain itself
+        self.position.y = f32::max(self.position.y + self.terrain_lift, terrain_height);
+    }
+
+    fn lift_above_terrain(y: f32, terrain_height: f32, margin: f32) -> f32 {
+        f32::max(y, terrain_height + margin)
+    }
+
+    // walks from the player to the camera and stops just before the first point where the terrain is above the line of sight
+    fn pull_in_before_terrain(player_pos: &Vector3f, camera_pos: &Vector3f, height_at: impl Fn(f32, f32) -> f32) -> Vector3f {
+        let point_at = |t: f32| Vector3f::new(
+            player_pos.x + (camera_pos.x - player_pos.x) * t,
+            player_pos.y + (camera_pos.y - player_pos.y) * t,
+            player_pos.z + (camera_pos.z - player_pos.z) * t,
+        );
+        let mut visible_fraction = 1.0;
+        for i in 1..=Camera::PULL_IN_SAMPLES {
+            let t = i as f32 / Camera::PULL_IN_SAMPLES as f32;
+            let point = point_at(t);
+            if height_at(point.x, point.z) > point.y {
+                visible_fraction = (i - 1) as f32 / Camera::PULL_IN_SAMPLES as f32;
+                break;
+            }
+        }
+        point_at(f32::max(visible_fraction, Camera::MIN_PULL_IN_FRACTION))
     }
 
     pub fn set_to_reflected_ray_camera_origin(&mut self, reflection_plane_y: f32) {        
@@ -99,4 +278,81 @@ impl Default for Camera {
     fn default() -> Self {
         Camera::new(20.0, 50.0)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn camera_below_terrain_is_raised_to_margin() {
+        assert_eq!(Camera::lift_above_terrain(3.0, 10.0, 2.0), 12.0);
+        assert_eq!(Camera::lift_above_terrain(30.0, 10.0, 2.0), 30.0);
+
+        let collision = CameraTerrainCollision { margin: 2.0, pull_in_towards_player: false, smoothing_rate: 10.0 };
+        let mut camera = Camera::default();
+        let player_pos = Vector3f::new(0.0, 0.0, 0.0);
+        for _ in 0..100 {
+            camera.position = Vector3f::new(0.0, 3.0, 40.0);
+            camera.resolve_terrain_collision(&collision, &player_pos, |_, _| 10.0, 1.0 / 60.0);
+            // smoothing may leave it inside the margin but never below the terrain
+            assert!(camera.position.y >= 10.0);
+        }
+        assert!((camera.position.y - 12.0).abs() < 1e-3, "Camera y was {}", camera.position.y);
+    }
+
+    #[test]
+    fn camera_is_pulled_in_front_of_hill() {
+        let player_pos = Vector3f::new(0.0, 0.0, 0.0);
+        let camera_pos = Vector3f::new(0.0, 10.0, 40.0);
+        // hill covering everything further than 20 units away from the player
+        let pulled = Camera::pull_in_before_terrain(&player_pos, &camera_pos, |_, z| if z > 20.0 { 50.0 } else { 0.0 });
+        assert!(pulled.z <= 20.0 && pulled.z > 15.0, "Camera z was {}", pulled.z);
+
+        let unblocked = Camera::pull_in_before_terrain(&player_pos, &camera_pos, |_, _| 0.0);
+        assert_eq!((unblocked.x, unblocked.y, unblocked.z), (0.0, 10.0, 40.0));
+    }
+
+    struct PressedKeys(Vec<Key>);
+
+    impl Keyboard for PressedKeys {
+        fn is_pressed(&self, key: Key) -> bool {
+            self.0.contains(&key)
+        }
+        fn is_mouse_select_active(&self) -> bool {
+            false
+        }
+    }
+
+    #[test]
+    fn free_fly_forward_moves_along_view_direction() {
+        let mut camera = Camera::default();
+        camera.position = Vector3f::new(1.0, 2.0, 3.0);
+        camera.pitch = 30.0;
+        camera.yaw = 45.0;
+        camera.update_mode(&PressedKeys(vec![camera.free_fly.toggle_key]));
+        assert_eq!(camera.mode, CameraMode::FreeFly);
+
+        let (forward, _) = camera.view_directions();
+        let frame_time = 0.5;
+        camera.fly(&PressedKeys(vec![Key::W]), (0.0, 0.0), frame_time);
+        let distance = camera.free_fly.speed * frame_time;
+        assert!((camera.position.x - (1.0 + forward.x * distance)).abs() < 1e-4);
+        assert!((camera.position.y - (2.0 + forward.y * distance)).abs() < 1e-4);
+        assert!((camera.position.z - (3.0 + forward.z * distance)).abs() < 1e-4);
+        // looking down so flying forward goes down
+        assert!(forward.y < 0.0);
+    }
+
+    #[test]
+    fn toggle_key_switches_mode_once_per_press() {
+        let mut camera = Camera::default();
+        let toggle = PressedKeys(vec![camera.free_fly.toggle_key]);
+        camera.update_mode(&toggle);
+        camera.update_mode(&toggle);
+        assert_eq!(camera.mode, CameraMode::FreeFly);
+        camera.update_mode(&PressedKeys(Vec::new()));
+        camera.update_mode(&toggle);
+        assert_eq!(camera.mode, CameraMode::Follow);
+    }
+}
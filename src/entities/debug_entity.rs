@@ -5,6 +5,7 @@ use crate::math::{
     Vector3f,
 };
 
+#[derive(Clone)]
 pub struct DebugEntity {
     pub model: DynamicVertexIndexedModel,
     pub position: Vector3f,
@@ -2,14 +2,122 @@ use crate::math::{
     Vector2f,
     Vector3f,
 };
+use crate::math::utils::{
+    f32_min,
+    f32_max,
+};
 use crate::models::TexturedModel;
 
+// position, rotation in degrees and scale of an entity
+pub type EntityTransform = (Vector3f, Vector3f, f32);
+
+bitflags! {
+    // an entity can be on several layers, the master renderer can draw one of them again into the render layer fbo
+    pub struct RenderLayers : u32 {
+        const DEFAULT = 0b01;
+        // drawn into the glow layer that post processing adds to the bloom
+        const GLOW    = 0b10;
+    }
+}
+
+// billboards ignore the entity rotation and turn to face the camera like particles do
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BillboardMode {
+    None,
+    Full,
+    // only turns around the y axis so the entity stays upright, e.g. for distant trees
+    Cylindrical,
+}
+
+impl Default for BillboardMode {
+    fn default() -> Self {
+        BillboardMode::None
+    }
+}
+
+// a coarser model that replaces the previous level from switch_distance to the camera on
+#[derive(Clone)]
+pub struct LodLevel {
+    pub model: TexturedModel,
+    pub switch_distance: f32,
+}
+
+// how a model is drawn while the entity crosses the fade band around a switch distance
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LodFade {
+    None,
+    // the level closer to the camera, keeps the pixels the incoming level discards
+    Out(f32),
+    In(f32),
+}
+
+impl Default for LodFade {
+    fn default() -> Self {
+        LodFade::None
+    }
+}
+
+impl LodFade {
+    // the transition factor and whether this is the incoming level, a factor of 0 draws the whole model
+    pub fn shader_values(&self) -> (f32, bool) {
+        match *self {
+            LodFade::None => (0.0, false),
+            LodFade::Out(factor) => (factor, false),
+            LodFade::In(factor) => (factor, true),
+        }
+    }
+
+    // the second of the two draws of an entity crossing a switch distance
+    pub fn is_incoming(&self) -> bool {
+        match *self {
+            LodFade::In(_) => true,
+            _ => false,
+        }
+    }
+}
+
+// the lod level an entity is drawn with in a frame, 0 is the entity model
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LodSelection {
+    pub level: usize,
+    // transition factor into the next level while the entity crosses its switch distance
+    pub fade_factor: Option<f32>,
+}
+
+impl LodSelection {
+    // the level the switch distances of the next frame move away from, it only changes once the entity is through a fade
+    pub fn settled_level(&self, previous_settled_level: usize) -> usize {
+        match self.fade_factor {
+            None => self.level,
+            Some(_) => previous_settled_level,
+        }
+    }
+}
+
+// 0 before the band centered on the switch distance, 1 after it, without a band the levels just swap
+pub fn lod_transition_factor(distance: f32, switch_distance: f32, fade_band: f32) -> f32 {
+    if fade_band <= 0.0 {
+        return if distance < switch_distance { 0.0 } else { 1.0 };
+    }
+    let band_start = switch_distance - fade_band / 2.0;
+    f32_min(f32_max((distance - band_start) / fade_band, 0.0), 1.0)
+}
+
+#[derive(Clone)]
 pub struct Entity {
     pub model: TexturedModel,
     pub position: Vector3f,
     pub rotation_deg: Vector3f,
     pub scale: f32,
     pub atlas_index: usize,    
+    pub render_layers: RenderLayers,
+    // hidden entities stay in the scene but are skipped by all render passes including shadows
+    pub visible: bool,
+    // overrides ShadowParams::max_caster_distance, e.g. to let large landmarks cast shadows from further away
+    pub max_shadow_cast_distance: Option<f32>,
+    pub billboard_mode: BillboardMode,
+    // ordered by switch distance, the entity model is used below the first one
+    pub lod_levels: Vec<LodLevel>,
 }
 
 impl Entity {
@@ -20,6 +128,11 @@ impl Entity {
             rotation_deg,
             scale,
             atlas_index: 0,
+            render_layers: RenderLayers::DEFAULT,
+            visible: true,
+            max_shadow_cast_distance: None,
+            billboard_mode: BillboardMode::default(),
+            lod_levels: Vec::new(),
         }
     }
 
@@ -30,7 +143,53 @@ impl Entity {
             rotation_deg,
             scale,
             atlas_index,
+            render_layers: RenderLayers::DEFAULT,
+            visible: true,
+            max_shadow_cast_distance: None,
+            billboard_mode: BillboardMode::default(),
+            lod_levels: Vec::new(),
+        }
+    }
+
+    // all entities get a copy of the same model handle so they share its vao and textures
+    pub fn spawn_many(model: &TexturedModel, transforms: &[EntityTransform]) -> Vec<Entity> {
+        transforms.iter()
+            .map(|(position, rotation_deg, scale)| Entity::new(model.clone(), position.clone(), rotation_deg.clone(), *scale))
+            .collect()
+    }
+
+    // the level at the given distance from the camera and the transition factor while fading into the next one
+    // a level is only left once the camera is half the hysteresis past its switch distance from the level drawn last without a fade
+    // so an entity near it doesn't keep switching back and forth
+    pub fn select_lod(&self, camera_distance: f32, fade_band: f32, hysteresis: f32, settled_level: usize) -> LodSelection {
+        let mut current_level = 0;
+        for (index, level) in self.lod_levels.iter().enumerate() {
+            let switch_distance = if index < settled_level {
+                level.switch_distance - hysteresis / 2.0
+            } else {
+                level.switch_distance + hysteresis / 2.0
+            };
+            let factor = lod_transition_factor(camera_distance, switch_distance, fade_band);
+            if factor >= 1.0 {
+                current_level = index + 1;
+            } else if factor > 0.0 {
+                return LodSelection { level: current_level, fade_factor: Some(factor) };
+            } else {
+                break;
+            }
         }
+        LodSelection { level: current_level, fade_factor: None }
+    }
+
+    // the model of the selected level and the next level with its transition factor while fading into it
+    pub fn lod_models(&self, selection: LodSelection) -> (&TexturedModel, Option<(&TexturedModel, f32)>) {
+        let model = |level: usize| if level == 0 { &self.model } else { &self.lod_levels[level - 1].model };
+        (model(selection.level), selection.fade_factor.map(|factor| (model(selection.level + 1), factor)))
+    }
+
+    // radius of the sphere around the entity position that contains the whole scaled model
+    pub fn bounding_radius(&self) -> Option<f32> {
+        self.model.bounding_radius.map(|radius| radius * self.scale)
     }
 
     pub fn set_position(&mut self, new_pos: &Vector3f) {
@@ -62,4 +221,99 @@ impl Entity {
         let v_offset = row as f32 / num_rows;
         Vector2f::new(u_offset, v_offset)
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        RawModel,
+        ModelTexture,
+        TextureId,
+    };
+
+    #[test]
+    fn spawn_many_shares_one_model() {
+        let mut texture = ModelTexture::default();
+        texture.tex_id = TextureId::Loaded(7);
+        let model = TexturedModel { raw_model: RawModel::new(3, 6), texture, ..TexturedModel::default() };
+        let transforms = vec![
+            (Vector3f::new(1.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(0.0, 2.0, 0.0), Vector3f::new(0.0, 90.0, 0.0), 0.5),
+            (Vector3f::new(0.0, 0.0, 3.0), Vector3f::zero(), 2.0),
+        ];
+
+        let entities = Entity::spawn_many(&model, &transforms);
+
+        assert_eq!(entities.len(), 3);
+        for (entity, (position, rotation_deg, scale)) in entities.iter().zip(transforms.iter()) {
+            assert_eq!((entity.position.x, entity.position.y, entity.position.z), (position.x, position.y, position.z));
+            assert_eq!(entity.rotation_deg.y, rotation_deg.y);
+            assert_eq!(entity.scale, *scale);
+            assert!(entity.model == model);
+        }
+    }
+
+    #[test]
+    fn lod_transition_factor_ramps_across_the_band() {
+        assert_eq!(lod_transition_factor(10.0, 50.0, 10.0), 0.0);
+        assert_eq!(lod_transition_factor(45.0, 50.0, 10.0), 0.0);
+        assert_eq!(lod_transition_factor(47.5, 50.0, 10.0), 0.25);
+        assert_eq!(lod_transition_factor(50.0, 50.0, 10.0), 0.5);
+        assert_eq!(lod_transition_factor(55.0, 50.0, 10.0), 1.0);
+        assert_eq!(lod_transition_factor(500.0, 50.0, 10.0), 1.0);
+        // without a band the levels swap at the switch distance
+        assert_eq!(lod_transition_factor(49.9, 50.0, 0.0), 0.0);
+        assert_eq!(lod_transition_factor(50.0, 50.0, 0.0), 1.0);
+    }
+
+    #[test]
+    fn lod_models_cross_fade_only_inside_the_band() {
+        let model = |vertex_count| TexturedModel { raw_model: RawModel::new(1, vertex_count), ..TexturedModel::default() };
+        let mut entity = Entity::new(model(300), Vector3f::zero(), Vector3f::zero(), 1.0);
+        entity.lod_levels = vec![
+            LodLevel { model: model(100), switch_distance: 50.0 },
+            LodLevel { model: model(10), switch_distance: 100.0 },
+        ];
+        let vertex_count = |model: &TexturedModel| model.raw_model.vertex_count;
+
+        let lod_models = |distance| entity.lod_models(entity.select_lod(distance, 10.0, 0.0, 0));
+
+        let (current, next) = lod_models(20.0);
+        assert_eq!(vertex_count(current), 300);
+        assert!(next.is_none());
+
+        let (current, next) = lod_models(52.5);
+        let (next, factor) = next.expect("Should be fading into the next level");
+        assert_eq!((vertex_count(current), vertex_count(next), factor), (300, 100, 0.75));
+
+        let (current, next) = lod_models(70.0);
+        assert_eq!(vertex_count(current), 100);
+        assert!(next.is_none());
+
+        let (current, next) = lod_models(200.0);
+        assert_eq!(vertex_count(current), 10);
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn lod_models_keep_the_settled_level_inside_the_hysteresis() {
+        let model = |vertex_count| TexturedModel { raw_model: RawModel::new(1, vertex_count), ..TexturedModel::default() };
+        let mut entity = Entity::new(model(300), Vector3f::zero(), Vector3f::zero(), 1.0);
+        entity.lod_levels = vec![LodLevel { model: model(100), switch_distance: 50.0 }];
+        let mut settled_level = 0;
+        let mut drawn = |distance| {
+            let selection = entity.select_lod(distance, 0.0, 4.0, settled_level);
+            assert!(selection.fade_factor.is_none());
+            settled_level = selection.settled_level(settled_level);
+            entity.lod_models(selection).0.raw_model.vertex_count
+        };
+
+        assert_eq!(drawn(51.0), 300);
+        assert_eq!(drawn(52.0), 100);
+        // moving back only switches once the camera is past the other side of the hysteresis
+        assert_eq!(drawn(49.0), 100);
+        assert_eq!(drawn(47.9), 300);
+        assert_eq!(drawn(51.0), 300);
+    }
+}
@@ -1,5 +1,10 @@
 use crate::entities::Terrain;
+use crate::models::{
+    TerrainGenerator,
+    TerrainNeighbors,
+};
 use crate::math::{
+    Matrix4f,
     Vector3f,
     Vector2f,
     BarycentricCoords,
@@ -7,12 +12,78 @@ use crate::math::{
 use std::f32;
 use std::cmp;
 
+// cloned for the particle update threads, the terrain height maps are shared with them
+#[derive(Clone)]
 pub struct Ground {
     pub terrains: Vec<Terrain>,
 }
 
+// where scattered objects may go, e.g. trees look wrong on steep slopes
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TerrainPlacement {
+    // angle between the terrain normal and straight up
+    pub max_slope_deg: f32,
+    // tilts the object so its up axis follows the terrain normal, e.g. for rocks but not for trees
+    pub aligns_to_normal: bool,
+    // random spots tried before giving up, with 0 no spot is ever found
+    pub max_attempts: usize,
+}
+
+impl Default for TerrainPlacement {
+    fn default() -> Self {
+        TerrainPlacement {
+            max_slope_deg: 30.0,
+            aligns_to_normal: false,
+            max_attempts: 10,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TerrainSpot {
+    pub position: Vector3f,
+    // only the tilt towards the terrain normal, zero when not aligned
+    pub rotation_deg: Vector3f,
+}
+
+impl TerrainSpot {
+    // the tilt with the object first turned around its own up axis so aligned objects don't all face the same way
+    pub fn rotation_with_yaw_deg(&self, yaw_deg: f32) -> Vector3f {
+        let tilt = Matrix4f::get_rotation(self.rotation_deg.x, self.rotation_deg.y, self.rotation_deg.z);
+        (tilt * Matrix4f::get_rotation(0.0, yaw_deg, 0.0)).rotation_degrees()
+    }
+}
+
+impl TerrainPlacement {
+    pub fn accepts_normal(&self, normal: &Vector3f) -> bool {
+        normal.y.max(-1.0).min(1.0).acos().to_degrees() <= self.max_slope_deg
+    }
+
+    // x and z rotation that turns the up axis onto the normal with the rotation order of Matrix4f::get_rotation
+    pub fn rotation_towards_normal(normal: &Vector3f) -> Vector3f {
+        let rot_z = (-normal.x).max(-1.0).min(1.0).asin();
+        let rot_x = normal.z.atan2(normal.y);
+        Vector3f::new(rot_x.to_degrees(), 0.0, rot_z.to_degrees())
+    }
+
+    fn find_spot(&self, mut next_xz: impl FnMut() -> (f32, f32), height_at: impl Fn(f32, f32) -> f32, normal_at: impl Fn(f32, f32) -> Vector3f) -> Option<TerrainSpot> {
+        for _ in 0..self.max_attempts {
+            let (x, z) = next_xz();
+            let normal = normal_at(x, z);
+            if !self.accepts_normal(&normal) {
+                continue;
+            }
+            let rotation_deg = if self.aligns_to_normal { TerrainPlacement::rotation_towards_normal(&normal) } else { Vector3f::zero() };
+            return Some(TerrainSpot { position: Vector3f::new(x, height_at(x, z), z), rotation_deg });
+        }
+        None
+    }
+}
+
 impl Ground {
-    
+    // distance between the height samples used for the normal, sampling through height_at_xz keeps normals consistent across tile seams
+    const NORMAL_SAMPLE_DISTANCE: f32 = 1.0;
+
     pub fn create_pos_above_terrain(&self, x: f32, y: f32, z: f32) -> Vector3f {
         let height = self.height_at_xz(x, z);
 		Vector3f::new(x, height + y, z)
@@ -23,6 +94,11 @@ impl Ground {
 		Vector3f::new(x, y, z)
 	}
 
+    // next_xz hands out candidate spots e.g. from a random generator, None when all attempts were too steep
+    pub fn find_spot_on_terrain(&self, placement: &TerrainPlacement, next_xz: impl FnMut() -> (f32, f32)) -> Option<TerrainSpot> {
+        placement.find_spot(next_xz, |x, z| self.height_at_xz(x, z), |x, z| self.normal_at(x, z))
+    }
+
     pub fn height_at_xz(&self, x: f32, z: f32) -> f32 {
         for terrain_cell in self.terrains.iter() {
             if terrain_cell.is_xz_within_terrain_cell(x, z) {
@@ -59,4 +135,77 @@ impl Ground {
         }
         return 0.0
     }
-}
\ No newline at end of file
+
+    // the tiles next to the tile index that are already part of the ground, for generating the tile with seamless edge normals
+    pub fn neighbors(&self, tile_x: i32, tile_z: i32) -> TerrainNeighbors<'_> {
+        let find = |x: i32, z: i32| self.terrains.iter()
+            .find(|terrain| terrain.tile_index() == (x, z))
+            .map(|terrain| terrain as &dyn TerrainGenerator);
+        TerrainNeighbors {
+            left: find(tile_x - 1, tile_z),
+            right: find(tile_x + 1, tile_z),
+            back: find(tile_x, tile_z - 1),
+            front: find(tile_x, tile_z + 1),
+        }
+    }
+
+    pub fn normal_at(&self, x: f32, z: f32) -> Vector3f {
+        let d = Ground::NORMAL_SAMPLE_DISTANCE;
+        let height_l = self.height_at_xz(x - d, z);
+        let height_r = self.height_at_xz(x + d, z);
+        let height_d = self.height_at_xz(x, z - d);
+        let height_u = self.height_at_xz(x, z + d);
+        let mut normal = Vector3f::new(height_l - height_r, 2.0 * d, height_d - height_u);
+        normal.normalize();
+        normal
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector4f;
+
+    #[test]
+    fn steep_slope_is_rejected_and_flat_ground_accepted() {
+        let placement = TerrainPlacement { max_slope_deg: 30.0, aligns_to_normal: false, max_attempts: 3 };
+        let mut steep = Vector3f::new(1.0, 1.0, 0.0);
+        steep.normalize();
+        let mut attempts = 0;
+        let spot = placement.find_spot(|| { attempts += 1; (1.0, 2.0) }, |_, _| 5.0, |_, _| steep.clone());
+        assert!(spot.is_none());
+        assert_eq!(attempts, 3);
+
+        let spot = placement.find_spot(|| (1.0, 2.0), |_, _| 5.0, |_, _| Vector3f::new(0.0, 1.0, 0.0));
+        let spot = spot.unwrap();
+        assert_eq!((spot.position.x, spot.position.y, spot.position.z), (1.0, 5.0, 2.0));
+        assert_eq!((spot.rotation_deg.x, spot.rotation_deg.y, spot.rotation_deg.z), (0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn aligned_rotation_turns_up_onto_normal() {
+        let mut normal = Vector3f::new(0.3, 0.8, -0.4);
+        normal.normalize();
+        let rotation = TerrainPlacement::rotation_towards_normal(&normal);
+        let rotated_up = Matrix4f::get_rotation(rotation.x, rotation.y, rotation.z).transform(&Vector4f::new(0.0, 1.0, 0.0, 0.0));
+        assert!((rotated_up.x - normal.x).abs() < 1e-5);
+        assert!((rotated_up.y - normal.y).abs() < 1e-5);
+        assert!((rotated_up.z - normal.z).abs() < 1e-5);
+
+        let placement = TerrainPlacement { max_slope_deg: 60.0, aligns_to_normal: true, max_attempts: 1 };
+        let spot = placement.find_spot(|| (0.0, 0.0), |_, _| 0.0, |_, _| normal.clone()).unwrap();
+        assert_eq!((spot.rotation_deg.x, spot.rotation_deg.z), (rotation.x, rotation.z));
+
+        // the yaw turns the object around the normal but keeps its up axis on it
+        let yawed = spot.rotation_with_yaw_deg(70.0);
+        let yawed_rotation = Matrix4f::get_rotation(yawed.x, yawed.y, yawed.z);
+        let yawed_up = yawed_rotation.transform(&Vector4f::new(0.0, 1.0, 0.0, 0.0));
+        assert!((yawed_up.x - normal.x).abs() < 1e-5);
+        assert!((yawed_up.y - normal.y).abs() < 1e-5);
+        assert!((yawed_up.z - normal.z).abs() < 1e-5);
+        let forward = Matrix4f::get_rotation(rotation.x, rotation.y, rotation.z).transform(&Vector4f::new(1.0, 0.0, 0.0, 0.0));
+        let yawed_forward = yawed_rotation.transform(&Vector4f::new(1.0, 0.0, 0.0, 0.0));
+        let cos_between = forward.x * yawed_forward.x + forward.y * yawed_forward.y + forward.z * yawed_forward.z;
+        assert!((cos_between - 70f32.to_radians().cos()).abs() < 1e-5);
+    }
+}
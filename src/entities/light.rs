@@ -1,25 +1,114 @@
-use super::super::math::Vector3f;
+use super::super::math::{
+    Color,
+    Vector3f,
+    Vector4f,
+};
 
+#[derive(Clone)]
 pub struct Light {
+    // only used by point lights
     pub position: Vector3f,
-    pub color: Vector3f,
+    // unit vector pointing towards an infinite (directional) light like the sun, None for point lights
+    pub direction: Option<Vector3f>,
+    pub color: Color,
     pub attenuation: Vector3f,
 }
 
 impl Light {
-    pub fn new_infinite(position: Vector3f, color: Vector3f) -> Light {
+    // what shader light slots without a light get loaded with, black so it adds nothing and attenuation 1 so nothing gets divided by 0
+    pub const UNUSED_SLOT: Light = Light {
+        position: Vector3f::ZERO,
+        direction: None,
+        color: Color::BLACK,
+        attenuation: Vector3f::POS_X_AXIS,
+    };
+
+    // the light comes from the direction of the given position as seen from the origin, how far away the position is doesn't matter
+    pub fn new_infinite(position: Vector3f, color: Color) -> Light {
+        Light::new_directional(position, color)
+    }
+
+    pub fn new_directional(mut to_light_direction: Vector3f, color: Color) -> Light {
+        to_light_direction.normalize();
         Light {
-            position,
+            position: Vector3f::ZERO,
+            direction: Some(to_light_direction),
             color,
             attenuation: Vector3f::new(1.0, 0.0, 0.0),       
         }
     }
 
-    pub fn new_point(position: Vector3f, color: Vector3f, attenuation: Vector3f) -> Light {
+    pub fn new_point(position: Vector3f, color: Color, attenuation: Vector3f) -> Light {
         Light {
             position,
+            direction: None,
             color,
             attenuation,       
         }
     }
-}
\ No newline at end of file
+
+    // unit vector from the origin towards the light, what the shadows and the analytic sky follow
+    pub fn to_light_direction(&self) -> Vector3f {
+        match &self.direction {
+            Some(direction) => direction.clone(),
+            None => {
+                let mut direction = self.position.clone();
+                direction.normalize();
+                direction
+            },
+        }
+    }
+
+    // what the shaders get as light_pos, w = 0 makes the xyz a direction so the shaders don't subtract the world position
+    pub fn shader_position(&self) -> Vector4f {
+        match &self.direction {
+            Some(direction) => Vector4f::new(direction.x, direction.y, direction.z, 0.0),
+            None => Vector4f::new(self.position.x, self.position.y, self.position.z, 1.0),
+        }
+    }
+
+    // the light to load into the shader light slot, fewer lights than slots (including none at all) leaves the rest unused
+    pub fn for_shader_slot(lights: &[Light], slot: usize) -> &Light {
+        lights.get(slot).unwrap_or(&Light::UNUSED_SLOT)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_lights_fill_all_slots_with_unused_light() {
+        let lights = Vec::new();
+        for slot in 0..4 {
+            let light = Light::for_shader_slot(&lights, slot);
+            assert_eq!(light.color, Color::BLACK);
+            assert_eq!(light.attenuation.x, 1.0);
+            assert_eq!((light.attenuation.y, light.attenuation.z), (0.0, 0.0));
+        }
+    }
+
+    #[test]
+    fn lights_fill_slots_in_order() {
+        let lights = vec![Light::new_infinite(Vector3f::new(1.0, 2.0, 3.0), Color::WHITE)];
+        assert_eq!(Light::for_shader_slot(&lights, 0).color, Color::WHITE);
+        assert_eq!(Light::for_shader_slot(&lights, 1).color, Color::BLACK);
+    }
+
+    #[test]
+    fn infinite_light_stores_normalized_direction_of_its_position() {
+        let old_sun_position = Vector3f::new(5000.0, 10000.0, 5000.0);
+        let sun = Light::new_infinite(old_sun_position.clone(), Color::WHITE);
+        let direction = sun.to_light_direction();
+        let length = old_sun_position.length();
+        assert!((direction.length() - 1.0).abs() < 1e-6);
+        assert!((direction.x - old_sun_position.x / length).abs() < 1e-6);
+        assert!((direction.y - old_sun_position.y / length).abs() < 1e-6);
+        assert!((direction.z - old_sun_position.z / length).abs() < 1e-6);
+
+        let shader_position = sun.shader_position();
+        assert_eq!((shader_position.x, shader_position.y, shader_position.z, shader_position.w), (direction.x, direction.y, direction.z, 0.0));
+        let lamp = Light::new_point(Vector3f::new(1.0, 2.0, 3.0), Color::WHITE, Vector3f::new(1.0, 0.01, 0.002));
+        assert_eq!(lamp.shader_position().w, 1.0);
+    }
+}
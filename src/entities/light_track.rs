@@ -0,0 +1,136 @@
+use crate::math::{
+    Color,
+    Easing,
+    Vector3f,
+};
+use super::light::Light;
+
+#[derive(Debug, Clone)]
+pub struct LightKeyframe {
+    // seconds from the start of the track
+    pub time: f32,
+    pub position: Vector3f,
+    pub color: Color,
+    // scales the color, lets the track fade a light without changing its hue
+    pub intensity: f32,
+    // how the light moves from this keyframe to the next one
+    pub easing: Easing,
+}
+
+impl LightKeyframe {
+    pub fn new(time: f32, position: Vector3f, color: Color) -> LightKeyframe {
+        LightKeyframe {
+            time,
+            position,
+            color,
+            intensity: 1.0,
+            easing: Easing::default(),
+        }
+    }
+}
+
+// scripted light animation, e.g. a sunset moving the sun light down and turning it red
+// for directional lights the keyframe positions are directions towards the light like in Light::new_infinite
+pub struct LightTrack {
+    keyframes: Vec<LightKeyframe>,
+    // loop over the track instead of holding the last keyframe
+    pub is_looping: bool,
+}
+
+impl LightTrack {
+    pub fn new(mut keyframes: Vec<LightKeyframe>) -> LightTrack {
+        assert!(!keyframes.is_empty(), "A light track needs at least one keyframe");
+        keyframes.sort_by(|a, b| a.time.partial_cmp(&b.time).expect("Keyframe times must not be NaN"));
+        LightTrack {
+            keyframes,
+            is_looping: false,
+        }
+    }
+
+    pub fn duration(&self) -> f32 {
+        self.keyframes[self.keyframes.len() - 1].time
+    }
+
+    // time is usually taken from the game clock e.g. WallClock::time_of_day
+    // only the position and color are animated, the kind and attenuation are kept from the source light
+    pub fn sample(&self, source: &Light, time: f32) -> Light {
+        let time = if self.is_looping && self.duration() > 0.0 { time.rem_euclid(self.duration()) } else { time };
+        let next_idx = self.keyframes.iter().position(|keyframe| keyframe.time > time);
+        let (prev, next) = match next_idx {
+            Some(0) => (&self.keyframes[0], &self.keyframes[0]),
+            Some(idx) => (&self.keyframes[idx - 1], &self.keyframes[idx]),
+            None => (&self.keyframes[self.keyframes.len() - 1], &self.keyframes[self.keyframes.len() - 1]),
+        };
+        let span = next.time - prev.time;
+        let t = if span > 0.0 { prev.easing.apply((time - prev.time) / span) } else { 0.0 };
+
+        // lerp of vectors returns the first argument at t = 1
+        let position = Vector3f::lerp(&next.position, &prev.position, t);
+        let color = prev.color.lerp(&next.color, t);
+        let intensity = prev.intensity + (next.intensity - prev.intensity) * t;
+        let mut light = source.clone();
+        match light.direction.as_mut() {
+            Some(direction) => {
+                *direction = position;
+                direction.normalize();
+            },
+            None => light.position = position,
+        }
+        light.color = Color::rgba(color.r * intensity, color.g * intensity, color.b * intensity, color.a);
+        light
+    }
+
+    pub fn apply(&self, light: &mut Light, time: f32) {
+        *light = self.sample(light, time);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn color_track_is_halfway_at_half_time() {
+        let keyframes = vec![
+            LightKeyframe::new(10.0, Vector3f::new(0.0, 0.0, 0.0), Color::rgb(1.0, 0.2, 0.0)),
+            LightKeyframe::new(20.0, Vector3f::new(100.0, 50.0, 0.0), Color::rgb(0.0, 0.2, 1.0)),
+        ];
+        let track = LightTrack::new(keyframes);
+        let lamp = Light::new_point(Vector3f::zero(), Color::WHITE, Vector3f::new(1.0, 0.01, 0.002));
+
+        let light = track.sample(&lamp, 15.0);
+        assert_eq!(light.color, Color::rgb(0.5, 0.2, 0.5));
+        assert_eq!((light.position.x, light.position.y), (50.0, 25.0));
+        assert_eq!((light.attenuation.y, light.attenuation.z), (0.01, 0.002));
+        // before the first and after the last keyframe the track holds the end keyframes
+        assert_eq!(track.sample(&lamp, 0.0).color, Color::rgb(1.0, 0.2, 0.0));
+        assert_eq!(track.sample(&lamp, 30.0).color, Color::rgb(0.0, 0.2, 1.0));
+    }
+
+    #[test]
+    fn looping_track_wraps_around() {
+        let mut start = LightKeyframe::new(0.0, Vector3f::zero(), Color::WHITE);
+        start.intensity = 0.0;
+        let keyframes = vec![start, LightKeyframe::new(4.0, Vector3f::zero(), Color::WHITE)];
+        let mut track = LightTrack::new(keyframes);
+        track.is_looping = true;
+        let lamp = Light::new_point(Vector3f::zero(), Color::WHITE, Vector3f::new(1.0, 0.0, 0.0));
+        assert_eq!(track.sample(&lamp, 6.0).color, Color::rgb(0.5, 0.5, 0.5));
+    }
+
+    #[test]
+    fn directional_lights_stay_directional() {
+        let keyframes = vec![
+            LightKeyframe::new(0.0, Vector3f::new(0.0, 10.0, 0.0), Color::WHITE),
+            LightKeyframe::new(1.0, Vector3f::new(10.0, 0.0, 0.0), Color::WHITE),
+        ];
+        let track = LightTrack::new(keyframes);
+        let mut sun = Light::new_directional(Vector3f::new(0.0, 1.0, 0.0), Color::WHITE);
+
+        track.apply(&mut sun, 0.5);
+        let direction = sun.direction.as_ref().expect("The sun must stay a directional light");
+        let expected = std::f32::consts::FRAC_1_SQRT_2;
+        assert!((direction.x - expected).abs() < 1e-6 && (direction.y - expected).abs() < 1e-6);
+        assert_eq!(sun.shader_position().w, 0.0);
+    }
+}
@@ -1,7 +1,9 @@
 pub mod entity;
 pub mod camera;
 pub mod light;
+pub mod light_track;
 pub mod terrain;
+pub mod terrain_tiles;
 pub mod player;
 pub mod ground;
 pub mod skybox;
@@ -10,13 +12,48 @@ pub mod water_tile;
 pub mod debug_entity;
 pub mod animated_entity;
 
-pub use self::entity::Entity;
+pub use self::entity::{
+    Entity,
+    EntityTransform,
+    RenderLayers,
+    BillboardMode,
+    LodLevel,
+    LodFade,
+    LodSelection,
+    lod_transition_factor,
+};
 pub use self::debug_entity::DebugEntity;
 pub use self::camera::Camera;
+pub use self::camera::CameraTerrainCollision;
+pub use self::camera::CameraMode;
+pub use self::camera::FreeFlyParams;
 pub use self::light::Light;
+pub use self::light_track::{
+    LightKeyframe,
+    LightTrack,
+};
 pub use self::terrain::Terrain;
-pub use self::player::Player;
+pub use self::terrain_tiles::{
+    TerrainTileManager,
+    TileIndex,
+    TileUpdate,
+};
+pub use self::player::{
+    Player,
+    JumpParams,
+    SlopeParams,
+};
 pub use self::ground::Ground;
-pub use self::skybox::Skybox;
-pub use self::water_tile::WaterTile;
+pub use self::ground::TerrainPlacement;
+pub use self::ground::TerrainSpot;
+pub use self::skybox::{
+    AtmosphereParams,
+    FogCutoff,
+    Skybox,
+};
+pub use self::water_tile::{
+    WaterTile,
+    WaterClipParams,
+    WaterHeightGroup,
+};
 pub use self::animated_entity::AnimatedEntity;
\ No newline at end of file
@@ -1,7 +1,6 @@
 use crate::constants::GRAVITY;
 use crate::display::{
     Keyboard,
-    Display,
     Key,
 };
 use crate::entities::{
@@ -19,27 +18,138 @@ pub enum PlayerEntityType {
     AnimatedModelEntity(AnimatedEntity),
 }
 
+#[derive(Debug, Clone, Copy)]
+pub struct JumpParams {
+    pub jump_impulse: f32,
+    pub gravity: f32,
+    // max downwards speed (positive value)
+    pub terminal_velocity: f32,
+    // when walking on the ground and the terrain below drops by less than this we stick to it instead of falling
+    pub ground_snap_distance: f32,
+}
+
+impl Default for JumpParams {
+    fn default() -> Self {
+        JumpParams {
+            jump_impulse: 30.0,
+            gravity: GRAVITY,
+            terminal_velocity: 150.0,
+            ground_snap_distance: 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SlopeParams {
+    // steepest slope (in degrees from horizontal) the player can walk up
+    pub max_walkable_slope_deg: f32,
+    // fraction of the run speed lost when walking up a slope of max_walkable_slope_deg, less steep slopes lose proportionally less
+    pub uphill_speed_penalty: f32,
+    // slide downhill when standing on terrain steeper than max_walkable_slope_deg
+    pub slides_on_steep: bool,
+    pub slide_speed: f32,
+}
+
+impl Default for SlopeParams {
+    fn default() -> Self {
+        SlopeParams {
+            max_walkable_slope_deg: 45.0,
+            uphill_speed_penalty: 0.5,
+            slides_on_steep: false,
+            slide_speed: 15.0,
+        }
+    }
+}
+
+impl SlopeParams {
+    // scale for the run speed when moving in move_dir (horizontal unit vector) over terrain with the given normal
+    // downhill and flat movement is unaffected, slopes steeper than the max can't be walked up at all
+    pub fn speed_factor(&self, normal: &Vector3f, move_dir: &Vector3f) -> f32 {
+        if normal.y <= 0.0 {
+            return 0.0;
+        }
+        let rise_per_run = -(normal.x * move_dir.x + normal.z * move_dir.z) / normal.y;
+        if rise_per_run <= 0.0 {
+            return 1.0;
+        }
+        let uphill_slope_deg = rise_per_run.atan().to_degrees();
+        if uphill_slope_deg >= self.max_walkable_slope_deg {
+            return 0.0;
+        }
+        1.0 - self.uphill_speed_penalty * uphill_slope_deg / self.max_walkable_slope_deg
+    }
+
+    pub fn is_too_steep(&self, normal: &Vector3f) -> bool {
+        normal.y.min(1.0).acos().to_degrees() > self.max_walkable_slope_deg
+    }
+}
+
+#[derive(Debug, Default)]
+struct VerticalMotion {
+    upwards_speed: f32,
+    is_in_air: bool,
+}
+
+impl VerticalMotion {
+    fn jump(&mut self, params: &JumpParams) {
+        if !self.is_in_air {
+            self.upwards_speed = params.jump_impulse;
+            self.is_in_air = true;
+        }
+    }
+
+    // returns the new height of the player
+    fn update(&mut self, params: &JumpParams, y: f32, ground_height: f32, dt: f32) -> f32 {
+        if !self.is_in_air {
+            let drop = y - ground_height;
+            if drop <= params.ground_snap_distance {
+                // standing or walking on the ground (also down steep slopes) -> stick to the terrain
+                self.upwards_speed = 0.0;
+                return ground_height;
+            }
+            // walked off a ledge
+            self.is_in_air = true;
+        }
+        self.upwards_speed = f32::max(self.upwards_speed + params.gravity * dt, -params.terminal_velocity);
+        let new_y = y + self.upwards_speed * dt;
+        // compare against the ground below the end position so a large step can't tunnel through the terrain (e.g. at tile seams)
+        if new_y <= ground_height {
+            self.upwards_speed = 0.0;
+            self.is_in_air = false;
+            ground_height
+        } else {
+            new_y
+        }
+    }
+}
+
 pub struct Player {
     pub entity: PlayerEntityType,
+    pub jump_params: JumpParams,
+    pub slope_params: SlopeParams,
     current_speed: f32,
     current_turn_speed: f32,
-    upwards_speed: f32,
-    is_in_air: bool,
+    vertical_motion: VerticalMotion,
+    // distance moved per second during the last frame, used to drive the animations
+    speed: f32,
     pub is_invisible_immovable: bool,
 }
 
 impl Player {
-    const RUN_SPEED: f32 = 20.0;
-    const TURN_SPEED: f32 = 160.0;
-    const JUMP_POWER: f32 = 30.0;
+    pub const RUN_SPEED: f32 = 20.0;
+    pub const TURN_SPEED: f32 = 160.0;
+    // speeds below this are treated as standing still so that tiny jitter (e.g. snapping to the terrain) doesn't count as movement
+    pub const MOVEMENT_DEADZONE: f32 = 0.1;
 
     pub fn new_animated(animated_entity: AnimatedEntity) -> Player {
         Player {
             entity: PlayerEntityType::AnimatedModelEntity(animated_entity),
+            jump_params: JumpParams::default(),
+            slope_params: SlopeParams::default(),
             current_speed: 0.0,
             current_turn_speed: 0.0,
-            upwards_speed: 0.0,
-            is_in_air: false,
+            vertical_motion: VerticalMotion::default(),
+            speed: 0.0,
             is_invisible_immovable: false,
         }
     }
@@ -47,10 +157,12 @@ impl Player {
     pub fn new(entity: Entity) -> Player {
         Player {
             entity: PlayerEntityType::StaticModelEntity(entity),
+            jump_params: JumpParams::default(),
+            slope_params: SlopeParams::default(),
             current_speed: 0.0,
             current_turn_speed: 0.0,
-            upwards_speed: 0.0,
-            is_in_air: false,
+            vertical_motion: VerticalMotion::default(),
+            speed: 0.0,
             is_invisible_immovable: false,
         }
     }
@@ -76,6 +188,31 @@ impl Player {
         }
     }
 
+    pub fn rotation_deg_mut(&mut self) -> &mut Vector3f {
+        match &mut self.entity {
+            PlayerEntityType::StaticModelEntity(entity) => &mut entity.rotation_deg,
+            PlayerEntityType::AnimatedModelEntity(entity) => &mut entity.rotation_deg,
+        }
+    }
+
+    // swaps the model used for the player (e.g. static model while debugging animations) keeping where the player is and faces
+    // returns the previous entity so it can be swapped back in later
+    pub fn set_entity(&mut self, entity: PlayerEntityType) -> PlayerEntityType {
+        let position = self.position().clone();
+        let rotation_deg = self.rotation_deg().clone();
+        let mut previous_entity = std::mem::replace(&mut self.entity, entity);
+        // animations start from the beginning whenever the animated model is shown again
+        if let PlayerEntityType::AnimatedModelEntity(animated_entity) = &mut previous_entity {
+            animated_entity.model.animation.stop();
+        }
+        if let PlayerEntityType::AnimatedModelEntity(animated_entity) = &mut self.entity {
+            animated_entity.model.animation.stop();
+        }
+        *self.position_mut() = position;
+        *self.rotation_deg_mut() = rotation_deg;
+        previous_entity
+    }
+
     fn increase_position(&mut self, dx: f32, dy: f32, dz: f32) {
         match &mut self.entity {
             PlayerEntityType::StaticModelEntity(entity) => entity.increase_position(dx, dy, dz),
@@ -90,30 +227,63 @@ impl Player {
         }
     }
 
-    pub fn move_player(&mut self, display: &Display, ground: &Ground) {
+    pub fn move_player(&mut self, keyboard: &dyn Keyboard, frame_time_sec: f32, ground: &Ground) {
         if self.is_invisible_immovable {
+            self.speed = 0.0;
             return;
         }
-        self.check_inputs(display);
-        self.increase_rotation(0.0, self.current_turn_speed * display.frame_time_sec, 0.0);
-        let distance = self.current_speed * display.frame_time_sec;
+        let previous_position = self.position().clone();
+        self.check_inputs(keyboard);
+        self.increase_rotation(0.0, self.current_turn_speed * frame_time_sec, 0.0);
         let (y_sin, y_cos) = self.rotation_deg().y.to_radians().sin_cos();
-        let dx = distance * y_sin;
-        let dz = distance * y_cos;
-        self.upwards_speed += GRAVITY * display.frame_time_sec;
-        let upwards_dist = self.upwards_speed * display.frame_time_sec;
-        self.increase_position(dx, upwards_dist, dz);
+        let mut dx = 0.0;
+        let mut dz = 0.0;
+        if !self.vertical_motion.is_in_air {
+            let normal = ground.normal_at(self.position().x, self.position().z);
+            if self.current_speed != 0.0 {
+                // moving backwards goes uphill when the slope is behind the player
+                let move_dir = Vector3f::new(y_sin * self.current_speed.signum(), 0.0, y_cos * self.current_speed.signum());
+                let distance = self.current_speed * self.slope_params.speed_factor(&normal, &move_dir) * frame_time_sec;
+                dx += distance * y_sin;
+                dz += distance * y_cos;
+            }
+            if self.slope_params.slides_on_steep && self.slope_params.is_too_steep(&normal) {
+                // the horizontal part of the normal points downhill
+                let mut downhill = Vector3f::new(normal.x, 0.0, normal.z);
+                downhill.normalize();
+                let slide_distance = self.slope_params.slide_speed * frame_time_sec;
+                dx += downhill.x * slide_distance;
+                dz += downhill.z * slide_distance;
+            }
+        } else {
+            let distance = self.current_speed * frame_time_sec;
+            dx = distance * y_sin;
+            dz = distance * y_cos;
+        }
+        self.increase_position(dx, 0.0, dz);
 
         let terrain_height_at_xz = ground.height_at_xz(self.position().x, self.position().z);
-        if self.position().y <= terrain_height_at_xz {
-            self.position_mut().y = terrain_height_at_xz;
-            self.is_in_air = false;
-            self.upwards_speed = 0.0;
-        }
+        let y = self.position().y;
+        let new_y = self.vertical_motion.update(&self.jump_params, y, terrain_height_at_xz, frame_time_sec);
+        self.position_mut().y = new_y;
+
+        self.speed = Player::speed_from_delta(&(self.position() - &previous_position), frame_time_sec);
     }
 
     pub fn is_moving(&self) -> bool {
-        self.current_speed > 0.0 || self.is_in_air
+        self.speed > 0.0 || self.vertical_motion.is_in_air
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed
+    }
+
+    fn speed_from_delta(position_delta: &Vector3f, frame_time_sec: f32) -> f32 {
+        if frame_time_sec <= 0.0 {
+            return 0.0;
+        }
+        let speed = position_delta.length() / frame_time_sec;
+        if speed < Player::MOVEMENT_DEADZONE { 0.0 } else { speed }
     }
 
     fn check_inputs(&mut self, keyboard: &Keyboard) {
@@ -139,9 +309,99 @@ impl Player {
     }
 
     fn jump(&mut self) {
-        if !self.is_in_air {
-            self.upwards_speed = Player::JUMP_POWER;
-            self.is_in_air = true;
+        self.vertical_motion.jump(&self.jump_params);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TexturedModel;
+
+    #[test]
+    fn jump_lands_on_ground_after_expected_airtime() {
+        let params = JumpParams::default();
+        let ground_height = 12.5;
+        let dt = 1.0 / 600.0;
+        let mut motion = VerticalMotion::default();
+        let mut y = ground_height;
+
+        motion.jump(&params);
+        let mut airtime = 0.0;
+        while motion.is_in_air {
+            y = motion.update(&params, y, ground_height, dt);
+            airtime += dt;
+            assert!(airtime < 10.0, "Player never landed");
         }
+
+        let expected_airtime = 2.0 * params.jump_impulse / -params.gravity;
+        assert!((airtime - expected_airtime).abs() < 0.01, "Airtime was {}, expected {}", airtime, expected_airtime);
+        assert_eq!(y, ground_height);
+        assert_eq!(motion.upwards_speed, 0.0);
+    }
+
+    #[test]
+    fn falling_speed_is_capped_by_terminal_velocity() {
+        let params = JumpParams::default();
+        let mut motion = VerticalMotion { upwards_speed: 0.0, is_in_air: true };
+        let mut y = 10_000.0;
+        for _ in 0..100 {
+            y = motion.update(&params, y, 0.0, 0.1);
+        }
+        assert_eq!(motion.upwards_speed, -params.terminal_velocity);
+    }
+
+    #[test]
+    fn speed_is_zero_within_deadzone() {
+        let jitter = Vector3f::new(0.0, 0.001, 0.0);
+        assert_eq!(Player::speed_from_delta(&jitter, 0.1), 0.0);
+
+        let walked = Vector3f::new(3.0, 0.0, 4.0);
+        assert!((Player::speed_from_delta(&walked, 0.5) - 10.0).abs() < 1e-5);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn switching_entity_keeps_position_and_rotation() {
+        let model = TexturedModel::default();
+        let mut player = Player::new(Entity::new(model.clone(), Vector3f::new(10.0, 2.0, -30.0), Vector3f::new(0.0, 45.0, 0.0), 1.0));
+        let previous = player.set_entity(PlayerEntityType::StaticModelEntity(Entity::new(model, Vector3f::zero(), Vector3f::zero(), 0.5)));
+
+        let position = player.position();
+        assert_eq!((position.x, position.y, position.z), (10.0, 2.0, -30.0));
+        assert_eq!(player.rotation_deg().y, 45.0);
+        match (&player.entity, previous) {
+            (PlayerEntityType::StaticModelEntity(current), PlayerEntityType::StaticModelEntity(previous)) => {
+                assert_eq!(current.scale, 0.5);
+                assert_eq!(previous.scale, 1.0);
+            },
+            _ => panic!("Expected static player entities"),
+        }
+    }
+
+    #[test]
+    fn uphill_slope_reduces_speed() {
+        let params = SlopeParams::default();
+        let forward = Vector3f::new(0.0, 0.0, 1.0);
+        let flat = Vector3f::new(0.0, 1.0, 0.0);
+        assert_eq!(params.speed_factor(&flat, &forward), 1.0);
+
+        // terrain rising along +z at half the max walkable slope
+        let slope_rad = (params.max_walkable_slope_deg / 2.0).to_radians();
+        let uphill = Vector3f::new(0.0, slope_rad.cos(), -slope_rad.sin());
+        let expected = 1.0 - params.uphill_speed_penalty / 2.0;
+        assert!((params.speed_factor(&uphill, &forward) - expected).abs() < 1e-5);
+        // walking down the same slope is not slowed
+        let backward = Vector3f::new(0.0, 0.0, -1.0);
+        assert_eq!(params.speed_factor(&uphill, &backward), 1.0);
+        assert!(!params.is_too_steep(&uphill));
+    }
+
+    #[test]
+    fn slopes_steeper_than_max_block_uphill_movement() {
+        let params = SlopeParams::default();
+        let slope_rad = (params.max_walkable_slope_deg + 10.0).to_radians();
+        let steep = Vector3f::new(-slope_rad.sin(), slope_rad.cos(), 0.0);
+        assert_eq!(params.speed_factor(&steep, &Vector3f::new(1.0, 0.0, 0.0)), 0.0);
+        assert!(params.is_too_steep(&steep));
+    }
+}
@@ -3,34 +3,104 @@ use crate::models::{
     TextureId,
 };
 use crate::display::{
-    Display,
     WallClock,
 };
+use crate::math::Vector3f;
 
 const DAY_SEGMENTS: f32 = WallClock::DAY_LENGTH / 4.0;
 
+// analytic sky (simplified Preetham model) that follows the sun instead of sampling the cubemaps
+#[derive(Debug, Clone)]
+pub struct AtmosphereParams {
+    // haziness of the air, 2 is a very clear sky and 10 a hazy one
+    pub turbidity: f32,
+    // scales the sky luminance before it gets mapped into [0, 1]
+    pub exposure: f32,
+}
+
+impl Default for AtmosphereParams {
+    fn default() -> Self {
+        AtmosphereParams {
+            turbidity: 2.5,
+            exposure: 0.08,
+        }
+    }
+}
+
+impl AtmosphereParams {
+    // angle in radians between straight up and the direction to the sun, the sun is where the directional light is
+    // so 0 is noon, pi/2 is the sun at the horizon and more than that is night
+    pub fn sun_zenith_angle(sun_position: &Vector3f) -> f32 {
+        let length = sun_position.length();
+        if length == 0.0 {
+            return 0.0;
+        }
+        (sun_position.y / length).max(-1.0).min(1.0).acos()
+    }
+}
+
+// linear fade to the fog color on top of the exponential fog, ending before the far plane so geometry never pops in there
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct FogCutoff {
+    pub start_distance: f32,
+    pub end_distance: f32,
+}
+
+impl FogCutoff {
+    // same constants as the exponential fog in the shaders
+    pub const FOG_DENSITY: f32 = 0.007;
+    pub const FOG_GRADIENT: f32 = 1.5;
+
+    // fog_start and fog_end of the shaders, an end of 0 turns the cutoff off
+    pub fn shader_values(fog_cutoff: &Option<FogCutoff>) -> (f32, f32) {
+        match fog_cutoff {
+            Some(cutoff) => (cutoff.start_distance, cutoff.end_distance),
+            None => (0.0, 0.0),
+        }
+    }
+
+    // 0 is no fog and 1 is fully the fog color, mirrors the visibility computed in the shaders
+    pub fn fog_factor(fog_cutoff: &Option<FogCutoff>, distance: f32) -> f32 {
+        let mut visibility = (-(distance * FogCutoff::FOG_DENSITY).powf(FogCutoff::FOG_GRADIENT)).exp();
+        let (fog_start, fog_end) = FogCutoff::shader_values(fog_cutoff);
+        if fog_end > 0.0 {
+            let cutoff_visibility = ((fog_end - distance) / (fog_end - fog_start).max(0.0001)).max(0.0).min(1.0);
+            visibility = visibility.min(cutoff_visibility);
+        }
+        1.0 - visibility.max(0.0).min(1.0)
+    }
+}
+
 pub struct Skybox {
     pub model: SkyboxModel,
-    pub rotation_yaw_deg: f32,
+    // accumulated rotation around rotation_axis, kept in [0, 360)
+    pub rotation_deg: f32,
+    // degrees per second, 0 gives a still sky
     pub rotate_speed: f32,
+    pub rotation_axis: Vector3f,
     // this is a hack for scenes that don't want to show a skybox :(
     pub invisible: bool,
     pub uses_fog: bool,
+    // None uses the day/night cubemaps
+    pub atmosphere: Option<AtmosphereParams>,
 }
 
 impl Skybox {
-    pub fn new(model: SkyboxModel, rotation_yaw_deg: f32) -> Skybox {
+    pub fn new(model: SkyboxModel, rotation_deg: f32) -> Skybox {
         Skybox {
             model,
-            rotation_yaw_deg,
+            rotation_deg,
             invisible: false,
             uses_fog: true,
             rotate_speed: 1.0,
+            rotation_axis: Vector3f::POS_Y_AXIS,
+            atmosphere: None,
         }
     }
 
-    pub fn increase_rotation(&mut self, display: &Display) {
-        self.rotation_yaw_deg += self.rotate_speed * display.frame_time_sec;
+    pub fn increase_rotation(&mut self, frame_time_sec: f32) {
+        // wrapping keeps the angle small so that precision doesn't degrade after running for a long time
+        self.rotation_deg = (self.rotation_deg + self.rotate_speed * frame_time_sec).rem_euclid(360.0);
     }
 
     pub fn get_day_night_textures(&self, wall_clock: &WallClock) -> (TextureId, TextureId, f32) {
@@ -47,4 +117,66 @@ impl Skybox {
             (self.model.night_texture_id, self.model.night_texture_id, (wall_clock.time_of_day - 3.0 * DAY_SEGMENTS) / DAY_SEGMENTS)
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::entities::Camera;
+    use crate::math::Matrix4f;
+    use crate::models::RawModel;
+
+    fn create_skybox() -> Skybox {
+        Skybox::new(SkyboxModel { raw_model: RawModel::default(), day_texture_id: TextureId::Empty, night_texture_id: TextureId::Empty, cycles_day_night: false }, 0.0)
+    }
+
+    #[test]
+    fn rotation_is_speed_times_time_wrapped() {
+        let mut skybox = create_skybox();
+        skybox.rotate_speed = 45.0;
+        let frame_time = 0.1;
+        for _ in 0..100 {
+            skybox.increase_rotation(frame_time);
+        }
+        // 10 seconds at 45 degrees per second is 450 degrees
+        let expected = (45.0 * 10.0f32) % 360.0;
+        assert!((skybox.rotation_deg - expected).abs() < 1e-2, "rotation was {}", skybox.rotation_deg);
+    }
+
+    #[test]
+    fn sun_direction_maps_to_zenith_angle() {
+        let eps = 1e-5;
+        assert!(AtmosphereParams::sun_zenith_angle(&Vector3f::new(0.0, 10_000.0, 0.0)).abs() < eps);
+        assert!((AtmosphereParams::sun_zenith_angle(&Vector3f::new(5000.0, 0.0, 0.0)) - std::f32::consts::FRAC_PI_2).abs() < eps);
+        assert!((AtmosphereParams::sun_zenith_angle(&Vector3f::new(0.0, 5000.0, 5000.0)) - std::f32::consts::FRAC_PI_4).abs() < eps);
+        // below the horizon at night
+        assert!((AtmosphereParams::sun_zenith_angle(&Vector3f::new(0.0, -1.0, 0.0)) - std::f32::consts::PI).abs() < eps);
+    }
+
+    #[test]
+    fn default_axis_matches_yaw_rotation() {
+        let mut camera = Camera::default();
+        camera.yaw = 30.0;
+        let skybox_rotation = 20.0;
+        let view_mat = Matrix4f::create_skybox_view_matrix(&camera, skybox_rotation, &create_skybox().rotation_axis);
+        let mut yaw_view_mat = Matrix4f::identity();
+        yaw_view_mat.rotate(&Vector3f::new(camera.pitch, -camera.yaw - skybox_rotation, camera.roll));
+        for row in 0..4 {
+            for col in 0..4 {
+                assert!((view_mat[row][col] - yaw_view_mat[row][col]).abs() < 1e-5);
+            }
+        }
+    }
+
+    #[test]
+    fn fog_is_opaque_at_the_cutoff_end_regardless_of_the_far_plane() {
+        for end_distance in [50.0, 300.0, 999.0, 2000.0].iter() {
+            let cutoff = Some(FogCutoff { start_distance: end_distance * 0.5, end_distance: *end_distance });
+            assert_eq!(FogCutoff::fog_factor(&cutoff, *end_distance), 1.0);
+            assert_eq!(FogCutoff::fog_factor(&cutoff, end_distance + 100.0), 1.0);
+            // before the start only the exponential fog applies
+            assert_eq!(FogCutoff::fog_factor(&cutoff, end_distance * 0.25), FogCutoff::fog_factor(&None, end_distance * 0.25));
+        }
+        assert_eq!(FogCutoff::shader_values(&None), (0.0, 0.0));
+    }
+}
@@ -1,38 +1,99 @@
-use std::rc::Rc;
+use std::sync::Arc;
+use crate::math::Vector3f;
 use crate::models::{
     TerrainTexture,
 	TerrainTexturePack,
     ModelLoader,
 	TerrainModel,
 	TerrainGenerator,	
+	TerrainNeighbors,
+	NeighborAwareTile,
+	HeightMap,
 };
 
+// vertex data of a terrain mesh before it is uploaded, the height map is indexed by [x][z]
+struct TerrainMeshData {
+	vertices: Vec<f32>,
+	normals: Vec<f32>,
+	texture_coords: Vec<f32>,
+	height_map: Vec<Vec<f32>>,
+}
+
+#[derive(Clone)]
 pub struct Terrain {
     pub x: f32,
     pub z: f32,
     pub model: TerrainModel,
     pub blend_texture: TerrainTexture,
     pub texture_pack: TerrainTexturePack,
+    // lowest and highest point of the height map, together with the tile size it bounds the whole tile
+    height_range: (f32, f32),
 }
 
 impl Terrain {
     pub const SIZE: f32 = 800.0;
     
     pub fn new(grid_x: i32, grid_z: i32, texture_pack: TerrainTexturePack, blend_texture: TerrainTexture, terrain_model: TerrainModel) -> Terrain {        
+        let height_range = Terrain::height_range(&terrain_model.height_map);
         Terrain {
             x: grid_x as f32 * Terrain::SIZE,
             z: grid_z as f32 * Terrain::SIZE,
             blend_texture,
             model: terrain_model,
 			texture_pack,
+			height_range,
         }
     }
 
+	fn height_range(height_map: &Vec<Vec<f32>>) -> (f32, f32) {
+		height_map.iter().flatten().fold((std::f32::INFINITY, std::f32::NEG_INFINITY), |(lowest, highest), &height| (lowest.min(height), highest.max(height)))
+	}
+
+	// corners of the axis aligned box around the tile
+	pub fn bounds(&self) -> (Vector3f, Vector3f) {
+		let (lowest, highest) = self.height_range;
+		(Vector3f::new(self.x, lowest, self.z), Vector3f::new(self.x + Terrain::SIZE, highest, self.z + Terrain::SIZE))
+	}
+
+	// number of grid cells along each side of the tile
+	pub fn cell_count(&self) -> usize {
+		self.model.height_map.len().max(1) - 1
+	}
+
+	// the two triangles of the grid cell at (gx, gz) in world coordinates, the same ones collision_mesh has for the cell
+	pub fn cell_triangles(&self, gx: usize, gz: usize) -> [[Vector3f; 3]; 2] {
+		let cell_size = Terrain::SIZE / self.cell_count() as f32;
+		let height_map = &self.model.height_map;
+		let vertex = |x: usize, z: usize| Vector3f::new(self.x + x as f32 * cell_size, height_map[x][z], self.z + z as f32 * cell_size);
+		let (top_left, top_right, bottom_left, bottom_right) = (vertex(gx, gz), vertex(gx + 1, gz), vertex(gx, gz + 1), vertex(gx + 1, gz + 1));
+		[[top_left, bottom_left.clone(), top_right.clone()], [top_right, bottom_left, bottom_right]]
+	}
+
+	// lets a tile use a different set of textures than its neighbours e.g. for a different biome
+	// the seams between tiles with different textures are not blended
+	pub fn set_textures(&mut self, texture_pack: TerrainTexturePack, blend_texture: TerrainTexture) {
+		self.texture_pack = texture_pack;
+		self.blend_texture = blend_texture;
+	}
+
+	// the grid index the tile was created with
+	pub fn tile_index(&self) -> (i32, i32) {
+		((self.x / Terrain::SIZE).round() as i32, (self.z / Terrain::SIZE).round() as i32)
+	}
+
 	pub fn is_xz_within_terrain_cell(&self, x: f32, z: f32) -> bool {
 		self.x <= x && x < self.x + Terrain::SIZE && self.z <= z && z < self.z + Terrain::SIZE 
 	}
 	    
     pub fn generate_terrain(loader: &mut ModelLoader, terrain_generator: &TerrainGenerator) -> TerrainModel {
+		let mesh = Terrain::generate_mesh_data(terrain_generator);
+		TerrainModel {
+			raw_model: loader.load_to_vao(&mesh.vertices, &mesh.texture_coords, &Terrain::grid_indices(mesh.height_map.len()), &mesh.normals),
+			height_map: Arc::new(mesh.height_map),
+		}
+	}
+
+	fn generate_mesh_data(terrain_generator: &dyn TerrainGenerator) -> TerrainMeshData {
 		let vertex_count: usize = terrain_generator.width();	
 		let count: usize = vertex_count * vertex_count;
 		let mut height_array = vec![vec![0.0f32; vertex_count]; vertex_count];
@@ -40,7 +101,6 @@ impl Terrain {
 		let mut vertices = vec![0.0f32; count * 3];
 		let mut normals = vec![0.0f32; count * 3];
 		let mut texture_coords = vec![0.0f32; count * 2];
-		let mut indices = vec![0u32; 6*(vertex_count-1)*(vertex_count-1)];
 		let mut vertex_pointer = 0;
 		for i in 0..vertex_count {
 			for j in 0..vertex_count {
@@ -58,6 +118,64 @@ impl Terrain {
 				vertex_pointer+=1;
 			}
 		}
+		TerrainMeshData {
+			vertices,
+			normals,
+			texture_coords,
+			height_map: height_array,
+		}
+	}
+
+	// the edge normals take the neighbouring tiles into account so the lighting doesn't jump at the seams
+	// a neighbour can be the generator it was made from or an already generated Terrain (Ground::neighbors)
+	pub fn generate_terrain_with_neighbors(loader: &mut ModelLoader, terrain_generator: &dyn TerrainGenerator, neighbors: TerrainNeighbors) -> TerrainModel {
+		Terrain::generate_terrain(loader, &NeighborAwareTile::new(terrain_generator, neighbors))
+	}
+
+	// vertex index and normal of the vertices along the edge that faces the tile in direction (dx, dz), one of which is 0
+	// the vertices are numbered like in the generated mesh so the normals can replace the ones of a loaded tile
+	pub fn edge_normals(terrain_generator: &dyn TerrainGenerator, (dx, dz): (i32, i32)) -> Vec<(usize, Vector3f)> {
+		let vertex_count = terrain_generator.width();
+		let last = vertex_count - 1;
+		(0..vertex_count).map(|i| {
+			let (x, z) = match (dx, dz) {
+				(-1, _) => (0, i),
+				(1, _) => (last, i),
+				(_, -1) => (i, 0),
+				_ => (i, last),
+			};
+			(z * vertex_count + x, terrain_generator.get_normal_at(x as isize, z as isize))
+		}).collect()
+	}
+
+	// lets designers author terrain as a gray png, vertical_scale is the height difference between black and white
+	pub fn from_heightmap(loader: &mut ModelLoader, path: &str, vertical_scale: f32) -> TerrainModel {
+		Terrain::generate_terrain(loader, &HeightMap::with_vertical_scale(path, vertical_scale))
+	}
+
+	// flat triangle list of the terrain surface in world coordinates e.g. for a physics engine
+	// uses the same grid and triangulation as the rendered mesh so collisions match what is drawn
+	pub fn collision_mesh(&self) -> (Vec<Vector3f>, Vec<u32>) {
+		Terrain::collision_mesh_from_heights(&self.model.height_map, self.x, self.z)
+	}
+
+	fn collision_mesh_from_heights(height_map: &Vec<Vec<f32>>, offset_x: f32, offset_z: f32) -> (Vec<Vector3f>, Vec<u32>) {
+		let vertex_count = height_map.len();
+		let mut positions = Vec::with_capacity(vertex_count * vertex_count);
+		// same vertex order as generate_terrain, the height map is indexed by [x][z]
+		for i in 0..vertex_count {
+			for j in 0..vertex_count {
+				let x = offset_x + (j as f32/(vertex_count - 1) as f32) * Terrain::SIZE;
+				let z = offset_z + (i as f32/(vertex_count - 1) as f32) * Terrain::SIZE;
+				positions.push(Vector3f::new(x, height_map[j][i], z));
+			}
+		}
+		(positions, Terrain::grid_indices(vertex_count))
+	}
+
+	// two counter clockwise (seen from above) triangles per grid cell
+	fn grid_indices(vertex_count: usize) -> Vec<u32> {
+		let mut indices = vec![0u32; 6*(vertex_count-1)*(vertex_count-1)];
 		let mut pointer = 0;
 		for gz in 0..vertex_count-1 {
 			for gx in 0..vertex_count-1 {
@@ -79,9 +197,138 @@ impl Terrain {
                 pointer+=1;
 			}
 		}
-		TerrainModel {
-			raw_model: loader.load_to_vao(&vertices, &texture_coords, &indices, &normals),
-			height_map: Rc::new(height_array),
+		indices
+	}
+}
+
+// already generated tiles serve as neighbours for new ones, outside of the tile the edge heights repeat
+impl TerrainGenerator for Terrain {
+	fn height(&self) -> usize {
+		self.model.height_map.len()
+	}
+
+	fn width(&self) -> usize {
+		self.model.height_map.len()
+	}
+
+	fn get_height(&self, x: isize, z: isize) -> f32 {
+		let last = self.model.height_map.len() as isize - 1;
+		self.model.height_map[x.max(0).min(last) as usize][z.max(0).min(last) as usize]
+	}
+}
+
+#[cfg(test)]
+mod tests {
+	use super::*;
+	use crate::entities::{
+		Ground,
+		TerrainTileManager,
+		TileIndex,
+	};
+	use std::collections::HashMap;
+	use crate::models::{
+		RawModel,
+		TerrainTexture,
+		TextureId,
+	};
+
+	fn terrain_from_mesh(tile_x: i32, tile_z: i32, mesh: TerrainMeshData) -> Terrain {
+		let texture = || TerrainTexture { tex_id: TextureId::Loaded(1) };
+		let texture_pack = TerrainTexturePack { background_texture: texture(), r_texture: texture(), g_texture: texture(), b_texture: texture() };
+		let model = TerrainModel { raw_model: RawModel::default(), height_map: Arc::new(mesh.height_map) };
+		Terrain::new(tile_x, tile_z, texture_pack, texture(), model)
+	}
+
+	fn normal_at_vertex(mesh: &TerrainMeshData, x: usize, z: usize) -> (f32, f32, f32) {
+		let vertex = z * mesh.height_map.len() + x;
+		(mesh.normals[vertex*3], mesh.normals[vertex*3+1], mesh.normals[vertex*3+2])
+	}
+
+	#[test]
+	fn tiles_generated_next_to_loaded_ones_share_their_seam_normals() {
+		// two height map tiles whose shared column has the same heights but which can't see past their own border
+		let left_heights = HeightMap::from_gray_values(3, 3, &[0.1, 0.4, 0.6, 0.2, 0.5, 0.9, 0.3, 0.3, 0.7], 10.0);
+		let right_heights = HeightMap::from_gray_values(3, 3, &[0.6, 0.2, 0.0, 0.9, 0.8, 0.1, 0.7, 0.4, 0.5], 10.0);
+		let right_alone = Terrain::generate_mesh_data(&right_heights);
+
+		// the same way the tiles are streamed, the right tile is generated once the left one is part of the ground
+		let ground = Ground { terrains: vec![terrain_from_mesh(0, 0, Terrain::generate_mesh_data(&left_heights))] };
+		let right = Terrain::generate_mesh_data(&NeighborAwareTile::new(&right_heights, ground.neighbors(1, 0)));
+		let ground = Ground { terrains: vec![terrain_from_mesh(1, 0, Terrain::generate_mesh_data(&right_heights))] };
+		let left = Terrain::generate_mesh_data(&NeighborAwareTile::new(&left_heights, ground.neighbors(0, 0)));
+
+		assert_ne!(normal_at_vertex(&right_alone, 0, 1), normal_at_vertex(&left, 2, 1));
+		for z in 0..3 {
+			assert_eq!(normal_at_vertex(&right, 0, z), normal_at_vertex(&left, 2, z), "z = {}", z);
+			assert_eq!(right.height_map[0][z], left.height_map[2][z]);
+		}
+	}
+
+	#[test]
+	fn streamed_tiles_update_the_seam_normals_of_their_loaded_neighbours() {
+		// 3x3 tiles cut from one height field so neighbouring tiles share their border heights
+		let gray_at = |gx: i32, gz: i32| (gx * gx * 3 + gz * 5 + gx * gz).rem_euclid(11) as f32 / 10.0;
+		let tile_heights = |(tile_x, tile_z): TileIndex| {
+			let gray_values: Vec<f32> = (0..3).flat_map(|z| (0..3).map(move |x| (x, z)))
+				.map(|(x, z)| gray_at(tile_x * 2 + x, tile_z * 2 + z)).collect();
+			HeightMap::from_gray_values(3, 3, &gray_values, 10.0)
+		};
+
+		// the normals each tile's vao would hold, generated against the tiles loaded before it
+		let mut tile_normals: HashMap<TileIndex, Vec<f32>> = HashMap::new();
+		let mut ground = Ground { terrains: Vec::new() };
+		let manager = TerrainTileManager { load_radius: 1, unload_radius: 2 };
+		let update = manager.update(&mut ground, 0.5 * Terrain::SIZE, 0.5 * Terrain::SIZE, |tile, ground| {
+			let heights = tile_heights(tile);
+			let mesh = Terrain::generate_mesh_data(&NeighborAwareTile::new(&heights, ground.neighbors(tile.0, tile.1)));
+			tile_normals.insert(tile, mesh.normals.clone());
+			terrain_from_mesh(tile.0, tile.1, mesh)
+		});
+		assert_eq!(tile_normals.len(), 9);
+
+		let normal_at = |tile_normals: &HashMap<TileIndex, Vec<f32>>, tile: TileIndex, x: usize, z: usize| {
+			let normals = &tile_normals[&tile];
+			let vertex = z * 3 + x;
+			(normals[vertex*3], normals[vertex*3+1], normals[vertex*3+2])
+		};
+		let seams_match = |tile_normals: &HashMap<TileIndex, Vec<f32>>| (-1..=1).all(|tile_x| (-1..=1).all(|tile_z| (0..3).all(|i| {
+			(tile_x == 1 || normal_at(tile_normals, (tile_x, tile_z), 2, i) == normal_at(tile_normals, (tile_x + 1, tile_z), 0, i))
+				&& (tile_z == 1 || normal_at(tile_normals, (tile_x, tile_z), i, 2) == normal_at(tile_normals, (tile_x, tile_z + 1), i, 0))
+		})));
+		// the tiles loaded first didn't know about the ones that came after them
+		assert!(!seams_match(&tile_normals));
+
+		// what ResourceManager::stream_terrain_tiles writes into the vaos of the loaded neighbours
+		for (tile, normals) in update.seam_normals.iter() {
+			let tile_normals = tile_normals.get_mut(tile).unwrap();
+			for (vertex, normal) in normals.iter() {
+				tile_normals[vertex*3..vertex*3+3].copy_from_slice(&[normal.x, normal.y, normal.z]);
+			}
+		}
+		assert!(seams_match(&tile_normals));
+		assert!(update.unloaded.is_empty());
+	}
+
+	#[test]
+	fn collision_mesh_is_grid_in_world_coords() {
+		let vertex_count = 4;
+		let height_map: Vec<Vec<f32>> = (0..vertex_count).map(|x| (0..vertex_count).map(|z| (x * 10 + z) as f32).collect()).collect();
+		let (positions, indices) = Terrain::collision_mesh_from_heights(&height_map, Terrain::SIZE, -Terrain::SIZE);
+
+		assert_eq!(positions.len(), vertex_count * vertex_count);
+		assert_eq!(indices.len(), 6 * (vertex_count - 1) * (vertex_count - 1));
+		assert!(indices.iter().all(|&index| (index as usize) < positions.len()));
+
+		let last = positions.last().unwrap();
+		assert_eq!((last.x, last.y, last.z), (2.0 * Terrain::SIZE, 33.0, 0.0));
+		assert_eq!(positions[1].y, height_map[1][0]);
+
+		// every triangle faces up like the rendered terrain so back face culling keeps it
+		for triangle in indices.chunks(3) {
+			let v0 = &positions[triangle[0] as usize];
+			let edge1 = &positions[triangle[1] as usize] - v0;
+			let edge2 = &positions[triangle[2] as usize] - v0;
+			assert!(edge1.cross_prod(&edge2).y > 0.0);
 		}
 	}
 }
\ No newline at end of file
@@ -0,0 +1,139 @@
+use crate::entities::{
+    Ground,
+    Terrain,
+};
+use crate::math::Vector3f;
+use crate::models::NeighborAwareTile;
+use std::cmp;
+
+pub type TileIndex = (i32, i32);
+
+pub struct TileUpdate {
+    // the dropped tiles so their meshes can be freed with ResourceManager::release_terrain_tile
+    pub unloaded: Vec<Terrain>,
+    // new normals for the edges of already loaded tiles that now have a new neighbour, by vertex index of the tile mesh
+    pub seam_normals: Vec<(TileIndex, Vec<(usize, Vector3f)>)>,
+}
+
+// keeps the terrain tiles of the ground around a position e.g. the player for worlds that are too large to load at once
+// tiles are loaded within load_radius and only dropped once they are further than unload_radius so walking back and forth over a tile border doesn't reload them
+#[derive(Debug, Clone, Copy)]
+pub struct TerrainTileManager {
+    // in tiles, measured along the x and z axis separately (a square of tiles around the center tile)
+    pub load_radius: i32,
+    pub unload_radius: i32,
+}
+
+impl Default for TerrainTileManager {
+    fn default() -> Self {
+        TerrainTileManager {
+            load_radius: 2,
+            unload_radius: 3,
+        }
+    }
+}
+
+impl TerrainTileManager {
+    pub fn tile_at(x: f32, z: f32) -> TileIndex {
+        ((x / Terrain::SIZE).floor() as i32, (z / Terrain::SIZE).floor() as i32)
+    }
+
+    fn tile_distance(a: TileIndex, b: TileIndex) -> i32 {
+        cmp::max((a.0 - b.0).abs(), (a.1 - b.1).abs())
+    }
+
+    // missing tiles within the load radius, closest first
+    pub fn tiles_to_load(&self, loaded: &[TileIndex], center: TileIndex) -> Vec<TileIndex> {
+        let mut to_load = Vec::new();
+        for x in center.0 - self.load_radius..=center.0 + self.load_radius {
+            for z in center.1 - self.load_radius..=center.1 + self.load_radius {
+                if !loaded.contains(&(x, z)) {
+                    to_load.push((x, z));
+                }
+            }
+        }
+        to_load.sort_by_key(|tile| TerrainTileManager::tile_distance(*tile, center));
+        to_load
+    }
+
+    pub fn is_too_far(&self, tile: TileIndex, center: TileIndex) -> bool {
+        TerrainTileManager::tile_distance(tile, center) > self.unload_radius
+    }
+
+    // create_tile builds the terrain for a tile index e.g. with ResourceManager::terrain_tile, it gets the ground
+    // with the tiles loaded so far for the neighbours (Ground::neighbors)
+    // a tile only sees the neighbours that were there before it so once all new tiles are in
+    // the edges of their neighbours are recomputed against them (see ResourceManager::stream_terrain_tiles)
+    pub fn update(&self, ground: &mut Ground, x: f32, z: f32, mut create_tile: impl FnMut(TileIndex, &Ground) -> Terrain) -> TileUpdate {
+        let center = TerrainTileManager::tile_at(x, z);
+        let (unloaded, kept) = ground.terrains.drain(..).partition(|terrain| self.is_too_far(terrain.tile_index(), center));
+        ground.terrains = kept;
+        let loaded: Vec<TileIndex> = ground.terrains.iter().map(|terrain| terrain.tile_index()).collect();
+        let new_tiles = self.tiles_to_load(&loaded, center);
+        for &tile in new_tiles.iter() {
+            let terrain = create_tile(tile, ground);
+            ground.terrains.push(terrain);
+        }
+        let seam_normals = new_tiles.iter().flat_map(|&tile| TerrainTileManager::neighbor_seam_normals(ground, tile)).collect();
+        TileUpdate { unloaded, seam_normals }
+    }
+
+    // the edges the loaded tiles around the tile share with it
+    fn neighbor_seam_normals(ground: &Ground, (tile_x, tile_z): TileIndex) -> Vec<(TileIndex, Vec<(usize, Vector3f)>)> {
+        [(-1, 0), (1, 0), (0, -1), (0, 1)].iter().filter_map(|&(dx, dz)| {
+            let neighbor_index = (tile_x + dx, tile_z + dz);
+            let neighbor = ground.terrains.iter().find(|terrain| terrain.tile_index() == neighbor_index)?;
+            let neighbor_tile = NeighborAwareTile::new(neighbor, ground.neighbors(neighbor_index.0, neighbor_index.1));
+            Some((neighbor_index, Terrain::edge_normals(&neighbor_tile, (-dx, -dz))))
+        }).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::models::{
+        RawModel,
+        TerrainModel,
+        TerrainTexture,
+        TerrainTexturePack,
+        TextureId,
+    };
+
+    fn create_tile((tile_x, tile_z): TileIndex) -> Terrain {
+        let texture = || TerrainTexture { tex_id: TextureId::Loaded(1) };
+        let texture_pack = TerrainTexturePack { background_texture: texture(), r_texture: texture(), g_texture: texture(), b_texture: texture() };
+        let model = TerrainModel { raw_model: RawModel::default(), height_map: Arc::new(Vec::new()) };
+        Terrain::new(tile_x, tile_z, texture_pack, texture(), model)
+    }
+
+    #[test]
+    fn tiles_load_around_center_and_unload_with_hysteresis() {
+        let manager = TerrainTileManager { load_radius: 1, unload_radius: 2 };
+        let center = TerrainTileManager::tile_at(-10.0, 1.5 * Terrain::SIZE);
+        assert_eq!(center, (-1, 1));
+
+        let to_load = manager.tiles_to_load(&[(-1, 1), (0, 0)], center);
+        assert_eq!(to_load.len(), 7);
+        assert!(!to_load.contains(&(0, 0)));
+        assert!(to_load.iter().all(|tile| !manager.is_too_far(*tile, center)));
+
+        // one tile past the load radius is kept, two are dropped
+        assert!(!manager.is_too_far((1, 1), center));
+        assert!(manager.is_too_far((2, 1), center));
+    }
+
+    #[test]
+    fn update_hands_back_the_dropped_tiles() {
+        let manager = TerrainTileManager { load_radius: 0, unload_radius: 1 };
+        let mut ground = Ground { terrains: vec![create_tile((0, 0)), create_tile((1, 0)), create_tile((3, 0))] };
+
+        let unloaded = manager.update(&mut ground, 0.5 * Terrain::SIZE, 0.5 * Terrain::SIZE, |tile, _| create_tile(tile)).unloaded;
+
+        let unloaded: Vec<TileIndex> = unloaded.iter().map(|terrain| terrain.tile_index()).collect();
+        let loaded: Vec<TileIndex> = ground.terrains.iter().map(|terrain| terrain.tile_index()).collect();
+        assert_eq!(unloaded, vec![(3, 0)]);
+        assert_eq!(loaded, vec![(0, 0), (1, 0)]);
+    }
+}
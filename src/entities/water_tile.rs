@@ -1,15 +1,48 @@
 use crate::math::{
     Matrix4f,
     Vector3f,
+    Vector4f,
 };
 use crate::models::{
     WaterModel,
 };
 
+#[derive(Debug, Clone, Copy)]
+pub struct WaterClipParams {
+    // the clip planes of the reflection and refraction passes overlap the water surface by this much to prevent glitches near the edge of the water
+    pub clip_plane_overlap: f32,
+    // moves the plane the reflection camera is mirrored at up (positive) or down relative to the water surface
+    pub reflection_camera_offset: f32,
+}
+
+impl Default for WaterClipParams {
+    fn default() -> Self {
+        WaterClipParams {
+            clip_plane_overlap: 0.07,
+            reflection_camera_offset: 0.0,
+        }
+    }
+}
+
+impl WaterClipParams {
+    // (above water, below water) clip planes named after what they clip away
+    // the reflection pass uses the below water plane and the refraction pass the above water plane
+    pub fn clip_planes(&self, water_height: f32) -> (Vector4f, Vector4f) {
+        let above_water_clip_plane = Vector4f::new(0.0, -1.0, 0.0, water_height + self.clip_plane_overlap);
+        let below_water_clip_plane = Vector4f::new(0.0, 1.0, 0.0, -water_height + self.clip_plane_overlap);
+        (above_water_clip_plane, below_water_clip_plane)
+    }
+
+    pub fn reflection_plane_height(&self, water_height: f32) -> f32 {
+        water_height + self.reflection_camera_offset
+    }
+}
+
 pub struct WaterTile {
     pub position: Vector3f,
     pub transform: Matrix4f,
     pub model: WaterModel,
+    pub clip_params: WaterClipParams,
 }
 
 impl WaterTile {
@@ -21,10 +54,89 @@ impl WaterTile {
             position,
             transform,
             model,
+            clip_params: WaterClipParams::default(),
+        }
+    }
+
+    pub fn with_clip_params(mut self, clip_params: WaterClipParams) -> Self {
+        self.clip_params = clip_params;
+        self
+    }
+
+    pub fn water_height(&self) -> f32 {
+        self.position.y
+    }
+
+    // tiles at the same height share one reflection and refraction pass, groups are in the order their first tile appears
+    pub fn group_by_height(water_tiles: &[WaterTile]) -> Vec<WaterHeightGroup<'_>> {
+        let mut groups: Vec<WaterHeightGroup> = Vec::new();
+        for water_tile in water_tiles {
+            let height = water_tile.water_height();
+            match groups.iter_mut().find(|group| (group.height - height).abs() <= WaterHeightGroup::HEIGHT_TOLERANCE) {
+                Some(group) => group.tiles.push(water_tile),
+                None => groups.push(WaterHeightGroup { height, clip_params: water_tile.clip_params, tiles: vec![water_tile] }),
+            }
         }
+        groups
     }
+}
+
+pub struct WaterHeightGroup<'a> {
+    pub height: f32,
+    // the passes are shared so the first tile of the group decides
+    pub clip_params: WaterClipParams,
+    pub tiles: Vec<&'a WaterTile>,
+}
+
+impl<'a> WaterHeightGroup<'a> {
+    const HEIGHT_TOLERANCE: f32 = 0.001;
 
-    pub fn get_water_height(water_tiles: &Vec<WaterTile>) -> f32 {
-        water_tiles[0].position.y
+    pub fn clip_planes(&self) -> (Vector4f, Vector4f) {
+        self.clip_params.clip_planes(self.height)
     }
-}
\ No newline at end of file
+
+    pub fn reflection_plane_height(&self) -> f32 {
+        self.clip_params.reflection_plane_height(self.height)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clip_planes_use_configured_overlap() {
+        let params = WaterClipParams { clip_plane_overlap: 0.5, reflection_camera_offset: 0.0 };
+        let water_height = 2.0;
+        let (above, below) = params.clip_planes(water_height);
+        // a point is kept when dot(plane, (p, 1)) >= 0
+        let keeps = |plane: &Vector4f, y: f32| plane.y * y + plane.w >= 0.0;
+
+        assert_eq!((above.y, above.w), (-1.0, 2.5));
+        assert_eq!((below.y, below.w), (1.0, -1.5));
+        assert!(keeps(&above, 2.4) && !keeps(&above, 2.6));
+        assert!(keeps(&below, 1.6) && !keeps(&below, 1.4));
+        // both passes draw a band of twice the overlap around the surface
+        assert!(keeps(&above, water_height) && keeps(&below, water_height));
+    }
+
+    #[test]
+    fn tiles_at_two_heights_get_two_passes() {
+        let tile = |x: f32, y: f32| WaterTile::new(Vector3f::new(x, y, 0.0), WaterModel { raw_model: Default::default(), dudv_tex_id: Default::default(), normal_map_tex_id: Default::default(), props: Default::default() });
+        let water_tiles = vec![tile(0.0, -0.2), tile(400.0, 12.0), tile(200.0, -0.2)];
+        let groups = WaterTile::group_by_height(&water_tiles);
+
+        assert_eq!(groups.len(), 2);
+        let positions = |group: &WaterHeightGroup| group.tiles.iter().map(|tile| tile.position.x).collect::<Vec<f32>>();
+        assert_eq!((groups[0].height, positions(&groups[0])), (-0.2, vec![0.0, 200.0]));
+        assert_eq!((groups[1].height, positions(&groups[1])), (12.0, vec![400.0]));
+
+        let overlap = WaterClipParams::default().clip_plane_overlap;
+        let (lake_above, lake_below) = groups[0].clip_planes();
+        let (pond_above, pond_below) = groups[1].clip_planes();
+        assert_eq!((lake_above.y, lake_above.w), (-1.0, -0.2 + overlap));
+        assert_eq!((lake_below.y, lake_below.w), (1.0, 0.2 + overlap));
+        assert_eq!((pond_above.y, pond_above.w), (-1.0, 12.0 + overlap));
+        assert_eq!((pond_below.y, pond_below.w), (1.0, -12.0 + overlap));
+    }
+}
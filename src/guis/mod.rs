@@ -2,5 +2,7 @@ pub mod panel;
 pub mod text;
 
 pub use self::panel::GuiPanel;
+pub use self::panel::GuiAnchor;
+pub use self::panel::PixelLayout;
 pub use self::text::GuiText;
 pub use self::text::TextMaterial;
\ No newline at end of file
@@ -1,13 +1,84 @@
+use crate::display::WindowSize;
+use crate::display::framebuffers::FboTexture;
 use crate::math::{
+    Color,
     Vector2f,
 };
 use crate::models::TextureId;
 
+// the point of the window a pixel layout is measured from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuiAnchor {
+    TopLeft,
+    TopRight,
+    BottomLeft,
+    BottomRight,
+    Center,
+}
+
+impl Default for GuiAnchor {
+    fn default() -> Self {
+        GuiAnchor::Center
+    }
+}
+
+impl GuiAnchor {
+    // position of the anchor in normalized device coords and the direction pointing into the window from it
+    fn ndc_point_and_inward_dir(&self) -> ((f32, f32), (f32, f32)) {
+        match self {
+            GuiAnchor::TopLeft => ((-1.0, 1.0), (1.0, -1.0)),
+            GuiAnchor::TopRight => ((1.0, 1.0), (-1.0, -1.0)),
+            GuiAnchor::BottomLeft => ((-1.0, -1.0), (1.0, 1.0)),
+            GuiAnchor::BottomRight => ((1.0, -1.0), (-1.0, 1.0)),
+            GuiAnchor::Center => ((0.0, 0.0), (1.0, 1.0)),
+        }
+    }
+}
+
+// places a panel in pixels so that it keeps its size on screen regardless of the resolution
+#[derive(Debug, Default, Clone)]
+pub struct PixelLayout {
+    pub anchor: GuiAnchor,
+    // distance in pixels from the anchor to the nearest corner of the panel measured into the window
+    // for the center anchor it is the offset of the panel center to the right and up
+    pub offset: Vector2f,
+    // width and height of the panel in pixels
+    pub size: Vector2f,
+}
+
+impl PixelLayout {
+    // returns the position of the panel center and its scale in normalized device coords like GuiPanel::position and GuiPanel::scale
+    pub fn to_ndc(&self, window_size: &WindowSize) -> (Vector2f, Vector2f) {
+        let pixel_to_ndc_x = 2.0 / window_size.width_f32;
+        let pixel_to_ndc_y = 2.0 / window_size.height_f32;
+        // the gui quad spans [-1, 1] so the scale is half of the size
+        let scale = Vector2f::new(self.size.x / window_size.width_f32, self.size.y / window_size.height_f32);
+        let ((anchor_x, anchor_y), (dir_x, dir_y)) = self.anchor.ndc_point_and_inward_dir();
+        let position = if self.anchor == GuiAnchor::Center {
+            Vector2f::new(self.offset.x * pixel_to_ndc_x, self.offset.y * pixel_to_ndc_y)
+        } else {
+            Vector2f::new(
+                anchor_x + dir_x * (self.offset.x * pixel_to_ndc_x + scale.x),
+                anchor_y + dir_y * (self.offset.y * pixel_to_ndc_y + scale.y),
+            )
+        };
+        (position, scale)
+    }
+}
+
 pub struct GuiPanel {
     pub texture_id: TextureId,
     pub position: Vector2f,
     // scale relative to screen width/height
     pub scale: Vector2f,
+    // panels and texts with lower layers are drawn first, within a layer insertion order is kept
+    pub layer: i32,
+    // when set the position and scale are recalculated from it every frame
+    pub pixel_layout: Option<PixelLayout>,
+    // multiplied with the texture color, lets one white texture be drawn as differently colored or faded panels
+    pub tint: Color,
+    // the fbo and its texture the panel shows, looked up again each frame since the fbo can be recreated
+    pub fbo_texture: Option<(&'static str, FboTexture)>,
 }
 
 impl GuiPanel {
@@ -16,6 +87,72 @@ impl GuiPanel {
             texture_id,
             position,
             scale,
+            layer: 0,
+            pixel_layout: None,
+            tint: Color::WHITE,
+            fbo_texture: None,
+        }
+    }
+
+    // shows a texture of one of the fbos, the texture id is set by FboMap::resolve_gui_panels
+    pub fn for_fbo_texture(fbo_name: &'static str, texture: FboTexture, position: Vector2f, scale: Vector2f) -> GuiPanel {
+        GuiPanel {
+            fbo_texture: Some((fbo_name, texture)),
+            ..GuiPanel::new(TextureId::Empty, position, scale)
+        }
+    }
+
+    pub fn with_pixel_layout(texture_id: TextureId, pixel_layout: PixelLayout) -> GuiPanel {
+        GuiPanel {
+            texture_id,
+            position: Vector2f::zero(),
+            scale: Vector2f::zero(),
+            layer: 0,
+            pixel_layout: Some(pixel_layout),
+            tint: Color::WHITE,
+            fbo_texture: None,
+        }
+    }
+
+    // position and scale in normalized device coords for the current window size
+    pub fn ndc_transform(&self, window_size: &WindowSize) -> (Vector2f, Vector2f) {
+        match &self.pixel_layout {
+            Some(pixel_layout) => pixel_layout.to_ndc(window_size),
+            None => (self.position.clone(), self.scale.clone()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn window_size(width: usize, height: usize) -> WindowSize {
+        WindowSize { width, height, width_f32: width as f32, height_f32: height as f32 }
+    }
+
+    fn assert_close(actual: &Vector2f, expected: (f32, f32)) {
+        assert!((actual.x - expected.0).abs() < 1e-5 && (actual.y - expected.1).abs() < 1e-5, "Got {:?}, expected {:?}", actual, expected);
+    }
+
+    #[test]
+    fn pixel_layout_converts_to_ndc_from_top_left() {
+        let layout = PixelLayout { anchor: GuiAnchor::TopLeft, offset: Vector2f::new(10.0, 20.0), size: Vector2f::new(100.0, 50.0) };
+        let (position, scale) = layout.to_ndc(&window_size(800, 600));
+        assert_close(&scale, (100.0 / 800.0, 50.0 / 600.0));
+        // panel center is at pixel (60, 45) from the top left corner
+        assert_close(&position, (60.0 / 400.0 - 1.0, 1.0 - 45.0 / 300.0));
+    }
+
+    #[test]
+    fn pixel_layout_keeps_pixel_size_and_anchor_on_resize() {
+        let layout = PixelLayout { anchor: GuiAnchor::BottomRight, offset: Vector2f::zero(), size: Vector2f::new(200.0, 200.0) };
+        for &(width, height) in &[(800, 600), (1920, 1080)] {
+            let (position, scale) = layout.to_ndc(&window_size(width, height));
+            // 2 ndc units per window width -> scale is half the size in pixels
+            assert_close(&Vector2f::new(scale.x * width as f32, scale.y * height as f32), (200.0, 200.0));
+            // right and bottom edges touch the window corner
+            assert_close(&Vector2f::new(position.x + scale.x, position.y - scale.y), (1.0, -1.0));
         }
     }
 }
\ No newline at end of file
@@ -8,8 +8,12 @@ use std::io::{
 use std::rc::Rc;
 
 use crate::math::{
+    Color,
     Vector2f,
-    Vector3f,
+    utils::{
+        f32_min,
+        f32_max,
+    },
 };
 use crate::models::{
     RawModel,
@@ -28,6 +32,8 @@ struct MetaFileCharDesc {
 struct MetaFile {
     atlas_size: (u32, u32),
     line_height: i32,
+    // padding (up, right, down, left) in atlas pixels. for distance field fonts this is the spread of the field
+    padding: [i32; 4],
     char_map: HashMap<char, MetaFileCharDesc>,
 } 
 
@@ -43,8 +49,9 @@ impl MetaFile {
         let buf_reader = BufReader::new(fnt_file);
 
         let mut char_map = HashMap::new();
-        let line_iter = buf_reader.lines();
-        let mut line_iter = line_iter.skip(1);
+        let mut line_iter = buf_reader.lines();
+        let font_info = line_iter.next().expect(".fnt file must have font info on line 1").expect("unable to read first line from reader");
+        let padding = MetaFile::parse_padding(&font_info);
         let line_info = line_iter.next().expect(".fnt file must have line info on line 2").expect("unable to read second line from reader");
         let line_info_tokens = line_info.split_whitespace().collect::<Vec<_>>();
         
@@ -89,10 +96,26 @@ impl MetaFile {
         Ok(MetaFile {
             char_map,
             line_height,
+            padding,
             atlas_size: (atlas_size_w, atlas_size_h),
         })
     }
 
+    fn parse_padding(font_info: &str) -> [i32; 4] {
+        let mut padding = [0; 4];
+        if let Some(padding_tkn) = font_info.split_whitespace().find(|tkn| tkn.starts_with("padding=")) {
+            let padding_str: String = MetaFile::get_num_from_tkn(padding_tkn);
+            for (i, val) in padding_str.split(",").take(4).enumerate() {
+                padding[i] = val.parse().expect("Padding must be a comma separated list of numbers");
+            }
+        }
+        padding
+    }
+
+    fn sdf_spread(&self) -> f32 {
+        self.padding.iter().sum::<i32>() as f32 / 4.0
+    }
+
     fn get_num_from_tkn<T>(str_token: &str) -> T 
         where T: std::str::FromStr,
               <T as std::str::FromStr>::Err: std::fmt::Debug
@@ -146,6 +169,11 @@ impl FontType {
             texture_atlas: texture_atlas_id,
         }
     }
+
+    // distance field spread in atlas pixels
+    pub fn sdf_spread(&self) -> f32 {
+        self.meta_file.sdf_spread()
+    }
 }
 
 pub struct GuiText {
@@ -153,43 +181,86 @@ pub struct GuiText {
     pub text_model: RawModel,
     pub position: Vector2f,
     pub material: TextMaterial,
+    pub layer: i32,
+    // kept so the mesh can be rebuilt for a new size (ResourceManager::resize_gui_text)
+    pub text: String,
+    pub font_size: usize,
 }
 
 impl GuiText {
-    pub fn new(font_type: FontType, text_model: RawModel, position: Vector2f, material: TextMaterial) -> GuiText {
+    // the glyph width and edge of the material are picked for the font size (TextMaterial::with_size)
+    pub fn new(font_type: FontType, text: &str, font_size: usize, text_model: RawModel, position: Vector2f, material: TextMaterial) -> GuiText {
+        let material = material.with_size(&font_type, font_size);
         GuiText {
             font_type,
             text_model,
             position,
-            material,        
+            material,
+            layer: 0,
+            text: text.to_string(),
+            font_size,
         }
     }
+
+    // the text model has to be the mesh of the text at the new size
+    pub fn resize(&mut self, font_size: usize, text_model: RawModel) {
+        self.material = std::mem::take(&mut self.material).with_size(&self.font_type, font_size);
+        self.font_size = font_size;
+        self.text_model = text_model;
+    }
 }
 
 pub struct TextMaterial {
-    pub color: Vector3f,
+    pub color: Color,
     pub width: f32,
     pub edge: f32,
     pub outline_width: f32,
     pub outline_edge: f32,
-    pub outline_color: Vector3f,
+    pub outline_color: Color,
     pub offset: Vector2f,
 }
 
 impl Default for TextMaterial {
     fn default() -> Self {
         TextMaterial {
-            color: Vector3f::new(1.0, 1.0, 1.0),
+            color: Color::WHITE,
             width: 0.5,
             edge: 0.1,
             outline_width: 0.5,
             outline_edge: 0.4,
-            outline_color: Vector3f::new(0.0, 1.0, 0.0),
+            outline_color: Color::rgb(0.0, 1.0, 0.0),
             offset: Vector2f::new(0.0, 0.0),
         }
     }
 }
 
+impl TextMaterial {
+    const MIN_EDGE: f32 = 0.02;
+    const MAX_EDGE: f32 = 0.5;
+
+    pub fn for_size(font: &FontType, font_size: usize) -> TextMaterial {
+        TextMaterial::default().with_size(font, font_size)
+    }
+
+    /**
+     * Picks width/edge so that the glyph boundary stays at distance 0.5 and the smoothstep covers roughly one screen pixel.
+     * Smaller fonts cover fewer pixels per atlas texel so they need a wider edge, otherwise thin strokes alias or disappear.
+     * The color, outline and offset are kept, the outline edge is only shortened so it stays within the distance field.
+     */
+    pub fn with_size(self, font: &FontType, font_size: usize) -> TextMaterial {
+        let font_scale = font_size as f32 / MetaFile::DEFAULT_FONT_SIZE;
+        let spread = f32_max(font.sdf_spread(), 1.0);
+        let edge = f32_min(f32_max(1.0 / (font_scale * spread), TextMaterial::MIN_EDGE), TextMaterial::MAX_EDGE);
+        let width = 0.5 - edge / 2.0;
+        TextMaterial {
+            width,
+            edge,
+            outline_edge: f32_min(self.outline_edge, 1.0 - self.outline_width),
+            ..self
+        }
+    }
+}
+
 pub mod text_mesh_creator {
     use super::*;
     
@@ -275,4 +346,50 @@ pub mod text_mesh_creator {
             char_count: char_cnt,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sdf_spread_from_fnt_file() {
+        let font = FontType::new("res/fonts/copperDf.fnt", TextureId::Empty);
+        assert_eq!(font.sdf_spread(), 8.0);
+    }
+
+    #[test]
+    fn derived_edges_within_sdf_range() {
+        let font = FontType::new("res/fonts/copperDf.fnt", TextureId::Empty);
+        for font_size in 1..=100 {
+            let material = TextMaterial::for_size(&font, font_size);
+            for &(width, edge) in [(material.width, material.edge), (material.outline_width, material.outline_edge)].iter() {
+                assert!(width >= 0.0 && edge > 0.0, "Invalid width {} or edge {} for size {}", width, edge, font_size);
+                assert!(width + edge <= 1.0, "Width {} + edge {} out of range for size {}", width, edge, font_size);
+            }
+        }
+    }
+
+    #[test]
+    fn gui_text_material_follows_the_font_size_and_keeps_the_outline() {
+        let font = FontType::new("res/fonts/copperDf.fnt", TextureId::Empty);
+        let material = TextMaterial {
+            outline_width: 0.6,
+            outline_edge: 0.3,
+            outline_color: Color::BLACK,
+            ..TextMaterial::default()
+        };
+        let mut text = GuiText::new(font, "hello", 1, RawModel::default(), Vector2f::zero(), material);
+        let small_edge = text.material.edge;
+        assert!((text.material.width + small_edge / 2.0 - 0.5).abs() < 1e-5);
+
+        text.resize(8, RawModel::new(7, 6));
+        assert_eq!((text.font_size, text.text_model.vao_id), (8, 7));
+        // bigger text covers more pixels per atlas texel so it gets a sharper edge
+        assert!(text.material.edge < small_edge);
+        assert!((text.material.width + text.material.edge / 2.0 - 0.5).abs() < 1e-5);
+        // the outline reaches past the glyph as it was set up
+        assert_eq!((text.material.outline_width, text.material.outline_edge), (0.6, 0.3));
+        assert!(text.material.outline_width > text.material.width);
+        assert_eq!(text.material.outline_color, Color::BLACK);
+    }
+}
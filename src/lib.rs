@@ -8,6 +8,8 @@ extern crate bitflags;
 // with macro use the order of mod definition matters
 #[macro_use]
 pub mod utils;
+#[macro_use]
+pub mod logging;
 
 pub mod constants;
 pub mod display;
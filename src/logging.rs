@@ -0,0 +1,112 @@
+use std::sync::RwLock;
+use crate::gl;
+
+// ordered from the most to the least important, messages less important than the max level are dropped
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Error,
+    Warn,
+    Info,
+    Debug,
+}
+
+impl Default for LogLevel {
+    fn default() -> Self {
+        LogLevel::Info
+    }
+}
+
+// can be called from the texture loading threads so it has to be thread safe
+pub type Logger = Box<dyn Fn(LogLevel, &str) + Send + Sync>;
+
+struct LogSink {
+    // None prints errors and warnings to stderr and everything else to stdout
+    logger: Option<Logger>,
+    max_level: LogLevel,
+}
+
+static SINK: RwLock<LogSink> = RwLock::new(LogSink {
+    logger: None,
+    max_level: LogLevel::Info,
+});
+
+// None restores the default printing
+pub fn set_logger(logger: Option<Logger>) {
+    // a logger that panicked doesn't leave anything half written behind so the poisoned lock can be reused
+    let mut sink = SINK.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    sink.logger = logger;
+}
+
+pub fn set_max_level(max_level: LogLevel) {
+    let mut sink = SINK.write().unwrap_or_else(|poisoned| poisoned.into_inner());
+    sink.max_level = max_level;
+}
+
+pub fn log(level: LogLevel, message: &str) {
+    let sink = SINK.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    if level > sink.max_level {
+        return;
+    }
+    match &sink.logger {
+        Some(logger) => logger(level, message),
+        None if level <= LogLevel::Warn => eprintln!("[{:?}] {}", level, message),
+        None => println!("[{:?}] {}", level, message),
+    }
+}
+
+// the gl and texture_lib crates can't use the macros below, they get handlers that forward into the sink instead
+pub fn connect_library_logging() {
+    gl::helper::set_debug_message_handler(Some(log_gl_debug_message));
+    gl::helper::set_error_handler(Some(|error| log(LogLevel::Error, &format!("GL error: {}", error))));
+    texture_lib::texture_loader::set_load_info_handler(Some(|message| log(LogLevel::Debug, message)));
+}
+
+fn gl_debug_message_level(severity: gl::types::GLenum, gltype: gl::types::GLenum) -> LogLevel {
+    if gltype == gl::DEBUG_TYPE_ERROR {
+        return LogLevel::Error;
+    }
+    match severity {
+        gl::DEBUG_SEVERITY_HIGH => LogLevel::Error,
+        gl::DEBUG_SEVERITY_MEDIUM => LogLevel::Warn,
+        gl::DEBUG_SEVERITY_LOW => LogLevel::Info,
+        _ => LogLevel::Debug,
+    }
+}
+
+fn log_gl_debug_message(severity: gl::types::GLenum, gltype: gl::types::GLenum, message: &str) {
+    log(gl_debug_message_level(severity, gltype), &format!("GL debug message type: 0x{:x}, severity: 0x{:x}, message: {}", gltype, severity, message));
+}
+
+#[macro_export]
+macro_rules! log_error {
+    ($($arg:tt)*) => ($crate::logging::log($crate::logging::LogLevel::Error, &format!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    ($($arg:tt)*) => ($crate::logging::log($crate::logging::LogLevel::Warn, &format!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! log_info {
+    ($($arg:tt)*) => ($crate::logging::log($crate::logging::LogLevel::Info, &format!($($arg)*)));
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    ($($arg:tt)*) => ($crate::logging::log($crate::logging::LogLevel::Debug, &format!($($arg)*)));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn gl_debug_messages_are_logged_by_severity() {
+        assert_eq!(gl_debug_message_level(gl::DEBUG_SEVERITY_HIGH, gl::DEBUG_TYPE_PERFORMANCE), LogLevel::Error);
+        assert_eq!(gl_debug_message_level(gl::DEBUG_SEVERITY_MEDIUM, gl::DEBUG_TYPE_PERFORMANCE), LogLevel::Warn);
+        assert_eq!(gl_debug_message_level(gl::DEBUG_SEVERITY_LOW, gl::DEBUG_TYPE_PERFORMANCE), LogLevel::Info);
+        // errors reported by the driver stay errors whatever severity it gives them
+        assert_eq!(gl_debug_message_level(gl::DEBUG_SEVERITY_LOW, gl::DEBUG_TYPE_ERROR), LogLevel::Error);
+    }
+}
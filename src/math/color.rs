@@ -0,0 +1,104 @@
+// colors are kept apart from Vector3f/Vector4f so that positions and colors don't get mixed up
+// the values are not tied to a color space, use to_linear/to_srgb when converting between the two
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct Color {
+    pub r: f32,
+    pub g: f32,
+    pub b: f32,
+    pub a: f32,
+}
+
+impl Color {
+    pub const WHITE: Color = Color::rgb(1.0, 1.0, 1.0);
+    pub const BLACK: Color = Color::rgb(0.0, 0.0, 0.0);
+
+    pub const fn rgb(r: f32, g: f32, b: f32) -> Color {
+        Color { r, g, b, a: 1.0 }
+    }
+
+    pub const fn rgba(r: f32, g: f32, b: f32, a: f32) -> Color {
+        Color { r, g, b, a }
+    }
+
+    // accepts "#RRGGBB" or "#RRGGBBAA", the leading # is optional
+    pub fn from_hex(hex: &str) -> Option<Color> {
+        let hex = hex.trim_start_matches('#');
+        if !(hex.len() == 6 || hex.len() == 8) || !hex.is_ascii() {
+            return None;
+        }
+        let channel = |i: usize| u8::from_str_radix(&hex[2*i..2*i + 2], 16).ok().map(|value| value as f32 / 255.0);
+        let alpha = if hex.len() == 8 { channel(3)? } else { 1.0 };
+        Some(Color::rgba(channel(0)?, channel(1)?, channel(2)?, alpha))
+    }
+
+    // alpha is never gamma encoded so it is left as is
+    pub fn to_linear(&self) -> Color {
+        Color::rgba(Self::srgb_to_linear(self.r), Self::srgb_to_linear(self.g), Self::srgb_to_linear(self.b), self.a)
+    }
+
+    pub fn to_srgb(&self) -> Color {
+        Color::rgba(Self::linear_to_srgb(self.r), Self::linear_to_srgb(self.g), Self::linear_to_srgb(self.b), self.a)
+    }
+
+    pub fn lerp(&self, other: &Color, t: f32) -> Color {
+        Color::rgba(
+            self.r + (other.r - self.r) * t,
+            self.g + (other.g - self.g) * t,
+            self.b + (other.b - self.b) * t,
+            self.a + (other.a - self.a) * t,
+        )
+    }
+
+    fn srgb_to_linear(c: f32) -> f32 {
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    }
+
+    fn linear_to_srgb(c: f32) -> f32 {
+        if c <= 0.0031308 {
+            c * 12.92
+        } else {
+            1.055 * c.powf(1.0 / 2.4) - 0.055
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_color_eq(a: &Color, b: &Color) {
+        let eps = 1e-5;
+        assert!((a.r - b.r).abs() < eps && (a.g - b.g).abs() < eps && (a.b - b.b).abs() < eps && (a.a - b.a).abs() < eps, "{:?} != {:?}", a, b);
+    }
+
+    #[test]
+    fn parses_hex_colors() {
+        assert_color_eq(&Color::from_hex("#FF8000").unwrap(), &Color::rgb(1.0, 128.0 / 255.0, 0.0));
+        assert_color_eq(&Color::from_hex("00ff0080").unwrap(), &Color::rgba(0.0, 1.0, 0.0, 128.0 / 255.0));
+        assert_eq!(Color::from_hex("#FF80"), None);
+        assert_eq!(Color::from_hex("#GG8000"), None);
+    }
+
+    #[test]
+    fn srgb_linear_round_trip() {
+        for &value in [0.0, 0.002, 0.04, 0.2, 0.5, 0.8, 1.0].iter() {
+            let color = Color::rgba(value, 1.0 - value, value * 0.5, 0.3);
+            assert_color_eq(&color.to_linear().to_srgb(), &color);
+        }
+        // mid grey in srgb is a lot darker in linear space
+        assert!((Color::rgb(0.5, 0.5, 0.5).to_linear().r - 0.214).abs() < 1e-3);
+    }
+
+    #[test]
+    fn lerps_all_channels() {
+        let from = Color::rgba(0.0, 0.2, 1.0, 0.0);
+        let to = Color::rgba(1.0, 0.4, 0.0, 1.0);
+        assert_color_eq(&from.lerp(&to, 0.0), &from);
+        assert_color_eq(&from.lerp(&to, 1.0), &to);
+        assert_color_eq(&from.lerp(&to, 0.25), &Color::rgba(0.25, 0.25, 0.75, 0.25));
+    }
+}
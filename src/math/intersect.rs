@@ -0,0 +1,141 @@
+use crate::math::Vector3f;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TriangleCull {
+    // hits from both sides count
+    None,
+    // only hits on the counter clockwise (front) side count
+    BackFaces,
+}
+
+const EPSILON: f32 = 1e-6;
+
+// Möller–Trumbore ray triangle intersection that hits both sides of the triangle
+// returns the distance t along dir and the barycentric coords (u, v) of the hit so that hit = (1 - u - v) * v0 + u * v1 + v * v2
+// if dir is normalized then t is in world units
+pub fn ray_triangle(origin: &Vector3f, dir: &Vector3f, v0: &Vector3f, v1: &Vector3f, v2: &Vector3f) -> Option<(f32, f32, f32)> {
+    ray_triangle_with_culling(origin, dir, v0, v1, v2, TriangleCull::None)
+}
+
+// rays parallel to the triangle and degenerate (zero area) triangles never hit, neither do triangles behind the origin
+pub fn ray_triangle_with_culling(origin: &Vector3f, dir: &Vector3f, v0: &Vector3f, v1: &Vector3f, v2: &Vector3f, cull: TriangleCull) -> Option<(f32, f32, f32)> {
+    let edge1 = v1 - v0;
+    let edge2 = v2 - v0;
+    let p = dir.cross_prod(&edge2);
+    // the determinant is the (scaled) cosine between the ray and the triangle normal, 0 means parallel or degenerate
+    let det = edge1.dot_product(&p);
+    match cull {
+        TriangleCull::BackFaces if det < EPSILON => return None,
+        _ if det.abs() < EPSILON => return None,
+        _ => (),
+    }
+    let inv_det = 1.0 / det;
+
+    let to_origin = origin - v0;
+    let u = to_origin.dot_product(&p) * inv_det;
+    if u < 0.0 || u > 1.0 {
+        return None;
+    }
+    let q = to_origin.cross_prod(&edge1);
+    let v = dir.dot_product(&q) * inv_det;
+    if v < 0.0 || u + v > 1.0 {
+        return None;
+    }
+    let t = edge2.dot_product(&q) * inv_det;
+    if t < EPSILON {
+        return None;
+    }
+    Some((t, u, v))
+}
+
+// slab test of the ray against the axis aligned box, returns the distances along dir where the ray enters and leaves it
+// the entry is negative if the origin is inside the box, boxes completely behind the origin are missed
+pub fn ray_aabb(origin: &Vector3f, dir: &Vector3f, min: &Vector3f, max: &Vector3f) -> Option<(f32, f32)> {
+    let mut t_enter = std::f32::NEG_INFINITY;
+    let mut t_exit = std::f32::INFINITY;
+    for &(o, d, lo, hi) in [(origin.x, dir.x, min.x, max.x), (origin.y, dir.y, min.y, max.y), (origin.z, dir.z, min.z, max.z)].iter() {
+        if d.abs() < EPSILON {
+            // parallel to the slab so it has to start between its planes
+            if o < lo || o > hi {
+                return None;
+            }
+            continue;
+        }
+        let (t0, t1) = ((lo - o) / d, (hi - o) / d);
+        t_enter = t_enter.max(t0.min(t1));
+        t_exit = t_exit.min(t0.max(t1));
+    }
+    if t_enter > t_exit || t_exit < 0.0 {
+        return None;
+    }
+    Some((t_enter, t_exit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_triangle() -> (Vector3f, Vector3f, Vector3f) {
+        (Vector3f::new(0.0, 0.0, 0.0), Vector3f::new(1.0, 0.0, 0.0), Vector3f::new(0.0, 1.0, 0.0))
+    }
+
+    fn assert_close(a: f32, b: f32) {
+        assert!((a - b).abs() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn front_hit_returns_distance_and_barycentric_coords() {
+        let (v0, v1, v2) = unit_triangle();
+        let origin = Vector3f::new(0.25, 0.5, 2.0);
+        let dir = Vector3f::new(0.0, 0.0, -1.0);
+        let (t, u, v) = ray_triangle_with_culling(&origin, &dir, &v0, &v1, &v2, TriangleCull::BackFaces).expect("ray should hit the triangle");
+        assert_close(t, 2.0);
+        assert_close(u, 0.25);
+        assert_close(v, 0.5);
+    }
+
+    #[test]
+    fn back_face_hit_depends_on_culling() {
+        let (v0, v1, v2) = unit_triangle();
+        let origin = Vector3f::new(0.25, 0.25, -1.0);
+        let dir = Vector3f::new(0.0, 0.0, 1.0);
+        assert!(ray_triangle_with_culling(&origin, &dir, &v0, &v1, &v2, TriangleCull::BackFaces).is_none());
+        let (t, _, _) = ray_triangle(&origin, &dir, &v0, &v1, &v2).expect("two sided test should hit the back face");
+        assert_close(t, 1.0);
+    }
+
+    #[test]
+    fn misses_return_none() {
+        let (v0, v1, v2) = unit_triangle();
+        let dir = Vector3f::new(0.0, 0.0, -1.0);
+        // beside the triangle
+        assert!(ray_triangle(&Vector3f::new(0.75, 0.75, 1.0), &dir, &v0, &v1, &v2).is_none());
+        // triangle is behind the origin
+        assert!(ray_triangle(&Vector3f::new(0.25, 0.25, -1.0), &dir, &v0, &v1, &v2).is_none());
+    }
+
+    #[test]
+    fn parallel_ray_and_degenerate_triangle_return_none() {
+        let (v0, v1, v2) = unit_triangle();
+        let parallel_dir = Vector3f::new(1.0, 0.0, 0.0);
+        assert!(ray_triangle(&Vector3f::new(-1.0, 0.25, 0.0), &parallel_dir, &v0, &v1, &v2).is_none());
+
+        let degenerate_v2 = Vector3f::new(2.0, 0.0, 0.0);
+        assert!(ray_triangle(&Vector3f::new(0.5, 0.0, 1.0), &Vector3f::new(0.0, 0.0, -1.0), &v0, &v1, &degenerate_v2).is_none());
+    }
+
+    #[test]
+    fn ray_aabb_returns_entry_and_exit_distance() {
+        let (min, max) = (Vector3f::new(0.0, 0.0, 0.0), Vector3f::new(2.0, 1.0, 2.0));
+        let (t_enter, t_exit) = ray_aabb(&Vector3f::new(-1.0, 0.5, 1.0), &Vector3f::new(1.0, 0.0, 0.0), &min, &max).expect("ray crosses the box");
+        assert_close(t_enter, 1.0);
+        assert_close(t_exit, 3.0);
+        // from inside the box the entry is behind the origin
+        let (t_enter, t_exit) = ray_aabb(&Vector3f::new(1.0, 0.5, 1.0), &Vector3f::new(0.0, -1.0, 0.0), &min, &max).expect("origin is inside the box");
+        assert_close(t_enter, -0.5);
+        assert_close(t_exit, 0.5);
+
+        assert!(ray_aabb(&Vector3f::new(-1.0, 2.0, 1.0), &Vector3f::new(1.0, 0.0, 0.0), &min, &max).is_none());
+        assert!(ray_aabb(&Vector3f::new(3.0, 0.5, 1.0), &Vector3f::new(1.0, 0.0, 0.0), &min, &max).is_none());
+    }
+}
@@ -1,5 +1,6 @@
 use std::ops::{Index, IndexMut, Mul};
 use super::{
+    Quaternion,
     Vector2f,
     Vector3f,
     Vector4f,
@@ -77,8 +78,12 @@ impl Matrix4f {
     }
     
     pub fn create_particle_transform_matrix(translation: &Vector3f, rotation_z_deg: f32, scale: f32, camera: &Camera) -> Matrix4f {
-        let camera_pitch = camera.pitch;
-        let camera_yaw = camera.yaw;        
+        Self::create_billboard_transform_matrix(translation, rotation_z_deg, scale, camera.pitch, camera.yaw, false)
+    }
+
+    // a cylindrical billboard only turns around the y axis towards the camera so it stays upright when the camera looks up or down
+    pub fn create_billboard_transform_matrix(translation: &Vector3f, rotation_z_deg: f32, scale: f32, camera_pitch: f32, camera_yaw: f32, is_cylindrical: bool) -> Matrix4f {
+        let camera_pitch = if is_cylindrical { 0.0 } else { camera_pitch };
         let mut transform_mat = Matrix4f::identity();
         transform_mat.scale(&Vector3f::new(scale, scale, scale));                        
         transform_mat.rotate(&Vector3f::new(0.0, 0.0, rotation_z_deg));
@@ -99,6 +104,18 @@ impl Matrix4f {
         transform_mat
     }
 
+    // same result as create_transform_matrix but written into an existing matrix, T * R * S is filled in directly instead of multiplying
+    pub fn create_transform_matrix_into(out: &mut Matrix4f, translation: &Vector3f, rot_xyz_degrees: &Vector3f, scale: f32) {
+        let rot_mat = Self::get_rotation(rot_xyz_degrees.x, rot_xyz_degrees.y, rot_xyz_degrees.z);
+        for i in 0..3 {
+            for j in 0..3 {
+                out.data[i][j] = rot_mat.data[i][j] * scale;
+            }
+            out.data[i][3] = translation[i];
+        }
+        out.data[3] = [0.0, 0.0, 0.0, 1.0];
+    }
+
     pub fn create_transform_matrix_with_s(translation: &Vector3f, rot_xyz_degrees: &Vector3f, scale: &Vector3f) -> Matrix4f {        
         let mut transform_mat = Matrix4f::identity();
         transform_mat.scale(scale);
@@ -121,6 +138,57 @@ impl Matrix4f {
         }
     }
 
+    // same as create_projection_matrix but depth goes from 1 at the near plane to 0 at the far plane, meant for [0, 1] ndc depth (clip control)
+    pub fn create_reversed_z_projection_matrix(near_plane: f32, far_plane: f32, fov_horizontal_degs: f32, aspect_ratio: f32) -> Matrix4f {
+        let mut data = [[0.0f32; 4]; 4];
+        let tan_fov_half = (fov_horizontal_degs.to_radians() / 2.0).tan();
+        data[0][0] = 1.0 / tan_fov_half;
+        data[1][1] = aspect_ratio / tan_fov_half;
+        data[2][2] = near_plane / (far_plane - near_plane);
+        data[2][3] = -(far_plane * near_plane) / (far_plane - near_plane);
+        data[3][2] = -1.0;
+
+        Matrix4f {
+            data,
+        }
+    }
+
+    // Lengyel's oblique near plane: replaces the near plane of a perspective projection with the view space clip plane
+    // so everything on the negative side of the plane is clipped without clip distances, the far plane is tilted to keep the depth range
+    // the camera has to be on the negative side of the plane (clip_plane.w < 0) otherwise the near plane ends up behind the camera
+    pub fn make_oblique_clip(projection: &Matrix4f, clip_plane: &Vector4f) -> Matrix4f {
+        // the frustum corner on the far plane opposite to the clip plane
+        let q = projection.inverse().transform(&Vector4f::new(clip_plane.x.signum(), clip_plane.y.signum(), 1.0, 1.0));
+        let c = (2.0 / Matrix4f::plane_dot(clip_plane, &q)) * clip_plane.clone();
+        let mut oblique = projection.clone();
+        // near plane of [-1, 1] depth is row 3 + row 2
+        for col in 0..4 {
+            oblique.data[2][col] = c[col] - projection.data[3][col];
+        }
+        oblique
+    }
+
+    // make_oblique_clip for create_reversed_z_projection_matrix where the near plane is at depth 1 and the far plane at 0
+    pub fn make_reversed_z_oblique_clip(projection: &Matrix4f, clip_plane: &Vector4f) -> Matrix4f {
+        let q = projection.inverse().transform(&Vector4f::new(clip_plane.x.signum(), clip_plane.y.signum(), 0.0, 1.0));
+        let c = (1.0 / Matrix4f::plane_dot(clip_plane, &q)) * clip_plane.clone();
+        let mut oblique = projection.clone();
+        // near plane of reversed z is row 3 - row 2
+        for col in 0..4 {
+            oblique.data[2][col] = projection.data[3][col] - c[col];
+        }
+        oblique
+    }
+
+    fn plane_dot(plane: &Vector4f, point: &Vector4f) -> f32 {
+        plane.x * point.x + plane.y * point.y + plane.z * point.z + plane.w * point.w
+    }
+
+    // planes (nx, ny, nz, d) transform with the inverse transpose, e.g. a world space clip plane into view space with the view matrix
+    pub fn transform_plane(&self, plane: &Vector4f) -> Vector4f {
+        self.inverse().transpose().transform(plane)
+    }
+
     pub fn update_ortho_projection_matrix(ortho_mat: &mut Matrix4f, frustum_width: f32, frustum_height: f32, frustum_length: f32) {        
         ortho_mat[0][0] = 2.0 / frustum_width;
         ortho_mat[1][1] = 2.0 / frustum_height;
@@ -130,12 +198,13 @@ impl Matrix4f {
 
     // view matrix makes objects move closer to the camera as we move towards them since it includes the negative of the camera translation
     // we dont want the skybox to move as we move around (but we do want it to rotate) so we zero out the translation
-    pub fn create_skybox_view_matrix(camera: &Camera, skybox_rotation_deg: f32) -> Matrix4f {        
+    // the skybox itself is rotated by skybox_rotation_deg around skybox_rotation_axis before the camera rotation is applied
+    pub fn create_skybox_view_matrix(camera: &Camera, skybox_rotation_deg: f32, skybox_rotation_axis: &Vector3f) -> Matrix4f {        
         // pitch is the rotation against transverse axis (pointing to right) -> for our object x axis is right
         // yaw is the rotation against vertical axis (pointing up) -> for our object y axis is up
         // roll is the rotation against longitudal axis (pointing forward) -> for our object z axis is forward
-        let rotation_xyz_degrees = Vector3f::new(camera.pitch, -camera.yaw - skybox_rotation_deg, camera.roll);
-        let mut view_mat = Matrix4f::identity();         
+        let rotation_xyz_degrees = Vector3f::new(camera.pitch, -camera.yaw, camera.roll);
+        let mut view_mat = Quaternion::from_angle_axis(-skybox_rotation_deg, skybox_rotation_axis).as_rot_mat();
         view_mat.rotate(&rotation_xyz_degrees);
         view_mat
     }
@@ -282,6 +351,14 @@ impl Matrix4f {
         rot
     }
 
+    // roll, pitch and yaw in degrees that get_rotation turns back into the rotation part of this matrix, the pitch stays within +-90
+    pub fn rotation_degrees(&self) -> Vector3f {
+        let pitch = self.data[0][2].max(-1.0).min(1.0).asin();
+        let roll = (-self.data[1][2]).atan2(self.data[2][2]);
+        let yaw = (-self.data[0][1]).atan2(self.data[0][0]);
+        Vector3f::new(roll.to_degrees(), pitch.to_degrees(), yaw.to_degrees())
+    }
+
     pub fn rotate(&mut self, rot_xyz_degrees: &Vector3f) {
         let rot_mat = Self::get_rotation(rot_xyz_degrees.x, rot_xyz_degrees.y, rot_xyz_degrees.z);
         self.pre_multiply_in_place(&rot_mat);
@@ -656,6 +733,15 @@ mod tests {
         }
     }
 
+    #[test]
+    fn rotation_degrees_round_trip_through_get_rotation() {
+        let rotation = Matrix4f::get_rotation(20.0, -35.0, 150.0);
+        let angles = rotation.rotation_degrees();
+        assert_f32_eq!(angles.x, 20.0, test_constants::EPS_MEDIUM, "roll mismatch");
+        assert_f32_eq!(angles.y, -35.0, test_constants::EPS_MEDIUM, "pitch mismatch");
+        assert_f32_eq!(angles.z, 150.0, test_constants::EPS_MEDIUM, "yaw mismatch");
+    }
+
     #[test]
     fn translate_transformation() {
         let mut m1 = Matrix4f {data: [[-1.3, 10.0, 2.8, 2.0], [-2.0, 3.0, -13.0, 9.0], [-1.4, 4.5, 0.0, 3.2], [-1.0, -2.0, 3.0, 5.0]] };        
@@ -817,6 +903,24 @@ mod tests {
         }
     }
 
+    #[test]
+    fn transform_matrix_into_matches_allocating_version() {
+        let mut scratch = Matrix4f {data: [[9.0; 4]; 4]};
+        let transforms = [
+            (Vector3f::new(3.0, -2.4, 11.2), Vector3f::new(30.0, 45.0, -60.0), 0.5),
+            (Vector3f::new(-150.0, 0.0, 7.0), Vector3f::new(0.0, 270.0, 10.0), 3.0),
+        ];
+        for (translation, rotation, scale) in transforms.iter() {
+            let expected = Matrix4f::create_transform_matrix(translation, rotation, *scale);
+            Matrix4f::create_transform_matrix_into(&mut scratch, translation, rotation, *scale);
+            for r in 0..4 {
+                for c in 0..4 {
+                    assert_f32_eq!(scratch[r][c], expected[r][c], test_constants::EPS_PRECISE, format!("(r,c)=({},{}) mismatch", r, c));
+                }
+            }
+        }
+    }
+
     #[test]
     fn test_inverse_2x2() {        
         let mut m1 = Matrix2f::new();
@@ -839,4 +943,84 @@ mod tests {
         assert_f32_eq!(res[1][0], 0.0, test_constants::EPS_BAD); 
         assert_f32_eq!(res[1][1], 1.0, test_constants::EPS_BAD); 
     }
-}
\ No newline at end of file
+
+    fn ndc_depth(projection: &Matrix4f, x: f32, y: f32, z: f32) -> f32 {
+        let clip = projection.transform(&Vector4f::new(x, y, z, 1.0));
+        clip.z / clip.w
+    }
+
+    #[test]
+    fn oblique_near_plane_is_the_clip_plane() {
+        let projection = Matrix4f::create_projection_matrix(-0.1, -1000.0, 70.0, 1.5);
+        // keep what is above y = 2 in view space, the camera is below the plane like the reflection camera below the water
+        let oblique = Matrix4f::make_oblique_clip(&projection, &Vector4f::new(0.0, 1.0, 0.0, -2.0));
+        // the near plane (row 3 + row 2) is a positive multiple of the clip plane
+        let near: Vec<f32> = (0..4).map(|col| oblique[3][col] + oblique[2][col]).collect();
+        let scale = near[1];
+        assert!(scale > 0.0);
+        assert_eq!((near[0], near[2]), (0.0, 0.0));
+        assert_f32_eq!(near[3], -2.0 * scale, test_constants::EPS_MEDIUM);
+
+        // points on the plane land on the near plane, points on the kept side are in front of it, the rest is clipped
+        assert_f32_eq!(ndc_depth(&oblique, 1.0, 2.0, -5.0), -1.0, test_constants::EPS_MEDIUM);
+        let kept = ndc_depth(&oblique, 0.0, 3.0, -20.0);
+        assert!(kept > -1.0 && kept < 1.0);
+        assert!(ndc_depth(&oblique, 0.0, 1.0, -20.0) < -1.0);
+        // only the depth row changes
+        assert_eq!(oblique[0], projection[0]);
+        assert_eq!(oblique[3], projection[3]);
+    }
+
+    #[test]
+    fn reversed_z_oblique_near_plane_is_at_depth_one() {
+        let projection = Matrix4f::create_reversed_z_projection_matrix(-0.1, -1000.0, 70.0, 1.5);
+        let oblique = Matrix4f::make_reversed_z_oblique_clip(&projection, &Vector4f::new(0.0, 1.0, 0.0, -2.0));
+        assert_f32_eq!(ndc_depth(&oblique, -1.0, 2.0, -5.0), 1.0, test_constants::EPS_MEDIUM);
+        let kept = ndc_depth(&oblique, 0.0, 3.0, -20.0);
+        assert!(kept > 0.0 && kept < 1.0);
+    }
+
+    #[test]
+    fn planes_transform_into_view_space() {
+        let mut view = Matrix4f::identity();
+        view.translate(&Vector3f::new(0.0, -5.0, 0.0));
+        // camera at y = 5, the plane y = 2 is 3 below it in view space
+        let view_plane = view.transform_plane(&Vector4f::new(0.0, 1.0, 0.0, -2.0));
+        assert_f32_eq!(view_plane.y, 1.0, test_constants::EPS_MEDIUM);
+        assert_f32_eq!(view_plane.w, 3.0, test_constants::EPS_MEDIUM);
+    }
+
+    #[test]
+    fn cylindrical_billboard_stays_upright_while_full_one_faces_the_camera() {
+        let mut camera = Camera::default();
+        camera.pitch = 30.0;
+        camera.yaw = 45.0;
+        let view = Matrix4f::create_view_matrix(&camera);
+        let position = Vector3f::new(5.0, 2.0, -3.0);
+
+        // the full billboard undoes the view rotation so it is seen straight on
+        let full = Matrix4f::create_billboard_transform_matrix(&position, 0.0, 1.0, camera.pitch, camera.yaw, false);
+        let full_in_view = &view * &full;
+        for r in 0..3 {
+            for c in 0..3 {
+                let expected = if r == c { 1.0 } else { 0.0 };
+                assert_f32_eq!(full_in_view[r][c], expected, test_constants::EPS_MEDIUM, format!("(r,c)=({},{}) mismatch", r, c));
+            }
+        }
+
+        // the cylindrical one keeps its up axis and turns its front towards the camera only horizontally
+        let cylindrical = Matrix4f::create_billboard_transform_matrix(&position, 0.0, 1.0, camera.pitch, camera.yaw, true);
+        let up = cylindrical.transform(&Vector4f::new(0.0, 1.0, 0.0, 0.0));
+        assert_f32_eq!(up.x, 0.0, test_constants::EPS_MEDIUM);
+        assert_f32_eq!(up.y, 1.0, test_constants::EPS_MEDIUM);
+        assert_f32_eq!(up.z, 0.0, test_constants::EPS_MEDIUM);
+        let full_front = full.transform(&Vector4f::new(0.0, 0.0, 1.0, 0.0));
+        let cylindrical_front = cylindrical.transform(&Vector4f::new(0.0, 0.0, 1.0, 0.0));
+        let full_horizontal_len = (full_front.x * full_front.x + full_front.z * full_front.z).sqrt();
+        assert_f32_eq!(cylindrical_front.y, 0.0, test_constants::EPS_MEDIUM);
+        assert_f32_eq!(cylindrical_front.x, full_front.x / full_horizontal_len, test_constants::EPS_MEDIUM);
+        assert_f32_eq!(cylindrical_front.z, full_front.z / full_horizontal_len, test_constants::EPS_MEDIUM);
+        // both keep the entity position
+        assert_eq!((cylindrical[0][3], cylindrical[1][3], cylindrical[2][3]), (5.0, 2.0, -3.0));
+    }
+}
@@ -39,6 +39,13 @@ pub fn distance(p1: &Vector3f, p2: &Vector3f) -> f32 {
     sq_sum.sqrt()
 }
 
+// same blend as in normalMappedFragShader.glsl -> strength 0 keeps the geometric normal, strength 1 uses the mapped normal
+pub fn blend_normal(geometric_normal: &Vector3f, mapped_normal: &Vector3f, strength: f32) -> Vector3f {
+    let mut normal = Vector3f::lerp(mapped_normal, geometric_normal, strength);
+    normal.normalize();
+    normal
+}
+
 // f32 is not eq so we can't use cmp (NaN stuff) .. but it we assume that we never have NaN then the following is good
 #[inline]
 pub fn f32_min(a: f32, b: f32) -> f32 {
@@ -73,4 +80,17 @@ mod tests {
         assert!(v1.dot_product(&u3).abs() < 1e-3, format!("Was {}", v1.dot_product(&u3)));
         assert!(u2.dot_product(&u3).abs() < 1e-3, format!("Was {}", u2.dot_product(&u3)));
     }
+
+    #[test]
+    fn blend_normal_strength_limits() {
+        let geometric = Vector3f::POS_Z_AXIS;
+        let mut mapped = Vector3f::new(0.3, -0.4, 0.8);
+        mapped.normalize();
+
+        let flat = blend_normal(&geometric, &mapped, 0.0);
+        assert!(distance(&flat, &geometric) < 1e-6, format!("Was {:?}", flat));
+
+        let full = blend_normal(&geometric, &mapped, 1.0);
+        assert!(distance(&full, &mapped) < 1e-6, format!("Was {:?}", full));
+    }
 }
\ No newline at end of file
@@ -400,6 +400,90 @@ impl Vector2f {
         let sq_sum = self.x * self.x + self.y * self.y;
         sq_sum.sqrt()
     }
+
+    // a zero vector has no direction so it stays zero instead of turning into nan
+    pub fn normalize(&mut self) {
+        let len = self.length();
+        if len == 0.0 {
+            return;
+        }
+        self.x /= len;
+        self.y /= len;
+    }
+
+    pub fn dot_product(&self, other: &Vector2f) -> f32 {
+        self.x * other.x + self.y * other.y
+    }
+
+    // counter clockwise around the origin
+    pub fn rotate(&mut self, angle_deg: f32) {
+        let (sin, cos) = angle_deg.to_radians().sin_cos();
+        let x = self.x * cos - self.y * sin;
+        let y = self.x * sin + self.y * cos;
+        self.x = x;
+        self.y = y;
+    }
+}
+
+impl Add for Vector2f {
+    type Output = Vector2f;
+
+    fn add(mut self, other: Vector2f) -> Vector2f {
+        self.x += other.x;
+        self.y += other.y;
+        self
+    }
+}
+
+impl Add<&Vector2f> for &Vector2f {
+    type Output = Vector2f;
+
+    fn add(self, other: &Vector2f) -> Vector2f {
+        Vector2f::new(self.x + other.x, self.y + other.y)
+    }
+}
+
+impl Sub for Vector2f {
+    type Output = Vector2f;
+
+    fn sub(mut self, other: Vector2f) -> Vector2f {
+        self.x -= other.x;
+        self.y -= other.y;
+        self
+    }
+}
+
+impl Sub<&Vector2f> for &Vector2f {
+    type Output = Vector2f;
+
+    fn sub(self, other: &Vector2f) -> Vector2f {
+        Vector2f::new(self.x - other.x, self.y - other.y)
+    }
+}
+
+impl AddAssign<&Vector2f> for Vector2f {
+    fn add_assign(&mut self, other: &Vector2f) {
+        self.x += other.x;
+        self.y += other.y;
+    }
+}
+
+impl Mul<f32> for Vector2f {
+    type Output = Vector2f;
+
+    fn mul(mut self, other: f32) -> Vector2f {
+        self.x *= other;
+        self.y *= other;
+        self
+    }
+}
+
+impl Mul<f32> for &Vector2f {
+    type Output = Vector2f;
+
+    fn mul(self, other: f32) -> Vector2f {
+        Vector2f::new(self.x * other, self.y * other)
+    }
 }
 
 impl IntoIterator for Vector2f {
@@ -409,4 +493,48 @@ impl IntoIterator for Vector2f {
     fn into_iter(self) -> Self::IntoIter {
         vec![self.x, self.y].into_iter()
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rotate_by_90_deg_turns_x_into_y() {
+        let mut v = Vector2f::new(1.0, 0.0);
+        v.rotate(90.0);
+        assert!(v.x.abs() < 1e-6);
+        assert!((v.y - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn perpendicular_vectors_have_zero_dot() {
+        let v = Vector2f::new(3.0, -2.0);
+        let mut perp = v.clone();
+        perp.rotate(90.0);
+        assert!(v.dot_product(&perp).abs() < 1e-5);
+        assert_eq!(Vector2f::new(2.0, 0.0).dot_product(&Vector2f::new(0.0, 5.0)), 0.0);
+    }
+
+    #[test]
+    fn normalize_zero_stays_zero() {
+        let mut v = Vector2f::zero();
+        v.normalize();
+        assert_eq!((v.x, v.y), (0.0, 0.0));
+        let mut v = Vector2f::new(3.0, 4.0);
+        v.normalize();
+        assert!((v.length() - 1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn vector2f_ops() {
+        let a = Vector2f::new(1.0, 2.0);
+        let b = Vector2f::new(3.0, -1.0);
+        let sum = &a + &b;
+        let diff = &a - &b;
+        let scaled = &a * 2.0;
+        assert_eq!((sum.x, sum.y), (4.0, 1.0));
+        assert_eq!((diff.x, diff.y), (-2.0, 3.0));
+        assert_eq!((scaled.x, scaled.y), (2.0, 4.0));
+    }
+}
@@ -145,6 +145,7 @@ fn animations_from_collada(collada_doc: &ColladaDocument) -> Animation {
                 keyframes,
             }
         );
+        animation.length_seconds = animation.length_seconds.max(length_seconds);
     }
     animation
 }
@@ -185,7 +186,7 @@ fn raw_model_from_obj_set(collada_doc: &ColladaDocument, loader: &mut ModelLoade
                             if vertices_seen.contains(&nvert_data.vidx) {
                                 nvert_data.n_vidx = nvidx_gen;
                                 nvidx_gen += 1;
-                                println!("Duplicated vertex found -- this is a vertex where for this vertex position we have multiple different (v,t,n) tuples");
+                                log_debug!("Duplicated vertex found -- this is a vertex where for this vertex position we have multiple different (v,t,n) tuples");
                             } else {
                                 vertices_seen.insert(nvert_data.vidx);
                             } 
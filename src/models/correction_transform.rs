@@ -1,7 +1,22 @@
 use crate::math::{
     Matrix4f,
+    Vector3f,
     Vector4f
 };
+use crate::obj_converter::ModelData;
+
+// rotation and uniform scale baked into the vertices of a model when it is loaded (see ModelProps::pre_transform)
+#[derive(Debug, Clone)]
+pub struct MeshPreTransform {
+    pub rotation_deg: Vector3f,
+    pub scale: f32,
+}
+
+impl MeshPreTransform {
+    pub fn correction_transform(&self) -> CorrectionTransform {
+        CorrectionTransform::create_coord_correction(Matrix4f::create_transform_matrix(&Vector3f::zero(), &self.rotation_deg, self.scale))
+    }
+}
 
 #[derive(Clone)]
 pub enum CorrectionTransform {
@@ -26,6 +41,39 @@ impl CorrectionTransform {
         }
     }
 
+    // bakes the correction into the mesh so it doesn't have to be applied every frame
+    // the positions and tangents are transformed like the model, the normals with the inverse transpose so they stay perpendicular to the surface
+    pub fn apply_to_mesh(&self, positions: &mut [f32], normals: &mut [f32], tangents: &mut [f32]) {
+        match self {
+            CorrectionTransform::None => {},
+            CorrectionTransform::CoordinateSystemCorrection(trans_mat, itrans_mat) => {
+                CorrectionTransform::transform_xyz(trans_mat, positions, 1.0, false);
+                CorrectionTransform::transform_xyz(&itrans_mat.transpose(), normals, 0.0, true);
+                CorrectionTransform::transform_xyz(trans_mat, tangents, 0.0, true);
+            },
+        }
+    }
+
+    // also updates the bounding radius since the correction may scale the model
+    pub fn apply_to_model_data(&self, model_data: &mut ModelData) {
+        self.apply_to_mesh(&mut model_data.vertices, &mut model_data.normals, &mut model_data.tangents);
+        model_data.furthest_point = model_data.vertices.chunks(3)
+            .map(|xyz| Vector3f::new(xyz[0], xyz[1], xyz[2]).length())
+            .fold(0.0, f32::max);
+    }
+
+    fn transform_xyz(mat: &Matrix4f, data: &mut [f32], w: f32, normalize: bool) {
+        for xyz in data.chunks_mut(3) {
+            let mut transformed = mat.transform(&Vector4f::new(xyz[0], xyz[1], xyz[2], w)).xyz();
+            if normalize {
+                transformed.normalize();
+            }
+            xyz[0] = transformed.x;
+            xyz[1] = transformed.y;
+            xyz[2] = transformed.z;
+        }
+    }
+
     pub fn apply_to_bind_transform(&self, transform_mat: &mut Matrix4f) {
         match self {
             CorrectionTransform::None => {},
@@ -43,4 +91,39 @@ impl CorrectionTransform {
             },
         }
     } 
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_xyz_eq(actual: &[f32], expected: &[f32]) {
+        for (a, e) in actual.iter().zip(expected.iter()) {
+            assert!((a - e).abs() < 1e-5, "Got {:?}, expected {:?}", actual, expected);
+        }
+    }
+
+    #[test]
+    fn baked_x_rotation_turns_z_up_into_y_up() {
+        let correction = CorrectionTransform::create_coord_correction(Matrix4f::get_rotation(-90.0, 0.0, 0.0));
+        let mut positions = vec![0.0, 0.0, 2.0,  0.0, 3.0, 0.0,  1.0, 0.0, 0.0];
+        let mut normals = vec![0.0, 0.0, 1.0,  0.0, 1.0, 0.0,  1.0, 0.0, 0.0];
+        let mut tangents = vec![1.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 0.0, 1.0];
+        correction.apply_to_mesh(&mut positions, &mut normals, &mut tangents);
+
+        assert_xyz_eq(&positions, &[0.0, 2.0, 0.0,  0.0, 0.0, -3.0,  1.0, 0.0, 0.0]);
+        assert_xyz_eq(&normals, &[0.0, 1.0, 0.0,  0.0, 0.0, -1.0,  1.0, 0.0, 0.0]);
+        assert_xyz_eq(&tangents, &[1.0, 0.0, 0.0,  1.0, 0.0, 0.0,  0.0, 1.0, 0.0]);
+    }
+
+    #[test]
+    fn baked_scale_keeps_normals_unit_length_and_updates_radius() {
+        let pre_transform = MeshPreTransform { rotation_deg: Vector3f::new(-90.0, 0.0, 0.0), scale: 2.0 };
+        let mut model_data = ModelData { vertices: vec![0.0, 0.0, 1.5], texture_coords: vec![0.0, 0.0], normals: vec![0.0, 0.0, 1.0], indices: vec![0], tangents: Vec::new(), colors: Vec::new(), furthest_point: 1.5 };
+        pre_transform.correction_transform().apply_to_model_data(&mut model_data);
+
+        assert_xyz_eq(&model_data.vertices, &[0.0, 3.0, 0.0]);
+        assert_xyz_eq(&model_data.normals, &[0.0, 1.0, 0.0]);
+        assert!((model_data.furthest_point - 3.0).abs() < 1e-5);
+    }
+}
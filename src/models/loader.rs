@@ -2,21 +2,50 @@ use crate::gl;
 use texture_lib::texture_loader::{
     load_rgba_2d_texture,
     Texture2DRGBA,
+    RGBA,
+};
+use crate::math::utils::{
+    f32_min,
+    f32_max,
+};
+use crate::math::{
+    Vector2f,
+    Vector3f,
 };
-use crate::math::utils::f32_min;
 use super::texture_id::TextureId;
 
 use std::collections::HashMap;
 use std::hash::{Hash, Hasher};
-use std::rc::Rc;
-use std::sync::mpsc;
+use std::sync::{
+    Arc,
+    OnceLock,
+    mpsc,
+};
 use threadpool::ThreadPool;
 
+// enumerating the extensions is slow so the hardware limit is only looked up for the first texture, None without anisotropic filtering
+fn max_hardware_anisotropy() -> Option<f32> {
+    static MAX_ANISOTROPY: OnceLock<Option<f32>> = OnceLock::new();
+    *MAX_ANISOTROPY.get_or_init(|| {
+        if gl::helper::supports_anisotropic_filtering() {
+            Some(gl::get_floatv(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT))
+        } else {
+            None
+        }
+    })
+}
+
 pub struct ModelLoader {    
     vao_list: Vec<u32>,
     vbo_list: Vec<u32>,
+    // the vertex and index buffers stored in each vao so unload_vao can free them with it
+    vao_vbos: HashMap<u32, Vec<u32>>,
+    // the vao between create_vao and unbind_vao
+    filled_vao: Option<u32>,
     tex_list: Vec<u32>,
     texture_loading_rcv: mpsc::Receiver<TextureResult>,
+    // received but over the upload budget of its frame
+    deferred_texture_result: Option<TextureResult>,
     loaded_texture_snd: mpsc::Sender<TextureResult>,
     pub texture_token_map: HashMap<u32, u32>,
     texture_token_gen: u32,
@@ -25,6 +54,99 @@ pub struct ModelLoader {
     cubemap_token_gen: u32,
     unprocessed_cubemap_textures: HashMap<u32, Vec<TextureResult>>,
     thread_pool: ThreadPool,
+    load_listeners: LoadListeners,
+    // per texture lod bias of the mipmapped textures so they can be re-applied when the global bias changes
+    mipmapped_tex_lod_biases: Vec<(u32, f32)>,
+    global_lod_bias: f32,
+    upload_budget: TextureUploadBudget,
+    // what was actually applied to each uploaded 2d texture keyed by gl id, which can differ from the requested params on weaker hardware
+    texture_filtering_report: HashMap<u32, AppliedTextureFiltering>,
+}
+
+// how many finished texture loads get uploaded to the gpu per update_resource_state call, the rest waits for the next frames
+// the byte budget is never exceeded except by a single texture larger than the whole budget, which is uploaded on its own
+// a budget of 0 still uploads one texture per call, otherwise the loads would never finish
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum TextureUploadBudget {
+    Count(usize),
+    Bytes(usize),
+}
+
+impl Default for TextureUploadBudget {
+    fn default() -> Self {
+        TextureUploadBudget::Count(1)
+    }
+}
+
+impl TextureUploadBudget {
+    fn allows_upload(&self, uploaded_count: usize, uploaded_bytes: usize, next_bytes: usize) -> bool {
+        match self {
+            TextureUploadBudget::Count(max_count) => uploaded_count < (*max_count).max(1),
+            TextureUploadBudget::Bytes(max_bytes) => uploaded_count == 0 || uploaded_bytes + next_bytes <= *max_bytes,
+        }
+    }
+
+    // takes the finished loads that fit into this frame's budget, the others stay queued in the channel
+    // the channel can't be peeked so the first load that doesn't fit is kept in deferred for the next call
+    fn receive_within_budget<T>(&self, receiver: &mpsc::Receiver<T>, deferred: &mut Option<T>, size_in_bytes: impl Fn(&T) -> usize) -> Vec<T> {
+        let mut received = Vec::new();
+        let mut received_bytes = 0;
+        loop {
+            let result = match deferred.take() {
+                Some(result) => result,
+                None => match receiver.try_recv() {
+                    Ok(result) => result,
+                    Err(mpsc::TryRecvError::Empty) => break,
+                    Err(mpsc::TryRecvError::Disconnected) => panic!("The generation side of texture loading has disconnected. This shouldnt happen"),
+                },
+            };
+            let result_bytes = size_in_bytes(&result);
+            if !self.allows_upload(received.len(), received_bytes, result_bytes) {
+                *deferred = Some(result);
+                break;
+            }
+            received_bytes += result_bytes;
+            received.push(result);
+        }
+        received
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ResourceLoadEvent {
+    // carries the id handed out when the texture was queued (TextureId::Loading)
+    TextureLoaded(TextureId),
+    CubemapLoaded(TextureId),
+    AllLoaded,
+}
+
+pub type ResourceLoadCallback = Box<dyn FnMut(ResourceLoadEvent)>;
+
+// callbacks are invoked from update_resource_state so they always run on the main (gl) thread
+#[derive(Default)]
+pub struct LoadListeners {
+    callbacks: Vec<ResourceLoadCallback>,
+}
+
+impl LoadListeners {
+    pub fn add(&mut self, callback: ResourceLoadCallback) {
+        self.callbacks.push(callback);
+    }
+
+    pub fn resource_finished(&mut self, event: ResourceLoadEvent, remaining_cnt: u32) {
+        log_debug!("Loaded {:?}, {} textures remaining", event, remaining_cnt);
+        self.notify(event);
+        if remaining_cnt == 0 {
+            log_info!("All queued textures loaded");
+            self.notify(ResourceLoadEvent::AllLoaded);
+        }
+    }
+
+    fn notify(&mut self, event: ResourceLoadEvent) {
+        for callback in self.callbacks.iter_mut() {
+            callback(event);
+        }
+    }
 }
 
 // the fields are Texture, temp_tex_id, params, texture_order (used for cubemaps)
@@ -43,10 +165,13 @@ pub struct TextureParams {
     use_mipmap: bool,
     mipmap_lod: f32,
     use_anisotropic_filtering: bool,
+    anisotropic_amount: f32,
+    // multiply the color by alpha when loading so that blending with gl::ONE, gl::ONE_MINUS_SRC_ALPHA doesn't give dark edges
+    premultiply_alpha: bool,
 }
 
 impl TextureParams {
-    const DEFAULT_ANISOTROPIC_AMOUNT: f32 = 4.0;
+    pub const DEFAULT_ANISOTROPIC_AMOUNT: f32 = 4.0;
 
     pub fn mipmapped_texture(mipmap_lod: f32) -> TextureParams {
         TextureParams {
@@ -56,13 +181,138 @@ impl TextureParams {
         }
     }
     pub fn anisotropic_texture() -> TextureParams {
+        TextureParams::anisotropic_texture_with_amount(TextureParams::DEFAULT_ANISOTROPIC_AMOUNT)
+    }
+    // for example 8x or 16x for ground textures that are viewed at grazing angles
+    pub fn anisotropic_texture_with_amount(anisotropic_amount: f32) -> TextureParams {
         TextureParams {
             use_mipmap: true,
             mipmap_lod: 0.0,
             use_anisotropic_filtering: true,
+            anisotropic_amount,
             ..Default::default()
         }
     }
+
+    pub fn with_premultiplied_alpha(self) -> TextureParams {
+        TextureParams {
+            premultiply_alpha: true,
+            ..self
+        }
+    }
+
+    // 1 means no anisotropic filtering and we can't go above what the hardware supports
+    fn clamp_anisotropic_amount(requested_amount: f32, max_hardware_amount: f32) -> f32 {
+        f32_max(1.0, f32_min(requested_amount, max_hardware_amount))
+    }
+
+    // the global bias shifts all mipmapped textures while keeping their relative sharpness
+    fn effective_lod_bias(texture_lod_bias: f32, global_lod_bias: f32) -> f32 {
+        texture_lod_bias + global_lod_bias
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextureMinFilter {
+    Linear,
+    LinearMipmapLinear,
+}
+
+impl Default for TextureMinFilter {
+    fn default() -> Self {
+        TextureMinFilter::Linear
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct AppliedTextureFiltering {
+    pub mipmaps: bool,
+    pub min_filter: TextureMinFilter,
+    // clamped to the hardware max, None when it wasn't requested or the hardware has no anisotropic filtering
+    pub anisotropy: Option<f32>,
+}
+
+impl AppliedTextureFiltering {
+    pub fn for_params(params: &TextureParams, max_hardware_anisotropy: Option<f32>) -> AppliedTextureFiltering {
+        if !params.use_mipmap {
+            return AppliedTextureFiltering::default();
+        }
+        let anisotropy = match max_hardware_anisotropy {
+            Some(max_amount) if params.use_anisotropic_filtering => Some(TextureParams::clamp_anisotropic_amount(params.anisotropic_amount, max_amount)),
+            _ => None,
+        };
+        AppliedTextureFiltering {
+            mipmaps: true,
+            min_filter: TextureMinFilter::LinearMipmapLinear,
+            anisotropy,
+        }
+    }
+}
+
+// filtering of textures viewed at grazing angles like the terrain
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TextureFiltering {
+    // mipmapped with the given lod bias
+    Trilinear(f32),
+    // the amount is clamped to what the hardware supports, without hardware support it falls back to trilinear
+    Anisotropic(f32),
+}
+
+impl Default for TextureFiltering {
+    fn default() -> Self {
+        TextureFiltering::Trilinear(-0.4)
+    }
+}
+
+impl TextureFiltering {
+    pub fn texture_params(&self) -> TextureParams {
+        match *self {
+            TextureFiltering::Trilinear(mipmap_lod) => TextureParams::mipmapped_texture(mipmap_lod),
+            TextureFiltering::Anisotropic(amount) => TextureParams::anisotropic_texture_with_amount(amount),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct WaterProps {
+    // the dudv and normal maps are sampled far towards the horizon where they shimmer without mipmaps, None loads them without mipmaps
+    pub map_filtering: Option<TextureFiltering>,
+    // water depth at which the refraction gets the full dudv distortion, shallower water is distorted less
+    // so the refraction doesn't pull in what is above the water line at the shore where the depth goes to 0
+    pub distortion_falloff_depth: f32,
+}
+
+impl Default for WaterProps {
+    fn default() -> Self {
+        WaterProps {
+            map_filtering: None,
+            distortion_falloff_depth: WaterProps::DEFAULT_DISTORTION_FALLOFF_DEPTH,
+        }
+    }
+}
+
+impl WaterProps {
+    pub const DEFAULT_DISTORTION_FALLOFF_DEPTH: f32 = 20.0;
+    // even a tiny falloff keeps the shore line itself undistorted
+    const MIN_DISTORTION_FALLOFF_DEPTH: f32 = 0.01;
+
+    // the value the water shader gets as distortion_falloff_depth
+    pub fn shader_falloff_depth(&self) -> f32 {
+        f32_max(self.distortion_falloff_depth, WaterProps::MIN_DISTORTION_FALLOFF_DEPTH)
+    }
+
+    // same smoothstep as the water shader does per fragment, 0 at the edge of the water and 1 from the falloff depth on
+    pub fn distortion_fade(&self, water_depth: f32) -> f32 {
+        let t = f32_max(0.0, f32_min(water_depth / self.shader_falloff_depth(), 1.0));
+        t * t * (3.0 - 2.0 * t)
+    }
+
+    pub fn map_texture_params(&self) -> TextureParams {
+        match self.map_filtering {
+            Some(filtering) => filtering.texture_params(),
+            None => TextureParams::default(),
+        }
+    }
 }
 
 impl Default for ModelLoader {
@@ -72,8 +322,11 @@ impl Default for ModelLoader {
         ModelLoader {
             vao_list: Vec::new(),
             vbo_list: Vec::new(),
+            vao_vbos: HashMap::new(),
+            filled_vao: None,
             tex_list: Vec::new(),
             texture_loading_rcv: receiver,
+            deferred_texture_result: None,
             loaded_texture_snd: transmitter,
             texture_token_map: HashMap::new(),
             texture_token_gen: 0,
@@ -81,6 +334,11 @@ impl Default for ModelLoader {
             unprocessed_cubemap_textures: HashMap::new(),
             loading_texture_cnt: 0,
             thread_pool: pool,
+            load_listeners: LoadListeners::default(),
+            mipmapped_tex_lod_biases: Vec::new(),
+            global_lod_bias: 0.0,
+            upload_budget: TextureUploadBudget::default(),
+            texture_filtering_report: HashMap::new(),
         }
     }
 }
@@ -92,28 +350,50 @@ impl ModelLoader {
         <ModelLoader as Default>::default()
     }
 
+    pub fn set_upload_budget(&mut self, upload_budget: TextureUploadBudget) {
+        self.upload_budget = upload_budget;
+    }
+
     pub fn update_resource_state(&mut self) {
-        let recv_res = self.texture_loading_rcv.try_recv();
-        if let Ok(texture_result) = recv_res {
+        let texture_results = self.upload_budget.receive_within_budget(&self.texture_loading_rcv, &mut self.deferred_texture_result, |texture_result| texture_result.0.data.len());
+        for texture_result in texture_results {
             if texture_result.3.is_cubemap {
                 let cubemap_token = texture_result.3.cubemap_token;
                 let unprocessed_textures = self.unprocessed_cubemap_textures.get_mut(&cubemap_token).expect("Cubemap id must exist in the map. Make sure the entry is created as the token is generated");
                 unprocessed_textures.push(texture_result);
-                if unprocessed_textures.len() == 6 {
+                let cubemap_done = unprocessed_textures.len() == 6;
+                if cubemap_done {
                     let cubemap_id = self.load_cube_map_into_graphics_lib(cubemap_token);
                     self.texture_token_map.insert(cubemap_token, cubemap_id);                    
                 }
                 self.loading_texture_cnt -= 1;
+                if cubemap_done {
+                    self.load_listeners.resource_finished(ResourceLoadEvent::CubemapLoaded(TextureId::Loading(cubemap_token)), self.loading_texture_cnt);
+                }
             } else {
+                let token = texture_result.1;
                 let tex_id = self.load_texture_into_graphics_lib(texture_result.0, texture_result.2);
-                self.texture_token_map.insert(texture_result.1, tex_id);
+                self.texture_token_map.insert(token, tex_id);
                 self.loading_texture_cnt -= 1;
+                self.load_listeners.resource_finished(ResourceLoadEvent::TextureLoaded(TextureId::Loading(token)), self.loading_texture_cnt);
             }
-        } else if let Err(mpsc::TryRecvError::Disconnected) = recv_res {
-            panic!("The generation side of texture loading has disconnected. This shouldnt happen")
         }
     }
 
+    pub fn add_load_listener(&mut self, callback: ResourceLoadCallback) {
+        self.load_listeners.add(callback);
+    }
+
+    // negative is sharper, applies to already loaded mipmapped textures and to the ones loaded later
+    pub fn set_global_lod_bias(&mut self, global_lod_bias: f32) {
+        self.global_lod_bias = global_lod_bias;
+        gl::active_texture(gl::TEXTURE0);
+        for (tex_id, texture_lod_bias) in self.mipmapped_tex_lod_biases.iter() {
+            gl::bind_texture(gl::TEXTURE_2D, *tex_id);
+            gl::tex_parameterf(gl::TEXTURE_2D, gl::TEXTURE_LOD_BIAS, TextureParams::effective_lod_bias(*texture_lod_bias, global_lod_bias));
+        }
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+    }
     
     pub fn resolve(&self, texture_id: TextureId) -> TextureId {
         match texture_id {
@@ -127,6 +407,37 @@ impl ModelLoader {
         }
     }
 
+    // unlike resolve this can be called while the texture is still in flight, it then just returns the token back
+    pub fn try_resolve(&self, texture_id: TextureId) -> TextureId {
+        texture_id.resolve_with(&self.texture_token_map)
+    }
+
+    // None while the texture is still loading and for cubemaps and fbo textures
+    pub fn texture_filtering(&self, texture_id: TextureId) -> Option<&AppliedTextureFiltering> {
+        match self.try_resolve(texture_id) {
+            TextureId::Loaded(tex_id) => self.texture_filtering_report.get(&tex_id),
+            _ => None,
+        }
+    }
+
+    pub fn texture_filtering_report(&self) -> &HashMap<u32, AppliedTextureFiltering> {
+        &self.texture_filtering_report
+    }
+
+    fn record_texture_filtering(&mut self, tex_id: u32, params: &TextureParams, max_hardware_anisotropy: Option<f32>) -> AppliedTextureFiltering {
+        let applied = AppliedTextureFiltering::for_params(params, max_hardware_anisotropy);
+        self.texture_filtering_report.insert(tex_id, applied);
+        applied
+    }
+
+    pub fn is_texture_ready(&self, texture_id: TextureId) -> bool {
+        self.try_resolve(texture_id).is_ready()
+    }
+
+    pub fn is_model_ready(&self, model: &TexturedModel) -> bool {
+        model.is_ready_with(&self.texture_token_map)
+    }
+
     
 
     pub fn load_to_vao_with_normal_map(&mut self, positions: &[f32], texture_coords: &[f32], indices: &[u32], normals: &[f32], tangents: &[f32]) -> RawModel {
@@ -140,6 +451,17 @@ impl ModelLoader {
         RawModel::new(vao_id, indices.len())
     }
 
+    // colors are rgb per vertex and get multiplied into the texture color by the entity shader
+    pub fn load_to_vao_with_colors(&mut self, positions: &[f32], texture_coords: &[f32], indices: &[u32], normals: &[f32], colors: &[f32]) -> RawModel {
+        let vao_id = self.create_vao();
+        self.bind_indices_buffer(indices);
+        for (attribute_num, coord_size, data) in RawModel::attributes_with_colors(positions, texture_coords, normals, colors).iter() {
+            self.store_data_in_attribute_list(*attribute_num, *coord_size, data);
+        }
+        self.unbind_vao();
+        RawModel::new_with_vertex_colors(vao_id, indices.len())
+    }
+
     pub fn load_to_vao(&mut self, positions: &[f32], texture_coords: &[f32], indices: &[u32], normals: &[f32]) -> RawModel {
         let vao_id = self.create_vao();
         self.bind_indices_buffer(indices);
@@ -180,6 +502,26 @@ impl ModelLoader {
         }
     }
 
+    // nothing is uploaded yet, the stream vbo has room for vertex_count vertices that are drawn without indices
+    pub fn load_dynamic_model_to_vao(&mut self, vertex_count: usize, dimension: u32) -> DynamicVertexIndexedModel {
+        let vao_id = self.create_vao();
+        let stream_draw_vbo = self.create_empty_float_vbo_for_attrib(RawModel::POS_ATTRIB, vertex_count, dimension);
+        self.unbind_vao();
+        DynamicVertexIndexedModel {
+            raw_model: RawModel::new(vao_id, vertex_count),
+            stream_draw_vbo,
+        }
+    }
+
+    // two indices per line, drawn with gl::LINES
+    pub fn load_lines_to_vao(&mut self, positions: &[f32], line_indices: &[u32]) -> RawModel {
+        let vao_id = self.create_vao();
+        self.bind_indices_buffer(line_indices);
+        self.store_data_in_attribute_list(RawModel::POS_ATTRIB, 3, positions);
+        self.unbind_vao();
+        RawModel::new(vao_id, line_indices.len())
+    }
+
     pub fn load_quads_mesh_to_vao(&mut self, positions: &[f32], texture_coords: &[f32]) -> RawModel {
         let vao_id = self.create_vao(); 
         self.store_data_in_attribute_list(RawModel::POS_ATTRIB, 2, positions);        
@@ -237,7 +579,10 @@ impl ModelLoader {
         let sender = self.loaded_texture_snd.clone();
         self.thread_pool.execute(move || {
             // make sure to not panic on thread
-            let texture = load_rgba_2d_texture(&file_name_str, params.reverse_texture_data).expect(&format!("Failed to load texture: {}", file_name_str));
+            let mut texture = load_texture_or_fallback(&file_name_str, params.reverse_texture_data);
+            if params.premultiply_alpha {
+                premultiply_alpha(&mut texture);
+            }
             sender.send((texture, texture_queue_id, params, extra_info)).expect("Failed to send");
         });
 
@@ -254,16 +599,18 @@ impl ModelLoader {
         gl::tex_parameter_iv(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::REPEAT);        
 
         gl::tex_image_2d(gl::TEXTURE_2D, 0, gl::RGBA, texture.width, texture.height, gl::UNSIGNED_BYTE, &texture.data);
-        if params.use_mipmap {
+        gl::helper::debug_check_error(|| format!("uploading texture {}", tex_id));
+        let max_hardware_anisotropy = if params.use_anisotropic_filtering { max_hardware_anisotropy() } else { None };
+        let applied = self.record_texture_filtering(tex_id, &params, max_hardware_anisotropy);
+        if applied.mipmaps {
              // turn on mipmapping, has to be called after loading the texture data 
             gl::generate_mipmap(gl::TEXTURE_2D);
             gl::tex_parameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::LINEAR_MIPMAP_LINEAR);
             // set texture detail level (more negative means nicer) things at a high angle like grass/flowers may seem blurry if this is positive or 0
-            gl::tex_parameterf(gl::TEXTURE_2D, gl::TEXTURE_LOD_BIAS, params.mipmap_lod);
-            if params.use_anisotropic_filtering {
-                let max_anisotropic = gl::get_floatv(gl::MAX_TEXTURE_MAX_ANISOTROPY_EXT);
-                let min_amount = f32_min(TextureParams::DEFAULT_ANISOTROPIC_AMOUNT, max_anisotropic);
-                gl::tex_parameterf(gl::TEXTURE_2D, gl::TEXTURE_MAX_ANISOTROPY_EXT, min_amount);
+            gl::tex_parameterf(gl::TEXTURE_2D, gl::TEXTURE_LOD_BIAS, TextureParams::effective_lod_bias(params.mipmap_lod, self.global_lod_bias));
+            self.mipmapped_tex_lod_biases.push((tex_id, params.mipmap_lod));
+            if let Some(amount) = applied.anisotropy {
+                gl::tex_parameterf(gl::TEXTURE_2D, gl::TEXTURE_MAX_ANISOTROPY_EXT, amount);
             }
 
         } else {        
@@ -330,18 +677,52 @@ impl ModelLoader {
     pub fn create_vao(&mut self) -> u32 {
         let vao_id = gl::gen_vertex_array();
         self.vao_list.push(vao_id);
+        self.filled_vao = Some(vao_id);
         gl::bind_vertex_array(vao_id);                
         vao_id
     }
     
-    fn unbind_vao(&self) {
+    fn unbind_vao(&mut self) {
+        self.filled_vao = None;
         // binding to 0 unbinds
         gl::bind_vertex_array(0);
     }
+
+    // frees a model's vao together with the buffers that were stored in it e.g. for streamed terrain tiles
+    // the vao must not be drawn afterwards, everything else is only freed when the loader is dropped
+    pub fn unload_vao(&mut self, vao_id: u32) {
+        if let Some(vbos) = self.vao_vbos.remove(&vao_id) {
+            self.vbo_list.retain(|vbo_id| !vbos.contains(vbo_id));
+            gl::delete_buffers(&vbos);
+        }
+        self.vao_list.retain(|id| *id != vao_id);
+        gl::delete_vertex_arrays(&[vao_id]);
+    }
+
+    // replaces the normals of single vertices of a model loaded with load_to_vao e.g. the edge of a terrain tile
+    // that got a new neighbour, the normals are the 4th buffer after the indices, positions and texture coords
+    pub fn update_normals(&mut self, vao_id: u32, normals: &[(usize, Vector3f)]) {
+        let normals_vbo = match self.vao_vbos.get(&vao_id).and_then(|vbos| vbos.get(3)) {
+            Some(vbo_id) => *vbo_id,
+            None => return,
+        };
+        gl::bind_buffer(gl::ARRAY_BUFFER, normals_vbo);
+        for (vertex, normal) in normals.iter() {
+            gl::buffer_sub_data(gl::ARRAY_BUFFER, vertex * 3, &[normal.x, normal.y, normal.z]);
+        }
+        gl::bind_buffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    fn track_vao_vbo(&mut self, vbo_id: u32) {
+        self.vbo_list.push(vbo_id);
+        if let Some(vao_id) = self.filled_vao {
+            self.vao_vbos.entry(vao_id).or_insert_with(Vec::new).push(vbo_id);
+        }
+    }
     
     fn store_data_in_attribute_list<T: AsGlType>(&mut self, attribute_num: u32, coord_size: u32, data: &[T]) {
         let vbo_id = gl::gen_buffer();
-        self.vbo_list.push(vbo_id);
+        self.track_vao_vbo(vbo_id);
         gl::bind_buffer(gl::ARRAY_BUFFER, vbo_id);
         gl::buffer_data(gl::ARRAY_BUFFER, data, gl::STATIC_DRAW);
         if T::as_gl_type() == gl::INT {
@@ -354,7 +735,7 @@ impl ModelLoader {
 
     fn bind_indices_buffer(&mut self, indices: &[u32]) {
         let vbo_id = gl::gen_buffer();
-        self.vbo_list.push(vbo_id);
+        self.track_vao_vbo(vbo_id);
         gl::bind_buffer(gl::ELEMENT_ARRAY_BUFFER, vbo_id);
         gl::buffer_data(gl::ELEMENT_ARRAY_BUFFER, indices, gl::STATIC_DRAW);
         // no unbind since we will bind data buffer next -> that means it HAS to be called after        
@@ -389,6 +770,8 @@ impl Drop for ModelLoader {
 pub struct RawModel {
     pub vao_id: u32,
     pub vertex_count: usize,
+    // the vao has data in COLOR_ATTRIB
+    pub has_vertex_colors: bool,
 }
 
 impl RawModel {
@@ -398,13 +781,36 @@ impl RawModel {
     pub const TANGENT_ATTRIB: u32 = 3;
     pub const JOINT_IDX_ATTRIB: u32 = 4;
     pub const JOINT_WEIGHT_ATTRIB: u32 = 5;
+    pub const COLOR_ATTRIB: u32 = 6;
+    // per instance data of instanced entities, the transform takes up 4 locations (one per column)
+    pub const INSTANCE_TRANSFORM_ATTRIB: u32 = 7;
+    pub const INSTANCE_ATLAS_OFFSET_ATTRIB: u32 = 11;
 
     pub fn new(vao_id: u32, vertex_count: usize) -> RawModel {
         RawModel {
             vao_id,
             vertex_count,
+            has_vertex_colors: false,
+        }
+    }
+
+    pub fn new_with_vertex_colors(vao_id: u32, vertex_count: usize) -> RawModel {
+        RawModel {
+            has_vertex_colors: true,
+            ..RawModel::new(vao_id, vertex_count)
         }
     }
+
+    // attribute slot, components per vertex and data of every vbo of a mesh with vertex colors
+    fn attributes_with_colors<'a>(positions: &'a [f32], texture_coords: &'a [f32], normals: &'a [f32], colors: &'a [f32]) -> [(u32, u32, &'a [f32]); 4] {
+        assert_eq!(colors.len() / 3, positions.len() / 3, "Every vertex needs a color");
+        [
+            (RawModel::POS_ATTRIB, 3, positions),
+            (RawModel::TEX_COORD_ATTRIB, 2, texture_coords),
+            (RawModel::NORMAL_ATTRIB, 3, normals),
+            (RawModel::COLOR_ATTRIB, 3, colors),
+        ]
+    }
 }
 
 #[derive(Clone)]
@@ -426,10 +832,17 @@ pub struct ModelTexture {
     pub shine_damper: f32,
     pub reflectivity: f32,
     pub has_transparency: bool,
+    // the color was multiplied by alpha when loading so the texture gets blended with gl::ONE, gl::ONE_MINUS_SRC_ALPHA
+    pub has_premultiplied_alpha: bool,
     pub uses_fake_lighting: bool,
+    // only used by normal mapped models
+    pub normal_strength: f32,
     // if this is 1 then the texture is not an atlas
     // also rows == columns since textures are power of two squares and so are textures
     pub number_of_rows_in_atlas: usize,
+    // tex coords are scaled then offset before the atlas lookup, a scale of (n, n) tiles the texture n times in each direction
+    pub uv_scale: Vector2f,
+    pub uv_offset: Vector2f,
 }
 
 impl Default for ModelTexture {
@@ -439,18 +852,66 @@ impl Default for ModelTexture {
             shine_damper: 1.0,
             reflectivity: 0.0,
             has_transparency: false,
+            has_premultiplied_alpha: false,
             uses_fake_lighting: false,
+            normal_strength: 1.0,
             number_of_rows_in_atlas: 1,
+            uv_scale: Vector2f::new(1.0, 1.0),
+            uv_offset: Vector2f::zero(),
         }
     }
 }
 
+impl ModelTexture {
+    // same as pass_tex_coord in the entity vertex shaders
+    pub fn transform_tex_coord(&self, tex_coord: &Vector2f, atlas_offset: &Vector2f) -> Vector2f {
+        let num_rows = self.number_of_rows_in_atlas as f32;
+        Vector2f::new(
+            (tex_coord.x * self.uv_scale.x + self.uv_offset.x) / num_rows + atlas_offset.x,
+            (tex_coord.y * self.uv_scale.y + self.uv_offset.y) / num_rows + atlas_offset.y,
+        )
+    }
+}
+
 #[derive(Clone)]
 pub struct TexturedModel {
     pub raw_model: RawModel,
     pub texture: ModelTexture,
     pub normal_map_tex_id: Option<TextureId>,
     pub extra_info_tex_id: Option<TextureId>,
+    // distance of the furthest vertex from the model origin, None when unknown so the model is never culled
+    pub bounding_radius: Option<f32>,
+    // min and max corner of the box around the vertices in model space, the model doesn't have to be centered on its origin
+    pub bounds: Option<(Vector3f, Vector3f)>,
+}
+
+// a model without vao, textures or bounds e.g. for tests that only need a model handle
+impl Default for TexturedModel {
+    fn default() -> TexturedModel {
+        TexturedModel {
+            raw_model: RawModel::default(),
+            texture: ModelTexture::default(),
+            normal_map_tex_id: None,
+            extra_info_tex_id: None,
+            bounding_radius: None,
+            bounds: None,
+        }
+    }
+}
+
+impl TexturedModel {
+    // a model is only usable once every texture it references has been uploaded
+    pub fn is_ready_with(&self, texture_token_map: &HashMap<u32, u32>) -> bool {
+        let is_ready = |tex_id: &TextureId| tex_id.resolve_with(texture_token_map).is_ready();
+        is_ready(&self.texture.tex_id)
+            && self.normal_map_tex_id.as_ref().map_or(true, is_ready)
+            && self.extra_info_tex_id.as_ref().map_or(true, is_ready)
+    }
+
+    // blended models are drawn after the opaque ones
+    pub fn is_transparent(&self) -> bool {
+        self.texture.has_transparency || self.texture.has_premultiplied_alpha
+    }
 }
 
 impl PartialEq for TexturedModel {
@@ -471,7 +932,8 @@ impl Hash for TexturedModel {
 #[derive(Clone)]
 pub struct TerrainModel {
     pub raw_model: RawModel,
-    pub height_map: Rc<Vec<Vec<f32>>>,
+    // shared with the threads that update particles colliding with the terrain
+    pub height_map: Arc<Vec<Vec<f32>>>,
 }
 
 #[derive(Clone)]
@@ -492,6 +954,8 @@ pub struct WaterModel {
     pub raw_model: RawModel,
     pub dudv_tex_id: TextureId,
     pub normal_map_tex_id: TextureId,
+    // the maps were loaded with these, the water renderer loads the rest into the shader
+    pub props: WaterProps,
 }
 
 #[derive(Default, Clone, PartialEq, Eq, Hash)]
@@ -506,6 +970,26 @@ pub struct DynamicVertexIndexedModel {
     pub stream_draw_vbo: u32,
 }
 
+// normals and tangents of a model drawn as lines, the line vertices are only known once the line length is
+#[derive(Clone)]
+pub struct DebugVectorLinesModel {
+    // normal lines followed by tangent lines, two vertices per line
+    pub lines: DynamicVertexIndexedModel,
+    // entities using the model with this vao get the lines drawn on top
+    pub target_vao_id: u32,
+    pub positions: Vec<f32>,
+    pub normals: Vec<f32>,
+    pub tangents: Vec<f32>,
+}
+
+// every triangle edge of a model once, for drawing it as a wireframe
+#[derive(Clone)]
+pub struct DebugEdgesModel {
+    pub lines: RawModel,
+    // entities using the model with this vao get the edges drawn on top
+    pub target_vao_id: u32,
+}
+
 impl ParticleModel {    
     pub const MODELVIEW_COLUMN1: u32 = 1;    
     pub const MODELVIEW_COLUMN2: u32 = 2;    
@@ -523,11 +1007,355 @@ impl ParticleModel {
 pub struct ParticleTexture {
     pub tex_id: TextureId,
     pub number_of_rows_in_atlas: usize,
-    pub additive: bool,
+    pub blend_mode: ParticleBlendMode,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum ParticleBlendMode {
+    // regular alpha blending where overlapping particles cover each other
+    Alpha,
+    // colors are always added which is good for effects like magic that get shinier where particles overlap
+    Additive,
+    // premultiplied alpha blending, the weight goes from alpha blending at 0.0 to additive blending at 1.0
+    // e.g. smoke that glows slightly
+    Mixed(f32),
+}
+
+impl ParticleBlendMode {
+    // source and destination factors for gl::blend_func
+    pub fn blend_factors(&self) -> (gl::types::GLenum, gl::types::GLenum) {
+        match self {
+            ParticleBlendMode::Alpha => (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA),
+            // this is achieved by always using 1.0 for the destination (already rendered) unlike gl::ONE_MINUS_SRC_ALPHA in alpha blending
+            ParticleBlendMode::Additive => (gl::SRC_ALPHA, gl::ONE),
+            // the shader multiplies the color by alpha and scales alpha down by the additive weight
+            // so the destination is kept more the more additive the blend is
+            ParticleBlendMode::Mixed(_) => (gl::ONE, gl::ONE_MINUS_SRC_ALPHA),
+        }
+    }
+
+    // the additive weight the shader should use for premultiplying or None if the color is to be output unchanged
+    pub fn premultiplied_additive_weight(&self) -> Option<f32> {
+        match self {
+            ParticleBlendMode::Mixed(additive_weight) => Some(additive_weight.max(0.0).min(1.0)),
+            _ => None,
+        }
+    }
+}
+
+impl Default for ParticleBlendMode {
+    fn default() -> Self {
+        ParticleBlendMode::Alpha
+    }
+}
+
+// the weight is compared bitwise so that the blend mode can be part of the particle texture hash map key
+impl PartialEq for ParticleBlendMode {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (ParticleBlendMode::Alpha, ParticleBlendMode::Alpha) => true,
+            (ParticleBlendMode::Additive, ParticleBlendMode::Additive) => true,
+            (ParticleBlendMode::Mixed(a), ParticleBlendMode::Mixed(b)) => a.to_bits() == b.to_bits(),
+            _ => false,
+        }
+    }
+}
+
+impl Eq for ParticleBlendMode {}
+
+impl Hash for ParticleBlendMode {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        match self {
+            ParticleBlendMode::Alpha => 0u32.hash(state),
+            ParticleBlendMode::Additive => 1u32.hash(state),
+            ParticleBlendMode::Mixed(additive_weight) => {
+                2u32.hash(state);
+                additive_weight.to_bits().hash(state);
+            },
+        }
+    }
 }
 
 #[derive(Default, Clone, PartialEq, Eq, Hash)]
 pub struct ParticleTexturedModel {
     pub model: ParticleModel,
     pub texture: ParticleTexture,
-}
\ No newline at end of file
+}
+// rounds to the nearest value so that opaque texels keep their exact color
+fn premultiply_alpha(texture: &mut Texture2DRGBA) {
+    for texel in texture.data.iter_mut() {
+        let alpha = texel.a as u32;
+        let premultiply = |channel: u8| ((channel as u32 * alpha + 127) / 255) as u8;
+        texel.r = premultiply(texel.r);
+        texel.g = premultiply(texel.g);
+        texel.b = premultiply(texel.b);
+    }
+}
+
+// a missing texture shouldn't take down the whole app, the error goes to the log and the model is drawn in magenta instead
+fn load_texture_or_fallback(file_name: &str, reverse: bool) -> Texture2DRGBA {
+    match load_rgba_2d_texture(file_name, reverse) {
+        Ok(texture) => texture,
+        Err(err) => {
+            log_error!("Failed to load texture {}: {}", file_name, err);
+            Texture2DRGBA {
+                width: 1,
+                height: 1,
+                data: vec![RGBA { r: 255, g: 0, b: 255, a: 255 }],
+            }
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+    use texture_lib::texture_loader::{
+        Texture,
+        RGBA,
+    };
+
+    #[test]
+    fn n_loaded_textures_notify_n_times_and_all_done_once() {
+        let events = Rc::new(RefCell::new(Vec::new()));
+        let mut listeners = LoadListeners::default();
+        let events_clone = events.clone();
+        listeners.add(Box::new(move |event| events_clone.borrow_mut().push(event)));
+
+        let queued_cnt = 5;
+        for token in 1..=queued_cnt {
+            listeners.resource_finished(ResourceLoadEvent::TextureLoaded(TextureId::Loading(token)), queued_cnt - token);
+        }
+
+        let events = events.borrow();
+        let loaded_cnt = events.iter().filter(|event| match event { ResourceLoadEvent::TextureLoaded(_) => true, _ => false }).count();
+        let all_done_cnt = events.iter().filter(|event| **event == ResourceLoadEvent::AllLoaded).count();
+        assert_eq!(loaded_cnt, queued_cnt as usize);
+        assert_eq!(all_done_cnt, 1);
+        assert_eq!(*events.last().unwrap(), ResourceLoadEvent::AllLoaded);
+    }
+
+    #[test]
+    fn uv_transform_tiles_and_offsets_tex_coords() {
+        let mut texture = ModelTexture::default();
+        let untouched = texture.transform_tex_coord(&Vector2f::new(0.25, 0.75), &Vector2f::zero());
+        assert_eq!((untouched.x, untouched.y), (0.25, 0.75));
+
+        texture.uv_scale = Vector2f::new(4.0, 2.0);
+        texture.uv_offset = Vector2f::new(0.5, 0.0);
+        let tiled = texture.transform_tex_coord(&Vector2f::new(0.25, 0.75), &Vector2f::zero());
+        assert_eq!((tiled.x, tiled.y), (1.5, 1.5));
+
+        // the atlas is applied after the uv transform so it stays in its cell
+        texture.number_of_rows_in_atlas = 2;
+        texture.uv_scale = Vector2f::new(1.0, 1.0);
+        texture.uv_offset = Vector2f::zero();
+        let in_atlas = texture.transform_tex_coord(&Vector2f::new(1.0, 0.5), &Vector2f::new(0.5, 0.0));
+        assert_eq!((in_atlas.x, in_atlas.y), (1.0, 0.25));
+    }
+
+    #[test]
+    fn anisotropic_amount_is_clamped() {
+        let max_hardware_amount = 8.0;
+        assert_eq!(TextureParams::clamp_anisotropic_amount(16.0, max_hardware_amount), 8.0);
+        assert_eq!(TextureParams::clamp_anisotropic_amount(0.5, max_hardware_amount), 1.0);
+        let params = TextureParams::anisotropic_texture();
+        assert_eq!(TextureParams::clamp_anisotropic_amount(params.anisotropic_amount, max_hardware_amount), TextureParams::DEFAULT_ANISOTROPIC_AMOUNT);
+    }
+
+    #[test]
+    fn terrain_filtering_selects_anisotropic_params_when_enabled() {
+        let params = TextureFiltering::default().texture_params();
+        assert!(params.use_mipmap && !params.use_anisotropic_filtering);
+        assert_eq!(params.mipmap_lod, -0.4);
+
+        let params = TextureFiltering::Anisotropic(16.0).texture_params();
+        assert!(params.use_mipmap && params.use_anisotropic_filtering);
+        assert_eq!(params.anisotropic_amount, 16.0);
+    }
+
+    #[test]
+    fn water_maps_use_mipmapped_params_only_when_filtering_is_enabled() {
+        let params = WaterProps::default().map_texture_params();
+        assert!(!params.use_mipmap && !params.use_anisotropic_filtering);
+
+        let params = WaterProps { map_filtering: Some(TextureFiltering::Trilinear(-0.5)), ..WaterProps::default() }.map_texture_params();
+        assert!(params.use_mipmap && !params.use_anisotropic_filtering);
+        assert_eq!(params.mipmap_lod, -0.5);
+
+        let params = WaterProps { map_filtering: Some(TextureFiltering::Anisotropic(8.0)), ..WaterProps::default() }.map_texture_params();
+        assert!(params.use_mipmap && params.use_anisotropic_filtering);
+        assert_eq!(params.anisotropic_amount, 8.0);
+    }
+
+    #[test]
+    fn vertex_colors_go_into_the_color_attribute() {
+        let positions = [0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0, 0.0];
+        let texture_coords = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let normals = [0.0, 0.0, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0, 1.0];
+        let colors = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let attributes = RawModel::attributes_with_colors(&positions, &texture_coords, &normals, &colors);
+
+        let (_, coord_size, data) = attributes.iter().find(|(attribute_num, _, _)| *attribute_num == RawModel::COLOR_ATTRIB).unwrap();
+        assert_eq!(*coord_size, 3);
+        assert_eq!(data.len() / *coord_size as usize, positions.len() / 3);
+        // no other attribute uses the color slot
+        assert_eq!(attributes.iter().filter(|(attribute_num, _, _)| *attribute_num == RawModel::COLOR_ATTRIB).count(), 1);
+
+        let raw_model = RawModel::new_with_vertex_colors(1, 3);
+        assert!(raw_model.has_vertex_colors && !RawModel::new(1, 3).has_vertex_colors);
+    }
+
+    #[test]
+    fn upload_budget_defers_textures_to_later_frames() {
+        let (sender, receiver) = mpsc::channel();
+        for size in [100, 200, 300].iter() {
+            sender.send(*size).unwrap();
+        }
+        let budget = TextureUploadBudget::Count(1);
+        let mut deferred = None;
+        let mut calls = 0;
+        let mut drained = Vec::new();
+        while drained.len() < 3 {
+            drained.extend(budget.receive_within_budget(&receiver, &mut deferred, |size| *size));
+            calls += 1;
+        }
+        assert_eq!(calls, 3);
+        assert_eq!(drained, vec![100, 200, 300]);
+        assert!(budget.receive_within_budget(&receiver, &mut deferred, |size| *size).is_empty());
+    }
+
+    #[test]
+    fn zero_budgets_still_upload_one_texture_per_call() {
+        let (sender, receiver) = mpsc::channel();
+        for size in [100, 200].iter() {
+            sender.send(*size).unwrap();
+        }
+        let mut deferred = None;
+        assert_eq!(TextureUploadBudget::Count(0).receive_within_budget(&receiver, &mut deferred, |size| *size), vec![100]);
+        assert_eq!(TextureUploadBudget::Bytes(0).receive_within_budget(&receiver, &mut deferred, |size| *size), vec![200]);
+    }
+
+    #[test]
+    fn byte_budget_is_only_exceeded_by_a_single_large_texture() {
+        let (sender, receiver) = mpsc::channel();
+        for size in [500, 100, 100, 50].iter() {
+            sender.send(*size).unwrap();
+        }
+        let budget = TextureUploadBudget::Bytes(150);
+        let mut deferred = None;
+        assert_eq!(budget.receive_within_budget(&receiver, &mut deferred, |size| *size), vec![500]);
+        assert_eq!(budget.receive_within_budget(&receiver, &mut deferred, |size| *size), vec![100]);
+        assert_eq!(deferred, Some(100));
+        assert_eq!(budget.receive_within_budget(&receiver, &mut deferred, |size| *size), vec![100, 50]);
+        assert_eq!(deferred, None);
+    }
+
+    #[test]
+    fn lod_bias_adds_global_to_texture_bias() {
+        let grass = TextureParams::mipmapped_texture(-2.4);
+        let ground = TextureParams::mipmapped_texture(-0.4);
+        assert_eq!(TextureParams::effective_lod_bias(grass.mipmap_lod, 0.0), -2.4);
+        assert!((TextureParams::effective_lod_bias(grass.mipmap_lod, 0.5) - -1.9).abs() < 1e-6);
+        assert!((TextureParams::effective_lod_bias(ground.mipmap_lod, -0.6) - -1.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn particle_blend_modes_map_to_blend_factors() {
+        assert_eq!(ParticleBlendMode::Alpha.blend_factors(), (gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA));
+        assert_eq!(ParticleBlendMode::Additive.blend_factors(), (gl::SRC_ALPHA, gl::ONE));
+        assert_eq!(ParticleBlendMode::Mixed(0.3).blend_factors(), (gl::ONE, gl::ONE_MINUS_SRC_ALPHA));
+        assert_eq!(ParticleBlendMode::Alpha.premultiplied_additive_weight(), None);
+        assert_eq!(ParticleBlendMode::Mixed(0.3).premultiplied_additive_weight(), Some(0.3));
+        assert_eq!(ParticleBlendMode::Mixed(2.0).premultiplied_additive_weight(), Some(1.0));
+    }
+
+    #[test]
+    fn premultiplying_scales_color_by_alpha() {
+        let texel = |r, g, b, a| RGBA { r, g, b, a };
+        let mut texture = Texture {
+            width: 3,
+            height: 1,
+            data: vec![texel(200, 100, 50, 255), texel(200, 100, 50, 128), texel(255, 255, 255, 0)],
+        };
+        premultiply_alpha(&mut texture);
+
+        let colors: Vec<(u8, u8, u8, u8)> = texture.data.iter().map(|texel| (texel.r, texel.g, texel.b, texel.a)).collect();
+        assert_eq!(colors, vec![(200, 100, 50, 255), (100, 50, 25, 128), (0, 0, 0, 0)]);
+    }
+
+    #[test]
+    fn model_is_ready_once_all_its_textures_resolve() {
+        let model = TexturedModel {
+            raw_model: RawModel::default(),
+            texture: ModelTexture { tex_id: TextureId::Loading(1), ..Default::default() },
+            normal_map_tex_id: Some(TextureId::Loading(2)),
+            ..TexturedModel::default()
+        };
+        let mut texture_token_map = HashMap::new();
+        assert!(!model.is_ready_with(&texture_token_map));
+
+        texture_token_map.insert(1, 10);
+        assert!(!model.is_ready_with(&texture_token_map));
+
+        texture_token_map.insert(2, 11);
+        assert!(model.is_ready_with(&texture_token_map));
+    }
+
+    #[test]
+    fn failed_texture_load_is_logged_and_falls_back() {
+        use crate::logging::{self, LogLevel};
+        use std::sync::{Arc, Mutex};
+
+        let captured = Arc::new(Mutex::new(Vec::new()));
+        let captured_clone = captured.clone();
+        logging::set_logger(Some(Box::new(move |level, message: &str| captured_clone.lock().unwrap().push((level, message.to_string())))));
+
+        let missing_file = "res/textures/does_not_exist.png";
+        let texture = load_texture_or_fallback(missing_file, false);
+        logging::set_logger(None);
+
+        assert_eq!((texture.width, texture.height), (1, 1));
+        assert_eq!(texture.data.len(), 1);
+        // other tests may log at the same time so only look for our message
+        let captured = captured.lock().unwrap();
+        assert!(captured.iter().any(|(level, message)| *level == LogLevel::Error && message.contains(missing_file)));
+    }
+
+    #[test]
+    fn anisotropic_texture_reports_clamped_amount_and_mipmaps() {
+        let mut loader = ModelLoader::new();
+        loader.record_texture_filtering(3, &TextureParams::anisotropic_texture_with_amount(16.0), Some(8.0));
+        loader.record_texture_filtering(4, &TextureParams::anisotropic_texture_with_amount(16.0), None);
+
+        let applied = loader.texture_filtering(TextureId::Loaded(3)).unwrap();
+        assert!(applied.mipmaps);
+        assert_eq!(applied.min_filter, TextureMinFilter::LinearMipmapLinear);
+        assert_eq!(applied.anisotropy, Some(8.0));
+        assert_eq!(loader.texture_filtering(TextureId::Loaded(4)).unwrap().anisotropy, None);
+        assert_eq!(loader.texture_filtering(TextureId::Loading(3)), None);
+    }
+
+    #[test]
+    fn water_distortion_fades_out_towards_the_shore() {
+        let props = WaterProps { distortion_falloff_depth: 4.0, ..WaterProps::default() };
+        assert_eq!(props.distortion_fade(0.0), 0.0);
+        assert_eq!(props.distortion_fade(2.0), 0.5);
+        assert_eq!(props.distortion_fade(4.0), 1.0);
+        assert_eq!(props.distortion_fade(30.0), 1.0);
+        // the refraction depth behind geometry above the water can be negative
+        assert_eq!(props.distortion_fade(-2.0), 0.0);
+        // smooth at both ends so the fade has no visible line where it starts or ends
+        assert!(props.distortion_fade(0.1) < 0.1 / 4.0);
+        assert!(props.distortion_fade(3.9) > 3.9 / 4.0);
+        let fades: Vec<f32> = (0..=8).map(|i| props.distortion_fade(i as f32 * 0.5)).collect();
+        assert!(fades.windows(2).all(|pair| pair[0] < pair[1]));
+
+        // without a falloff the water is distorted everywhere except right at its edge
+        let props = WaterProps { distortion_falloff_depth: 0.0, ..WaterProps::default() };
+        assert_eq!(props.distortion_fade(0.0), 0.0);
+        assert_eq!(props.distortion_fade(0.5), 1.0);
+    }
+}
@@ -5,6 +5,8 @@ use super::{
         TerrainTexture,  
         TerrainTexturePack,
         TextureParams,
+        TextureFiltering,
+        WaterProps,
         TerrainModel,
         QuadModel,
         SkyboxModel,
@@ -12,23 +14,41 @@ use super::{
         ParticleModel,
         ParticleTexture,
         DynamicVertexIndexedModel,
+        DebugVectorLinesModel,
+        DebugEdgesModel,
         RawModel,
+        ResourceLoadCallback,
+        TextureUploadBudget,
+        AppliedTextureFiltering,
+    },
+    terrain_generator::{
+        HeightsGenerator,
+        NoiseType,
+        TerrainNeighbors,
     },
-    terrain_generator::HeightsGenerator,
     texture_id::TextureId,
     collada_load_helper::load_collada_animated_model,
     CorrectionTransform,
+    MeshPreTransform,
 };
 use crate::animations::{
     AnimatedModel,    
 };
-use crate::entities::Terrain;
+use crate::entities::{
+    Entity,
+    EntityTransform,
+    Ground,
+    Terrain,
+    TerrainTileManager,
+};
 use crate::obj_converter::{
     load_obj_model,
-    load_simple_obj_model
+    load_simple_obj_model,
+    ModelData,
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use crate::guis::{
+    GuiPanel,
     text::FontType,
     text::GuiText,
     text::TextMaterial,
@@ -37,6 +57,7 @@ use crate::guis::{
 use crate::math::{
     Vector2f,
 };
+use crate::renderers::debug_renderer::unique_edges;
 
 #[derive(Default)]
 pub struct ResourceManager {
@@ -44,9 +65,14 @@ pub struct ResourceManager {
     terrain_generator: HeightsGenerator,
     texture_pack: Option<TerrainTexturePack>,
     blend_texture: Option<TerrainTexture>,
+    // used for the texture pack and blend map, terrain is mostly seen at grazing angles where anisotropic filtering helps the most
+    terrain_texture_filtering: TextureFiltering,
     terrain_model: Option<TerrainModel>,
+    // meshes of streamed terrain tiles by generator seed and tile index, freed with release_terrain_tile
+    terrain_tiles: HashMap<(u32, NoiseType, i32, i32), TerrainModel>,
     quad_model: Option<QuadModel>,
     water_model: Option<WaterModel>,
+    water_props: WaterProps,
     // skyboxes
     skybox_model: Option<SkyboxModel>,
     cathedral_skybox: Option<SkyboxModel>,
@@ -57,6 +83,10 @@ pub struct ResourceManager {
     simple_point_particle_model: Option<ParticleModel>,
     // debugging models
     debug_model: Option<DynamicVertexIndexedModel>,
+    // vertex data is dropped after upload unless the model was asked for before init, e.g. to draw its normals
+    retained_vertex_data_models: HashSet<ModelType>,
+    retains_all_vertex_data: bool,
+    vertex_data: HashMap<ModelType, ModelData>,
     
     models: HashMap<ModelType, TexturedModel>,
     gui_textures: HashMap<&'static str, TextureId>,
@@ -105,6 +135,8 @@ pub struct AtlasProps(usize);
 
 pub struct ModelProps {
     pub has_transparency: bool,
+    // avoids dark halos around the edges of transparent textures like foliage
+    pub has_premultiplied_alpha: bool,
     pub uses_fake_lighting: bool,
     pub uses_mipmaps: bool,
     pub uses_anisotropic_filtering: bool,
@@ -112,12 +144,16 @@ pub struct ModelProps {
     pub reflectivity: f32,
     pub atlas_props: AtlasProps,
     pub normal_map: Option<&'static str>,
+    // how much the normal map perturbs the surface normal in [0,1]
+    pub normal_strength: f32,
     pub extra_info_map: Option<&'static str>,
+    // for models exported with other axis conventions or units, baked into the mesh when it is loaded
+    pub pre_transform: Option<MeshPreTransform>,
 }
 
 impl ModelProps {
     fn get_texture_params(&self) -> TextureParams {        
-        if self.uses_mipmaps {
+        let params = if self.uses_mipmaps {
             if self.uses_anisotropic_filtering {
                 TextureParams::anisotropic_texture()
             } else if self.normal_map.is_some() {
@@ -127,7 +163,12 @@ impl ModelProps {
             }
         } else {
             TextureParams::default()
-        }        
+        };
+        if self.has_premultiplied_alpha {
+            params.with_premultiplied_alpha()
+        } else {
+            params
+        }
     }
 }
 
@@ -138,6 +179,7 @@ pub struct Models;
 impl Models {
     const DEFAULT_PROPS: ModelProps = ModelProps {
         has_transparency: false, 
+        has_premultiplied_alpha: false,
         uses_fake_lighting: false, 
         uses_mipmaps: false,
         uses_anisotropic_filtering: false,
@@ -145,7 +187,9 @@ impl Models {
         reflectivity: 0.0, 
         atlas_props: AtlasProps(1),
         normal_map: None,
+        normal_strength: 1.0,
         extra_info_map: None,
+        pre_transform: None,
     };
     const GUI_PROPS: ModelProps = ModelProps {        
         ..Self::DEFAULT_PROPS
@@ -244,6 +288,31 @@ impl ResourceManager {
     pub const SMOKE_ATLAS: ParticleTextureProps = ("res/textures/particles/smoke.png", 8);
     pub const FIRE_ATLAS: ParticleTextureProps = ("res/textures/particles/fire.png", 8);
     
+    pub fn add_load_listener(&mut self, callback: ResourceLoadCallback) {
+        self.loader.add_load_listener(callback);
+    }
+
+    pub fn set_texture_upload_budget(&mut self, upload_budget: TextureUploadBudget) {
+        self.loader.set_upload_budget(upload_budget);
+    }
+
+    // live tweakable sharpness of all mipmapped textures on top of their own lod bias
+    pub fn set_texture_lod_bias(&mut self, lod_bias: f32) {
+        self.loader.set_global_lod_bias(lod_bias);
+    }
+
+    // for debug overlays, what filtering a texture really got after clamping to the hardware limits
+    pub fn texture_filtering(&self, texture_id: TextureId) -> Option<&AppliedTextureFiltering> {
+        self.loader.texture_filtering(texture_id)
+    }
+
+    // gui panels keep the texture id they were created with, so panels created before their texture finished loading get resolved here
+    pub fn resolve_gui_panels(&self, guis: &mut [GuiPanel]) {
+        for gui in guis.iter_mut() {
+            gui.texture_id = self.loader.try_resolve(gui.texture_id);
+        }
+    }
+
     pub fn are_textures_loading(&mut self) -> bool {
         if self.loader.loading_texture_cnt == 0 {
             self.texture_pack = self.texture_pack.take().map(|mut texture_pack| {
@@ -327,22 +396,39 @@ impl ResourceManager {
         }
     }
 
+    fn bake_pre_transform(model_props: &ModelProps, model_data: &mut ModelData) {
+        if let Some(pre_transform) = &model_props.pre_transform {
+            pre_transform.correction_transform().apply_to_model_data(model_data);
+        }
+    }
+
     pub fn init(&mut self, Model(model_type, obj_file, texture_file, model_props): &Model) {
         // thread safe coz only one mutable reference to resource manager can be held
         if self.models.contains_key(model_type) {
             return;
         }
         
-        let (raw_model, normal_map) = if let Some(normal_map_texture) = model_props.normal_map {
-            let model_data = load_obj_model(obj_file, true).expect(&format!("Unable to load {}", obj_file));
+        let (raw_model, normal_map, model_data) = if let Some(normal_map_texture) = model_props.normal_map {
+            let mut model_data = load_obj_model(obj_file, true).expect(&format!("Unable to load {}", obj_file));
+            ResourceManager::bake_pre_transform(model_props, &mut model_data);
             let normal_map = self.loader.load_texture(normal_map_texture, TextureParams::default());
             let raw_model = self.loader.load_to_vao_with_normal_map(&model_data.vertices, &model_data.texture_coords, &model_data.indices, &model_data.normals, &model_data.tangents);
-            (raw_model, Some(normal_map.tex_id))
+            (raw_model, Some(normal_map.tex_id), model_data)
         } else {            
-            let model_data = load_simple_obj_model(obj_file).expect(&format!("Unable to load simple {}", obj_file));
-            let raw_model = self.loader.load_to_vao(&model_data.vertices, &model_data.texture_coords, &model_data.indices, &model_data.normals);            
-            (raw_model, None)
+            let mut model_data = load_simple_obj_model(obj_file).expect(&format!("Unable to load simple {}", obj_file));
+            ResourceManager::bake_pre_transform(model_props, &mut model_data);
+            let raw_model = if model_data.colors.is_empty() {
+                self.loader.load_to_vao(&model_data.vertices, &model_data.texture_coords, &model_data.indices, &model_data.normals)
+            } else {
+                self.loader.load_to_vao_with_colors(&model_data.vertices, &model_data.texture_coords, &model_data.indices, &model_data.normals, &model_data.colors)
+            };
+            (raw_model, None, model_data)
         };
+        let bounding_radius = model_data.furthest_point;
+        let bounds = model_data.bounds();
+        if self.retains_all_vertex_data || self.retained_vertex_data_models.contains(model_type) {
+            self.vertex_data.insert(model_type.clone(), model_data);
+        }
 
         let extra_info_texture = if let Some(extra_info_tex_name) = model_props.extra_info_map {
             let texture = self.loader.load_texture(extra_info_tex_name, TextureParams::default());
@@ -353,11 +439,13 @@ impl ResourceManager {
         
         let mut texture = self.loader.load_texture(texture_file, model_props.get_texture_params());
         texture.has_transparency = model_props.has_transparency;
+        texture.has_premultiplied_alpha = model_props.has_premultiplied_alpha;
         texture.uses_fake_lighting = model_props.uses_fake_lighting;
         texture.shine_damper = model_props.shine_damper;
         texture.reflectivity = model_props.reflectivity;
+        texture.normal_strength = model_props.normal_strength;
         texture.number_of_rows_in_atlas = model_props.atlas_props.0;
-        let model = TexturedModel { raw_model, texture, normal_map_tex_id: normal_map, extra_info_tex_id: extra_info_texture };
+        let model = TexturedModel { raw_model, texture, normal_map_tex_id: normal_map, extra_info_tex_id: extra_info_texture, bounding_radius: Some(bounding_radius), bounds: Some(bounds) };
 
         self.models.insert(model_type.clone(), model);
     }
@@ -365,17 +453,29 @@ impl ResourceManager {
     pub fn model(&self, model_type: ModelType) -> TexturedModel {
         self.models.get(&model_type).expect(&format!("Need to call init_model({:?}) before accessing the model", model_type)).clone()
     }
+
+    // looks the model up once instead of once per entity
+    pub fn spawn_many(&self, model_type: ModelType, transforms: &[EntityTransform]) -> Vec<Entity> {
+        let model = self.models.get(&model_type).expect(&format!("Need to call init_model({:?}) before spawning entities", model_type));
+        Entity::spawn_many(model, transforms)
+    }
     
+    // has to be set before init_terrain_textures, textures that are already loaded keep their filtering
+    pub fn set_terrain_texture_filtering(&mut self, filtering: TextureFiltering) {
+        self.terrain_texture_filtering = filtering;
+    }
+
     pub fn init_terrain_textures(&mut self) {        
+        let filtering = self.terrain_texture_filtering;
         if let None = self.texture_pack {
-            let background_texture = self.loader.load_terrain_texture("res/textures/terrain/grassy2.png", TextureParams::mipmapped_texture(-0.4));
-            let r_texture = self.loader.load_terrain_texture("res/textures/terrain/mud.png", TextureParams::mipmapped_texture(-0.4));
-            let g_texture = self.loader.load_terrain_texture("res/textures/terrain/grassFlowers.png", TextureParams::mipmapped_texture(-0.4));
-            let b_texture = self.loader.load_terrain_texture("res/textures/terrain/path.png", TextureParams::mipmapped_texture(-0.4));
+            let background_texture = self.loader.load_terrain_texture("res/textures/terrain/grassy2.png", filtering.texture_params());
+            let r_texture = self.loader.load_terrain_texture("res/textures/terrain/mud.png", filtering.texture_params());
+            let g_texture = self.loader.load_terrain_texture("res/textures/terrain/grassFlowers.png", filtering.texture_params());
+            let b_texture = self.loader.load_terrain_texture("res/textures/terrain/path.png", filtering.texture_params());
             self.texture_pack = Some(TerrainTexturePack { background_texture, r_texture, g_texture, b_texture, });
         }
         if let None = self.blend_texture {
-            self.blend_texture = Some(self.loader.load_terrain_texture("res/textures/terrain/blendMap.png", TextureParams::mipmapped_texture(-0.4)));
+            self.blend_texture = Some(self.loader.load_terrain_texture("res/textures/terrain/blendMap.png", filtering.texture_params()));
         }
     }
 
@@ -394,10 +494,55 @@ impl ResourceManager {
         }
     }
 
+    // loads the terrain from a gray png instead of generating it, has to be called before init_terrain_model
+    pub fn init_terrain_model_from_heightmap(&mut self, heightmap: &str, vertical_scale: f32) {
+        if let None = self.terrain_model {
+            let model = Terrain::from_heightmap(&mut self.loader, heightmap, vertical_scale);
+            self.terrain_model = Some(model);
+        }
+    }
+
     pub fn terrain_model(&self) -> TerrainModel {
         self.terrain_model.clone().expect("Need to call init_terrain_model before accessing the model")
     }
 
+    // a tile of a seamless world (HeightsGenerator::new_seamless), all tiles have to come from the same generator
+    // the edge normals are made to match the already loaded neighbours (Ground::neighbors)
+    pub fn terrain_tile(&mut self, tile_x: i32, tile_z: i32, generator: &HeightsGenerator, neighbors: TerrainNeighbors) -> Terrain {
+        let loader = &mut self.loader;
+        let model = self.terrain_tiles.entry(ResourceManager::terrain_tile_key(generator, tile_x, tile_z))
+            .or_insert_with(|| Terrain::generate_terrain_with_neighbors(loader, &generator.tile(tile_x, tile_z), neighbors))
+            .clone();
+        Terrain::new(tile_x, tile_z, self.terrain_pack(), self.blend_texture(), model)
+    }
+
+    // keeps the tiles of the ground around x, z loaded, the loaded tiles that got a new neighbour
+    // get their edge normals recomputed so the seam is lit the same from both sides
+    pub fn stream_terrain_tiles(&mut self, tile_manager: &TerrainTileManager, generator: &HeightsGenerator, ground: &mut Ground, x: f32, z: f32) {
+        let update = tile_manager.update(ground, x, z, |(tile_x, tile_z), ground| self.terrain_tile(tile_x, tile_z, generator, ground.neighbors(tile_x, tile_z)));
+        for (tile, normals) in update.seam_normals.iter() {
+            if let Some(terrain) = ground.terrains.iter().find(|terrain| terrain.tile_index() == *tile) {
+                self.loader.update_normals(terrain.model.raw_model.vao_id, normals);
+            }
+        }
+        for terrain in update.unloaded {
+            self.release_terrain_tile(terrain, generator);
+        }
+    }
+
+    // generators with the same seed but another noise give different heights so they can't share tiles
+    fn terrain_tile_key(generator: &HeightsGenerator, tile_x: i32, tile_z: i32) -> (u32, NoiseType, i32, i32) {
+        (generator.seed(), generator.noise_type(), tile_x, tile_z)
+    }
+
+    // frees the mesh of a tile that was unloaded (TerrainTileManager::update), the generator has to be the one it was created with
+    pub fn release_terrain_tile(&mut self, terrain: Terrain, generator: &HeightsGenerator) {
+        let (tile_x, tile_z) = terrain.tile_index();
+        if let Some(model) = self.terrain_tiles.remove(&ResourceManager::terrain_tile_key(generator, tile_x, tile_z)) {
+            self.loader.unload_vao(model.raw_model.vao_id);
+        }
+    }
+
     pub fn init_gui_textures(&mut self) {        
         let props = Models::GUI_PROPS;
         if !self.gui_textures.contains_key(ResourceManager::HEALTHBAR_TEXTURE) {
@@ -416,6 +561,15 @@ impl ResourceManager {
         }
     }
 
+    // per asset alternative to are_textures_loading, models that were never initialized are not ready
+    pub fn is_ready(&self, model_type: ModelType) -> bool {
+        self.models.get(&model_type).map_or(false, |model| self.loader.is_model_ready(model))
+    }
+
+    pub fn is_texture_ready(&self, texture_name: &str) -> bool {
+        self.gui_textures.get(texture_name).map_or(false, |tex_id| self.loader.is_texture_ready(*tex_id))
+    }
+
     pub fn get_gui_texture(&self, texture_name: &str) -> TextureId {
          self.gui_textures.get(texture_name).expect("Must call init_gui_textures first").clone()    
     }
@@ -478,6 +632,12 @@ impl ResourceManager {
         }
     }
 
+    // has to be set before init_water, same as the terrain filtering
+    // the water models keep them so the water renderer loads them into the shader
+    pub fn set_water_props(&mut self, props: WaterProps) {
+        self.water_props = props;
+    }
+
     pub fn init_water(&mut self) {
         if let None = self.water_model {
             let positions = vec![
@@ -487,12 +647,13 @@ impl ResourceManager {
                 1.0, 0.0, -1.0, 
             ];
             let raw_model = self.loader.load_simple_model_to_vao(&positions, 3);
-            let dudv_tex_id = self.loader.load_terrain_texture("res/textures/water/waterDUDV.png", TextureParams::default()).tex_id;
-            let normal_map_tex_id = self.loader.load_terrain_texture("res/textures/water/normalMap.png", TextureParams::default()).tex_id;
+            let dudv_tex_id = self.loader.load_terrain_texture("res/textures/water/waterDUDV.png", self.water_props.map_texture_params()).tex_id;
+            let normal_map_tex_id = self.loader.load_terrain_texture("res/textures/water/normalMap.png", self.water_props.map_texture_params()).tex_id;
             self.water_model = Some(WaterModel {
                 raw_model,
                 dudv_tex_id,
                 normal_map_tex_id,
+                props: self.water_props,
             });
         }
     }
@@ -527,15 +688,20 @@ impl ResourceManager {
         let font_type = self.get_font(font_name);
         let text_mesh = create_mesh(text, &font_type, font_size);
         let text_model = self.loader.load_quads_mesh_to_vao(&text_mesh.positions, &text_mesh.tex_coords);
-        GuiText::new(font_type, text_model, position, material)
+        GuiText::new(font_type, text, font_size, text_model, position, material)
+    }
+
+    // rebuilds the mesh of the text for the new size and frees the old one
+    pub fn resize_gui_text(&mut self, gui_text: &mut GuiText, font_size: usize) {
+        let text_mesh = create_mesh(&gui_text.text, &gui_text.font_type, font_size);
+        let text_model = self.loader.load_quads_mesh_to_vao(&text_mesh.positions, &text_mesh.tex_coords);
+        self.loader.unload_vao(gui_text.text_model.vao_id);
+        gui_text.resize(font_size, text_model);
     }
 
     pub fn init_simple_point_particle_model(&mut self) {
         if let None = self.simple_point_particle_model {            
-            let raw_model = RawModel {
-                vao_id: self.loader.create_vao(),
-                vertex_count: 1,
-            };
+            let raw_model = RawModel::new(self.loader.create_vao(), 1);
             let stream_draw_vbo = self.loader.create_empty_float_vbo_for_attrib(RawModel::POS_ATTRIB, ParticleModel::MAX_INSTANCES, 3);
             self.simple_point_particle_model = Some(ParticleModel {
                 raw_model,
@@ -575,22 +741,33 @@ impl ResourceManager {
         self.simple_point_particle_model.as_ref().expect("Must init_simple_point_particle_model before accessing it").clone()
     }
 
-    pub fn init_particle_textures(&mut self) {
-        let texture_props = vec![ResourceManager::PARTICLE_ATLAS, ResourceManager::FIRE_ATLAS, ResourceManager::SMOKE_ATLAS];
-
-        for texture_prop in texture_props.iter() {
-            if !self.particle_textures.contains_key(texture_prop) {
+    // scenes register the particle textures they use e.g. &[ResourceManager::PARTICLE_STAR] or their own (path, rows in atlas)
+    pub fn init_particle_textures(&mut self, texture_props: &[ParticleTextureProps]) {
+        for texture_prop in ResourceManager::particle_textures_to_load(&self.particle_textures, texture_props) {
+            let mut particle_texture = self.loader.load_particle_texture(texture_prop.0, TextureParams::default());
+            particle_texture.number_of_rows_in_atlas = texture_prop.1;
+            
+            self.particle_textures.insert(texture_prop, particle_texture);
+        }
+    }
 
-                let mut particle_texture = self.loader.load_particle_texture(texture_prop.0, TextureParams::default());
-                particle_texture.number_of_rows_in_atlas = texture_prop.1;
-                
-                self.particle_textures.insert(texture_prop.clone(), particle_texture);
+    // the requested textures that aren't loaded yet, each one once
+    fn particle_textures_to_load(particle_textures: &HashMap<ParticleTextureProps, ParticleTexture>, texture_props: &[ParticleTextureProps]) -> Vec<ParticleTextureProps> {
+        let mut to_load: Vec<ParticleTextureProps> = Vec::new();
+        for texture_prop in texture_props {
+            if !particle_textures.contains_key(texture_prop) && !to_load.contains(texture_prop) {
+                to_load.push(*texture_prop);
             }
         }
+        to_load
     }
 
     pub fn particle_texture(&self, texture_prop: ParticleTextureProps) -> ParticleTexture {
-        self.particle_textures.get(&texture_prop).expect("Must init_particle_textures before fetching").clone()
+        ResourceManager::registered_particle_texture(&self.particle_textures, texture_prop).clone()
+    }
+
+    fn registered_particle_texture(particle_textures: &HashMap<ParticleTextureProps, ParticleTexture>, texture_prop: ParticleTextureProps) -> &ParticleTexture {
+        particle_textures.get(&texture_prop).unwrap_or_else(|| panic!("Particle texture {} with {} rows must be passed to init_particle_textures before fetching", texture_prop.0, texture_prop.1))
     }
 
     
@@ -629,6 +806,49 @@ impl ResourceManager {
     pub fn debug_cuboid_model(&self) -> DynamicVertexIndexedModel {
         self.debug_model.clone().expect("Need to call init_debug_cuboid_model before accessing the model")
     }
+
+    // has to be called before init of the model, models that are already loaded have dropped their vertex data
+    pub fn retain_vertex_data(&mut self, model_type: ModelType) {
+        self.retained_vertex_data_models.insert(model_type);
+    }
+
+    // e.g. to show the wireframe of every model, has to be set before the models are loaded
+    pub fn set_retains_all_vertex_data(&mut self, retains_all_vertex_data: bool) {
+        self.retains_all_vertex_data = retains_all_vertex_data;
+    }
+
+    pub fn debug_edges_model(&mut self, model_type: ModelType) -> DebugEdgesModel {
+        let target_vao_id = self.model(model_type.clone()).raw_model.vao_id;
+        let model_data = self.vertex_data.get(&model_type).expect(&format!("Need to call retain_vertex_data({:?}) before init_model", model_type));
+        let edges = unique_edges(&model_data.indices);
+        let lines = self.loader.load_lines_to_vao(&model_data.vertices, &edges);
+        DebugEdgesModel {
+            lines,
+            target_vao_id,
+        }
+    }
+
+    // edges of all models that kept their vertex data
+    pub fn debug_edges_models(&mut self) -> Vec<DebugEdgesModel> {
+        let model_types: Vec<ModelType> = self.vertex_data.keys().cloned().collect();
+        model_types.into_iter().map(|model_type| self.debug_edges_model(model_type)).collect()
+    }
+
+    // models without a normal map have no tangents so only their normals get lines
+    pub fn debug_vector_lines_model(&mut self, model_type: ModelType) -> DebugVectorLinesModel {
+        let target_vao_id = self.model(model_type.clone()).raw_model.vao_id;
+        let model_data = self.vertex_data.get(&model_type).expect(&format!("Need to call retain_vertex_data({:?}) before init_model", model_type));
+        // tangents carry their handedness in a fourth component
+        let line_vertex_count = 2 * (model_data.normals.len() / 3 + model_data.tangents.len() / 4);
+        let lines = self.loader.load_dynamic_model_to_vao(line_vertex_count, 3);
+        DebugVectorLinesModel {
+            lines,
+            target_vao_id,
+            positions: model_data.vertices.clone(),
+            normals: model_data.normals.clone(),
+            tangents: model_data.tangents.clone(),
+        }
+    }
 }
 
 mod skybox_model_data {
@@ -676,4 +896,49 @@ mod skybox_model_data {
         -SIZE, -SIZE,  SIZE,
         SIZE, -SIZE,  SIZE
     ];
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn register(particle_textures: &mut HashMap<ParticleTextureProps, ParticleTexture>, texture_props: &[ParticleTextureProps]) -> Vec<ParticleTextureProps> {
+        let to_load = ResourceManager::particle_textures_to_load(particle_textures, texture_props);
+        for texture_prop in to_load.iter() {
+            particle_textures.insert(*texture_prop, ParticleTexture { number_of_rows_in_atlas: texture_prop.1, ..ParticleTexture::default() });
+        }
+        to_load
+    }
+
+    #[test]
+    fn only_registered_particle_textures_are_loaded() {
+        let custom_atlas: ParticleTextureProps = ("res/textures/particles/custom.png", 2);
+        let mut particle_textures = HashMap::new();
+
+        let loaded = register(&mut particle_textures, &[ResourceManager::PARTICLE_STAR, custom_atlas, ResourceManager::PARTICLE_STAR]);
+        assert_eq!(loaded, vec![ResourceManager::PARTICLE_STAR, custom_atlas]);
+        // registering again only loads what is new
+        let loaded = register(&mut particle_textures, &[custom_atlas, ResourceManager::FIRE_ATLAS]);
+        assert_eq!(loaded, vec![ResourceManager::FIRE_ATLAS]);
+
+        assert_eq!(particle_textures.len(), 3);
+        assert_eq!(ResourceManager::registered_particle_texture(&particle_textures, custom_atlas).number_of_rows_in_atlas, 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "Particle texture res/textures/particles/smoke.png with 8 rows must be passed to init_particle_textures")]
+    fn unregistered_particle_texture_names_the_texture() {
+        let mut particle_textures = HashMap::new();
+        register(&mut particle_textures, &[ResourceManager::PARTICLE_STAR]);
+        ResourceManager::registered_particle_texture(&particle_textures, ResourceManager::SMOKE_ATLAS);
+    }
+
+    #[test]
+    fn terrain_tiles_of_another_noise_type_are_not_shared() {
+        let perlin = HeightsGenerator::new_seamless(7, NoiseType::Perlin);
+        let ridged = HeightsGenerator::new_seamless(7, NoiseType::Ridged);
+        assert_eq!(ResourceManager::terrain_tile_key(&perlin, 1, -2), ResourceManager::terrain_tile_key(&HeightsGenerator::new_seamless(7, NoiseType::Perlin), 1, -2));
+        assert_ne!(ResourceManager::terrain_tile_key(&perlin, 1, -2), ResourceManager::terrain_tile_key(&ridged, 1, -2));
+        assert_ne!(ResourceManager::terrain_tile_key(&perlin, 1, -2), ResourceManager::terrain_tile_key(&perlin, -2, 1));
+    }
+}
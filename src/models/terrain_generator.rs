@@ -1,9 +1,6 @@
 use std::f32;
 use crate::math::Vector3f;
-use texture_lib::texture_loader::{
-	load_rgba_2d_texture,
-	Texture2DRGBA,
-};
+use texture_lib::texture_loader::load_rgba_2d_texture;
 use crate::utils::gen_murmur3_f32;
 
 pub trait TerrainGenerator {
@@ -35,46 +32,182 @@ pub trait TerrainGenerator {
 	}    
 }
 
+// the tiles around a terrain tile, they share their border row of vertices with it
+#[derive(Default)]
+pub struct TerrainNeighbors<'a> {
+    // towards -x
+    pub left: Option<&'a dyn TerrainGenerator>,
+    // towards +x
+    pub right: Option<&'a dyn TerrainGenerator>,
+    // towards -z
+    pub back: Option<&'a dyn TerrainGenerator>,
+    // towards +z
+    pub front: Option<&'a dyn TerrainGenerator>,
+}
+
+// reads the heights just outside of the tile from its neighbours so the normals along the edges
+// come out the same on both sides of the seam instead of each tile guessing what lies beyond its border
+pub struct NeighborAwareTile<'a> {
+    tile: &'a dyn TerrainGenerator,
+    neighbors: TerrainNeighbors<'a>,
+}
+
+impl<'a> NeighborAwareTile<'a> {
+    pub fn new(tile: &'a dyn TerrainGenerator, neighbors: TerrainNeighbors<'a>) -> Self {
+        NeighborAwareTile {
+            tile,
+            neighbors,
+        }
+    }
+}
+
+impl<'a> TerrainGenerator for NeighborAwareTile<'a> {
+    fn height(&self) -> usize {
+        self.tile.height()
+    }
+
+    fn width(&self) -> usize {
+        self.tile.width()
+    }
+
+    fn get_height(&self, x: isize, z: isize) -> f32 {
+        // the last vertex of a tile is the first one of the next so the neighbour coordinates are shifted by one less than the width
+        let last_x = self.width() as isize - 1;
+        let last_z = self.height() as isize - 1;
+        let neighbor = if x < 0 {
+            self.neighbors.left.map(|left| (left, x + last_x, z))
+        } else if x > last_x {
+            self.neighbors.right.map(|right| (right, x - last_x, z))
+        } else if z < 0 {
+            self.neighbors.back.map(|back| (back, x, z + last_z))
+        } else if z > last_z {
+            self.neighbors.front.map(|front| (front, x, z - last_z))
+        } else {
+            None
+        };
+        match neighbor {
+            Some((neighbor, x, z)) => neighbor.get_height(x, z),
+            None => self.tile.get_height(x, z),
+        }
+    }
+}
+
+// heights come from the gray values of an image, black is the lowest and white the highest point
 pub struct HeightMap {
-    height_map: Texture2DRGBA, 
+    // gray values in [0, 1] on a square grid, one row per z
+    samples: Vec<f32>,
+    size: usize,
+    vertical_scale: f32,
 }
 
 impl HeightMap {
-    const MAX_HEIGHT: f32 = 40.0;
+    // height difference between black and white, middle gray ends up at height 0
+    pub const DEFAULT_VERTICAL_SCALE: f32 = 40.0;
 
     pub fn new(height_map: &str) -> Self {
+        HeightMap::with_vertical_scale(height_map, HeightMap::DEFAULT_VERTICAL_SCALE)
+    }
+
+    pub fn with_vertical_scale(height_map: &str, vertical_scale: f32) -> Self {
         let height_data = load_rgba_2d_texture(height_map, false).expect(&format!("Couldn't load height map file: {}", height_map));
-		assert_eq!(height_data.width, height_data.height, "Height map must be square");	
+        // gray images have the same value in all channels so red is enough
+        let gray_values: Vec<f32> = height_data.data.iter().map(|color| color.r as f32 / 255.0).collect();
+        HeightMap::from_gray_values(height_data.width, height_data.height, &gray_values, vertical_scale)
+    }
+
+    // terrain cells are square so a non square image gets stretched over a grid as wide as its longer side
+    // values outside of [0, 1] are clamped
+    pub fn from_gray_values(width: usize, height: usize, gray_values: &[f32], vertical_scale: f32) -> Self {
+        assert!(width > 0 && height > 0, "Height map must not be empty");
+        assert_eq!(gray_values.len(), width * height, "Height map needs one gray value per pixel");
+        let size = width.max(height).max(2);
+        let pixel = |x: usize, z: usize| {
+            let gray = gray_values[z * width + x];
+            if gray.is_nan() { 0.0 } else { gray.max(0.0).min(1.0) }
+        };
+        let mut samples = Vec::with_capacity(size * size);
+        for z in 0..size {
+            for x in 0..size {
+                // bilinear filtering between the pixels around the grid point
+                let image_x = x as f32 * (width - 1) as f32 / (size - 1) as f32;
+                let image_z = z as f32 * (height - 1) as f32 / (size - 1) as f32;
+                let (x0, z0) = (image_x.floor() as usize, image_z.floor() as usize);
+                let (x1, z1) = ((x0 + 1).min(width - 1), (z0 + 1).min(height - 1));
+                let (fx, fz) = (image_x - x0 as f32, image_z - z0 as f32);
+                let top = pixel(x0, z0) * (1.0 - fx) + pixel(x1, z0) * fx;
+                let bottom = pixel(x0, z1) * (1.0 - fx) + pixel(x1, z1) * fx;
+                samples.push(top * (1.0 - fz) + bottom * fz);
+            }
+        }
         HeightMap {
-            height_map: height_data,
+            samples,
+            size,
+            vertical_scale,
         }
     }
 }
 
 impl TerrainGenerator for HeightMap {
     fn height(&self) -> usize {
-        self.height_map.height
+        self.size
     }
 
     fn width(&self) -> usize {
-        self.height_map.width
+        self.size
     }
 
+    // outside of the image the edge pixels are repeated so the normals along the border don't tilt towards 0
     fn get_height(&self, x: isize, z: isize) -> f32 {
-		if x < 0 || x >= self.height() as isize || z < 0 || z >= self.width() as isize {
-			return 0.0;
-		}
-		let (x, z) = (x as usize, z as usize);
-		let color = self.height_map.get_color(z, x);		
-		let grayscale = color.r as f32;
-		const MAX_COLOR: f32 = 256.0;
-		let rescaled = (grayscale - MAX_COLOR/2.0) / MAX_COLOR;
-		rescaled * HeightMap::MAX_HEIGHT
-	}
+        let last = self.size as isize - 1;
+        let (x, z) = (x.max(0).min(last) as usize, z.max(0).min(last) as usize);
+        (self.samples[z * self.size + x] - 0.5) * self.vertical_scale
+    }
+}
+
+// the noise each octave samples, the octaves are combined the same way for all of them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NoiseType {
+    // smoothed random values at the grid points, cosine interpolated in between
+    Value,
+    Perlin,
+    Simplex,
+    // folded perlin noise which gives sharp crests like mountain ridges
+    Ridged,
+}
+
+impl Default for NoiseType {
+    fn default() -> Self {
+        NoiseType::Value
+    }
 }
 
 pub struct HeightsGenerator {
     seed: u32,
+    noise_type: NoiseType,
+    // flat edges let copies of the same terrain cell link up, without them the noise continues across tiles (see tile)
+    has_flat_edges: bool,
+}
+
+// one tile of a seamless generator, samples the generator at the tile's offset in the infinite noise
+// neighbouring tiles share their edge vertices so their heights and normals match along the seam
+pub struct TerrainTile<'a> {
+    generator: &'a HeightsGenerator,
+    offset_x: isize,
+    offset_z: isize,
+}
+
+impl<'a> TerrainGenerator for TerrainTile<'a> {
+    fn height(&self) -> usize {
+        self.generator.height()
+    }
+
+    fn width(&self) -> usize {
+        self.generator.width()
+    }
+
+    fn get_height(&self, x: isize, z: isize) -> f32 {
+        self.generator.get_height(x + self.offset_x, z + self.offset_z)
+    }
 }
 
 impl HeightsGenerator {    
@@ -86,6 +219,58 @@ impl HeightsGenerator {
     const ROUGHNESS: f32 = 0.05;
     // flat edge width -> used so that terrain cells link up correctly
     const EDGE_WIDTH: usize = 10;
+    // skew factors between the square grid and the simplex (triangle) grid
+    const SIMPLEX_F2: f32 = 0.366_025_4; // (sqrt(3) - 1) / 2
+    const SIMPLEX_G2: f32 = 0.211_324_87; // (3 - sqrt(3)) / 6
+
+    pub fn new(seed: u32, noise_type: NoiseType) -> Self {
+        HeightsGenerator {
+            seed,
+            noise_type,
+            has_flat_edges: true,
+        }
+    }
+
+    // for worlds made of different tiles, each tile gets its part of the noise with tile
+    pub fn new_seamless(seed: u32, noise_type: NoiseType) -> Self {
+        HeightsGenerator {
+            seed,
+            noise_type,
+            has_flat_edges: false,
+        }
+    }
+
+    pub fn seed(&self) -> u32 {
+        self.seed
+    }
+
+    pub fn noise_type(&self) -> NoiseType {
+        self.noise_type
+    }
+
+    // the same tile index always gives the same heights for a seed
+    pub fn tile(&self, tile_x: i32, tile_z: i32) -> TerrainTile<'_> {
+        // the last row of vertices of a tile is the first row of the next one
+        let cells_per_tile = (HeightsGenerator::WIDTH - 1) as isize;
+        TerrainTile {
+            generator: self,
+            offset_x: tile_x as isize * cells_per_tile,
+            offset_z: tile_z as isize * cells_per_tile,
+        }
+    }
+
+    fn sample_noise(&self, x: f32, y: f32, octave_width: usize) -> f32 {
+        match self.noise_type {
+            NoiseType::Value => self.get_interpolated_noise(x, y, octave_width),
+            NoiseType::Perlin => self.get_perlin_noise(x, y, octave_width),
+            NoiseType::Simplex => self.get_simplex_noise(x, y, octave_width),
+            // 0 where perlin crosses 0 and negative elsewhere so that the flat edges stay at 0
+            NoiseType::Ridged => {
+                let ridge = 1.0 - self.get_perlin_noise(x, y, octave_width).abs();
+                ridge * ridge - 1.0
+            },
+        }
+    }
 
     pub fn get_smooth_noise(&self, x: isize, y: isize, ow: usize) -> f32 {
         // we do gaussian blurring here to smoothen the noise
@@ -98,10 +283,10 @@ impl HeightsGenerator {
     }    
 
     fn check_outside_autogenerated_bounds(&self, x: isize, y: isize, octave_width: usize) -> bool {
-        x < (HeightsGenerator::EDGE_WIDTH/octave_width) as isize 
+        self.has_flat_edges && (x < (HeightsGenerator::EDGE_WIDTH/octave_width) as isize 
             || y < (HeightsGenerator::EDGE_WIDTH/octave_width) as isize
             || x > ((HeightsGenerator::WIDTH - HeightsGenerator::EDGE_WIDTH)/octave_width) as isize
-            || y > ((HeightsGenerator::HEIGHT - HeightsGenerator::EDGE_WIDTH)/octave_width) as isize        
+            || y > ((HeightsGenerator::HEIGHT - HeightsGenerator::EDGE_WIDTH)/octave_width) as isize)
     }
 
     fn get_noise(&self, x: isize, y: isize, octave_width: usize) -> f32 {
@@ -131,6 +316,69 @@ impl HeightsGenerator {
         noise_mid
     }
 
+    // random unit gradient at the grid point dotted with the offset from it
+    fn gradient_dot(&self, grid_x: isize, grid_y: isize, dx: f32, dy: f32) -> f32 {
+        let angle = gen_murmur3_f32(grid_x as u32, grid_y as u32, self.seed) * 2.0 * f32::consts::PI;
+        angle.cos() * dx + angle.sin() * dy
+    }
+
+    // grid points outside of the generated region get no gradient so the edges are flat
+    fn perlin_corner(&self, grid_x: isize, grid_y: isize, dx: f32, dy: f32, octave_width: usize) -> f32 {
+        if self.check_outside_autogenerated_bounds(grid_x, grid_y, octave_width) {
+            return 0.0;
+        }
+        self.gradient_dot(grid_x, grid_y, dx, dy)
+    }
+
+    fn get_perlin_noise(&self, x: f32, y: f32, octave_width: usize) -> f32 {
+        let x_whole = x.floor();
+        let y_whole = y.floor();
+        let x_frac = x - x_whole;
+        let y_frac = y - y_whole;
+        let x_whole = x_whole as isize;
+        let y_whole = y_whole as isize;
+        let noise_1 = self.perlin_corner(x_whole, y_whole, x_frac, y_frac, octave_width);
+        let noise_2 = self.perlin_corner(x_whole+1, y_whole, x_frac - 1.0, y_frac, octave_width);
+        let noise_3 = self.perlin_corner(x_whole, y_whole+1, x_frac, y_frac - 1.0, octave_width);
+        let noise_4 = self.perlin_corner(x_whole+1, y_whole+1, x_frac - 1.0, y_frac - 1.0, octave_width);
+        let noise_mid_12 = HeightsGenerator::cosine_interpolate(noise_1, noise_2, x_frac);
+        let noise_mid_34 = HeightsGenerator::cosine_interpolate(noise_3, noise_4, x_frac);
+        // max of 2d perlin noise is sqrt(0.5) so rescale to roughly [-1,1]
+        HeightsGenerator::cosine_interpolate(noise_mid_12, noise_mid_34, y_frac) * f32::consts::SQRT_2
+    }
+
+    fn get_simplex_noise(&self, x: f32, y: f32, octave_width: usize) -> f32 {
+        // find the simplex (triangle) we are in by skewing into the square grid
+        let skew = (x + y) * HeightsGenerator::SIMPLEX_F2;
+        let i = (x + skew).floor();
+        let j = (y + skew).floor();
+        let unskew = (i + j) * HeightsGenerator::SIMPLEX_G2;
+        let x0 = x - (i - unskew);
+        let y0 = y - (j - unskew);
+        let (i1, j1) = if x0 > y0 { (1, 0) } else { (0, 1) };
+        let x1 = x0 - i1 as f32 + HeightsGenerator::SIMPLEX_G2;
+        let y1 = y0 - j1 as f32 + HeightsGenerator::SIMPLEX_G2;
+        let x2 = x0 - 1.0 + 2.0 * HeightsGenerator::SIMPLEX_G2;
+        let y2 = y0 - 1.0 + 2.0 * HeightsGenerator::SIMPLEX_G2;
+        let (i, j) = (i as isize, j as isize);
+        let corner = |grid_x: isize, grid_y: isize, dx: f32, dy: f32| {
+            let falloff = 0.5 - dx*dx - dy*dy;
+            // the simplex grid is skewed so the bounds are checked at the corner's position in the square grid
+            // a corner reaches less than one square cell so checking the surrounding square grid points keeps the edges flat
+            let (corner_x, corner_y) = (x - dx, y - dy);
+            let is_outside = self.check_outside_autogenerated_bounds(corner_x.floor() as isize, corner_y.floor() as isize, octave_width)
+                || self.check_outside_autogenerated_bounds(corner_x.ceil() as isize, corner_y.ceil() as isize, octave_width);
+            if falloff < 0.0 || is_outside {
+                0.0
+            } else {
+                falloff.powi(4) * self.gradient_dot(grid_x, grid_y, dx, dy)
+            }
+        };
+        let total = corner(i, j, x0, y0) + corner(i + i1, j + j1, x1, y1) + corner(i + 1, j + 1, x2, y2);
+        // scale to roughly [-1,1]
+        70.0 * total
+    }
+
     fn cosine_interpolate(x0: f32, x1: f32, alpha: f32) -> f32 {
         let angle = alpha * f32::consts::PI;
         // our angle is [0,180] degs and that maps to cos [1,-1] which we want to map to [0,1] using the following
@@ -142,9 +390,7 @@ impl HeightsGenerator {
 impl Default for HeightsGenerator {
     fn default() -> Self {     
         let seed = 1234;
-        HeightsGenerator {
-            seed,
-        }    
+        HeightsGenerator::new(seed, NoiseType::default())
     }
 }
 
@@ -168,10 +414,110 @@ impl TerrainGenerator for HeightsGenerator {
         let mut amplitude = HeightsGenerator::AMPLITUDE; 
         for _ in 0..HeightsGenerator::OCTAVES {            
             let octave_width_f32 = octave_width as f32;
-            total_noise += self.get_interpolated_noise(x / octave_width_f32, z / octave_width_f32, octave_width) * amplitude;
+            total_noise += self.sample_noise(x / octave_width_f32, z / octave_width_f32, octave_width) * amplitude;
             octave_width = octave_width >> 1;
             amplitude *= HeightsGenerator::ROUGHNESS;
         }        
         total_noise
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOISE_TYPES: [NoiseType; 4] = [NoiseType::Value, NoiseType::Perlin, NoiseType::Simplex, NoiseType::Ridged];
+
+    fn sample_heights(generator: &HeightsGenerator) -> Vec<f32> {
+        (20..40).flat_map(|x| (50..70).map(move |z| (x, z))).map(|(x, z)| generator.get_height(x, z)).collect()
+    }
+
+    #[test]
+    fn each_noise_type_is_deterministic_for_seed() {
+        for noise_type in NOISE_TYPES.iter() {
+            let heights = sample_heights(&HeightsGenerator::new(42, *noise_type));
+            assert_eq!(heights, sample_heights(&HeightsGenerator::new(42, *noise_type)), "{:?}", noise_type);
+            assert!(heights.iter().all(|height| height.is_finite()));
+            assert_ne!(heights, sample_heights(&HeightsGenerator::new(43, *noise_type)), "{:?}", noise_type);
+        }
+    }
+
+    #[test]
+    fn noise_types_produce_different_terrain() {
+        let heights: Vec<Vec<f32>> = NOISE_TYPES.iter().map(|noise_type| sample_heights(&HeightsGenerator::new(42, *noise_type))).collect();
+        for a in 0..heights.len() {
+            for b in a+1..heights.len() {
+                assert_ne!(heights[a], heights[b], "{:?} and {:?}", NOISE_TYPES[a], NOISE_TYPES[b]);
+            }
+        }
+    }
+
+    #[test]
+    fn edges_stay_flat_for_all_noise_types() {
+        for noise_type in NOISE_TYPES.iter() {
+            let generator = HeightsGenerator::new(42, *noise_type);
+            assert_eq!(generator.get_height(0, 64), 0.0, "{:?}", noise_type);
+            assert_eq!(generator.get_height(64, HeightsGenerator::HEIGHT as isize - 1), 0.0, "{:?}", noise_type);
+        }
+    }
+
+    #[test]
+    fn adjacent_tiles_match_along_shared_edge() {
+        let last = HeightsGenerator::WIDTH as isize - 1;
+        for noise_type in NOISE_TYPES.iter() {
+            let generator = HeightsGenerator::new_seamless(42, *noise_type);
+            let (tile, right, below) = (generator.tile(-1, 3), generator.tile(0, 3), generator.tile(-1, 4));
+            for i in 0..=last {
+                assert_eq!(tile.get_height(last, i), right.get_height(0, i), "{:?}", noise_type);
+                assert_eq!(tile.get_height(i, last), below.get_height(i, 0), "{:?}", noise_type);
+                let (normal, right_normal) = (tile.get_normal_at(last, i), right.get_normal_at(0, i));
+                assert_eq!((normal.x, normal.y, normal.z), (right_normal.x, right_normal.y, right_normal.z), "{:?}", noise_type);
+            }
+            // the edges are not simply flattened
+            assert!((0..=last).any(|i| tile.get_height(last, i) != 0.0), "{:?}", noise_type);
+            assert_eq!(sample_heights(&generator), sample_heights(&HeightsGenerator::new_seamless(42, *noise_type)));
+        }
+    }
+
+    #[test]
+    fn height_map_scales_gray_values_onto_a_square_grid() {
+        // a 3x2 image, the 1.5 is out of range and gets clamped to white
+        let gray_values = [0.0, 0.5, 1.5, 1.0, 0.5, 0.0];
+        let height_map = HeightMap::from_gray_values(3, 2, &gray_values, 10.0);
+        assert_eq!((height_map.width(), height_map.height()), (3, 3));
+
+        let grid: Vec<Vec<f32>> = (0..3).map(|z| (0..3).map(|x| height_map.get_height(x, z)).collect()).collect();
+        assert_eq!(grid, vec![
+            vec![-5.0, 0.0, 5.0],
+            // the image only has two rows so the middle row is blended from both
+            vec![0.0, 0.0, 0.0],
+            vec![5.0, 0.0, -5.0],
+        ]);
+        // the edges repeat outside of the grid
+        assert_eq!(height_map.get_height(-1, 0), -5.0);
+        assert_eq!(height_map.get_height(3, 2), -5.0);
+    }
+
+    #[test]
+    fn edge_normals_match_across_the_seam_with_neighbor_data() {
+        // the right column of the left tile is the left column of the right tile
+        let left = HeightMap::from_gray_values(3, 3, &[0.1, 0.4, 0.6, 0.2, 0.5, 0.9, 0.3, 0.3, 0.7], 10.0);
+        let right = HeightMap::from_gray_values(3, 3, &[0.6, 0.2, 0.0, 0.9, 0.8, 0.1, 0.7, 0.4, 0.5], 10.0);
+        let normal_pair = |left: &dyn TerrainGenerator, right: &dyn TerrainGenerator, z: isize| {
+            let (a, b) = (left.get_normal_at(2, z), right.get_normal_at(0, z));
+            ((a.x, a.y, a.z), (b.x, b.y, b.z))
+        };
+        // each tile on its own repeats its edge so the normals disagree
+        let (a, b) = normal_pair(&left, &right, 1);
+        assert_ne!(a, b);
+
+        let left_tile = NeighborAwareTile::new(&left, TerrainNeighbors { right: Some(&right), ..Default::default() });
+        let right_tile = NeighborAwareTile::new(&right, TerrainNeighbors { left: Some(&left), ..Default::default() });
+        for z in 0..3 {
+            let (a, b) = normal_pair(&left_tile, &right_tile, z);
+            assert_eq!(a, b, "z = {}", z);
+        }
+        // inside the tile nothing changes
+        assert_eq!(left_tile.get_height(1, 1), left.get_height(1, 1));
+    }
+}
@@ -1,4 +1,6 @@
-#[derive(Copy, Clone, PartialEq, Eq, Hash)]
+use std::collections::HashMap;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub enum TextureId {
     Empty,
     Loading(u32),
@@ -15,10 +17,45 @@ impl TextureId {
             TextureId::FboTexture(id) => *id,
         }
     }
+
+    // swaps a loading token for its gl id once the upload is done, a texture that is still loading stays Loading
+    pub fn resolve_with(&self, texture_token_map: &HashMap<u32, u32>) -> TextureId {
+        match self {
+            TextureId::Loading(token) => texture_token_map.get(token).map_or(*self, |gl_id| TextureId::Loaded(*gl_id)),
+            _ => *self,
+        }
+    }
+
+    pub fn is_ready(&self) -> bool {
+        match self {
+            TextureId::Loaded(_) | TextureId::FboTexture(_) => true,
+            TextureId::Empty | TextureId::Loading(_) => false,
+        }
+    }
 }
 
 impl Default for TextureId {
     fn default() -> Self {
         TextureId::Empty
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn loading_gui_texture_resolves_once_uploaded() {
+        let gui_texture = TextureId::Loading(3);
+        let mut texture_token_map = HashMap::new();
+        assert_eq!(gui_texture.resolve_with(&texture_token_map), TextureId::Loading(3));
+        assert!(!gui_texture.resolve_with(&texture_token_map).is_ready());
+
+        // update_resource_state records the gl id under the token once the texture is uploaded
+        texture_token_map.insert(3, 17);
+        let resolved = gui_texture.resolve_with(&texture_token_map);
+        assert_eq!(resolved, TextureId::Loaded(17));
+        assert!(resolved.is_ready());
+        assert_eq!(TextureId::FboTexture(5).resolve_with(&texture_token_map), TextureId::FboTexture(5));
+    }
+}
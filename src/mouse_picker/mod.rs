@@ -3,10 +3,12 @@ use crate::math::{
     Vector2f,
     Vector3f,
     Vector4f,
+    intersect,
 };
 use crate::entities::{
     Camera,
     Ground,
+    Terrain,
 };
 use crate::display::{
     Display,
@@ -31,41 +33,75 @@ impl MousePicker {
         //println!("Mouse ray is: {:?}. Mouse is at ({},{})", mouse_ray, display.mouse_pos.cur_x, display.mouse_pos.cur_y);
     }
 
+    // closest hit with the same triangles the terrain is drawn with so bumpy terrain and looking up from below work too
     fn search_ground_intersection(ray: Vector3f, camera: &Camera, ground: &Ground) -> Option<Vector3f> {
+        let closest_t = ground.terrains.iter()
+            .filter_map(|terrain| MousePicker::closest_terrain_hit(&camera.position, &ray, terrain))
+            .fold(None, |closest: Option<f32>, t| Some(closest.map_or(t, |closest| closest.min(t))));
+        closest_t.map(|t| &camera.position + &(&ray * t))
+    }
 
-        const IT_LIMIT: u8 = 100;
-        let mut low = 0.0;
-        let mut high = 5000.0;
-        let mut mid = 0.0;
-        let mut it_cnt = 0;
-        let mut was_below = false;
-        let mut was_above = false;
-        // bin search implementation doesnt let you look up from under terrain (has to do with how low and high is set)
-        // and it may give wrong result if terrain is bumpy and first jump takes you too far
-        while it_cnt < IT_LIMIT {
-            mid = (low + high) / 2.0;
-            let ray_x = ray.x * mid + camera.position.x;
-            let ray_y = ray.y * mid + camera.position.y;
-            let ray_z = ray.z * mid + camera.position.z;
-            let terrain_height = ground.height_at_xz(ray_x, ray_z);
-            if terrain_height < ray_y {
-                low = mid;                
-                was_above = true;
-            } else {                
-                high = mid;
-                was_below = true;
+    // tiles the ray misses are skipped and on the others only the cells below the ray are tested, walked in the order the ray crosses them
+    // (2d dda) so the first hit is the closest one, a triangle hit always lies in the cell the triangle belongs to
+    fn closest_terrain_hit(origin: &Vector3f, ray: &Vector3f, terrain: &Terrain) -> Option<f32> {
+        let cell_count = terrain.cell_count();
+        if cell_count == 0 {
+            return None;
+        }
+        let (min, max) = terrain.bounds();
+        let (t_enter, t_exit) = intersect::ray_aabb(origin, ray, &min, &max)?;
+        let cell_size = Terrain::SIZE / cell_count as f32;
+        let start = origin + &(ray * t_enter.max(0.0));
+        let last_cell = cell_count as isize - 1;
+        let cell_of = |coord: f32, tile_start: f32| (((coord - tile_start) / cell_size).floor() as isize).max(0).min(last_cell);
+        // per axis: the cell step, the ray distance to the next cell border and the ray distance across a whole cell
+        let axis = |cell: isize, origin: f32, dir: f32, tile_start: f32| {
+            if dir == 0.0 {
+                return (0, std::f32::INFINITY, std::f32::INFINITY);
+            }
+            let (step, next_cell) = if dir > 0.0 { (1, cell + 1) } else { (-1, cell) };
+            (step, (tile_start + next_cell as f32 * cell_size - origin) / dir, cell_size / dir.abs())
+        };
+        let (mut gx, mut gz) = (cell_of(start.x, terrain.x), cell_of(start.z, terrain.z));
+        let (step_x, mut t_next_x, t_delta_x) = axis(gx, origin.x, ray.x, terrain.x);
+        let (step_z, mut t_next_z, t_delta_z) = axis(gz, origin.z, ray.z, terrain.z);
+        loop {
+            let hit = terrain.cell_triangles(gx as usize, gz as usize).iter()
+                .filter_map(|[v0, v1, v2]| intersect::ray_triangle(origin, ray, v0, v1, v2))
+                .map(|(t, _, _)| t)
+                .fold(None, |closest: Option<f32>, t| Some(closest.map_or(t, |closest| closest.min(t))));
+            if hit.is_some() {
+                return hit;
+            }
+            if t_next_x.min(t_next_z) > t_exit {
+                return None;
+            }
+            if t_next_x < t_next_z {
+                gx += step_x;
+                t_next_x += t_delta_x;
+            } else {
+                gz += step_z;
+                t_next_z += t_delta_z;
+            }
+            if gx < 0 || gx > last_cell || gz < 0 || gz > last_cell {
+                return None;
             }
-            it_cnt += 1;
         }
+    }
 
-        if was_above && was_below {
-            Some(Vector3f::new(ray.x * mid  + camera.position.x, ray.y * mid + camera.position.y, ray.z * mid + camera.position.z))
-        } else {
-            None
-        }
+    // closest hit of the ray starting at origin with a triangle mesh whose vertices are already in world space
+    pub fn search_mesh_intersection(origin: &Vector3f, ray: &Vector3f, vertices: &[Vector3f], indices: &[u32]) -> Option<Vector3f> {
+        MousePicker::closest_mesh_hit(origin, ray, vertices, indices).map(|t| origin + &(ray * t))
+    }
+
+    fn closest_mesh_hit(origin: &Vector3f, ray: &Vector3f, vertices: &[Vector3f], indices: &[u32]) -> Option<f32> {
+        indices.chunks_exact(3)
+            .filter_map(|triangle| intersect::ray_triangle(origin, ray, &vertices[triangle[0] as usize], &vertices[triangle[1] as usize], &vertices[triangle[2] as usize]))
+            .map(|(t, _, _)| t)
+            .fold(None, |closest: Option<f32>, t| Some(closest.map_or(t, |closest| closest.min(t))))
     }
 
-    fn calculate_mouse_ray(&self, display: &Display, proj_matrix: &Matrix4f, camera: &Camera) -> Vector3f {
+    pub fn calculate_mouse_ray(&self, display: &Display, proj_matrix: &Matrix4f, camera: &Camera) -> Vector3f {
         let mouse_x = display.mouse_pos.cur_x as f32;
         let mouse_y = display.mouse_pos.cur_y as f32;
         let ndc_coords = MousePicker::viewport_to_normalized_device_coords(mouse_x, mouse_y, display);
@@ -94,10 +130,70 @@ impl MousePicker {
     }
 
     fn viewport_to_normalized_device_coords(mouse_x: f32, mouse_y: f32, display: &Display) -> Vector2f {
-        // from [(0,height), (width, 0)] to [(-1,-1), (1,1)] 
-        // move by half w/h to center then scale by half w/h down
-        let WindowSize{width_f32: w, height_f32: h, width: _, height: _} = display.get_size();
-        Vector2f::new((2.0*mouse_x - w)/w, (h - 2.0*mouse_y)/h)
+        // from [(0,height), (width, 0)] to [(-1,-1), (1,1)] of the part of the window the scene is drawn to (all of it unless letterboxed)
+        let WindowSize{width_f32: _, height_f32: h, width, height} = display.get_size();
+        let (x, y) = display.letterbox(width, height).to_normalized_device_coords(h, mouse_x, mouse_y);
+        Vector2f::new(x, y)
     }
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::entities::Terrain;
+    use crate::models::{
+        RawModel,
+        TerrainModel,
+        TerrainTexture,
+        TerrainTexturePack,
+        TextureId,
+    };
+
+    // rises by one unit per grid step along x
+    fn sloped_ground() -> Ground {
+        let texture = || TerrainTexture { tex_id: TextureId::Loaded(1) };
+        let texture_pack = TerrainTexturePack { background_texture: texture(), r_texture: texture(), g_texture: texture(), b_texture: texture() };
+        let height_map = (0..16).map(|x| vec![x as f32; 16]).collect();
+        let model = TerrainModel { raw_model: RawModel::default(), height_map: Arc::new(height_map) };
+        Ground { terrains: vec![Terrain::new(0, 0, texture_pack, texture(), model)] }
+    }
+
+    #[test]
+    fn ground_picking_hits_the_terrain_mesh() {
+        let ground = sloped_ground();
+        let mut camera = Camera::default();
+        camera.position = Vector3f::new(400.0, 100.0, 400.0);
+
+        let hit = MousePicker::search_ground_intersection(Vector3f::new(0.0, -1.0, 0.0), &camera, &ground).expect("Ray points down onto the terrain");
+        assert_eq!((hit.x, hit.z), (400.0, 400.0));
+        assert!((hit.y - ground.height_at_xz(400.0, 400.0)).abs() < 1e-3);
+
+        // from below the terrain the surface is hit too
+        camera.position.y = -100.0;
+        let hit = MousePicker::search_ground_intersection(Vector3f::new(0.0, 1.0, 0.0), &camera, &ground).expect("Ray points up onto the terrain");
+        assert!((hit.y - ground.height_at_xz(400.0, 400.0)).abs() < 1e-3);
+
+        assert!(MousePicker::search_ground_intersection(Vector3f::new(0.0, 1.0, 0.0), &Camera::default(), &Ground { terrains: Vec::new() }).is_none());
+    }
+
+    #[test]
+    fn ground_picking_walks_the_cells_along_a_slanted_ray() {
+        let ground = sloped_ground();
+        let (vertices, indices) = ground.terrains[0].collision_mesh();
+        let camera_position = Vector3f::new(-50.0, 30.0, 120.0);
+        let mut camera = Camera::default();
+        camera.position = camera_position.clone();
+        let mut ray = Vector3f::new(1.0, -0.02, 0.3);
+        ray.normalize();
+
+        let hit = MousePicker::search_ground_intersection(ray.clone(), &camera, &ground).expect("Ray comes down onto the slope");
+        let brute_force_hit = MousePicker::search_mesh_intersection(&camera_position, &ray, &vertices, &indices).expect("Same triangles");
+        assert!((hit.x - brute_force_hit.x).abs() < 1e-2 && (hit.y - brute_force_hit.y).abs() < 1e-2 && (hit.z - brute_force_hit.z).abs() < 1e-2,
+            "{:?} != {:?}", hit, brute_force_hit);
+
+        // above the highest point of the tile and pointing up
+        camera.position.y = 100.0;
+        assert!(MousePicker::search_ground_intersection(Vector3f::new(0.0, 1.0, 0.0), &camera, &ground).is_none());
+    }
+}
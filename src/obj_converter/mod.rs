@@ -1,4 +1,5 @@
 pub mod obj_loader;
 
 pub use self::obj_loader::load_obj_model;
-pub use self::obj_loader::load_simple_obj_model;
\ No newline at end of file
+pub use self::obj_loader::load_simple_obj_model;
+pub use self::obj_loader::ModelData;
\ No newline at end of file
@@ -28,6 +28,9 @@ pub fn load_obj_model(file_name: &str, compute_tangent: bool) -> std::io::Result
     let mut textures = Vec::new();
     let mut normals = Vec::new();
     let mut tangents = Vec::new();
+    // extended obj vertices "v x y z r g b", vertices without a color are white
+    let mut colors = Vec::new();
+    let mut has_colors = false;
             
     let mut textures_sorted: Option<Vec<Vector2f>> = None;
     let mut normals_sorted: Option<Vec<Vector3f>> = None;
@@ -53,6 +56,8 @@ pub fn load_obj_model(file_name: &str, compute_tangent: bool) -> std::io::Result
                     let y = tokens[2].parse().unwrap();
                     let z = tokens[3].parse().unwrap();
                     vertices.push(Vector3f::new(x, y, z));
+                    colors.push(parse_vertex_color(&tokens));
+                    has_colors |= tokens.len() >= 7;
                     let dist = vertices[vertices.len()-1].length();
                     if furthest_distance < dist {
                         furthest_distance = dist;
@@ -82,7 +87,7 @@ pub fn load_obj_model(file_name: &str, compute_tangent: bool) -> std::io::Result
                         face_vertices[i - 1] = process_face_token(tokens[i], textures_mut_ref, 
                             normals_mut_ref, &mut indices, &textures, &normals, 
                             &mut vertex_dupes, &mut extra_vertex_id_generator, &mut vertices, 
-                            &mut tangents, file_name);
+                            &mut tangents, &mut colors, file_name);
                     }
                     if compute_tangent {
                         calculate_tangents(face_vertices, &mut tangents, &vertices, textures_mut_ref);
@@ -108,6 +113,13 @@ pub fn load_obj_model(file_name: &str, compute_tangent: bool) -> std::io::Result
     } else {
         Vec::new()
     };
+    let flat_colors = if has_colors {
+        colors.into_iter()
+              .flat_map(|c| c.into_iter())
+              .collect::<Vec<f32>>()
+    } else {
+        Vec::new()
+    };
     let flat_normals = normals_sorted.expect(ERROR_MSG).into_iter()
                                      .flat_map(|v| v.into_iter())
                                      .collect::<Vec<f32>>();
@@ -119,6 +131,7 @@ pub fn load_obj_model(file_name: &str, compute_tangent: bool) -> std::io::Result
         normals: flat_normals, 
         indices,
         tangents: flat_tangents, 
+        colors: flat_colors,
         furthest_point: furthest_distance,
     })
 }
@@ -144,7 +157,7 @@ fn update_tangent_with_handedness_and_average(tangent: TanAndBitan, normal: &Vec
 
 fn process_face_token(token: &str, textures_sorted: &mut Vec<Vector2f>, normals_sorted: &mut Vec<Vector3f>, indices: &mut Vec<u32>, 
                 textures: &Vec<Vector2f>, normals: &Vec<Vector3f>, vertex_dupes: &mut HashMap<usize, Vec<(usize, usize, usize)>>,
-                extra_vertex_id_gen: &mut usize, vertices: &mut Vec<Vector3f>, tangents: &mut Vec<TanAndBitan>, _file_name: &str) -> usize {
+                extra_vertex_id_gen: &mut usize, vertices: &mut Vec<Vector3f>, tangents: &mut Vec<TanAndBitan>, colors: &mut Vec<Vector3f>, _file_name: &str) -> usize {
     let idx: Vec<_> = token.split("/").collect();
     let mut vertex_index = idx[0].parse::<usize>().expect(".obj didn't contain vertices") - 1;
     let texture_index = idx[1].parse::<usize>().expect(".obj didn't contain vt texture coords") - 1;
@@ -165,6 +178,7 @@ fn process_face_token(token: &str, textures_sorted: &mut Vec<Vector2f>, normals_
     else {
         // we have found a duplicate -> generate new vertex id and new entries in vertices/normals/textures
         vertices.push(vertices[vertex_index].clone());
+        colors.push(colors[vertex_index].clone());
         tangents.push(new_tan_bitan());
         vertex_index = *extra_vertex_id_gen;
         tex_norm_tups.push((texture_index, normal_index, vertex_index));
@@ -176,6 +190,14 @@ fn process_face_token(token: &str, textures_sorted: &mut Vec<Vector2f>, normals_
     vertex_index
 }
 
+fn parse_vertex_color(v_tokens: &[&str]) -> Vector3f {
+    if v_tokens.len() < 7 {
+        return Vector3f::new(1.0, 1.0, 1.0);
+    }
+    let channel = |i: usize| v_tokens[i].parse().expect("Invalid vertex color in .obj");
+    Vector3f::new(channel(4), channel(5), channel(6))
+}
+
 fn new_tan_bitan() -> TanAndBitan {
     (Vector3f::zero(), Vector3f::zero(), 0)
 }
@@ -215,5 +237,22 @@ pub struct ModelData {
     pub normals: Vec<f32>,
     pub indices: Vec<u32>,
     pub tangents: Vec<f32>,
+    // rgb per vertex, empty when the obj has no vertex colors
+    pub colors: Vec<f32>,
     pub furthest_point: f32,
 }
+
+impl ModelData {
+    // min and max corner of the box around all vertices in model space
+    pub fn bounds(&self) -> (Vector3f, Vector3f) {
+        let mut min = Vector3f::new(f32::MAX, f32::MAX, f32::MAX);
+        let mut max = Vector3f::new(f32::MIN, f32::MIN, f32::MIN);
+        for xyz in self.vertices.chunks(3) {
+            for i in 0..3 {
+                min[i] = min[i].min(xyz[i]);
+                max[i] = max[i].max(xyz[i]);
+            }
+        }
+        (min, max)
+    }
+}
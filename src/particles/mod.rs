@@ -3,6 +3,11 @@ pub mod particle_system;
 
 pub use self::particle_master::Particle;
 pub use self::particle_master::ParticleMaster;
+pub use self::particle_master::ParticleCullDistance;
+pub use self::particle_master::ParticleAttractor;
+pub use self::particle_master::TerrainCollision;
+pub use self::particle_master::ParticleRotationMode;
+pub use self::particle_master::ParticleUpdateMode;
 pub use self::particle_system::ParticleSystem;
 pub use self::particle_system::ParticleSystemProps;
 pub use self::particle_system::AdvancedParticleSystem;
\ No newline at end of file
@@ -1,21 +1,32 @@
-use std::cmp::Ordering;
+use std::cmp::{
+    self,
+    Ordering,
+};
 use std::collections::HashMap;
+use std::sync::{
+    mpsc,
+    Arc,
+};
+use threadpool::ThreadPool;
 
 use crate::constants::GRAVITY;
-use crate::display::{
-    Display,
+use crate::entities::{
+    Camera,
+    Ground,
 };
-use crate::entities::Camera;
 use crate::math::{
     Matrix4f,
     Vector2f,
     Vector3f,
+    Vector4f,
 };
 use crate::models::{
+    ParticleModel,
     ParticleTexturedModel,
 };
 use crate::renderers::{
     particle_renderer::ParticleRenderer,
+    particle_renderer::ParticleSceneDepth,
     particle_renderer_gpu_instanced::ParticleRendererGpuInstanced,    
 };
 use crate::utils::insertion_sort;
@@ -36,7 +47,19 @@ pub struct Particle {
     pub texture_offset1: Vector2f,
     pub texture_offset2: Vector2f,
     pub blend: f32,
+    // set by the particle master when the system that spawned the particle is too far from the camera
+    pub is_culled: bool,
+    // gravity wells that pull (or push with negative strength) the particle every update
+    // shared by all particles of a system so spawning doesn't allocate, None without attractors
+    pub attractors: Option<Arc<Vec<ParticleAttractor>>>,
+    // the system that emitted the particle, used to enforce the per system particle limit
+    pub system_id: Option<usize>,
+    pub terrain_collision: TerrainCollision,
+    pub rotation_mode: ParticleRotationMode,
+    spawn_position: Vector3f,
     elapsed_time: f32,
+    // frame time accumulated while far away particles skip their updates
+    pending_update_time: f32,
     distance_sq_from_camera: f32,
 }
 
@@ -45,6 +68,7 @@ impl Particle {
     pub fn new(model: ParticleTexturedModel, position: Vector3f, velocity: Vector3f, gravity_effect: f32, rotation_deg_z: f32, scale: f32, lifetime: f32,) -> Self {
         Particle {
             model,
+            spawn_position: position.clone(),
             position,
             velocity,
             gravity_effect,
@@ -55,23 +79,79 @@ impl Particle {
             texture_offset1: Vector2f::zero(),
             texture_offset2: Vector2f::zero(),
             blend: 0.0,
+            is_culled: false,
+            attractors: None,
+            system_id: None,
+            terrain_collision: TerrainCollision::default(),
+            rotation_mode: ParticleRotationMode::default(),
+            pending_update_time: 0.0,
             distance_sq_from_camera: 0.0,
         }
     }
 
-    pub fn update(&mut self, display: &Display, camera: &Camera) {
-        self.velocity.y += GRAVITY * display.frame_time_sec * self.gravity_effect;
-        let dpos_per_frame = self.velocity.clone() * display.frame_time_sec;
+    pub fn update(&mut self, frame_time_sec: f32, camera: &Camera, ground: Option<&Ground>) {
+        let previous_position = self.position.clone();
+        self.velocity.y += GRAVITY * frame_time_sec * self.gravity_effect;
+        for attractor in self.attractors.iter().flat_map(|attractors| attractors.iter()) {
+            self.velocity += &(attractor.acceleration_at(&self.position) * frame_time_sec);
+        }
+        let dpos_per_frame = self.velocity.clone() * frame_time_sec;
         self.position += &dpos_per_frame;
+        if let Some(ground) = ground {
+            self.collide_with_terrain(&previous_position, |x, z| ground.height_at_xz(x, z));
+        }
         self.update_texture_atlas_data();
         self.update_dist(camera);
-        self.elapsed_time += display.frame_time_sec;        
+        self.elapsed_time += frame_time_sec;        
     }
 
     pub fn is_alive(&self) -> bool {
         self.elapsed_time < self.lifetime
     }
 
+    // only a particle that moves from above the surface to below it collides
+    // so particles spawned below the terrain keep moving instead of getting snapped onto the surface
+    fn collide_with_terrain(&mut self, previous_position: &Vector3f, height_at: impl Fn(f32, f32) -> f32) {
+        if self.terrain_collision == TerrainCollision::None {
+            return;
+        }
+        let was_above = previous_position.y >= height_at(previous_position.x, previous_position.z);
+        let height = height_at(self.position.x, self.position.z);
+        if !was_above || self.position.y >= height {
+            return;
+        }
+        match self.terrain_collision {
+            TerrainCollision::Bounce { restitution } => {
+                self.position.y = height;
+                self.velocity.y = -self.velocity.y * restitution;
+            },
+            TerrainCollision::Die => self.elapsed_time = self.lifetime,
+            TerrainCollision::None => (),
+        }
+    }
+
+    // the quad gets turned so its x axis points where the particle moves on screen, streak textures should point right
+    // a particle moving straight towards or away from the camera keeps its rotation
+    pub fn align_rotation_to_velocity(&mut self, view_matrix: &Matrix4f) {
+        if let Some(rotation_deg_z) = Particle::screen_space_rotation_deg(&self.velocity, view_matrix) {
+            self.rotation_deg_z = rotation_deg_z;
+        }
+    }
+
+    fn screen_space_rotation_deg(velocity: &Vector3f, view_matrix: &Matrix4f) -> Option<f32> {
+        let view_velocity = view_matrix.transform(&Vector4f::new(velocity.x, velocity.y, velocity.z, 0.0));
+        if view_velocity.x.abs() < f32::EPSILON && view_velocity.y.abs() < f32::EPSILON {
+            return None;
+        }
+        Some(view_velocity.y.atan2(view_velocity.x).to_degrees())
+    }
+
+    // the whole quad is on the clipped side, it is at most scale away from the particle position
+    // quads crossing the plane are cut by the clip distance in the shader
+    pub fn is_below_clip_plane(&self, clip_plane: &Vector4f) -> bool {
+        clip_plane.dot_product_v3(&self.position) + clip_plane.w < -self.scale
+    }
+
     fn update_dist(&mut self, camera: &Camera) {
         self.distance_sq_from_camera = (&camera.position - &self.position).length_squared();
     }
@@ -107,42 +187,593 @@ impl PartialOrd for Particle {
     }
 }
 
+// rotation of the particle quad around the view direction
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParticleRotationMode {
+    // keeps the rotation it was spawned with
+    Fixed,
+    // random rotation at spawn which is then kept
+    Random,
+    // follows the direction the particle moves on screen, e.g. for sparks and debris
+    AlignVelocity,
+}
+
+impl Default for ParticleRotationMode {
+    fn default() -> Self {
+        ParticleRotationMode::Fixed
+    }
+}
+
+// what happens to a particle that falls onto the terrain surface
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TerrainCollision {
+    // the particle passes through the terrain
+    None,
+    // the y velocity gets reflected and scaled by the restitution, 1 keeps all the speed and 0 makes the particle stick to the ground
+    Bounce { restitution: f32 },
+    Die,
+}
+
+impl Default for TerrainCollision {
+    fn default() -> Self {
+        TerrainCollision::None
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ParticleAttractor {
+    pub position: Vector3f,
+    // negative strength repels the particles
+    pub strength: f32,
+}
+
+impl ParticleAttractor {
+    // the inverse square falloff is clamped at this distance so particles passing through the attractor don't get flung away
+    const MIN_DISTANCE_SQ: f32 = 1.0;
+
+    pub fn new(position: Vector3f, strength: f32) -> Self {
+        ParticleAttractor { position, strength }
+    }
+
+    pub fn acceleration_at(&self, particle_position: &Vector3f) -> Vector3f {
+        let mut to_attractor = &self.position - particle_position;
+        let distance_sq = to_attractor.length_squared();
+        if distance_sq == 0.0 {
+            // no direction to pull in when sitting exactly on the attractor
+            return Vector3f::zero();
+        }
+        to_attractor.normalize();
+        to_attractor * (self.strength / distance_sq.max(ParticleAttractor::MIN_DISTANCE_SQ))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct ParticleCullDistance {
+    // particles of systems further away than this are not rendered
+    pub render_distance: f32,
+    // systems further away than this don't emit and their particles are only updated every few frames
+    pub update_distance: f32,
+}
+
+impl ParticleCullDistance {
+    fn is_beyond_render(&self, distance_sq: f32) -> bool {
+        distance_sq > self.render_distance * self.render_distance
+    }
+
+    fn is_beyond_update(&self, distance_sq: f32) -> bool {
+        distance_sq > self.update_distance * self.update_distance
+    }
+}
+
+// particles don't affect each other so their updates can be split across threads
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParticleUpdateMode {
+    Serial,
+    // number of threads the particles are split between, the sorting for rendering still happens on the calling thread
+    Parallel(usize),
+}
+
+impl Default for ParticleUpdateMode {
+    fn default() -> Self {
+        ParticleUpdateMode::Serial
+    }
+}
+
 pub struct ParticleMaster {
     particles: HashMap<ParticleTexturedModel, Vec<Particle>>,
+    // live particles of every system so the per system limit doesn't have to scan the group on every spawn
+    // a system always emits with the same texture so all of its particles are in one group
+    system_particle_counts: HashMap<Option<usize>, usize>,
     particle_renderer: Box<dyn ParticleRenderer>,
+    cull_distance: Option<ParticleCullDistance>,
+    frame_counter: usize,
+    // only exists in the parallel update mode, its threads are kept between frames
+    thread_pool: Option<ThreadPool>,
 }
 
 impl ParticleMaster {
+    // far away particles still get updated every this many frames so they don't pop when we approach them
+    const FAR_UPDATE_INTERVAL: usize = 8;
+
     pub fn new(projection_matrix: &Matrix4f) -> Self {
         ParticleMaster {
             particles: HashMap::new(),            
+            system_particle_counts: HashMap::new(),
             particle_renderer: Box::new(ParticleRendererGpuInstanced::new(projection_matrix)),
+            cull_distance: None,
+            frame_counter: 0,
+            thread_pool: None,
         }
     }
 
+    // only pays off with many particles, handing the particles to the threads and back costs more than updating a few hundred of them
+    pub fn set_update_mode(&mut self, update_mode: ParticleUpdateMode) {
+        self.thread_pool = match update_mode {
+            ParticleUpdateMode::Parallel(thread_count) if thread_count > 1 => Some(ThreadPool::new(thread_count)),
+            _ => None,
+        };
+    }
+
+    pub fn set_cull_distance(&mut self, render_distance: f32, update_distance: f32) {
+        self.cull_distance = Some(ParticleCullDistance { render_distance, update_distance });
+    }
+
     pub fn add_particle(&mut self, particle: Particle) {
-        let entry = self.particles.entry(particle.model.clone()).or_insert(Vec::new());
-        entry.push(particle);
+        self.add_particle_with_limit(particle, None);
     }
 
-    pub fn update(&mut self, display: &Display, camera: &Camera) {
-        for (_texture, particles) in self.particles.iter_mut() {
-            for particle in particles.iter_mut() {
-                particle.update(display, camera);
+    // once the system has max_per_system live particles or the instance buffer of the texture is full
+    // the new particle replaces an old one instead of growing the group
+    pub fn add_particle_with_limit(&mut self, particle: Particle, max_per_system: Option<usize>) {
+        let group = self.particles.entry(particle.model.clone()).or_insert(Vec::new());
+        ParticleMaster::insert_with_limit(group, &mut self.system_particle_counts, particle, max_per_system);
+    }
+
+    fn insert_with_limit(group: &mut Vec<Particle>, system_counts: &mut HashMap<Option<usize>, usize>, particle: Particle, max_per_system: Option<usize>) {
+        let system_count = system_counts.get(&particle.system_id).cloned().unwrap_or(0);
+        let is_system_full = max_per_system.map_or(false, |max_count| system_count >= max_count);
+        let is_group_full = group.len() >= ParticleModel::MAX_INSTANCES;
+        if !is_system_full && !is_group_full {
+            *system_counts.entry(particle.system_id).or_insert(0) += 1;
+            group.push(particle);
+            return;
+        }
+        // a system at its own cap recycles its own particles
+        // when the buffer is full the system furthest over its share of the buffer gives up its oldest particle
+        let recycled_system = if is_system_full {
+            particle.system_id
+        } else {
+            ParticleMaster::system_furthest_over_share(group, particle.system_id)
+        };
+        let recycled_index = ParticleMaster::oldest_particle_index(group, |other| other.system_id == recycled_system)
+            .or_else(|| ParticleMaster::oldest_particle_index(group, |_| true));
+        if let Some(index) = recycled_index {
+            if group[index].system_id != particle.system_id {
+                if let Some(recycled_count) = system_counts.get_mut(&group[index].system_id) {
+                    *recycled_count -= 1;
+                }
+                *system_counts.entry(particle.system_id).or_insert(0) += 1;
             }
-            insertion_sort(particles);
-            particles.retain(|particle_ref| particle_ref.is_alive());
+            group[index] = particle;
+        }
+    }
+
+    // every system in the group gets the same share so the one with the most particles is furthest over it
+    // the inserting system counts with the new particle and keeps ties so it doesn't push out a system with as many particles as itself
+    fn system_furthest_over_share(group: &[Particle], inserting_system: Option<usize>) -> Option<usize> {
+        let mut group_counts: HashMap<Option<usize>, usize> = HashMap::new();
+        for particle in group {
+            *group_counts.entry(particle.system_id).or_insert(0) += 1;
+        }
+        let inserting_count = group_counts.get(&inserting_system).cloned().unwrap_or(0) + 1;
+        group_counts.into_iter()
+            .filter(|&(_, count)| count > inserting_count)
+            .max_by_key(|&(system_id, count)| (count, system_id))
+            .map_or(inserting_system, |(system_id, _)| system_id)
+    }
+
+    // the update removes the dead particles so the counts are rebuilt once per frame
+    fn recount_system_particles<'a>(system_counts: &mut HashMap<Option<usize>, usize>, groups: impl Iterator<Item = &'a Vec<Particle>>) {
+        system_counts.clear();
+        for particle in groups.flatten() {
+            *system_counts.entry(particle.system_id).or_insert(0) += 1;
         }
-        self.particles.retain(|_key, particles| particles.len() > 0);
     }
 
-    pub fn render(&mut self, camera: &Camera) {
-        self.particle_renderer.render(&self.particles, camera);
+    fn oldest_particle_index(particles: &[Particle], filter: impl Fn(&Particle) -> bool) -> Option<usize> {
+        particles.iter().enumerate()
+            .filter(|(_, particle)| filter(particle))
+            .max_by(|(_, a), (_, b)| a.elapsed_time.partial_cmp(&b.elapsed_time).unwrap_or(Ordering::Equal))
+            .map(|(index, _)| index)
     }
 
-    pub fn emit_particles(&mut self, particle_systems: &Vec<(AdvancedParticleSystem, Vector3f)>, display: &Display) {
+    // particles that collide with the terrain need the ground to query its height
+    pub fn update(&mut self, frame_time_sec: f32, camera: &Camera, ground: Option<&Ground>) {
+        self.frame_counter = self.frame_counter.wrapping_add(1);
+        let is_far_update_frame = self.frame_counter % ParticleMaster::FAR_UPDATE_INTERVAL == 0;
+        let groups = self.particles.values_mut().collect();
+        ParticleMaster::update_groups(groups, self.thread_pool.as_ref(), frame_time_sec, camera, ground, self.cull_distance, is_far_update_frame);
+        ParticleMaster::recount_system_particles(&mut self.system_particle_counts, self.particles.values());
+        // we intentionally keep the empty vectors in the map so that the storage they allocated is reused 
+        // when the particle system emits again instead of reallocating the vector every time
+    }
+
+    pub fn render(&mut self, camera: &Camera, scene_depth: &ParticleSceneDepth) {
+        self.particle_renderer.render(&self.particles, camera, None, scene_depth);
+    }
+
+    // e.g. for the water reflection where only what is above the water may show up
+    pub fn render_clipped(&mut self, camera: &Camera, clip_plane: &Vector4f, scene_depth: &ParticleSceneDepth) {
+        self.particle_renderer.render(&self.particles, camera, Some(clip_plane), scene_depth);
+    }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.particle_renderer.update_projection_matrix(projection_matrix);
+    }
+
+    pub fn emit_particles(&mut self, particle_systems: &Vec<(AdvancedParticleSystem, Vector3f)>, frame_time_sec: f32, camera: &Camera) {
         for (system, pos) in particle_systems {
-            system.emit_particles(self, pos, display);
+            if let Some(cull_distance) = self.cull_distance {
+                if cull_distance.is_beyond_update((&camera.position - pos).length_squared()) {
+                    continue;
+                }
+            }
+            system.emit_particles(self, pos, frame_time_sec);
         }
     }
-}
\ No newline at end of file
+
+    // without a thread pool the groups are updated on the calling thread
+    fn update_groups(mut groups: Vec<&mut Vec<Particle>>, thread_pool: Option<&ThreadPool>, frame_time_sec: f32, camera: &Camera, ground: Option<&Ground>, 
+                cull_distance: Option<ParticleCullDistance>, is_far_update_frame: bool) {
+        let thread_pool = match thread_pool {
+            Some(thread_pool) => thread_pool,
+            None => {
+                for particles in groups {
+                    ParticleMaster::update_particles(particles, frame_time_sec, camera, ground, cull_distance, is_far_update_frame);
+                }
+                return;
+            },
+        };
+        // pool jobs have to own their data so the chunks are moved into the jobs and sent back
+        // the camera and the ground are cloned once per frame, the ground only shares its height maps
+        let frame_state = Arc::new((camera.clone(), Matrix4f::create_view_matrix(camera), ground.cloned()));
+        let thread_count = thread_pool.max_count();
+        let particle_count: usize = groups.iter().map(|particles| particles.len()).sum();
+        let chunk_len = cmp::max(1, (particle_count + thread_count - 1) / thread_count);
+        let (sender, receiver) = mpsc::channel();
+        let mut job_count = 0;
+        for (group_index, particles) in groups.iter_mut().enumerate() {
+            let mut head = std::mem::take(&mut **particles);
+            // split off from the back so every particle is moved once, the first chunk keeps the storage of the group
+            while !head.is_empty() {
+                let chunk_start = (head.len() - 1) / chunk_len * chunk_len;
+                let mut chunk = if chunk_start == 0 { std::mem::take(&mut head) } else { head.split_off(chunk_start) };
+                let sender = sender.clone();
+                let frame_state = Arc::clone(&frame_state);
+                thread_pool.execute(move || {
+                    let (camera, view_matrix, ground) = &*frame_state;
+                    ParticleMaster::update_particle_states(&mut chunk, frame_time_sec, camera, view_matrix, ground.as_ref(), cull_distance, is_far_update_frame);
+                    sender.send((group_index, chunk_start, chunk)).expect("Particle update receiver was dropped");
+                });
+                job_count += 1;
+            }
+        }
+        // the jobs hold the only senders left so a panicking job ends the iteration instead of blocking forever
+        drop(sender);
+        let mut updated_chunks: Vec<(usize, usize, Vec<Particle>)> = receiver.iter().take(job_count).collect();
+        assert_eq!(updated_chunks.len(), job_count, "A particle update job panicked");
+        updated_chunks.sort_by_key(|(group_index, chunk_start, _)| (*group_index, *chunk_start));
+        for (group_index, _, chunk) in updated_chunks {
+            let particles = &mut *groups[group_index];
+            if particles.is_empty() {
+                *particles = chunk;
+            } else {
+                particles.extend(chunk);
+            }
+        }
+        for particles in groups {
+            ParticleMaster::finish_update(particles);
+        }
+    }
+
+    fn update_particles(particles: &mut Vec<Particle>, frame_time_sec: f32, camera: &Camera, ground: Option<&Ground>, cull_distance: Option<ParticleCullDistance>, is_far_update_frame: bool) {
+        let view_matrix = Matrix4f::create_view_matrix(camera);
+        ParticleMaster::update_particle_states(particles, frame_time_sec, camera, &view_matrix, ground, cull_distance, is_far_update_frame);
+        ParticleMaster::finish_update(particles);
+    }
+
+    fn update_particle_states(particles: &mut [Particle], frame_time_sec: f32, camera: &Camera, view_matrix: &Matrix4f, ground: Option<&Ground>, 
+                cull_distance: Option<ParticleCullDistance>, is_far_update_frame: bool) {
+        for particle in particles.iter_mut() {
+            // use the spawn point so that all particles of a system get culled together
+            let spawn_dist_sq = (&camera.position - &particle.spawn_position).length_squared();
+            let (is_culled, is_far) = match cull_distance {
+                Some(cull_distance) => (cull_distance.is_beyond_render(spawn_dist_sq), cull_distance.is_beyond_update(spawn_dist_sq)),
+                None => (false, false),
+            };
+            particle.is_culled = is_culled;
+            particle.pending_update_time += frame_time_sec;
+            if !is_far || is_far_update_frame {
+                let update_time = particle.pending_update_time;
+                particle.update(update_time, camera, ground);
+                particle.pending_update_time = 0.0;
+                if particle.rotation_mode == ParticleRotationMode::AlignVelocity {
+                    particle.align_rotation_to_velocity(view_matrix);
+                }
+            }
+        }
+    }
+
+    // back to front for blending and without the dead particles
+    fn finish_update(particles: &mut Vec<Particle>) {
+        insertion_sort(particles);
+        particles.retain(|particle_ref| particle_ref.is_alive());
+    }
+}
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::renderers::particle_renderer_gpu_instanced::ParticleRendererGpuInstanced;
+
+    fn create_particles(count: usize, spawn_pos: &Vector3f) -> Vec<Particle> {
+        let mut model = ParticleTexturedModel::default();
+        model.texture.number_of_rows_in_atlas = 1;
+        (0..count).map(|_| {
+            Particle::new(model.clone(), spawn_pos.clone(), Vector3f::zero(), 0.0, 0.0, 1.0, 10.0)
+        }).collect()
+    }
+
+    fn rendered_count(particles: &[Particle], camera: &Camera) -> usize {
+        let view_mat = Matrix4f::create_view_matrix(camera);
+        let mut storage = Vec::new();
+        ParticleRendererGpuInstanced::fill_instance_data(particles, &view_mat, camera, None, &mut storage)
+    }
+
+    #[test]
+    fn system_beyond_cull_distance_is_not_rendered() {
+        let camera = Camera::default();
+        let cull_distance = Some(ParticleCullDistance { render_distance: 100.0, update_distance: 150.0 });
+
+        let mut near_particles = create_particles(10, &Vector3f::new(0.0, 0.0, -50.0));
+        ParticleMaster::update_particles(&mut near_particles, 0.016, &camera, None, cull_distance, false);
+        assert_eq!(rendered_count(&near_particles, &camera), 10);
+
+        let mut far_particles = create_particles(10, &Vector3f::new(0.0, 0.0, -120.0));
+        ParticleMaster::update_particles(&mut far_particles, 0.016, &camera, None, cull_distance, false);
+        assert_eq!(far_particles.len(), 10);
+        assert_eq!(rendered_count(&far_particles, &camera), 0);
+    }
+
+    #[test]
+    fn far_particles_accumulate_time_until_update_frame() {
+        let camera = Camera::default();
+        let cull_distance = Some(ParticleCullDistance { render_distance: 10.0, update_distance: 10.0 });
+        let mut particles = create_particles(1, &Vector3f::new(0.0, 0.0, -500.0));
+        particles[0].velocity = Vector3f::new(1.0, 0.0, 0.0);
+
+        ParticleMaster::update_particles(&mut particles, 0.5, &camera, None, cull_distance, false);
+        assert_eq!(particles[0].position.x, 0.0);
+        ParticleMaster::update_particles(&mut particles, 0.5, &camera, None, cull_distance, true);
+        assert_eq!(particles[0].position.x, 1.0);
+    }
+
+    #[test]
+    fn system_at_cap_recycles_oldest_particle() {
+        let camera = Camera::default();
+        let mut particles = Vec::new();
+        let mut system_counts = HashMap::new();
+        let new_particle = || {
+            let mut particle = create_particles(1, &Vector3f::new(0.0, 0.0, -10.0)).pop().unwrap();
+            particle.system_id = Some(7);
+            particle
+        };
+
+        for _ in 0..5 {
+            ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(), Some(5));
+        }
+        ParticleMaster::update_particles(&mut particles, 1.0, &camera, None, None, false);
+        for _ in 0..20 {
+            ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(), Some(5));
+        }
+
+        assert_eq!(particles.len(), 5);
+        assert_eq!(system_counts[&Some(7)], 5);
+        // all the old ones got replaced by the newer ones
+        assert!(particles.iter().all(|particle| particle.elapsed_time == 0.0));
+
+        // once particles die the recount makes room for new ones again
+        particles[0].lifetime = 0.5;
+        ParticleMaster::update_particles(&mut particles, 1.0, &camera, None, None, false);
+        ParticleMaster::recount_system_particles(&mut system_counts, std::iter::once(&particles));
+        assert_eq!(system_counts[&Some(7)], 4);
+        ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(), Some(5));
+        assert_eq!(particles.len(), 5);
+        assert_eq!(system_counts[&Some(7)], 5);
+    }
+
+    #[test]
+    fn full_group_recycles_from_the_system_furthest_over_its_share() {
+        let mut particles = Vec::new();
+        let mut system_counts = HashMap::new();
+        let new_particle = |system_id| {
+            let mut particle = create_particles(1, &Vector3f::new(0.0, 0.0, -10.0)).pop().unwrap();
+            particle.system_id = Some(system_id);
+            particle
+        };
+        let group_count = |particles: &Vec<Particle>, system_id| particles.iter().filter(|particle| particle.system_id == Some(system_id)).count();
+
+        let fill = |particles: &mut Vec<Particle>, system_counts: &mut HashMap<Option<usize>, usize>, counts: &[(usize, usize)]| {
+            particles.clear();
+            system_counts.clear();
+            for &(system_id, count) in counts {
+                for _ in 0..count {
+                    ParticleMaster::insert_with_limit(particles, system_counts, new_particle(system_id), None);
+                }
+            }
+            assert_eq!(particles.len(), ParticleModel::MAX_INSTANCES);
+        };
+
+        // the new system and the smaller one both take from the biggest one
+        fill(&mut particles, &mut system_counts, &[(1, 6000), (2, 4000)]);
+        for _ in 0..10 {
+            ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(3), None);
+            ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(2), None);
+        }
+        assert_eq!((group_count(&particles, 1), group_count(&particles, 2), group_count(&particles, 3)), (5980, 4010, 10));
+
+        // once the buffer is shared evenly a system recycles its own particles and only a newcomer takes from the biggest one
+        fill(&mut particles, &mut system_counts, &[(1, 3334), (2, 3333), (3, 3333)]);
+        ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(3), None);
+        ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(2), None);
+        assert_eq!((group_count(&particles, 1), group_count(&particles, 2), group_count(&particles, 3)), (3334, 3333, 3333));
+        ParticleMaster::insert_with_limit(&mut particles, &mut system_counts, new_particle(4), None);
+        assert_eq!((group_count(&particles, 1), group_count(&particles, 4)), (3333, 1));
+        for &system_id in [1, 2, 3, 4].iter() {
+            assert_eq!(system_counts[&Some(system_id)], group_count(&particles, system_id));
+        }
+    }
+
+    #[test]
+    fn particle_gains_velocity_towards_attractor() {
+        let camera = Camera::default();
+        let mut particles = create_particles(1, &Vector3f::new(0.0, 0.0, -10.0));
+        particles[0].attractors = Some(Arc::new(vec![
+            ParticleAttractor::new(Vector3f::new(2.0, 0.0, -10.0), 8.0),
+            ParticleAttractor::new(Vector3f::new(-4.0, 0.0, -10.0), -16.0),
+        ]));
+
+        ParticleMaster::update_particles(&mut particles, 0.5, &camera, None, None, false);
+        // both attractors push towards +x with 8 / 2^2 and 16 / 4^2 respectively
+        assert!((particles[0].velocity.x - 1.5).abs() < 1e-5);
+        assert_eq!(particles[0].velocity.y, 0.0);
+        assert_eq!(particles[0].velocity.z, 0.0);
+    }
+
+    #[test]
+    fn attractor_acceleration_is_finite_at_zero_distance() {
+        let attractor = ParticleAttractor::new(Vector3f::new(1.0, 2.0, 3.0), 100.0);
+        let at_center = attractor.acceleration_at(&Vector3f::new(1.0, 2.0, 3.0));
+        assert_eq!((at_center.x, at_center.y, at_center.z), (0.0, 0.0, 0.0));
+        let very_close = attractor.acceleration_at(&Vector3f::new(1.0, 2.0, 3.0001));
+        assert!(very_close.length().is_finite());
+        assert!(very_close.length() <= 100.0 + 1e-3);
+    }
+
+    #[test]
+    fn bouncing_particle_reflects_y_velocity_scaled_by_restitution() {
+        let camera = Camera::default();
+        // without terrain cells the ground is flat at height 0
+        let ground = Ground { terrains: Vec::new() };
+        let mut particles = create_particles(1, &Vector3f::new(0.0, 1.0, -10.0));
+        particles[0].velocity = Vector3f::new(0.0, -4.0, 0.0);
+        particles[0].terrain_collision = TerrainCollision::Bounce { restitution: 0.5 };
+
+        ParticleMaster::update_particles(&mut particles, 0.5, &camera, Some(&ground), None, false);
+        assert_eq!(particles[0].position.y, 0.0);
+        assert_eq!(particles[0].velocity.y, 2.0);
+    }
+
+    #[test]
+    fn particle_spawned_below_terrain_is_not_moved_onto_it() {
+        let camera = Camera::default();
+        let ground = Ground { terrains: Vec::new() };
+        let mut particles = create_particles(2, &Vector3f::new(0.0, -1.0, -10.0));
+        particles[0].terrain_collision = TerrainCollision::Bounce { restitution: 0.5 };
+        particles[1].terrain_collision = TerrainCollision::Die;
+        for particle in particles.iter_mut() {
+            particle.velocity = Vector3f::new(0.0, -4.0, 0.0);
+        }
+
+        ParticleMaster::update_particles(&mut particles, 0.5, &camera, Some(&ground), None, false);
+        assert_eq!(particles.len(), 2);
+        assert!(particles.iter().all(|particle| particle.position.y == -3.0 && particle.velocity.y == -4.0));
+    }
+
+    #[test]
+    fn particle_moving_up_right_is_rotated_45_deg() {
+        // level camera looking down -z so screen right is +x and screen up is +y
+        let camera = Camera::new(0.0, 50.0);
+        let view_matrix = Matrix4f::create_view_matrix(&camera);
+        let mut particles = create_particles(1, &Vector3f::new(0.0, 0.0, -10.0));
+        particles[0].velocity = Vector3f::new(1.0, 1.0, 0.0);
+        particles[0].align_rotation_to_velocity(&view_matrix);
+        assert!((particles[0].rotation_deg_z - 45.0).abs() < 1e-4);
+
+        // moving away from the camera has no screen direction
+        particles[0].velocity = Vector3f::new(0.0, 0.0, -1.0);
+        particles[0].align_rotation_to_velocity(&view_matrix);
+        assert!((particles[0].rotation_deg_z - 45.0).abs() < 1e-4);
+    }
+
+    fn create_seeded_groups(seed: u64, terrain_collision: TerrainCollision) -> Vec<Vec<Particle>> {
+        use rand::{Rng, SeedableRng, rngs::StdRng};
+        let mut rng = StdRng::seed_from_u64(seed);
+        [7, 50, 13].iter().map(|&count| {
+            let mut particles = create_particles(count, &Vector3f::new(0.0, 0.0, -10.0));
+            for particle in particles.iter_mut() {
+                // spread along the slope of the test ground
+                particle.position = Vector3f::new(rng.gen::<f32>() * 700.0, 8.0, 400.0);
+                particle.velocity = Vector3f::new(rng.gen::<f32>() - 0.5, rng.gen::<f32>() * 4.0, rng.gen::<f32>() - 0.5);
+                particle.gravity_effect = rng.gen::<f32>();
+                particle.lifetime = rng.gen::<f32>() * 2.0 + 1.0;
+                particle.rotation_mode = ParticleRotationMode::AlignVelocity;
+                particle.terrain_collision = terrain_collision;
+            }
+            particles
+        }).collect()
+    }
+
+    // one terrain tile at the origin rising by 1 per grid cell along x
+    fn sloped_ground() -> Ground {
+        use crate::entities::Terrain;
+        use crate::models::{
+            RawModel,
+            TerrainModel,
+            TerrainTexture,
+            TerrainTexturePack,
+            TextureId,
+        };
+        let texture = || TerrainTexture { tex_id: TextureId::Loaded(1) };
+        let texture_pack = TerrainTexturePack { background_texture: texture(), r_texture: texture(), g_texture: texture(), b_texture: texture() };
+        let height_map = (0..16).map(|x| vec![x as f32; 16]).collect();
+        let model = TerrainModel { raw_model: RawModel::default(), height_map: Arc::new(height_map) };
+        Ground { terrains: vec![Terrain::new(0, 0, texture_pack, texture(), model)] }
+    }
+
+    // returns the particles left after the updates
+    fn assert_parallel_update_matches_serial(terrain_collision: TerrainCollision, ground: Option<&Ground>) -> usize {
+        let camera = Camera::default();
+        let thread_pool = ThreadPool::new(4);
+        let mut serial_groups = create_seeded_groups(42, terrain_collision);
+        let mut parallel_groups = create_seeded_groups(42, terrain_collision);
+        // shorter than the shortest lifetime
+        for _ in 0..10 {
+            ParticleMaster::update_groups(serial_groups.iter_mut().collect(), None, 0.1, &camera, ground, None, false);
+            ParticleMaster::update_groups(parallel_groups.iter_mut().collect(), Some(&thread_pool), 0.1, &camera, ground, None, false);
+        }
+        for (serial, parallel) in serial_groups.iter().zip(parallel_groups.iter()) {
+            assert_eq!(serial.len(), parallel.len());
+            for (a, b) in serial.iter().zip(parallel.iter()) {
+                assert_eq!((a.position.x, a.position.y, a.position.z), (b.position.x, b.position.y, b.position.z));
+                assert_eq!((a.velocity.x, a.velocity.y, a.velocity.z), (b.velocity.x, b.velocity.y, b.velocity.z));
+                assert_eq!((a.rotation_deg_z, a.elapsed_time), (b.rotation_deg_z, b.elapsed_time));
+            }
+        }
+        serial_groups.iter().map(|particles| particles.len()).sum()
+    }
+
+    #[test]
+    fn parallel_update_matches_serial_update() {
+        assert_parallel_update_matches_serial(TerrainCollision::None, None);
+    }
+
+    #[test]
+    fn parallel_update_with_terrain_collision_matches_serial_update() {
+        let ground = sloped_ground();
+        let bouncing_count = assert_parallel_update_matches_serial(TerrainCollision::Bounce { restitution: 0.5 }, Some(&ground));
+        let dying_count = assert_parallel_update_matches_serial(TerrainCollision::Die, Some(&ground));
+        // none of them is old enough to die on its own
+        assert_eq!(bouncing_count, 70);
+        assert!(dying_count < bouncing_count);
+    }
+}
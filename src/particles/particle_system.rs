@@ -3,7 +3,11 @@ use rand::{
     prelude::ThreadRng,
 };
 use std::f32;
-use crate::display::Display;
+use std::sync::Arc;
+use std::sync::atomic::{
+    AtomicUsize,
+    Ordering,
+};
 use crate::math::{
     Vector3f,
 };
@@ -11,14 +15,18 @@ use crate::models::{
     ParticleModel,
     ParticleTexture,
     ParticleTexturedModel,
+    ParticleBlendMode,
 };
 use crate::particles::{
     Particle,
     ParticleMaster,
+    ParticleAttractor,
+    TerrainCollision,
+    ParticleRotationMode,
 };
 
 pub trait ParticleSystem {
-    fn emit_particles(&self, particle_master: &mut ParticleMaster, spawn_pos: &Vector3f, display: &Display);
+    fn emit_particles(&self, particle_master: &mut ParticleMaster, spawn_pos: &Vector3f, frame_time_sec: f32);
 }
 
 pub struct SimpleParticleSystem {
@@ -51,9 +59,9 @@ impl SimpleParticleSystem {
 }
 
 impl ParticleSystem for SimpleParticleSystem {
-    fn emit_particles(&self, particle_master: &mut ParticleMaster, spawn_pos: &Vector3f, display: &Display) {
+    fn emit_particles(&self, particle_master: &mut ParticleMaster, spawn_pos: &Vector3f, frame_time_sec: f32) {
 
-        let delta = self.particles_per_sec * display.frame_time_sec;
+        let delta = self.particles_per_sec * frame_time_sec;
         let count = delta.floor() as usize;
         let percentage_to_spawn = delta % 1.0;
         let mut rng = rand::thread_rng();
@@ -81,11 +89,20 @@ pub struct ParticleSystemProps {
     pub speed_error: f32,
     pub life_error: f32,
     pub scale_error: f32,
-    pub randomize_rotation: bool,
+    pub rotation_mode: ParticleRotationMode,
     pub direction: Option<(Vector3f, f32)>,
-    pub additive_blending: bool,
+    pub blend_mode: ParticleBlendMode,
+    // forces of all attractors are summed, positions are in world space
+    pub attractors: Vec<ParticleAttractor>,
+    // when the system has this many live particles the oldest one gets recycled for each new one
+    pub max_particles: Option<usize>,
+    // whether the particles bounce off or die on the terrain surface instead of falling through it
+    pub terrain_collision: TerrainCollision,
 }
 
+// every advanced system gets a unique id so the particle master can tell apart their particles
+static NEXT_SYSTEM_ID: AtomicUsize = AtomicUsize::new(0);
+
 pub struct AdvancedParticleSystem {
     particle_model: ParticleTexturedModel,
     particles_per_sec: f32, 
@@ -96,9 +113,13 @@ pub struct AdvancedParticleSystem {
     speed_error: f32,
     life_error: f32,
     scale_error: f32,
-    randomize_rotation: bool,
+    rotation_mode: ParticleRotationMode,
     direction: Option<Vector3f>,
     direction_deviation: Option<f32>,
+    attractors: Option<Arc<Vec<ParticleAttractor>>>,
+    max_particles: Option<usize>,
+    terrain_collision: TerrainCollision,
+    system_id: usize,
 }
 
 impl AdvancedParticleSystem {    
@@ -109,7 +130,7 @@ impl AdvancedParticleSystem {
         } else { 
             (None, None)
         };
-        texture.additive = props.additive_blending;
+        texture.blend_mode = props.blend_mode;
         Self {
             particle_model: ParticleTexturedModel { model: particle_model, texture },
             particles_per_sec: props.particles_per_sec, 
@@ -120,9 +141,13 @@ impl AdvancedParticleSystem {
             speed_error: props.speed_error * props.speed,
             life_error: props.life_error * props.life_length,
             scale_error: props.scale_error * props.scale,
-            randomize_rotation: props.randomize_rotation,
+            rotation_mode: props.rotation_mode,
             direction,
             direction_deviation,
+            attractors: if props.attractors.is_empty() { None } else { Some(Arc::new(props.attractors)) },
+            max_particles: props.max_particles,
+            terrain_collision: props.terrain_collision,
+            system_id: NEXT_SYSTEM_ID.fetch_add(1, Ordering::Relaxed),
         }
     }
 
@@ -135,9 +160,15 @@ impl AdvancedParticleSystem {
         velocity.normalize();
         velocity *= AdvancedParticleSystem::generate_value_using_error(rng, self.speed, self.speed_error);
         let particle_scale = AdvancedParticleSystem::generate_value_using_error(rng, self.scale, self.scale_error);
-        let particle_rotation = if self.randomize_rotation { rng.gen::<f32>() * 360.0 } else { 0.0 };
+        // aligned particles get their rotation on their first update
+        let particle_rotation = if self.rotation_mode == ParticleRotationMode::Random { rng.gen::<f32>() * 360.0 } else { 0.0 };
         let particle_life = AdvancedParticleSystem::generate_value_using_error(rng, self.life_length, self.life_error);
-        Particle::new(self.particle_model.clone(), spawn_pos.clone(), velocity, self.gravity_effect, particle_rotation, particle_scale, particle_life)
+        let mut particle = Particle::new(self.particle_model.clone(), spawn_pos.clone(), velocity, self.gravity_effect, particle_rotation, particle_scale, particle_life);
+        particle.attractors = self.attractors.clone();
+        particle.system_id = Some(self.system_id);
+        particle.terrain_collision = self.terrain_collision;
+        particle.rotation_mode = self.rotation_mode;
+        particle
     }
 
     fn generate_random_direction(rng: &mut ThreadRng) -> Vector3f {
@@ -171,21 +202,21 @@ impl AdvancedParticleSystem {
 }
 
 impl ParticleSystem for AdvancedParticleSystem {
-    fn emit_particles(&self, particle_master: &mut ParticleMaster, spawn_pos: &Vector3f, display: &Display) {
-        let delta = self.particles_per_sec * display.frame_time_sec;
+    fn emit_particles(&self, particle_master: &mut ParticleMaster, spawn_pos: &Vector3f, frame_time_sec: f32) {
+        let delta = self.particles_per_sec * frame_time_sec;
         let count = delta.floor() as usize;
         let percentage_to_spawn = delta % 1.0;
         let mut rng = rand::thread_rng();
 
         for _ in 0..count {            
-            particle_master.add_particle(
-                self.create_particle(&mut rng, spawn_pos)
+            particle_master.add_particle_with_limit(
+                self.create_particle(&mut rng, spawn_pos), self.max_particles
             );
         }
 
         if rng.gen::<f32>() < percentage_to_spawn {
-            particle_master.add_particle(
-                self.create_particle(&mut rng, spawn_pos)
+            particle_master.add_particle_with_limit(
+                self.create_particle(&mut rng, spawn_pos), self.max_particles
             );
         }
     }
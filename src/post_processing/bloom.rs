@@ -0,0 +1,175 @@
+use crate::display::{
+    Display,
+    framebuffers::FramebufferObject,
+    framebuffers::FboFlags,
+};
+use crate::gl;
+use crate::renderers::image_renderer::ImageRenderer;
+use crate::shaders::shader::Shader;
+use crate::shaders::post_processing::{
+    HorizontalBlurShader,
+    VerticalBlurShader,
+    CombineShader,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BloomParams {
+    // how many times the brightness texture gets halved and blurred, more levels give a wider glow
+    pub mip_count: usize,
+    // 1 blurs with a one texel step on each level, larger values spread the samples further apart
+    pub blur_radius: f32,
+    // the chain stops early once a level would get smaller than this in either direction
+    pub min_mip_size: usize,
+}
+
+impl Default for BloomParams {
+    fn default() -> Self {
+        BloomParams {
+            mip_count: 5,
+            blur_radius: 1.0,
+            min_mip_size: 8,
+        }
+    }
+}
+
+// sizes of the downsample levels, the first one is half the screen and each next one half the previous
+pub fn mip_chain_sizes(width: usize, height: usize, mip_count: usize, min_mip_size: usize) -> Vec<(usize, usize)> {
+    let mut sizes = Vec::new();
+    let (mut mip_width, mut mip_height) = (width / 2, height / 2);
+    while sizes.len() < mip_count && mip_width >= min_mip_size && mip_height >= min_mip_size {
+        sizes.push((mip_width, mip_height));
+        mip_width /= 2;
+        mip_height /= 2;
+    }
+    sizes
+}
+
+struct BloomLevel {
+    width: usize,
+    height: usize,
+    horizontal: ImageRenderer,
+    vertical: ImageRenderer,
+    // where the blurred smaller levels get added onto this one, the smallest level has nothing to add
+    upsample: Option<ImageRenderer>,
+}
+
+pub struct BloomChain {
+    params: BloomParams,
+    horizontal_blur: HorizontalBlurShader,
+    vertical_blur: VerticalBlurShader,
+    combine: CombineShader,
+    levels: Vec<BloomLevel>,
+    screen_size: (usize, usize),
+}
+
+impl BloomChain {
+    pub fn new(width: usize, height: usize, params: BloomParams) -> Self {
+        let mut combine = CombineShader::new();
+        combine.start();
+        combine.init();
+        combine.stop();
+
+        let mut bloom = BloomChain {
+            params,
+            horizontal_blur: HorizontalBlurShader::new(width / 2),
+            vertical_blur: VerticalBlurShader::new(height / 2),
+            combine,
+            levels: Vec::new(),
+            screen_size: (width, height),
+        };
+        bloom.create_levels();
+        bloom
+    }
+
+    pub fn params(&self) -> &BloomParams {
+        &self.params
+    }
+
+    pub fn set_params(&mut self, params: BloomParams) {
+        let needs_new_levels = params.mip_count != self.params.mip_count || params.min_mip_size != self.params.min_mip_size;
+        self.params = params;
+        if needs_new_levels {
+            self.create_levels();
+        }
+    }
+
+    // the level fbos follow the window size
+    pub fn resize(&mut self, width: usize, height: usize) {
+        if self.screen_size != (width, height) {
+            self.screen_size = (width, height);
+            self.create_levels();
+        }
+    }
+
+    fn create_levels(&mut self) {
+        let (width, height) = self.screen_size;
+        let sizes = mip_chain_sizes(width, height, self.params.mip_count, self.params.min_mip_size);
+        let smallest = sizes.len().saturating_sub(1);
+        self.levels = sizes.iter().enumerate().map(|(i, &(width, height))| {
+            BloomLevel {
+                width,
+                height,
+                horizontal: ImageRenderer::new(Some(FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1))),
+                vertical: ImageRenderer::new(Some(FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1))),
+                upsample: if i < smallest { Some(ImageRenderer::new(Some(FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1)))) } else { None },
+            }
+        }).collect();
+    }
+
+    // returns the texture with the blurred brightness of all levels added together
+    // expects the quad vao to be bound like the other post processing steps
+    pub fn render(&mut self, brightness_texture: u32, display: &Display) -> u32 {
+        let blur_radius = self.params.blur_radius.max(0.01);
+
+        // downsample: every level blurs the output of the previous one into its own smaller fbos
+        let mut source_texture = brightness_texture;
+        for level in self.levels.iter_mut() {
+            gl::active_texture(gl::TEXTURE0);
+            gl::bind_texture(gl::TEXTURE_2D, source_texture);
+            self.horizontal_blur.start();
+            self.horizontal_blur.load_viewport_dimension(((level.width as f32 / blur_radius) as usize).max(1));
+            level.horizontal.render_quad(display);
+            self.horizontal_blur.stop();
+
+            gl::bind_texture(gl::TEXTURE_2D, level.horizontal.get_color_texture().expect("Bloom level must have a horizontal blur texture"));
+            self.vertical_blur.start();
+            self.vertical_blur.load_viewport_dimension(((level.height as f32 / blur_radius) as usize).max(1));
+            level.vertical.render_quad(display);
+            self.vertical_blur.stop();
+
+            source_texture = level.vertical.get_color_texture().expect("Bloom level must have a vertical blur texture");
+        }
+
+        // upsample: walk back up adding the accumulated smaller levels onto each larger one
+        let mut bloom_texture = source_texture;
+        for level in self.levels.iter_mut().rev() {
+            if let Some(upsample) = level.upsample.as_mut() {
+                gl::active_texture(gl::TEXTURE0);
+                gl::bind_texture(gl::TEXTURE_2D, level.vertical.get_color_texture().expect("Bloom level must have a vertical blur texture"));
+                gl::active_texture(gl::TEXTURE1);
+                gl::bind_texture(gl::TEXTURE_2D, bloom_texture);
+                self.combine.start();
+                upsample.render_quad(display);
+                self.combine.stop();
+                bloom_texture = upsample.get_color_texture().expect("Bloom level must have an upsample texture");
+            }
+        }
+        bloom_texture
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn mip_chain_halves_down_to_the_min_size() {
+        let sizes = mip_chain_sizes(1920, 1080, 6, 16);
+        assert_eq!(sizes, vec![(960, 540), (480, 270), (240, 135), (120, 67), (60, 33), (30, 16)]);
+
+        // the next level would be 15x8 so asking for more mips doesn't add any
+        assert_eq!(mip_chain_sizes(1920, 1080, 10, 16).len(), 6);
+        assert_eq!(mip_chain_sizes(1920, 1080, 2, 16).len(), 2);
+        assert!(mip_chain_sizes(20, 20, 5, 16).is_empty());
+    }
+}
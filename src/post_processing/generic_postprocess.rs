@@ -42,6 +42,10 @@ impl<ShaderType: Shader> GenericPostprocess<ShaderType> {
         self.shader.stop();
     }
 
+    pub fn set_render_target(&mut self, render_target: Option<FramebufferObject>) {
+        self.renderer.set_render_target(render_target);
+    }
+
     pub fn get_output_texture(&self) -> Result<u32, &'static str> {
         self.renderer.get_color_texture()            
     }
@@ -1,2 +1,4 @@
 pub mod post_processing;
-pub mod generic_postprocess;
\ No newline at end of file
+pub mod generic_postprocess;
+pub mod bloom;
+pub mod stages;
\ No newline at end of file
@@ -1,4 +1,9 @@
-use super::generic_postprocess::GenericPostprocess;
+use std::any::Any;
+use super::bloom::BloomParams;
+use super::stages::{
+    BloomStage,
+    ContrastStage,
+};
 use crate::display::{
     Display,
     framebuffers::FboMap,
@@ -10,74 +15,235 @@ use crate::models::{
     RawModel,
     QuadModel,
 };
-use crate::shaders::post_processing::{
-    HorizontalBlurShader,
-    VerticalBlurShader,
-    ContrastShader,
-    CombineShader,
-};
+
+// the two fbos the stages alternate between, a stage always reads one and writes the other
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PingPongFbo {
+    Ping,
+    Pong,
+}
+
+impl PingPongFbo {
+    pub fn other(self) -> Self {
+        match self {
+            PingPongFbo::Ping => PingPongFbo::Pong,
+            PingPongFbo::Pong => PingPongFbo::Ping,
+        }
+    }
+
+    fn fbo_name(self) -> &'static str {
+        match self {
+            PingPongFbo::Ping => FboMap::POST_PROCESS_PING_FBO,
+            PingPongFbo::Pong => FboMap::POST_PROCESS_PONG_FBO,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StageInput {
+    // the resolved camera output, only the first stage reads it as its input
+    CameraTexture,
+    PingPong(PingPongFbo),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StageOutput {
+    PingPong(PingPongFbo),
+    // the last stage draws straight to the default framebuffer
+    Screen,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct StagePass {
+    pub stage: usize,
+    pub input: StageInput,
+    pub output: StageOutput,
+}
+
+// every stage reads what the previous one wrote, the last one writes to the screen
+pub fn plan_stage_passes(stage_count: usize) -> Vec<StagePass> {
+    let mut passes = Vec::with_capacity(stage_count);
+    let mut input = StageInput::CameraTexture;
+    let mut next_fbo = PingPongFbo::Ping;
+    for stage in 0..stage_count {
+        let output = if stage + 1 == stage_count {
+            StageOutput::Screen
+        } else {
+            StageOutput::PingPong(next_fbo)
+        };
+        passes.push(StagePass { stage, input, output });
+        input = StageInput::PingPong(next_fbo);
+        next_fbo = next_fbo.other();
+    }
+    passes
+}
+
+// textures any stage may sample besides the output of the previous stage
+pub struct PostProcessInputs {
+    pub camera_texture: u32,
+    pub camera_brightness: u32,
+    pub glow_layer_texture: Option<u32>,
+}
+
+// where a stage has to draw its final quad, stages that use their own intermediate fbos must bind this again before drawing
+pub struct StageTarget<'a> {
+    fbo: Option<&'a mut FramebufferObject>,
+}
+
+impl<'a> StageTarget<'a> {
+    pub fn bind(&mut self, display: &Display) {
+        match self.fbo.as_mut() {
+            Some(fbo) => fbo.bind(),
+            None => display.restore_default_framebuffer(),
+        }
+    }
+}
+
+// stages are Any so PostProcessing::stage_mut can hand out a stage with its concrete type
+pub trait PostProcessStage: Any {
+    // the quad vao is bound and the target already bound when this is called
+    fn render(&mut self, input_texture: u32, inputs: &PostProcessInputs, target: &mut StageTarget, display: &Display);
+
+    // for stages that own fbos which follow the window size
+    fn resize(&mut self, _width: usize, _height: usize) {
+    }
+}
+
+fn find_stage<S: PostProcessStage>(stages: &[Box<dyn PostProcessStage>]) -> Option<&S> {
+    stages.iter().find_map(|stage| (stage.as_ref() as &dyn Any).downcast_ref::<S>())
+}
+
+pub struct PostProcessingBuilder {
+    stages: Vec<Box<dyn PostProcessStage>>,
+}
+
+impl PostProcessingBuilder {
+    pub fn new() -> Self {
+        PostProcessingBuilder {
+            stages: Vec::new(),
+        }
+    }
+
+    // stages run in the order they were added
+    pub fn with_stage<S: PostProcessStage + 'static>(mut self, stage: S) -> Self {
+        self.stages.push(Box::new(stage));
+        self
+    }
+
+    pub fn build(self, quad_model: QuadModel, display: &Display) -> PostProcessing {
+        let mut post_processing_fbos = FboMap::new_postprocessing_fbos(display);
+        let screen_size = display.get_size();
+        for fbo in [PingPongFbo::Ping, PingPongFbo::Pong].iter() {
+            post_processing_fbos.insert(fbo.fbo_name(), FramebufferObject::new(screen_size.width, screen_size.height, FboFlags::COLOR_TEX, 1));
+        }
+        display.restore_default_framebuffer();
+
+        PostProcessing {
+            quad_model,
+            passes: plan_stage_passes(self.stages.len()),
+            stages: self.stages,
+            post_processing_fbos,
+        }
+    }
+}
 
 pub struct PostProcessing {
     quad_model: QuadModel,
-    contrast_changer: GenericPostprocess<ContrastShader>,
-    horizontal_blur: GenericPostprocess<HorizontalBlurShader>,
-    vertical_blur: GenericPostprocess<VerticalBlurShader>,
-    //brightness_filter: GenericPostprocess<BrightnessFilterShader>,
-    combine_shader: GenericPostprocess<CombineShader>,
+    stages: Vec<Box<dyn PostProcessStage>>,
+    passes: Vec<StagePass>,
     pub post_processing_fbos: FboMap,
 }
 
 impl PostProcessing {
+    // the default chain: bloom added onto the camera output followed by the contrast change
     pub fn new(quad_model: QuadModel, display: &Display) -> Self {
-        let screen_size = display.get_size();
-        let width = screen_size.width;
-        let height = screen_size.height;
+        PostProcessingBuilder::new()
+            .with_stage(BloomStage::new(display, Default::default()))
+            .with_stage(ContrastStage::new())
+            .build(quad_model, display)
+    }
 
-        let horizontal_blur = GenericPostprocess::new(HorizontalBlurShader::new(width / 5), 
-            Some(FramebufferObject::new(width / 5, height / 5, FboFlags::COLOR_TEX, 1)));
-        let vertical_blur = GenericPostprocess::new(VerticalBlurShader::new(height / 5), 
-            Some(FramebufferObject::new(width / 5, height / 5, FboFlags::COLOR_TEX, 1)));
+    pub fn passes(&self) -> &[StagePass] {
+        &self.passes
+    }
 
-        // this final step upscales this image back to screen size
-        let contrast_changer = GenericPostprocess::new(ContrastShader::new(), None);
+    // the first stage of the given type e.g. to change its settings after the chain was built
+    pub fn stage<S: PostProcessStage>(&self) -> Option<&S> {
+        find_stage(&self.stages)
+    }
 
-        // shaders required for bloom effect
-        let combine_shader = GenericPostprocess::new(CombineShader::new(), Some(FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1)));
-        // no need for brightness filter .. we are using mutliple render targets to get a brightness texture
-        // let brightness_filter = GenericPostprocess::new(BrightnessFilterShader::new(), Some(FramebufferObject::new(width / 2, height / 2, FboFlags::COLOR_TEX, 1)));
+    pub fn stage_mut<S: PostProcessStage>(&mut self) -> Option<&mut S> {
+        self.stages.iter_mut().find_map(|stage| (stage.as_mut() as &mut dyn Any).downcast_mut::<S>())
+    }
 
-        let post_processing_fbos = FboMap::new_postprocessing_fbos(display);
+    // None when the chain has no bloom stage
+    pub fn bloom_params(&self) -> Option<&BloomParams> {
+        self.stage::<BloomStage>().map(|bloom| bloom.bloom_params())
+    }
 
-        PostProcessing {
-            quad_model,
-            contrast_changer,
-            horizontal_blur,
-            vertical_blur,
-            //brightness_filter,
-            combine_shader,
-            post_processing_fbos,
+    // does nothing when the chain has no bloom stage
+    pub fn set_bloom_params(&mut self, params: BloomParams) {
+        if let Some(bloom) = self.stage_mut::<BloomStage>() {
+            bloom.set_bloom_params(params);
         }
     }
 
-    // here we setup our chain of post processing steps
-    pub fn do_post_processing(&mut self, display: &Display) {
+    pub fn do_post_processing(&mut self, display: &Display, glow_layer_texture: Option<u32>) {
+        let screen_size = display.get_size();
+        self.resize(screen_size.width, screen_size.height);
+
         let camera_texture_fbo = self.post_processing_fbos.fbos.get_mut(FboMap::CAMERA_TEXTURE_FBO).expect("A camera texture must be present for postprocessing");
         let camera_texture = camera_texture_fbo.color_texture(0).expect("A camera texture must be present for postprocessing");
 
+        // without any stages the camera output is shown as is
+        if self.passes.is_empty() {
+            camera_texture_fbo.resolve_to_screen(display);
+            return;
+        }
+
         let camera_brightness_fbo = self.post_processing_fbos.fbos.get_mut(FboMap::CAMERA_BRIGHTNESS_FBO).expect("A camera brightness texture must be present for bloom effect");
         let camera_brightness = camera_brightness_fbo.color_texture(0).expect("A camera texture must be present for postprocessing");
-        
+
+        let inputs = PostProcessInputs {
+            camera_texture,
+            camera_brightness,
+            glow_layer_texture,
+        };
+
         self.start();
-        
-        self.horizontal_blur.render_with_one_input(camera_brightness, display);
-        self.vertical_blur.render_with_one_input(self.horizontal_blur.get_output_texture().unwrap(), display);
-        self.combine_shader.render_with_two_inputs(camera_texture, self.vertical_blur.get_output_texture().unwrap(), display);
-        self.contrast_changer.render_with_one_input(self.combine_shader.get_output_texture().unwrap(), display);
+
+        for pass in self.passes.iter() {
+            let input_texture = match pass.input {
+                StageInput::CameraTexture => camera_texture,
+                StageInput::PingPong(fbo) => self.post_processing_fbos.fbos[fbo.fbo_name()].color_texture(0).expect("Ping pong fbos must have a color texture"),
+            };
+            let mut target = StageTarget {
+                fbo: match pass.output {
+                    StageOutput::PingPong(fbo) => Some(self.post_processing_fbos.fbos.get_mut(fbo.fbo_name()).expect("Ping pong fbos must be present for postprocessing")),
+                    StageOutput::Screen => None,
+                },
+            };
+            target.bind(display);
+            self.stages[pass.stage].render(input_texture, &inputs, &mut target, display);
+        }
 
         self.end();
         display.restore_default_framebuffer();
     }
 
+    // the ping pong fbos and any stage owned fbos follow the window size
+    fn resize(&mut self, width: usize, height: usize) {
+        for fbo in [PingPongFbo::Ping, PingPongFbo::Pong].iter() {
+            let current = &self.post_processing_fbos.fbos[fbo.fbo_name()];
+            if (current.viewport_width, current.viewport_height) != (width, height) {
+                self.post_processing_fbos.replace(fbo.fbo_name(), || FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1));
+            }
+        }
+        for stage in self.stages.iter_mut() {
+            stage.resize(width, height);
+        }
+    }
+
     fn start(&mut self) {
         gl::bind_vertex_array(self.quad_model.raw_model.vao_id);
         gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
@@ -89,4 +255,55 @@ impl PostProcessing {
         gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
         gl::enable(gl::DEPTH_TEST);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn two_stages_ping_pong_from_the_camera_texture_to_the_screen() {
+        let passes = plan_stage_passes(2);
+        assert_eq!(passes, vec![
+            StagePass { stage: 0, input: StageInput::CameraTexture, output: StageOutput::PingPong(PingPongFbo::Ping) },
+            StagePass { stage: 1, input: StageInput::PingPong(PingPongFbo::Ping), output: StageOutput::Screen },
+        ]);
+    }
+
+    #[test]
+    fn each_stage_reads_what_the_previous_one_wrote() {
+        let passes = plan_stage_passes(4);
+        assert_eq!(passes.len(), 4);
+        for pair in passes.windows(2) {
+            let written = match pair[0].output {
+                StageOutput::PingPong(fbo) => fbo,
+                StageOutput::Screen => panic!("only the last stage may write to the screen"),
+            };
+            assert_eq!(pair[1].input, StageInput::PingPong(written));
+            // a stage never reads and writes the same fbo
+            assert_ne!(pair[1].output, StageOutput::PingPong(written));
+        }
+        assert_eq!(passes[3].output, StageOutput::Screen);
+        assert!(plan_stage_passes(0).is_empty());
+    }
+
+    struct TestStage(u32);
+    struct OtherStage;
+
+    impl PostProcessStage for TestStage {
+        fn render(&mut self, _input_texture: u32, _inputs: &PostProcessInputs, _target: &mut StageTarget, _display: &Display) {
+        }
+    }
+
+    impl PostProcessStage for OtherStage {
+        fn render(&mut self, _input_texture: u32, _inputs: &PostProcessInputs, _target: &mut StageTarget, _display: &Display) {
+        }
+    }
+
+    #[test]
+    fn stages_are_found_by_their_type() {
+        let stages: Vec<Box<dyn PostProcessStage>> = vec![Box::new(OtherStage), Box::new(TestStage(1)), Box::new(TestStage(2))];
+        assert_eq!(find_stage::<TestStage>(&stages).map(|stage| stage.0), Some(1));
+        assert!(find_stage::<BloomStage>(&stages).is_none());
+    }
+}
@@ -0,0 +1,100 @@
+use super::generic_postprocess::GenericPostprocess;
+use super::bloom::{
+    BloomChain,
+    BloomParams,
+};
+use super::post_processing::{
+    PostProcessStage,
+    PostProcessInputs,
+    StageTarget,
+};
+use crate::display::{
+    Display,
+    framebuffers::FramebufferObject,
+    framebuffers::FboFlags,
+};
+use crate::shaders::post_processing::{
+    ContrastShader,
+    CombineShader,
+};
+
+// adds the blurred brightness of the camera output (and the glow layer) onto the stage input
+pub struct BloomStage {
+    bloom: BloomChain,
+    // adds the render layer texture to the brightness so entities on that layer always glow
+    glow_layer_combine: GenericPostprocess<CombineShader>,
+    // draws into whatever target the pipeline hands to the stage
+    combine_shader: GenericPostprocess<CombineShader>,
+    screen_size: (usize, usize),
+}
+
+impl BloomStage {
+    pub fn new(display: &Display, params: BloomParams) -> Self {
+        let screen_size = display.get_size();
+        let width = screen_size.width;
+        let height = screen_size.height;
+
+        // blurs the brightness texture over a chain of smaller and smaller fbos
+        let bloom = BloomChain::new(width, height, params);
+        let glow_layer_combine = GenericPostprocess::new(CombineShader::new(), Some(FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1)));
+        let combine_shader = GenericPostprocess::new(CombineShader::new(), None);
+        display.restore_default_framebuffer();
+
+        BloomStage {
+            bloom,
+            glow_layer_combine,
+            combine_shader,
+            screen_size: (width, height),
+        }
+    }
+
+    pub fn set_bloom_params(&mut self, params: BloomParams) {
+        self.bloom.set_params(params);
+    }
+
+    pub fn bloom_params(&self) -> &BloomParams {
+        self.bloom.params()
+    }
+}
+
+impl PostProcessStage for BloomStage {
+    fn render(&mut self, input_texture: u32, inputs: &PostProcessInputs, target: &mut StageTarget, display: &Display) {
+        let bloom_source = match inputs.glow_layer_texture {
+            Some(glow_layer_texture) => {
+                self.glow_layer_combine.render_with_two_inputs(inputs.camera_brightness, glow_layer_texture, display);
+                self.glow_layer_combine.get_output_texture().unwrap()
+            },
+            None => inputs.camera_brightness,
+        };
+        let bloom_texture = self.bloom.render(bloom_source, display);
+        // the bloom chain left its own fbos bound
+        target.bind(display);
+        self.combine_shader.render_with_two_inputs(input_texture, bloom_texture, display);
+    }
+
+    fn resize(&mut self, width: usize, height: usize) {
+        self.bloom.resize(width, height);
+        if self.screen_size != (width, height) {
+            self.screen_size = (width, height);
+            self.glow_layer_combine.set_render_target(Some(FramebufferObject::new(width, height, FboFlags::COLOR_TEX, 1)));
+        }
+    }
+}
+
+pub struct ContrastStage {
+    contrast_changer: GenericPostprocess<ContrastShader>,
+}
+
+impl ContrastStage {
+    pub fn new() -> Self {
+        ContrastStage {
+            contrast_changer: GenericPostprocess::new(ContrastShader::new(), None),
+        }
+    }
+}
+
+impl PostProcessStage for ContrastStage {
+    fn render(&mut self, input_texture: u32, _inputs: &PostProcessInputs, _target: &mut StageTarget, display: &Display) {
+        self.contrast_changer.render_with_one_input(input_texture, display);
+    }
+}
@@ -11,6 +11,7 @@ use crate::math::{
 use crate::models::{    
     RawModel,    
 };
+use super::attrib_array_scope::AttribArrayScope;
 
 pub struct AnimatedEntityRenderer {
     shader: AnimatedModelShader,
@@ -36,6 +37,10 @@ impl AnimatedEntityRenderer {
             accumulator: AccumulatedJointTransforms::new(),
         }
     }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.proj_matrix = projection_matrix.clone();
+    }
     
     pub fn render_entities(&mut self, entities: &Vec<AnimatedEntity>, camera: &Camera) {
         for entity in entities {
@@ -51,11 +56,7 @@ impl AnimatedEntityRenderer {
         gl::bind_texture(gl::TEXTURE_2D, animated_entity.model.tex_id.unwrap());
 
         gl::bind_vertex_array(animated_entity.model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::JOINT_IDX_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::JOINT_WEIGHT_ATTRIB);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB, RawModel::NORMAL_ATTRIB, RawModel::JOINT_IDX_ATTRIB, RawModel::JOINT_WEIGHT_ATTRIB]);
 
         // load transform matrix into shader        
         self.mvp_matrix.make_identity();
@@ -71,11 +72,7 @@ impl AnimatedEntityRenderer {
 
         gl::draw_elements(gl::TRIANGLES, animated_entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);
         
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::JOINT_IDX_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::JOINT_WEIGHT_ATTRIB);
+        drop(attrib_arrays);
         gl::bind_vertex_array(0);
 
         self.shader.stop();
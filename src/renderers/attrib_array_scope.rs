@@ -0,0 +1,76 @@
+use crate::gl;
+
+// enables vertex attrib arrays of the bound vao and disables the same ones again when dropped
+// so every enable has a matching disable even when a render function returns early
+// has to be dropped while the vao it was created for is still bound
+pub struct AttribArrayScope {
+    attribs: [u32; AttribArrayScope::MAX_ATTRIBS],
+    attrib_count: usize,
+    disable: fn(u32),
+}
+
+impl AttribArrayScope {
+    pub const MAX_ATTRIBS: usize = 16;
+
+    pub fn enable(attribs: &[u32]) -> AttribArrayScope {
+        AttribArrayScope::with_switches(attribs, gl::enable_vertex_attrib_array, gl::disable_vertex_attrib_array)
+    }
+
+    fn with_switches(attribs: &[u32], enable: fn(u32), disable: fn(u32)) -> AttribArrayScope {
+        assert!(attribs.len() <= AttribArrayScope::MAX_ATTRIBS, "Too many vertex attributes for one scope");
+        let mut scope = AttribArrayScope {
+            attribs: [0; AttribArrayScope::MAX_ATTRIBS],
+            attrib_count: 0,
+            disable,
+        };
+        for &attrib in attribs {
+            enable(attrib);
+            scope.attribs[scope.attrib_count] = attrib;
+            scope.attrib_count += 1;
+        }
+        scope
+    }
+
+    pub fn attribs(&self) -> &[u32] {
+        &self.attribs[..self.attrib_count]
+    }
+}
+
+impl Drop for AttribArrayScope {
+    fn drop(&mut self) {
+        for &attrib in self.attribs[..self.attrib_count].iter() {
+            (self.disable)(attrib);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::cell::RefCell;
+
+    thread_local! {
+        static ENABLED: RefCell<Vec<u32>> = RefCell::new(Vec::new());
+        static DISABLED: RefCell<Vec<u32>> = RefCell::new(Vec::new());
+    }
+
+    fn record_enable(attrib: u32) {
+        ENABLED.with(|enabled| enabled.borrow_mut().push(attrib));
+    }
+
+    fn record_disable(attrib: u32) {
+        DISABLED.with(|disabled| disabled.borrow_mut().push(attrib));
+    }
+
+    #[test]
+    fn dropping_the_scope_disables_exactly_the_enabled_attribs() {
+        let attribs = [0, 1, 2, 6];
+        {
+            let scope = AttribArrayScope::with_switches(&attribs, record_enable, record_disable);
+            assert_eq!(scope.attribs(), &attribs);
+            assert!(DISABLED.with(|disabled| disabled.borrow().is_empty()));
+        }
+        assert_eq!(ENABLED.with(|enabled| enabled.borrow().clone()), attribs.to_vec());
+        assert_eq!(DISABLED.with(|disabled| disabled.borrow().clone()), attribs.to_vec());
+    }
+}
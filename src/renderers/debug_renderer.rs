@@ -1,23 +1,97 @@
+use std::collections::HashSet;
 use crate::gl;
+use crate::display::DepthMode;
 use crate::entities::{
     DebugEntity,
     Camera,    
+    Entity,
 };
 use crate::shaders::DebugShader;
 use crate::math::{
     Matrix4f,
     Vector3f,
+    Vector4f,
 };
 use crate::models::{    
     RawModel,
     DynamicVertexIndexedModel,
+    DebugVectorLinesModel,
+    DebugEdgesModel,
 };
+use super::attrib_array_scope::AttribArrayScope;
+use super::entity_renderer::EntityRenderer;
 
 pub struct DebugRenderer {
     shader: DebugShader,
     mvp_matrix: Matrix4f,
     proj_matrix: Matrix4f,
     view_matrix: Matrix4f,
+    // stream vbo and line length of the last vector lines upload, the lines only change with the length
+    uploaded_vector_lines: Option<(u32, f32)>,
+}
+
+// pairs of vertex indices, an edge shared by two triangles is only listed once
+pub fn unique_edges(triangle_indices: &[u32]) -> Vec<u32> {
+    let mut seen = HashSet::new();
+    let mut edges = Vec::new();
+    for triangle in triangle_indices.chunks(3) {
+        for i in 0..triangle.len() {
+            let (a, b) = (triangle[i], triangle[(i + 1) % triangle.len()]);
+            if seen.insert((a.min(b), a.max(b))) {
+                edges.push(a);
+                edges.push(b);
+            }
+        }
+    }
+    edges
+}
+
+// the shaded pass draws filled polygons pushed back by the polygon offset so the edges drawn at their real depth aren't z fighting with them
+#[derive(Clone)]
+pub struct WireframeOverlay {
+    // one model for a single model or ResourceManager::debug_edges_models for all of them
+    pub edges_models: Vec<DebugEdgesModel>,
+    pub color: Vector4f,
+}
+
+impl WireframeOverlay {
+    pub const POLYGON_OFFSET_FACTOR: f32 = 1.0;
+    pub const POLYGON_OFFSET_UNITS: f32 = 1.0;
+
+    pub fn new(edges_models: Vec<DebugEdgesModel>) -> Self {
+        WireframeOverlay {
+            edges_models,
+            color: Vector4f::new(0.0, 0.0, 0.0, 1.0),
+        }
+    }
+
+    // pushed towards the far plane which has the smaller depth with reversed z
+    pub fn polygon_offset(depth_mode: DepthMode) -> (f32, f32) {
+        match depth_mode {
+            DepthMode::Standard => (Self::POLYGON_OFFSET_FACTOR, Self::POLYGON_OFFSET_UNITS),
+            DepthMode::ReversedZ => (-Self::POLYGON_OFFSET_FACTOR, -Self::POLYGON_OFFSET_UNITS),
+        }
+    }
+
+    // the edges pass where they are at the same depth as the surface they lie on
+    pub fn edges_depth_func(depth_mode: DepthMode) -> gl::types::GLenum {
+        match depth_mode {
+            DepthMode::Standard => gl::LEQUAL,
+            DepthMode::ReversedZ => gl::GEQUAL,
+        }
+    }
+}
+
+// two endpoints per vertex, the vectors can have more components than the xyz used here e.g. tangents with handedness
+pub fn debug_vector_lines(positions: &[f32], vectors: &[f32], vector_size: usize, length: f32) -> Vec<f32> {
+    let mut lines = Vec::with_capacity(2 * positions.len());
+    for (position, vector) in positions.chunks(3).zip(vectors.chunks(vector_size)) {
+        lines.extend_from_slice(position);
+        for i in 0..3 {
+            lines.push(position[i] + vector[i] * length);
+        }
+    }
+    lines
 }
 
 impl DebugRenderer {    
@@ -32,6 +106,9 @@ impl DebugRenderer {
         Vector3f {x: 0.5, y: 0.5, z: -0.5},
         Vector3f {x: -0.5, y: 0.5, z: -0.5},
     ];
+
+    const NORMAL_LINE_COLOR: Vector4f = Vector4f {x: 0.0, y: 1.0, z: 0.0, w: 1.0};
+    const TANGENT_LINE_COLOR: Vector4f = Vector4f {x: 1.0, y: 0.0, z: 0.0, w: 1.0};
     
     pub fn new(projection_matrix: &Matrix4f) -> Self {     
         let shader = DebugShader::new();
@@ -44,9 +121,14 @@ impl DebugRenderer {
             mvp_matrix,
             proj_matrix,
             view_matrix,
+            uploaded_vector_lines: None,
         }
     }
     
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.proj_matrix = projection_matrix.clone();
+    }
+
     pub fn render_cube(&mut self, entity: &DebugEntity, camera: &Camera, ) {
         self.render(entity, camera, &Self::CUBE_VERTS);
     }
@@ -62,7 +144,7 @@ impl DebugRenderer {
         gl::helper::disable_culling();
         
         gl::bind_vertex_array(entity.model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
 
         // load transform matrix into shader        
         self.mvp_matrix.make_identity();
@@ -72,6 +154,7 @@ impl DebugRenderer {
         self.mvp_matrix.pre_multiply_in_place(&self.view_matrix);        
         self.mvp_matrix.pre_multiply_in_place(&self.proj_matrix);
         self.shader.load_mvp_matrix(&self.mvp_matrix);
+        self.shader.load_line_color(None);
 
         self.update_vbo(&entity.model, vertices);
 
@@ -79,12 +162,89 @@ impl DebugRenderer {
 
         gl::disable(gl::BLEND);
         gl::helper::enable_backface_culling();
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        drop(attrib_arrays);
         gl::bind_vertex_array(0);
 
         self.shader.stop();
     }
 
+    // normals in green and tangents in red on top of every entity using the lines model's target model
+    pub fn render_vector_lines(&mut self, lines_model: &DebugVectorLinesModel, entities: &[&Entity], camera: &Camera, line_length: f32) {
+        if entities.is_empty() {
+            return;
+        }
+        self.shader.start();
+        self.view_matrix = Matrix4f::create_view_matrix(camera);
+        self.update_vector_lines_vbo(lines_model, line_length);
+
+        gl::bind_vertex_array(lines_model.lines.raw_model.vao_id);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
+        let normal_line_vertices = 2 * lines_model.normals.len() / 3;
+        let tangent_line_vertices = 2 * lines_model.tangents.len() / 4;
+
+        let mut transform = Matrix4f::identity();
+        for entity in entities {
+            self.mvp_matrix.make_identity();
+            EntityRenderer::entity_transform_into(&mut transform, entity, camera.pitch, camera.yaw);
+            self.mvp_matrix.pre_multiply_in_place(&transform);
+            self.mvp_matrix.pre_multiply_in_place(&self.view_matrix);
+            self.mvp_matrix.pre_multiply_in_place(&self.proj_matrix);
+            self.shader.load_mvp_matrix(&self.mvp_matrix);
+
+            self.shader.load_line_color(Some(&Self::NORMAL_LINE_COLOR));
+            gl::draw_arrays(gl::LINES, 0, normal_line_vertices);
+            if tangent_line_vertices > 0 {
+                self.shader.load_line_color(Some(&Self::TANGENT_LINE_COLOR));
+                gl::draw_arrays(gl::LINES, normal_line_vertices, tangent_line_vertices);
+            }
+        }
+
+        drop(attrib_arrays);
+        gl::bind_vertex_array(0);
+        self.shader.stop();
+    }
+
+    pub fn render_edges(&mut self, edges_model: &DebugEdgesModel, entities: &[&Entity], camera: &Camera, color: &Vector4f, depth_mode: DepthMode) {
+        if entities.is_empty() {
+            return;
+        }
+        self.shader.start();
+        self.view_matrix = Matrix4f::create_view_matrix(camera);
+        self.shader.load_line_color(Some(color));
+        gl::depth_func(WireframeOverlay::edges_depth_func(depth_mode));
+
+        gl::bind_vertex_array(edges_model.lines.vao_id);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
+        let mut transform = Matrix4f::identity();
+        for entity in entities {
+            self.mvp_matrix.make_identity();
+            EntityRenderer::entity_transform_into(&mut transform, entity, camera.pitch, camera.yaw);
+            self.mvp_matrix.pre_multiply_in_place(&transform);
+            self.mvp_matrix.pre_multiply_in_place(&self.view_matrix);
+            self.mvp_matrix.pre_multiply_in_place(&self.proj_matrix);
+            self.shader.load_mvp_matrix(&self.mvp_matrix);
+            gl::draw_elements(gl::LINES, edges_model.lines.vertex_count, gl::UNSIGNED_INT);
+        }
+        drop(attrib_arrays);
+        gl::bind_vertex_array(0);
+
+        gl::depth_func(depth_mode.depth_func());
+        self.shader.stop();
+    }
+
+    fn update_vector_lines_vbo(&mut self, lines_model: &DebugVectorLinesModel, line_length: f32) {
+        let vbo = lines_model.lines.stream_draw_vbo;
+        if self.uploaded_vector_lines == Some((vbo, line_length)) {
+            return;
+        }
+        let mut data = debug_vector_lines(&lines_model.positions, &lines_model.normals, 3, line_length);
+        data.extend(debug_vector_lines(&lines_model.positions, &lines_model.tangents, 4, line_length));
+        gl::bind_buffer(gl::ARRAY_BUFFER, vbo);
+        gl::buffer_sub_data(gl::ARRAY_BUFFER, 0, &data);
+        gl::bind_buffer(gl::ARRAY_BUFFER, 0);
+        self.uploaded_vector_lines = Some((vbo, line_length));
+    }
+
     fn update_vbo(&mut self, model: &DynamicVertexIndexedModel, vertices: &[Vector3f; 8]) {
         let vbo = model.stream_draw_vbo;
         gl::bind_buffer(gl::ARRAY_BUFFER, vbo);
@@ -98,4 +258,37 @@ impl DebugRenderer {
         gl::buffer_sub_data(gl::ARRAY_BUFFER, 0, &data);
         gl::bind_buffer(gl::ARRAY_BUFFER, 0);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn vector_line_goes_from_position_along_vector() {
+        let lines = debug_vector_lines(&[1.0, 2.0, 3.0], &[0.0, 1.0, 0.0], 3, 0.5);
+        assert_eq!(lines, vec![1.0, 2.0, 3.0, 1.0, 2.5, 3.0]);
+    }
+
+    #[test]
+    fn vector_lines_skip_tangent_handedness() {
+        let lines = debug_vector_lines(&[0.0, 0.0, 0.0, 1.0, 1.0, 1.0], &[1.0, 0.0, 0.0, -1.0, 0.0, 0.0, 2.0, 1.0], 4, 2.0);
+        assert_eq!(lines, vec![0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 5.0]);
+    }
+
+    #[test]
+    fn cube_has_edges_and_face_diagonals_once() {
+        let cube_indices = [
+            0, 1, 2, 0, 2, 3,
+            4, 6, 5, 4, 7, 6,
+            0, 4, 5, 0, 5, 1,
+            1, 5, 6, 1, 6, 2,
+            2, 6, 7, 2, 7, 3,
+            3, 7, 4, 3, 4, 0,
+        ];
+        let edges = unique_edges(&cube_indices);
+        // 12 cube edges and a diagonal on each of the 6 faces
+        assert_eq!(edges.len(), 2 * 18);
+        assert_eq!(unique_edges(&[0, 1, 2, 2, 1, 3]), vec![0, 1, 1, 2, 2, 0, 1, 3, 3, 2]);
+    }
+}
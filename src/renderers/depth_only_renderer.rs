@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use crate::entities::{
+    Camera,
+    Entity,
+    Terrain,
+};
+use crate::gl;
+use crate::math::{
+    Matrix4f,
+    Vector3f,
+    Vector4f,
+};
+use crate::models::{
+    RawModel,
+    TexturedModel,
+};
+use crate::shaders::DepthOnlyShader;
+use super::attrib_array_scope::AttribArrayScope;
+use super::entity_renderer::EntityRenderer;
+
+// draws the scene geometry into the depth buffer only, skipping all the lighting and texturing of the color shaders
+pub struct DepthOnlyRenderer {
+    shader: DepthOnlyShader,
+    proj_mat: Matrix4f,
+    transform_mat: Matrix4f,
+    // billboards face the camera like in the entity renderer
+    camera_pitch: f32,
+    camera_yaw: f32,
+}
+
+impl DepthOnlyRenderer {
+
+    pub fn new(projection_matrix: &Matrix4f) -> Self {
+        DepthOnlyRenderer {
+            shader: DepthOnlyShader::new(),
+            proj_mat: projection_matrix.clone(),
+            transform_mat: Matrix4f::identity(),
+            camera_pitch: 0.0,
+            camera_yaw: 0.0,
+        }
+    }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.proj_mat = projection_matrix.clone();
+    }
+
+    pub fn start_render(&mut self, camera: &Camera, clip_plane: &Vector4f) {
+        self.shader.start();
+        let view_mat = Matrix4f::create_view_matrix(camera);
+        self.shader.load_vp_matrix(&(&self.proj_mat * view_mat));
+        self.shader.load_clip_plane(clip_plane);
+        self.camera_pitch = camera.pitch;
+        self.camera_yaw = camera.yaw;
+        gl::color_mask(false);
+    }
+
+    pub fn render_entities(&mut self, entities_by_tex: &HashMap<&TexturedModel, Vec<&Entity>>) {
+        for (textured_model, entities) in entities_by_tex.iter() {
+            let uses_alpha_test = textured_model.is_transparent();
+            if uses_alpha_test {
+                gl::helper::disable_culling();
+                gl::active_texture(gl::TEXTURE0);
+                gl::bind_texture(gl::TEXTURE_2D, textured_model.texture.tex_id.unwrap());
+                self.shader.load_atlas_number_of_rows(textured_model.texture.number_of_rows_in_atlas);
+                self.shader.load_uv_transform(&textured_model.texture.uv_scale, &textured_model.texture.uv_offset);
+            }
+            self.shader.load_uses_alpha_test(uses_alpha_test);
+            gl::bind_vertex_array(textured_model.raw_model.vao_id);
+            let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB]);
+
+            for entity in entities.iter() {
+                if uses_alpha_test {
+                    self.shader.load_atlas_offset(&entity.get_atlas_offset());
+                }
+                EntityRenderer::entity_transform_into(&mut self.transform_mat, entity, self.camera_pitch, self.camera_yaw);
+                self.shader.load_transformation_matrix(&self.transform_mat);
+                gl::draw_elements(gl::TRIANGLES, entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);
+            }
+
+            drop(attrib_arrays);
+            gl::bind_vertex_array(0);
+            if uses_alpha_test {
+                gl::helper::enable_backface_culling();
+            }
+        }
+    }
+
+    pub fn render_terrain(&mut self, terrains: &Vec<Terrain>) {
+        self.shader.load_uses_alpha_test(false);
+        for terrain in terrains.iter() {
+            gl::bind_vertex_array(terrain.model.raw_model.vao_id);
+            let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
+
+            let terrain_pos = Vector3f::new(terrain.x as f32, 0.0, terrain.z as f32);
+            Matrix4f::create_transform_matrix_into(&mut self.transform_mat, &terrain_pos, &Vector3f::ZERO, 1.0);
+            self.shader.load_transformation_matrix(&self.transform_mat);
+            gl::draw_elements(gl::TRIANGLES, terrain.model.raw_model.vertex_count, gl::UNSIGNED_INT);
+
+            drop(attrib_arrays);
+        }
+        gl::bind_vertex_array(0);
+    }
+
+    pub fn stop_render(&mut self) {
+        gl::color_mask(true);
+        self.shader.stop();
+    }
+}
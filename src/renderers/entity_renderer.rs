@@ -1,12 +1,15 @@
 use crate::gl;
 use crate::entities::{
     Entity,
+    BillboardMode,
+    LodFade,
     Camera,
+    FogCutoff,
     Light,
 };
 use crate::math::{
+    Color,
     Matrix4f,
-    Vector3f,
     Vector4f,
 };
 use crate::models::{
@@ -15,28 +18,85 @@ use crate::models::{
 };
 use crate::shaders::StaticShader;
 use crate::shadows::shadow_params::ShadowParams;
+use super::attrib_array_scope::AttribArrayScope;
+
+// how the entities of one texture group are submitted
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EntityDrawPath {
+    PerEntity,
+    Instanced,
+}
+
+impl EntityDrawPath {
+    // uploading the instance data has a cost of its own which only pays off for larger groups, None never instances
+    pub fn for_group_size(group_size: usize, instancing_threshold: Option<usize>) -> EntityDrawPath {
+        match instancing_threshold {
+            Some(threshold) if group_size > threshold => EntityDrawPath::Instanced,
+            _ => EntityDrawPath::PerEntity,
+        }
+    }
+}
 
 pub struct EntityRenderer {
     shader: StaticShader,
+    // reused for every entity instead of creating a new matrix
+    transform_mat: Matrix4f,
+    // billboard entities get turned towards the camera of the current pass
+    camera_pitch: f32,
+    camera_yaw: f32,
+    // shared by all models, every instanced draw points the instance attributes of its vao at it
+    instance_vbo: u32,
+    instance_data: Vec<f32>,
+    // enabled between prepare and unprepare so they are always disabled again on the same vao
+    attrib_arrays: Option<AttribArrayScope>,
 }
 
 impl EntityRenderer {    
     
+    // 16 floats of the transform and 2 of the atlas offset
+    pub const INSTANCED_DATA_LENGTH: usize = 18;
+    // larger groups are split into several instanced draws
+    pub const MAX_INSTANCES: usize = 1000;
+
     pub fn new(projection_matrix: &Matrix4f) -> EntityRenderer {     
         let mut shader = StaticShader::new();
         shader.start();
         shader.load_projection_matrix(projection_matrix);
         shader.connect_texture_units();
         shader.stop();
+        let instance_vbo = gl::gen_buffer();
+        gl::bind_buffer(gl::ARRAY_BUFFER, instance_vbo);
+        gl::buffer_data_unitialized::<f32>(gl::ARRAY_BUFFER, EntityRenderer::MAX_INSTANCES * EntityRenderer::INSTANCED_DATA_LENGTH, gl::STREAM_DRAW);
+        gl::bind_buffer(gl::ARRAY_BUFFER, 0);
         EntityRenderer {
             shader,
+            transform_mat: Matrix4f::identity(),
+            camera_pitch: 0.0,
+            camera_yaw: 0.0,
+            instance_vbo,
+            instance_data: Vec::with_capacity(EntityRenderer::MAX_INSTANCES * EntityRenderer::INSTANCED_DATA_LENGTH),
+            attrib_arrays: None,
         }
     }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.shader.start();
+        self.shader.load_projection_matrix(projection_matrix);
+        self.shader.stop();
+    }
+
+    pub fn update_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.shader.start();
+        self.shader.load_fog_cutoff(fog_cutoff);
+        self.shader.stop();
+    }
     
-    pub fn start_render(&mut self, lights: &Vec<Light>, camera: &Camera, sky_color: &Vector3f, to_shadow_space: &Matrix4f, shadow_params: &ShadowParams) {
+    pub fn start_render(&mut self, lights: &Vec<Light>, camera: &Camera, sky_color: &Color, to_shadow_space: &Matrix4f, shadow_params: &ShadowParams) {
         self.shader.start();
         self.shader.load_lights(lights);
         self.shader.load_view_matrix(camera);
+        self.camera_pitch = camera.pitch;
+        self.camera_yaw = camera.yaw;
         self.shader.load_sky_color(sky_color);
         
         self.shader.load_to_shadowmap_space(to_shadow_space);
@@ -44,9 +104,12 @@ impl EntityRenderer {
 
         gl::active_texture(gl::TEXTURE1);
         gl::bind_texture(gl::TEXTURE_2D, shadow_params.shadow_map_texture);
+        gl::bind_sampler(1, shadow_params.shadow_map_sampler);
     }
 
     pub fn stop_render(&mut self) {
+        // other textures on the unit are sampled with their own parameters again
+        gl::bind_sampler(1, 0);
         self.shader.stop();
     }
 
@@ -54,15 +117,28 @@ impl EntityRenderer {
         if textured_model.texture.has_transparency {
             gl::helper::disable_culling();
         }
+        if textured_model.texture.has_premultiplied_alpha {
+            // the texture color was already multiplied by alpha when loading
+            gl::enable(gl::BLEND);
+            gl::blend_func(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        }
 
+        // a scope left over from the previous model has to be disabled while its own vao is still bound
+        drop(self.attrib_arrays.take());
         gl::bind_vertex_array(textured_model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
+        // the color attribute is only enabled for models that have it, the shader falls back to no vertex colors otherwise
+        let attribs: &[u32] = if textured_model.raw_model.has_vertex_colors {
+            &[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB, RawModel::NORMAL_ATTRIB, RawModel::COLOR_ATTRIB]
+        } else {
+            &[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB, RawModel::NORMAL_ATTRIB]
+        };
+        self.attrib_arrays = Some(AttribArrayScope::enable(attribs));
+        self.shader.load_has_vertex_color(textured_model.raw_model.has_vertex_colors);
 
         self.shader.load_shine_variables(textured_model.texture.shine_damper, textured_model.texture.reflectivity);
         self.shader.load_uses_fake_lighting(textured_model.texture.uses_fake_lighting);
         self.shader.load_atlas_number_of_rows(textured_model.texture.number_of_rows_in_atlas);
+        self.shader.load_uv_transform(&textured_model.texture.uv_scale, &textured_model.texture.uv_offset);
 
         // clip plane for water 
         self.shader.load_clip_plane(clip_plane);
@@ -82,23 +158,138 @@ impl EntityRenderer {
     }
 
     pub fn render(&mut self, entity: &Entity) {
+        self.render_lod(entity, &entity.model, LodFade::None);
+    }
+
+    // draws one of the entity's lod models, prepare_textured_model has to be called with the same model
+    pub fn render_lod(&mut self, entity: &Entity, model: &TexturedModel, fade: LodFade) {
         // load transform matrix into shader
-        let transform_mat = Matrix4f::create_transform_matrix(&entity.position, &entity.rotation_deg, entity.scale);
-        self.shader.load_transformation_matrix(&transform_mat);
+        EntityRenderer::entity_transform_into(&mut self.transform_mat, entity, self.camera_pitch, self.camera_yaw);
+        self.shader.load_transformation_matrix(&self.transform_mat);
         self.shader.load_atlas_offset(&entity.get_atlas_offset());
+        let (fade, is_fading_in) = fade.shader_values();
+        self.shader.load_lod_fade(fade, is_fading_in);
         
-        gl::draw_elements(gl::TRIANGLES, entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);
+        gl::draw_elements(gl::TRIANGLES, model.raw_model.vertex_count, gl::UNSIGNED_INT);
     }
 
-    pub fn unprepare_textured_model(&self, textured_model: &TexturedModel) {
+    // draws all the entities with the given model in as few draw calls as possible, prepare_textured_model has to be called with the same model
+    // returns the number of draw calls
+    pub fn render_instanced(&mut self, entities: &[&Entity], model: &TexturedModel) -> usize {
+        let vao_id = model.raw_model.vao_id;
+        self.add_instance_attribs(vao_id);
+        let instance_attrib_arrays = AttribArrayScope::enable(&[
+            RawModel::INSTANCE_TRANSFORM_ATTRIB,
+            RawModel::INSTANCE_TRANSFORM_ATTRIB + 1,
+            RawModel::INSTANCE_TRANSFORM_ATTRIB + 2,
+            RawModel::INSTANCE_TRANSFORM_ATTRIB + 3,
+            RawModel::INSTANCE_ATLAS_OFFSET_ATTRIB,
+        ]);
+        self.shader.load_is_instanced(true);
+        self.shader.load_lod_fade(0.0, false);
+
+        let mut draw_calls = 0;
+        for chunk in entities.chunks(EntityRenderer::MAX_INSTANCES) {
+            EntityRenderer::fill_instance_data(chunk, self.camera_pitch, self.camera_yaw, &mut self.instance_data);
+            gl::bind_buffer(gl::ARRAY_BUFFER, self.instance_vbo);
+            // orphan the old storage so we don't have to wait for the previous draw to finish reading it
+            gl::buffer_data_unitialized::<f32>(gl::ARRAY_BUFFER, EntityRenderer::MAX_INSTANCES * EntityRenderer::INSTANCED_DATA_LENGTH, gl::STREAM_DRAW);
+            gl::buffer_sub_data(gl::ARRAY_BUFFER, 0, &self.instance_data);
+            gl::bind_buffer(gl::ARRAY_BUFFER, 0);
+            gl::draw_elements_instanced(gl::TRIANGLES, model.raw_model.vertex_count, gl::UNSIGNED_INT, chunk.len());
+            draw_calls += 1;
+        }
+
+        self.shader.load_is_instanced(false);
+        drop(instance_attrib_arrays);
+        draw_calls
+    }
+
+    // the attribute pointers are vao state but are set again on every draw instead of remembering the vaos
+    // so a vao id that is reused after its model was unloaded still gets them, leaves the vao bound
+    fn add_instance_attribs(&self, vao_id: u32) {
+        gl::bind_vertex_array(vao_id);
+        gl::bind_buffer(gl::ARRAY_BUFFER, self.instance_vbo);
+        for column in 0..4 {
+            let attrib = RawModel::INSTANCE_TRANSFORM_ATTRIB + column;
+            gl::vertex_attrib_pointer_interleaved::<f32>(attrib, 4, gl::FLOAT, EntityRenderer::INSTANCED_DATA_LENGTH, 4 * column as usize);
+            gl::vertex_attrib_divisor(attrib, 1);
+        }
+        gl::vertex_attrib_pointer_interleaved::<f32>(RawModel::INSTANCE_ATLAS_OFFSET_ATTRIB, 2, gl::FLOAT, EntityRenderer::INSTANCED_DATA_LENGTH, 16);
+        gl::vertex_attrib_divisor(RawModel::INSTANCE_ATLAS_OFFSET_ATTRIB, 1);
+        gl::bind_buffer(gl::ARRAY_BUFFER, 0);
+    }
+
+    // also used by the shadow pass so billboards cast the shadow of how they are drawn
+    pub fn entity_transform_into(transform_mat: &mut Matrix4f, entity: &Entity, camera_pitch: f32, camera_yaw: f32) {
+        match entity.billboard_mode {
+            BillboardMode::None => Matrix4f::create_transform_matrix_into(transform_mat, &entity.position, &entity.rotation_deg, entity.scale),
+            BillboardMode::Full | BillboardMode::Cylindrical => {
+                let is_cylindrical = entity.billboard_mode == BillboardMode::Cylindrical;
+                *transform_mat = Matrix4f::create_billboard_transform_matrix(&entity.position, 0.0, entity.scale, camera_pitch, camera_yaw, is_cylindrical);
+            },
+        }
+    }
+
+    // the storage buffer is cleared first and keeps its capacity between frames
+    pub fn fill_instance_data(entities: &[&Entity], camera_pitch: f32, camera_yaw: f32, storage_buffer: &mut Vec<f32>) {
+        storage_buffer.clear();
+        let mut transform_mat = Matrix4f::identity();
+        for entity in entities.iter() {
+            EntityRenderer::entity_transform_into(&mut transform_mat, entity, camera_pitch, camera_yaw);
+            // store column wise
+            for col in 0..4 {
+                for row in 0..4 {
+                    storage_buffer.push(transform_mat[row][col]);
+                }
+            }
+            let atlas_offset = entity.get_atlas_offset();
+            storage_buffer.push(atlas_offset.x);
+            storage_buffer.push(atlas_offset.y);
+        }
+    }
+
+    pub fn unprepare_textured_model(&mut self, textured_model: &TexturedModel) {
         if textured_model.texture.has_transparency {
             gl::helper::enable_backface_culling(); // restore backbace culling for next model
         }
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
+        if textured_model.texture.has_premultiplied_alpha {
+            gl::disable(gl::BLEND);
+        }
+        self.attrib_arrays = None;
 
         gl::bind_vertex_array(0);
         gl::bind_texture(gl::TEXTURE_2D, 0);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector3f;
+
+    #[test]
+    fn groups_above_the_threshold_are_instanced() {
+        assert_eq!(EntityDrawPath::for_group_size(17, Some(16)), EntityDrawPath::Instanced);
+        assert_eq!(EntityDrawPath::for_group_size(16, Some(16)), EntityDrawPath::PerEntity);
+        assert_eq!(EntityDrawPath::for_group_size(2, Some(16)), EntityDrawPath::PerEntity);
+        assert_eq!(EntityDrawPath::for_group_size(10_000, None), EntityDrawPath::PerEntity);
+    }
+
+    #[test]
+    fn instance_data_holds_the_transform_columns_and_atlas_offset() {
+        let model = TexturedModel { raw_model: RawModel::new(1, 3), ..TexturedModel::default() };
+        let first = Entity::new(model.clone(), Vector3f::new(1.0, 2.0, 3.0), Vector3f::zero(), 1.0);
+        let second = Entity::new(model, Vector3f::new(-4.0, 0.0, 5.0), Vector3f::zero(), 2.0);
+        let mut storage = Vec::new();
+
+        EntityRenderer::fill_instance_data(&[&first, &second], 0.0, 0.0, &mut storage);
+
+        assert_eq!(storage.len(), 2 * EntityRenderer::INSTANCED_DATA_LENGTH);
+        // the 4th column is the translation
+        assert_eq!(&storage[12..16], &[1.0, 2.0, 3.0, 1.0]);
+        let second_data = &storage[EntityRenderer::INSTANCED_DATA_LENGTH..];
+        assert_eq!(&second_data[12..16], &[-4.0, 0.0, 5.0, 1.0]);
+        assert_eq!(second_data[0], 2.0);
+    }
+}
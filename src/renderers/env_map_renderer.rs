@@ -4,7 +4,9 @@ use crate::entities::{
     Camera,
 };
 use crate::math::{
+    distance,
     Matrix4f,
+    Vector3f,
 };
 use crate::models::{
     TexturedModel,
@@ -12,10 +14,27 @@ use crate::models::{
     TextureId,
 };
 use crate::shaders::EnvMapShader;
+use super::attrib_array_scope::AttribArrayScope;
+
+// the gl state changes of one env map pass, the render loop only executes these so the plan can be checked without a context
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum EnvMapPassStep {
+    StartShader,
+    BindEnvMap,
+    // binds the model and texture of the entity at this index
+    PrepareModel(usize),
+    DrawEntity(usize),
+    UnprepareModel(usize),
+    StopShader,
+}
 
 pub struct EnvMapRenderer {
     shader: EnvMapShader,
     proj_mat: Matrix4f,
+    // transparent reflective entities are drawn after the opaque ones from far to near so they blend correctly
+    sorts_transparent_back_to_front: bool,
+    // enabled between prepare and unprepare so they are always disabled again on the same vao
+    attrib_arrays: Option<AttribArrayScope>,
 }
 
 impl EnvMapRenderer {    
@@ -28,52 +47,165 @@ impl EnvMapRenderer {
         Self {
             shader,
             proj_mat: projection_matrix.clone(),
+            sorts_transparent_back_to_front: true,
+            attrib_arrays: None,
         }
     }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.proj_mat = projection_matrix.clone();
+    }
+
+    pub fn set_sorts_transparent_back_to_front(&mut self, sorts_transparent_back_to_front: bool) {
+        self.sorts_transparent_back_to_front = sorts_transparent_back_to_front;
+    }
    
     pub fn render(&mut self, entities: &Vec<Entity>, camera: &Camera, env_map_texture_id: &TextureId) {
-        for entity in entities {
-            self.prepare_textured_model(&entity.model, env_map_texture_id);
-            self.render_entity(entity, camera);
+        let steps = EnvMapRenderer::plan_pass(entities, &camera.position, self.sorts_transparent_back_to_front);
+        for step in steps {
+            match step {
+                EnvMapPassStep::StartShader => self.start_render(camera),
+                EnvMapPassStep::BindEnvMap => {
+                    gl::active_texture(gl::TEXTURE1);
+                    gl::bind_texture(gl::TEXTURE_CUBE_MAP, env_map_texture_id.unwrap());
+                },
+                EnvMapPassStep::PrepareModel(i) => self.prepare_textured_model(&entities[i].model),
+                EnvMapPassStep::DrawEntity(i) => self.render_entity(&entities[i]),
+                EnvMapPassStep::UnprepareModel(i) => self.unprepare_textured_model(&entities[i].model),
+                EnvMapPassStep::StopShader => self.shader.stop(),
+            }
+        }
+    }
+
+    // opaque entities are grouped by model so consecutive entities sharing one are prepared once
+    pub fn plan_pass(entities: &[Entity], camera_position: &Vector3f, sorts_transparent_back_to_front: bool) -> Vec<EnvMapPassStep> {
+        let mut steps = Vec::new();
+        if entities.is_empty() {
+            return steps;
         }
+        let model_key = |i: &usize| {
+            let model = &entities[*i].model;
+            (model.raw_model.vao_id, model.texture.tex_id)
+        };
+        let (mut opaque, mut transparent): (Vec<usize>, Vec<usize>) = (0..entities.len())
+            .partition(|i| !entities[*i].model.is_transparent());
+        // stable so entities sharing a vao keep their order
+        opaque.sort_by_key(|i| entities[*i].model.raw_model.vao_id);
+        if sorts_transparent_back_to_front {
+            let camera_distance = |i: &usize| distance(&entities[*i].position, camera_position);
+            transparent.sort_by(|a, b| camera_distance(b).partial_cmp(&camera_distance(a)).unwrap());
+        }
+
+        steps.push(EnvMapPassStep::StartShader);
+        steps.push(EnvMapPassStep::BindEnvMap);
+        let mut prepared: Option<usize> = None;
+        for i in opaque.into_iter().chain(transparent.into_iter()) {
+            let same_model = prepared.map_or(false, |p| model_key(&p) == model_key(&i));
+            if !same_model {
+                if let Some(p) = prepared {
+                    steps.push(EnvMapPassStep::UnprepareModel(p));
+                }
+                steps.push(EnvMapPassStep::PrepareModel(i));
+                prepared = Some(i);
+            }
+            steps.push(EnvMapPassStep::DrawEntity(i));
+        }
+        if let Some(p) = prepared {
+            steps.push(EnvMapPassStep::UnprepareModel(p));
+        }
+        steps.push(EnvMapPassStep::StopShader);
+        steps
+    }
+
+    fn start_render(&mut self, camera: &Camera) {
+        self.shader.start();
+        let view_mat = Matrix4f::create_view_matrix(camera);
+        let vp = &self.proj_mat * view_mat;
+        self.shader.load_vp_matrix(&vp);
+        self.shader.load_camera_position(&camera.position);
     }
 
-    fn prepare_textured_model(&mut self, textured_model: &TexturedModel, env_map_texture_id: &TextureId) {
+    fn prepare_textured_model(&mut self, textured_model: &TexturedModel) {
+        if textured_model.texture.has_transparency {
+            gl::helper::disable_culling();
+        }
+        if textured_model.is_transparent() {
+            gl::enable(gl::BLEND);
+            if textured_model.texture.has_premultiplied_alpha {
+                gl::blend_func(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+            } else {
+                gl::blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+            }
+        }
+
+        // a scope left over from the previous model has to be disabled while its own vao is still bound
+        drop(self.attrib_arrays.take());
         gl::bind_vertex_array(textured_model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
+        self.attrib_arrays = Some(AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB, RawModel::NORMAL_ATTRIB]));
 
         gl::active_texture(gl::TEXTURE0); // activate bank 0
         gl::bind_texture(gl::TEXTURE_2D, textured_model.texture.tex_id.unwrap());
-        gl::active_texture(gl::TEXTURE1); // activate bank 0
-        gl::bind_texture(gl::TEXTURE_CUBE_MAP, env_map_texture_id.unwrap());
     }
 
-    fn render_entity(&mut self, entity: &Entity, camera: &Camera) {
-        self.shader.start();
+    fn render_entity(&mut self, entity: &Entity) {
         // load transform matrix into shader
         let transform_mat = Matrix4f::create_transform_matrix(&entity.position, &entity.rotation_deg, entity.scale);
-        let view_mat = Matrix4f::create_view_matrix(camera);
-        let mvp = &self.proj_mat * view_mat;
-        self.shader.load_vp_matrix(&mvp);
         self.shader.load_model_matrix(&transform_mat);
-        self.shader.load_camera_position(&camera.position);
                 
         gl::draw_elements(gl::TRIANGLES, entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);
-
-        self.shader.stop();
     }
 
-    pub fn unprepare_textured_model(&self, textured_model: &TexturedModel) {
+    pub fn unprepare_textured_model(&mut self, textured_model: &TexturedModel) {
         if textured_model.texture.has_transparency {
             gl::helper::enable_backface_culling(); // restore backbace culling for next model
         }
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
+        if textured_model.is_transparent() {
+            gl::disable(gl::BLEND);
+        }
+        self.attrib_arrays = None;
 
         gl::bind_vertex_array(0);
         gl::bind_texture(gl::TEXTURE_2D, 0);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::ModelTexture;
+
+    fn env_mapped_entity(vao_id: u32, transparent: bool, z: f32) -> Entity {
+        let raw_model = RawModel::new(vao_id, 36);
+        let mut texture = ModelTexture::default();
+        texture.tex_id = TextureId::Loaded(vao_id);
+        texture.has_transparency = transparent;
+        let model = TexturedModel { raw_model, texture, ..TexturedModel::default() };
+        Entity::new(model, Vector3f::new(0.0, 0.0, z), Vector3f::zero(), 1.0)
+    }
+
+    #[test]
+    fn shader_and_env_map_are_set_up_once_for_all_entities() {
+        let entities: Vec<Entity> = (0..5).map(|i| env_mapped_entity(1 + i % 2, false, 0.0)).collect();
+        let steps = EnvMapRenderer::plan_pass(&entities, &Vector3f::zero(), true);
+        let count = |step: EnvMapPassStep| steps.iter().filter(|s| **s == step).count();
+        assert_eq!(count(EnvMapPassStep::StartShader), 1);
+        assert_eq!(count(EnvMapPassStep::StopShader), 1);
+        assert_eq!(count(EnvMapPassStep::BindEnvMap), 1);
+        // the entities share two models
+        assert_eq!(steps.iter().filter(|s| matches!(s, EnvMapPassStep::PrepareModel(_))).count(), 2);
+        assert_eq!(steps.iter().filter(|s| matches!(s, EnvMapPassStep::DrawEntity(_))).count(), 5);
+    }
+
+    #[test]
+    fn transparent_entities_are_drawn_last_from_back_to_front() {
+        let entities = vec![
+            env_mapped_entity(1, true, -2.0),
+            env_mapped_entity(1, true, -8.0),
+            env_mapped_entity(2, false, -1.0),
+        ];
+        let draws: Vec<EnvMapPassStep> = EnvMapRenderer::plan_pass(&entities, &Vector3f::zero(), true).into_iter()
+            .filter(|s| matches!(s, EnvMapPassStep::DrawEntity(_)))
+            .collect();
+        assert_eq!(draws, vec![EnvMapPassStep::DrawEntity(2), EnvMapPassStep::DrawEntity(1), EnvMapPassStep::DrawEntity(0)]);
+    }
+}
@@ -1,4 +1,9 @@
 use std::collections::HashMap;
+use crate::display::{
+    Display,
+    WindowSize,
+    framebuffers::FboMap,
+};
 use crate::guis::{
     GuiPanel,
     GuiText,
@@ -8,7 +13,9 @@ use crate::models::{
     RawModel,
 };
 use crate::math::{
+    Color,
     Matrix4f,
+    Vector2f,
 };
 use crate::gl;
 use crate::shaders::{
@@ -17,9 +24,54 @@ use crate::shaders::{
 };
 use super::master_renderer::RenderGroup;
 
+// where the gui ends up, drawing it into a texture first allows fading the whole ui and rendering it at its own resolution
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum GuiRenderTarget {
+    Screen,
+    // resolution_scale is relative to the window size, the opacity applies to the whole ui when it is composited over the scene
+    Texture { resolution_scale: f32, opacity: f32 },
+}
+
+impl Default for GuiRenderTarget {
+    fn default() -> Self {
+        GuiRenderTarget::Screen
+    }
+}
+
+impl GuiRenderTarget {
+    // the fbo the panels and texts are drawn into, None draws into the currently bound framebuffer
+    pub fn draw_fbo(&self) -> Option<&'static str> {
+        match self {
+            GuiRenderTarget::Screen => None,
+            GuiRenderTarget::Texture { .. } => Some(FboMap::GUI_FBO),
+        }
+    }
+
+    pub fn fbo_size(&self, window_size: &WindowSize) -> Option<(usize, usize)> {
+        match *self {
+            GuiRenderTarget::Screen => None,
+            GuiRenderTarget::Texture { resolution_scale, .. } => {
+                let scaled = |size: f32| ((size * resolution_scale).round() as usize).max(1);
+                Some((scaled(window_size.width_f32), scaled(window_size.height_f32)))
+            },
+        }
+    }
+
+    // tint of the quad that blends the gui texture over the scene
+    // the texture holds premultiplied colors so the opacity scales all channels
+    pub fn composite_tint(&self) -> Option<Color> {
+        match *self {
+            GuiRenderTarget::Screen => None,
+            GuiRenderTarget::Texture { opacity, .. } => Some(Color::rgba(opacity, opacity, opacity, opacity)),
+        }
+    }
+}
+
 pub struct GuiRenderer {
     gui_shader: GuiShader,
     text_shader: TextShader,
+    target: GuiRenderTarget,
+    gui_fbos: FboMap,
 }
 
 impl GuiRenderer {
@@ -27,38 +79,134 @@ impl GuiRenderer {
         GuiRenderer {
             gui_shader: GuiShader::new(),
             text_shader: TextShader::new(),
+            target: GuiRenderTarget::default(),
+            gui_fbos: FboMap { fbos: HashMap::new() },
+        }
+    }
+
+    pub fn set_target(&mut self, target: GuiRenderTarget) {
+        self.target = target;
+        if target.draw_fbo().is_none() {
+            self.gui_fbos.fbos.remove(FboMap::GUI_FBO);
         }
     }
 
-    pub fn render(&mut self, guis: &Vec<GuiPanel>, gui_model: &RawModel, texts: &Vec<GuiText>) {
+    pub fn render(&mut self, guis: &Vec<GuiPanel>, gui_model: &RawModel, texts: &Vec<GuiText>, display: &Display) {
         gl::helper::push_debug_group(RenderGroup::DRAW_GUI.id, RenderGroup::DRAW_GUI.name);
+        let window_size = &display.scene_size();
         
         // turn on alpha blending
         gl::enable(gl::BLEND);
-        // linear blending
-        gl::blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        if let Some(fbo_name) = self.target.draw_fbo() {
+            self.bind_gui_fbo(fbo_name, window_size);
+            // alpha is accumulated like the colors so the texture ends up with premultiplied alpha
+            gl::blend_func_separate(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA, gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        } else {
+            // linear blending
+            gl::blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+        }
         
         self.gui_shader.start();
         gl::bind_vertex_array(gui_model.vao_id);
         gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
 
         // with guis we want to be able to draw one gui on top of another even tho both have z = 0, so we disable depth test
+        // that means draw order is the only thing deciding what ends up on top so we go through the layers in ascending order
         gl::disable(gl::DEPTH_TEST);
 
-        for gui in guis.iter() {
+        let panels = GuiRenderer::panel_draw_order(guis);
+        for layer in GuiRenderer::layers(guis, texts) {
+            self.render_panels(panels.iter().copied().filter(|gui| gui.layer == layer), gui_model, window_size);
+            self.render_texts(texts.iter().filter(|text| text.layer == layer));
+        }
+
+        if let Some(fbo_name) = self.target.draw_fbo() {
+            display.restore_default_framebuffer();
+            self.composite(fbo_name, gui_model);
+        }
+
+        gl::enable(gl::DEPTH_TEST);
+        gl::disable(gl::BLEND);
+        gl::bind_texture(gl::TEXTURE_2D, 0);
+
+        gl::helper::pop_debug_group();
+    }
+
+    // the fbo follows the window size and is recreated when it changes
+    fn bind_gui_fbo(&mut self, fbo_name: &'static str, window_size: &WindowSize) {
+        let (width, height) = self.target.fbo_size(window_size).expect("A gui fbo needs a size");
+        let is_outdated = self.gui_fbos.fbos.get(fbo_name).map_or(true, |fbo| (fbo.viewport_width, fbo.viewport_height) != (width, height));
+        if is_outdated {
+            self.gui_fbos.replace(fbo_name, || FboMap::new_gui_fbo(width, height));
+        }
+        self.gui_fbos.fbos.get_mut(fbo_name).unwrap().bind();
+        gl::clear_color(0.0, 0.0, 0.0, 0.0);
+        gl::clear(gl::COLOR_BUFFER_BIT);
+    }
+
+    // draws the gui texture as a fullscreen quad blended over what is already on the screen
+    fn composite(&mut self, fbo_name: &'static str, gui_model: &RawModel) {
+        let gui_texture = self.gui_fbos.fbos[fbo_name].color_texture(0).expect("The gui fbo must have a color texture");
+        let tint = self.target.composite_tint().expect("Only gui textures are composited");
+        gl::blend_func(gl::ONE, gl::ONE_MINUS_SRC_ALPHA);
+        // the quad is flipped since fbo textures start at the bottom, so it must not be culled
+        gl::helper::disable_culling();
+
+        self.gui_shader.start();
+        gl::bind_vertex_array(gui_model.vao_id);
+        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        gl::active_texture(gl::TEXTURE0);
+        gl::bind_texture(gl::TEXTURE_2D, gui_texture);
+        self.gui_shader.load_transformation_matrix(&Matrix4f::create_gui_transform_matrix(&Vector2f::zero(), &Vector2f::new(1.0, -1.0)));
+        self.gui_shader.load_tint(&tint);
+        gl::draw_arrays(gl::TRIANGLE_STRIP, 0, gui_model.vertex_count);
+        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        gl::bind_vertex_array(0);
+        self.gui_shader.stop();
+
+        gl::helper::enable_backface_culling();
+    }
+
+    // panels sorted by ascending layer, the sort is stable so panels within a layer keep their insertion order
+    pub fn panel_draw_order(guis: &[GuiPanel]) -> Vec<&GuiPanel> {
+        let mut panels: Vec<&GuiPanel> = guis.iter().collect();
+        panels.sort_by_key(|gui| gui.layer);
+        panels
+    }
+
+    fn layers(guis: &[GuiPanel], texts: &[GuiText]) -> Vec<i32> {
+        let mut layers: Vec<i32> = guis.iter().map(|gui| gui.layer).chain(texts.iter().map(|text| text.layer)).collect();
+        layers.sort();
+        layers.dedup();
+        layers
+    }
+
+    fn render_panels<'a>(&mut self, guis: impl Iterator<Item = &'a GuiPanel>, gui_model: &RawModel, window_size: &WindowSize) {
+        self.gui_shader.start();
+        gl::bind_vertex_array(gui_model.vao_id);
+        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
+
+        // panels whose texture is still loading are skipped until the resource manager resolves them
+        for gui in guis.filter(|gui| gui.texture_id.is_ready()) {
             gl::active_texture(gl::TEXTURE0);
             gl::bind_texture(gl::TEXTURE_2D, gui.texture_id.unwrap());
-            let transform_mat = Matrix4f::create_gui_transform_matrix(&gui.position, &gui.scale);
+            let (transform_mat, tint) = GuiRenderer::panel_uniforms(gui, window_size);
             self.gui_shader.load_transformation_matrix(&transform_mat);
+            self.gui_shader.load_tint(tint);
             gl::draw_arrays(gl::TRIANGLE_STRIP, 0, gui_model.vertex_count);
         }
 
-        
         gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
         gl::bind_vertex_array(0);
         self.gui_shader.stop();
+    }
 
+    fn panel_uniforms<'a>(gui: &'a GuiPanel, window_size: &WindowSize) -> (Matrix4f, &'a Color) {
+        let (position, scale) = gui.ndc_transform(window_size);
+        (Matrix4f::create_gui_transform_matrix(&position, &scale), &gui.tint)
+    }
 
+    fn render_texts<'a>(&mut self, texts: impl Iterator<Item = &'a GuiText>) {
         self.text_shader.start();
         let text_by_font = GuiRenderer::group_text_by_font(texts);
         for (font_type, text_vec) in text_by_font.iter() {
@@ -80,20 +228,59 @@ impl GuiRenderer {
         gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
         gl::bind_vertex_array(0);
         self.text_shader.stop();
-
-        gl::enable(gl::DEPTH_TEST);
-        gl::disable(gl::BLEND);
-        gl::bind_texture(gl::TEXTURE_2D, 0);
-
-        gl::helper::pop_debug_group();
     }
 
-    fn group_text_by_font(texts: &Vec<GuiText>) -> HashMap<&FontType, Vec<&GuiText>> {
+    fn group_text_by_font<'a>(texts: impl Iterator<Item = &'a GuiText>) -> HashMap<&'a FontType, Vec<&'a GuiText>> {
         let mut result = HashMap::new();
-        for text in texts.iter() {
+        for text in texts {
             let group = result.entry(&text.font_type).or_insert(Vec::new());
             group.push(text);
         }
         result
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::TextureId;
+
+    fn panel(texture: u32, layer: i32) -> GuiPanel {
+        let mut panel = GuiPanel::new(TextureId::Loaded(texture), Vector2f::zero(), Vector2f::new(1.0, 1.0));
+        panel.layer = layer;
+        panel
+    }
+
+    #[test]
+    fn panels_draw_in_ascending_layer_order() {
+        let guis = vec![panel(1, 2), panel(2, -1), panel(3, 0), panel(4, 2), panel(5, -1)];
+        let order: Vec<u32> = GuiRenderer::panel_draw_order(&guis).iter().map(|gui| gui.texture_id.unwrap()).collect();
+        assert_eq!(order, vec![2, 5, 3, 1, 4]);
+        assert_eq!(GuiRenderer::layers(&guis, &[]), vec![-1, 0, 2]);
+    }
+
+    #[test]
+    fn panel_tint_defaults_to_opaque_white_and_is_loaded_per_panel() {
+        let mut faded = panel(1, 0);
+        assert_eq!(faded.tint, Color::rgba(1.0, 1.0, 1.0, 1.0));
+
+        faded.tint = Color::rgba(1.0, 0.0, 0.0, 0.5);
+        let window_size = WindowSize { width: 800, height: 600, width_f32: 800.0, height_f32: 600.0 };
+        let (_, tint) = GuiRenderer::panel_uniforms(&faded, &window_size);
+        assert_eq!(tint, &Color::rgba(1.0, 0.0, 0.0, 0.5));
+    }
+
+    #[test]
+    fn gui_texture_target_draws_into_gui_fbo_and_composites_over_scene() {
+        let window_size = WindowSize { width: 800, height: 600, width_f32: 800.0, height_f32: 600.0 };
+        let screen = GuiRenderTarget::default();
+        assert_eq!(screen.draw_fbo(), None);
+        assert_eq!(screen.composite_tint(), None);
+
+        let texture = GuiRenderTarget::Texture { resolution_scale: 0.5, opacity: 0.25 };
+        assert_eq!(texture.draw_fbo(), Some(FboMap::GUI_FBO));
+        assert_eq!(texture.fbo_size(&window_size), Some((400, 300)));
+        // premultiplied so the colors fade along with the alpha
+        assert_eq!(texture.composite_tint(), Some(Color::rgba(0.25, 0.25, 0.25, 0.25)));
+    }
+}
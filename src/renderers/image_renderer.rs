@@ -15,6 +15,11 @@ impl ImageRenderer {
         }
     }
 
+    // e.g. to follow the window size, the old target is freed
+    pub fn set_render_target(&mut self, render_target: Option<FramebufferObject>) {
+        self.target_fbo = render_target;
+    }
+
     pub fn get_color_texture(&self) -> Result<u32, &'static str> {
         match &self.target_fbo {
             Some(fbo) => {
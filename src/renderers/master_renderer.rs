@@ -1,6 +1,8 @@
 use std::collections::HashMap;
 use crate::display::{
+    DepthMode,
     Display,
+    Viewport,
     WallClock,
     framebuffers::{
         FboMap,
@@ -9,23 +11,61 @@ use crate::display::{
 use crate::gl;
 use crate::entities::*;
 use crate::math::{
+    Color,
     Matrix4f,
     Vector3f,
     Vector4f,
 };
+use crate::math::utils::distance;
 use crate::models::{
     TexturedModel,
+    DebugVectorLinesModel,
 };
 use crate::particles::ParticleMaster;
+use crate::shadows::shadow_map_freeze::ShadowMapFreezeParams;
+use crate::shadows::shadow_box::ShadowBox;
+use crate::shadows::shadow_params::ShadowMapResolution;
 use super::shadowmap_renderer::ShadowMapRenderer;
-use super::entity_renderer::EntityRenderer;
+use super::entity_renderer::{
+    EntityRenderer,
+    EntityDrawPath,
+};
 use super::normal_map_entity_renderer::NormalMapEntityRenderer;
-use super::terrain_renderer::TerrainRenderer;
-use super::skybox_renderer::SkyboxRenderer;
-use super::water_renderer::WaterRenderer;
-use super::debug_renderer::DebugRenderer;
+use super::terrain_renderer::{
+    TerrainRenderer,
+    TerrainTessellationParams,
+};
+use super::skybox_renderer::{
+    SkyboxRenderer,
+    SkyboxDepth,
+};
+use super::render_stats::RenderStats;
+use super::water_renderer::{
+    WaterRenderer,
+    WaterPass,
+    WaterPassClearColors,
+    RefractionMode,
+    ReflectionClipMode,
+};
+use super::depth_only_renderer::DepthOnlyRenderer;
+use super::thumbnail::{
+    Thumbnail,
+    ThumbnailParams,
+};
+use super::debug_renderer::{
+    DebugRenderer,
+    WireframeOverlay,
+};
 use super::env_map_renderer::EnvMapRenderer;
+use super::particle_renderer::{
+    ParticleDepthTest,
+    ParticleSceneDepth,
+};
 use super::animated_entity_renderer::AnimatedEntityRenderer;
+use super::outline_renderer::{
+    OutlineRenderer,
+    OutlineParams,
+};
 
 pub struct RenderGroup {
     pub id: u32,
@@ -43,6 +83,109 @@ impl RenderGroup {
     pub const PARTICLE_EFFECTS_PASS: RenderGroup = RenderGroup {id: 7, name: "ParticleEffects"};
     pub const POST_PROCESSING: RenderGroup = RenderGroup {id: 8, name: "PostProcessing"};
     pub const DRAW_GUI: RenderGroup = RenderGroup {id: 9, name: "GuiOverlayDraw"};
+    pub const DRAW_TRANSPARENT_ENTITIES: RenderGroup = RenderGroup {id: 10, name: "TransparentEntityDrawPass"};
+    pub const DRAW_RENDER_LAYER: RenderGroup = RenderGroup {id: 11, name: "RenderLayerPass"};
+    pub const DEPTH_ONLY_PASS: RenderGroup = RenderGroup {id: 12, name: "DepthOnlyPass"};
+    pub const THUMBNAIL_PASS: RenderGroup = RenderGroup {id: 13, name: "ThumbnailPass"};
+}
+
+// what is drawn into the camera fbo after the scene itself, in the order of camera_overlay_passes
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CameraOverlayPass {
+    WireframeEdges,
+    Water,
+    EnvMappedEntities,
+    Outline,
+    // after everything that writes depth so they are tested against the complete scene including water and transparent entities
+    Particles,
+    ShadowBox,
+    DebugVectorLines,
+}
+
+// a draw of the scene, either from the camera, into one of the water fbos or of the entities on the render layer
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ScenePass {
+    Camera,
+    Water(WaterPass),
+    Layer,
+}
+
+// the fbo of the pass is cleared with clear while every shader of the pass gets fog as its fog color
+#[derive(Debug, Clone, PartialEq)]
+struct ScenePassColors {
+    clear: Color,
+    fog: Color,
+}
+
+// what gets drawn into the refraction fbo, decided up front so the choice can be checked without a context
+enum RefractionPassPlan<'e> {
+    // the full scene with the color shaders
+    Shaded,
+    // every visible entity grouped by model, only writing depth
+    DepthOnly(HashMap<&'e TexturedModel, Vec<&'e Entity>>),
+}
+
+// what is behind the scene geometry in the camera fbo
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SceneBackground {
+    Skybox,
+    // no skybox and the camera fbo is cleared with alpha 0 so the scene can be composited over other layers
+    Transparent,
+}
+
+impl Default for SceneBackground {
+    fn default() -> Self {
+        SceneBackground::Skybox
+    }
+}
+
+impl SceneBackground {
+    pub const TRANSPARENT_CLEAR_COLOR: Color = Color::rgba(0.0, 0.0, 0.0, 0.0);
+
+    pub fn camera_clear_color(&self, sky_color: &Color) -> Color {
+        match self {
+            SceneBackground::Skybox => sky_color.clone(),
+            SceneBackground::Transparent => SceneBackground::TRANSPARENT_CLEAR_COLOR,
+        }
+    }
+
+    pub fn draws_skybox(&self) -> bool {
+        *self == SceneBackground::Skybox
+    }
+}
+
+// the sky is what the camera fbo is cleared to, distant geometry fades into the fog color
+#[derive(Debug, Clone, PartialEq)]
+pub struct SkyColors {
+    pub sky: Color,
+    pub fog: Color,
+}
+
+impl Default for SkyColors {
+    fn default() -> Self {
+        SkyColors::new(&SkyColors::DEFAULT_SKY_COLOR)
+    }
+}
+
+impl SkyColors {
+    pub const DEFAULT_SKY_COLOR: Color = Color::rgb(0.5444, 0.62, 0.69);
+
+    // the fog matches the sky unless set separately
+    pub fn new(sky: &Color) -> Self {
+        SkyColors {
+            sky: sky.clone(),
+            fog: sky.clone(),
+        }
+    }
+
+    pub fn with_fog_color(mut self, fog: &Color) -> Self {
+        self.fog = fog.clone();
+        self
+    }
+
+    pub fn camera_clear_color(&self, background: SceneBackground) -> Color {
+        background.camera_clear_color(&self.sky)
+    }
 }
 
 pub struct MasterRenderer {    
@@ -54,22 +197,67 @@ pub struct MasterRenderer {
     shadowmap_renderer: ShadowMapRenderer,
     env_map_renderer: EnvMapRenderer,
     animated_entity_renderer: AnimatedEntityRenderer,
+    outline_renderer: OutlineRenderer,
+    depth_only_renderer: DepthOnlyRenderer,
+    // indices into the entities passed to render which get a highlighted outline
+    outlined_entity_ids: Vec<usize>,
+    outline_params: OutlineParams,
+    // part of the camera fbo we render into, None means the whole fbo
+    viewport: Option<Viewport>,
+    projection_matrix: Matrix4f,
+    particle_projection_outdated: bool,
+    depth_mode: DepthMode,
+    water_clear_colors: WaterPassClearColors,
+    sky_colors: SkyColors,
+    stats: RenderStats,
+    particle_depth_test: ParticleDepthTest,
+    background: SceneBackground,
+    // entities on this layer are drawn a second time into the render layer fbo
+    layer_pass: Option<RenderLayers>,
+    // the water reflection only needs what is close to the water so it can use a shorter far plane than the camera, None keeps Display::FAR
+    reflection_far_distance: Option<f32>,
+    refraction_mode: RefractionMode,
+    reflection_clip_mode: ReflectionClipMode,
+    debug_renderer: DebugRenderer,
+    // draws the debug entity around the shadow box every frame
+    shows_shadow_box: bool,
+    // normals and tangents of one model drawn as lines on all entities using it
+    debug_vector_lines: Option<DebugVectorLinesModel>,
+    debug_vector_line_length: f32,
+    // edges drawn on top of the shaded entities of the overlay models
+    wireframe_overlay: Option<WireframeOverlay>,
+    // draws particles into the water reflection too, clipped to what is above the water
+    reflects_particles: bool,
+    // distance over which entities cross fade between lod levels, centered on the switch distance
+    lod_fade_band: f32,
+    // distance the camera has to move past a lod switch distance before an entity that settled on one level switches back
+    lod_hysteresis: f32,
+    // lod level each entity settled on in the camera pass of a view (the whole screen or one split screen viewport), indexed like the entities
+    settled_lod_levels: HashMap<Option<Viewport>, Vec<usize>>,
+    // texture groups with more entities than this are drawn instanced, None always draws them one by one
+    instancing_threshold: Option<usize>,
 }
 
 impl MasterRenderer {
 
-    const SKY_COLOR: Vector3f = Vector3f{ x: 0.5444, y: 0.62, z: 0.69 };
+    pub const DEFAULT_LOD_FADE_BAND: f32 = 4.0;
+    pub const DEFAULT_LOD_HYSTERESIS: f32 = 2.0;
+    pub const DEFAULT_INSTANCING_THRESHOLD: usize = 16;
+    pub const DEFAULT_DEBUG_VECTOR_LINE_LENGTH: f32 = 0.2;
 
     pub fn new(projection_matrix: &Matrix4f, aspect_ratio: f32) -> MasterRenderer {
         let entity_renderer = EntityRenderer::new(projection_matrix);
         let normal_map_entity_renderer = NormalMapEntityRenderer::new(projection_matrix);
         let terrain_renderer = TerrainRenderer::new(projection_matrix);
         let skybox_renderer = SkyboxRenderer::new(projection_matrix);
-        let water_renderer = WaterRenderer::new(projection_matrix, &MasterRenderer::SKY_COLOR);
+        let sky_colors = SkyColors::default();
+        let water_renderer = WaterRenderer::new(projection_matrix, &sky_colors.fog);
         let shadowmap_renderer = ShadowMapRenderer::new(aspect_ratio);
-        let _debug_renderer = DebugRenderer::new(projection_matrix);
+        let debug_renderer = DebugRenderer::new(projection_matrix);
         let env_map_renderer = EnvMapRenderer::new(projection_matrix);
         let animated_entity_renderer = AnimatedEntityRenderer::new(projection_matrix);
+        let outline_renderer = OutlineRenderer::new(projection_matrix);
+        let depth_only_renderer = DepthOnlyRenderer::new(projection_matrix);
 
         MasterRenderer {
             entity_renderer,
@@ -80,59 +268,434 @@ impl MasterRenderer {
             shadowmap_renderer,
             env_map_renderer,
             animated_entity_renderer,
+            outline_renderer,
+            depth_only_renderer,
+            outlined_entity_ids: Vec::new(),
+            outline_params: OutlineParams::default(),
+            viewport: None,
+            projection_matrix: projection_matrix.clone(),
+            particle_projection_outdated: false,
+            depth_mode: DepthMode::default(),
+            water_clear_colors: WaterPassClearColors::with_sky_color(&sky_colors.sky),
+            sky_colors,
+            stats: RenderStats::default(),
+            particle_depth_test: ParticleDepthTest::default(),
+            background: SceneBackground::default(),
+            layer_pass: None,
+            reflection_far_distance: None,
+            refraction_mode: RefractionMode::default(),
+            reflection_clip_mode: ReflectionClipMode::default(),
+            debug_renderer,
+            shows_shadow_box: false,
+            debug_vector_lines: None,
+            debug_vector_line_length: MasterRenderer::DEFAULT_DEBUG_VECTOR_LINE_LENGTH,
+            reflects_particles: false,
+            wireframe_overlay: None,
+            lod_fade_band: MasterRenderer::DEFAULT_LOD_FADE_BAND,
+            lod_hysteresis: MasterRenderer::DEFAULT_LOD_HYSTERESIS,
+            settled_lod_levels: HashMap::new(),
+            instancing_threshold: Some(MasterRenderer::DEFAULT_INSTANCING_THRESHOLD),
         }
     }
+
+    pub fn set_viewport(&mut self, viewport: Viewport) {
+        self.viewport = Some(viewport);
+        self.update_projection_matrix(&viewport.projection_matrix(self.depth_mode));
+    }
+
+    pub fn reset_viewport(&mut self, projection_matrix: &Matrix4f) {
+        self.viewport = None;
+        self.update_projection_matrix(projection_matrix);
+    }
+
+    pub fn set_outlined_entities(&mut self, entity_ids: &[usize], params: OutlineParams) {
+        self.outlined_entity_ids.clear();
+        self.outlined_entity_ids.extend_from_slice(entity_ids);
+        self.outline_params = params;
+    }
+
+    pub fn clear_outlined_entities(&mut self) {
+        self.outlined_entity_ids.clear();
+    }
+
+    // also makes the water reflection clear to the new sky, set custom water clear colors afterwards
+    pub fn set_sky_colors(&mut self, sky_colors: &SkyColors) {
+        self.water_renderer.update_fog_color(&sky_colors.fog);
+        self.water_clear_colors.reflection = sky_colors.sky.clone();
+        self.sky_colors = sky_colors.clone();
+    }
+
+    pub fn set_water_clear_colors(&mut self, clear_colors: WaterPassClearColors) {
+        self.water_clear_colors = clear_colors;
+    }
+
+    pub fn set_particle_depth_test(&mut self, particle_depth_test: ParticleDepthTest) {
+        self.particle_depth_test = particle_depth_test;
+    }
+
+    pub fn set_background(&mut self, background: SceneBackground) {
+        self.background = background;
+    }
+
+    pub fn set_skybox_depth(&mut self, depth: SkyboxDepth) {
+        self.skybox_renderer.set_depth(depth);
+    }
+
+    // None leaves only the exponential fog
+    pub fn set_fog_cutoff(&mut self, fog_cutoff: Option<FogCutoff>) {
+        self.entity_renderer.update_fog_cutoff(&fog_cutoff);
+        self.normal_map_entity_renderer.update_fog_cutoff(&fog_cutoff);
+        self.terrain_renderer.update_fog_cutoff(&fog_cutoff);
+        self.water_renderer.update_fog_cutoff(&fog_cutoff);
+    }
+
+    pub fn set_layer_pass(&mut self, layer_pass: Option<RenderLayers>) {
+        self.layer_pass = layer_pass;
+    }
+
+    pub fn set_instancing_threshold(&mut self, instancing_threshold: Option<usize>) {
+        self.instancing_threshold = instancing_threshold;
+    }
+
+    // 0 swaps lod levels without a cross fade
+    pub fn set_lod_fade_band(&mut self, lod_fade_band: f32) {
+        self.lod_fade_band = lod_fade_band.max(0.0);
+    }
+
+    pub fn set_lod_hysteresis(&mut self, lod_hysteresis: f32) {
+        self.lod_hysteresis = lod_hysteresis.max(0.0);
+    }
+
+    // the texture of the render layer pass, None when no layer pass is done so post processing doesn't read a stale texture
+    pub fn render_layer_texture(&self, framebuffers: &FboMap) -> Option<u32> {
+        self.layer_pass?;
+        framebuffers.fbos.get(FboMap::RENDER_LAYER_FBO).and_then(|fbo| fbo.color_texture(0))
+    }
+
+    // has to be called once per frame before rendering, a split screen frame renders the scene once per viewport
+    pub fn begin_frame(&mut self) {
+        self.stats.reset();
+    }
+
+    // stats of the last rendered frame
+    pub fn render_stats(&self) -> &RenderStats {
+        &self.stats
+    }
+
+    // trades shadow acne (too little bias) against shadows detaching from their casters (too much)
+    pub fn set_shadow_bias(&mut self, constant_bias: f32, slope_bias: f32, max_bias: f32) {
+        self.shadowmap_renderer.shadow_params.set_bias(constant_bias, slope_bias, max_bias);
+    }
+
+    // how far from the camera shadows are cast, the shadow map texels get spread over a smaller area for shorter distances
+    pub fn set_shadow_distance(&mut self, shadow_distance: f32) {
+        self.shadowmap_renderer.set_shadow_distance(shadow_distance);
+    }
+
+    // e.g. after Display::set_target_aspect_ratio, the shadow box has to cover the new view frustum
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32, projection_matrix: &Matrix4f) {
+        self.shadowmap_renderer.set_aspect_ratio(aspect_ratio);
+        self.update_projection_matrix(projection_matrix);
+    }
+
+    // the debug entity passed to render needs a debug cuboid model (ResourceManager::debug_cuboid_model)
+    pub fn set_shows_shadow_box(&mut self, shows_shadow_box: bool) {
+        self.shows_shadow_box = shows_shadow_box;
+    }
+
+    // the lines model comes from ResourceManager::debug_vector_lines_model, None stops drawing them
+    pub fn set_debug_vector_lines(&mut self, debug_vector_lines: Option<DebugVectorLinesModel>) {
+        self.debug_vector_lines = debug_vector_lines;
+    }
+
+    // in model space so the lines get longer with the entity scale
+    pub fn set_debug_vector_line_length(&mut self, line_length: f32) {
+        self.debug_vector_line_length = line_length;
+    }
+
+    // the edges models come from ResourceManager::debug_edges_model or debug_edges_models, None turns the overlay off
+    pub fn set_wireframe_overlay(&mut self, wireframe_overlay: Option<WireframeOverlay>) {
+        self.wireframe_overlay = wireframe_overlay;
+    }
+
+    // off by default since the particles are drawn once more for every water height
+    pub fn set_reflects_particles(&mut self, reflects_particles: bool) {
+        self.reflects_particles = reflects_particles;
+    }
+
+    // subdivides terrain close to the camera, ignored when the context can't tessellate
+    pub fn set_terrain_tessellation(&mut self, tessellation: TerrainTessellationParams) {
+        self.terrain_renderer.set_tessellation_params(tessellation);
+    }
+
+    // false draws every entity into the shadow map, even the ones far outside of the shadow box
+    pub fn set_shadow_caster_culling(&mut self, culls_to_shadow_box: bool) {
+        self.shadowmap_renderer.shadow_params.culls_to_shadow_box = culls_to_shadow_box;
+    }
+
+    // the shadow map fbo gets recreated at the new size before the next shadow pass
+    pub fn set_shadow_map_resolution(&mut self, resolution: ShadowMapResolution) {
+        self.shadowmap_renderer.set_resolution(resolution);
+    }
+
+    pub fn set_max_shadow_caster_distance(&mut self, max_caster_distance: Option<f32>) {
+        self.shadowmap_renderer.shadow_params.max_caster_distance = max_caster_distance;
+    }
+
+    pub fn set_reflection_far_distance(&mut self, far_distance: Option<f32>) {
+        self.reflection_far_distance = far_distance;
+    }
+
+    // DepthOnly saves shading the refraction pass when the water only needs the depth for its fog and color
+    pub fn set_refraction_mode(&mut self, refraction_mode: RefractionMode) {
+        self.refraction_mode = refraction_mode;
+    }
+
+    // the refraction pass always uses clip distances since the water shader reads its depth back with the camera projection
+    pub fn set_reflection_clip_mode(&mut self, reflection_clip_mode: ReflectionClipMode) {
+        self.reflection_clip_mode = reflection_clip_mode;
+    }
+
+    // None renders the shadow map every frame, otherwise it is kept until the camera or sun move past the thresholds
+    pub fn set_shadow_map_freeze(&mut self, freeze_params: Option<ShadowMapFreezeParams>) {
+        self.shadowmap_renderer.set_freeze_params(freeze_params);
+    }
+
+    // the projection matrix has to match the depth mode, pass Display::depth_mode after Display::set_depth_mode as it may fall back
+    pub fn set_depth_mode(&mut self, depth_mode: DepthMode, projection_matrix: &Matrix4f) {
+        self.depth_mode = depth_mode;
+        self.water_renderer.update_depth_mode(depth_mode);
+        self.skybox_renderer.update_depth_mode(depth_mode);
+        self.update_projection_matrix(projection_matrix);
+    }
+
+    fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.projection_matrix = projection_matrix.clone();
+        self.entity_renderer.update_projection_matrix(projection_matrix);
+        self.normal_map_entity_renderer.update_projection_matrix(projection_matrix);
+        self.terrain_renderer.update_projection_matrix(projection_matrix);
+        self.skybox_renderer.update_projection_matrix(projection_matrix);
+        self.water_renderer.update_projection_matrix(projection_matrix);
+        self.env_map_renderer.update_projection_matrix(projection_matrix);
+        self.animated_entity_renderer.update_projection_matrix(projection_matrix);
+        self.outline_renderer.update_projection_matrix(projection_matrix);
+        self.depth_only_renderer.update_projection_matrix(projection_matrix);
+        self.debug_renderer.update_projection_matrix(projection_matrix);
+        // particle master is not owned by us so it gets updated on the next render
+        self.particle_projection_outdated = true;
+    }
+
+    // projection for a pass with its own far plane, None when the pass uses the camera projection
+    fn pass_projection(projection_matrix: &Matrix4f, depth_mode: DepthMode, far_distance: Option<f32>) -> Option<Matrix4f> {
+        far_distance.map(|far_distance| depth_mode.with_far_distance(projection_matrix, far_distance))
+    }
+
+    // switches the projection of the renderers used in render_pass without changing the camera projection
+    // the skybox keeps its projection so it doesn't get clipped by a short far plane
+    fn load_pass_projection(&mut self, projection_matrix: &Matrix4f) {
+        self.entity_renderer.update_projection_matrix(projection_matrix);
+        self.normal_map_entity_renderer.update_projection_matrix(projection_matrix);
+        self.terrain_renderer.update_projection_matrix(projection_matrix);
+        self.animated_entity_renderer.update_projection_matrix(projection_matrix);
+    }
+
+    // renders the two cameras side by side into the left and right half of the screen
+    pub fn render_split_screen(&mut self, cameras: (&mut Camera, &mut Camera), screen: Viewport, lights: &Vec<Light>, entities: &Vec<Entity>, normal_mapped_entities: &Vec<Entity>, terrains: &Vec<Terrain>, 
+                player: &Player, water_tiles: &Vec<WaterTile>, skybox: &Skybox, display: &Display, framebuffers: &mut FboMap, particle_master: &mut ParticleMaster, 
+                entities_with_env_map: &Vec<Entity>, debug_entity: &mut DebugEntity) {
+        let (left, right) = screen.split_side_by_side();
+        self.set_viewport(left);
+        self.render(lights, cameras.0, entities, normal_mapped_entities, terrains, player, water_tiles, skybox, display, framebuffers, particle_master, entities_with_env_map, debug_entity);
+        self.set_viewport(right);
+        self.render(lights, cameras.1, entities, normal_mapped_entities, terrains, player, water_tiles, skybox, display, framebuffers, particle_master, entities_with_env_map, debug_entity);
+        self.reset_viewport(&display.projection_matrix);
+    }
     
     pub fn render(&mut self, lights: &Vec<Light>, camera: &mut Camera, entities: &Vec<Entity>, normal_mapped_entities: &Vec<Entity>, terrains: &Vec<Terrain>, 
                 player: &Player, water_tiles: &Vec<WaterTile>, skybox: &Skybox, display: &Display, framebuffers: &mut FboMap, particle_master: &mut ParticleMaster, 
-                entities_with_env_map: &Vec<Entity>, _debug_entity: &mut DebugEntity) {
+                entities_with_env_map: &Vec<Entity>, debug_entity: &mut DebugEntity) {
 
+        let screen_size = display.get_size();
+        framebuffers.resize_screen_fbos(screen_size.width, screen_size.height);
         self.do_shadowmap_render_passes(camera, framebuffers, entities, normal_mapped_entities, player, lights, terrains);
 
-        self.do_water_render_passes(water_tiles, camera, framebuffers, entities, normal_mapped_entities, terrains, player, lights, skybox, display);
-        
-        let camera_tex_fbo = framebuffers.fbos.get_mut(FboMap::CAMERA_TEXTURE_FBO_MULTI).expect("Must have a camera output fbo to which to render the scene for post processing");
-        camera_tex_fbo.bind(); // we will unbind it later after particle effects are drawn
+        // the lod levels are picked once for the camera, the water passes and overlays draw the same levels
+        let lods = self.select_lods(entities, camera);
 
-        let above_infinity_plane = Vector4f::new(0.0, -1.0, 0.0, 10_000.0);
-        self.render_pass(lights, camera, entities, normal_mapped_entities, terrains, player, skybox, &display.wall_clock, &above_infinity_plane);
-        // render water
-        self.water_renderer.render(water_tiles, framebuffers, camera, display, lights);
+        self.bind_camera_fbo(framebuffers); // we will unbind it later after particle effects are drawn
 
-        // render entities which have an env map -> for the time being this happens outside of render pass but needs to be integrated at some point
-        self.env_map_renderer.render(entities_with_env_map, camera, &skybox.model.day_texture_id);
+        let above_infinity_plane = Vector4f::new(0.0, -1.0, 0.0, 10_000.0);
+        if self.wireframe_overlay.is_some() {
+            let (factor, units) = WireframeOverlay::polygon_offset(self.depth_mode);
+            gl::enable(gl::POLYGON_OFFSET_FILL);
+            gl::polygon_offset(factor, units);
+        }
+        let camera_pass_colors = MasterRenderer::scene_pass_colors(ScenePass::Camera, &self.sky_colors, self.background, &self.water_clear_colors);
+        self.render_pass(lights, camera, entities, &lods, normal_mapped_entities, terrains, player, skybox, &display.wall_clock, &above_infinity_plane, ScenePass::Camera, &camera_pass_colors);
 
-        // render particles
-        particle_master.render(&camera);
+        let overlay_passes = MasterRenderer::camera_overlay_passes(self.wireframe_overlay.is_some(), self.shows_shadow_box, self.debug_vector_lines.is_some());
+        for overlay_pass in overlay_passes {
+            match overlay_pass {
+                CameraOverlayPass::WireframeEdges => if let Some(wireframe_overlay) = &self.wireframe_overlay {
+                    gl::disable(gl::POLYGON_OFFSET_FILL);
+                    for edges_model in wireframe_overlay.edges_models.iter() {
+                        let target_entities = MasterRenderer::entities_drawn_with(edges_model.target_vao_id, entities, &lods, normal_mapped_entities);
+                        self.debug_renderer.render_edges(edges_model, &target_entities, camera, &wireframe_overlay.color, self.depth_mode);
+                    }
+                },
+                CameraOverlayPass::Water => {
+                    // every water height needs its own reflection and refraction so they are done one height at a time
+                    // and the tiles at that height are drawn into the camera fbo before the water fbos are reused for the next height
+                    self.water_renderer.update_wave_factor(display.frame_time_sec);
+                    for water_group in WaterTile::group_by_height(water_tiles) {
+                        self.do_water_render_passes(&water_group, camera, framebuffers, entities, &lods, normal_mapped_entities, terrains, player, lights, skybox, display, particle_master);
+                        self.bind_camera_fbo(framebuffers);
+                        self.water_renderer.render(water_group.tiles.iter().cloned(), framebuffers, camera, lights);
+                    }
+                },
+                // for the time being this happens outside of render pass but needs to be integrated at some point
+                CameraOverlayPass::EnvMappedEntities => self.env_map_renderer.render(entities_with_env_map, camera, &skybox.model.day_texture_id),
+                // uses the stencil of the camera fbo
+                CameraOverlayPass::Outline => self.outline_renderer.render(entities, &self.outlined_entity_ids, camera, &self.outline_params),
+                CameraOverlayPass::Particles => self.render_particles(camera, framebuffers, particle_master),
+                CameraOverlayPass::ShadowBox => self.render_shadow_box(debug_entity, camera),
+                CameraOverlayPass::DebugVectorLines => if let Some(lines_model) = &self.debug_vector_lines {
+                    let target_entities = MasterRenderer::entities_drawn_with(lines_model.target_vao_id, entities, &lods, normal_mapped_entities);
+                    self.debug_renderer.render_vector_lines(lines_model, &target_entities, camera, self.debug_vector_line_length);
+                },
+            }
+        }
+        if self.viewport.is_some() {
+            Viewport::reset_scissor();
+        }
         display.restore_default_framebuffer();
 
-        //let obb_ref = &self.shadowmap_renderer.shadow_box.frustum_corners;
-        //self.debug_renderer.render(debug_entity, camera, obb_ref); 
-        //debug_entity.position = self.shadowmap_renderer.shadow_box.world_space_center.clone();
-        //debug_entity.scale = Vector3f::new(100.0, 100.0, 100.0);
-        //debug_entity.scale = 0.80 * Vector3f::new(self.shadowmap_renderer.shadow_box.width, self.shadowmap_renderer.shadow_box.height, self.shadowmap_renderer.shadow_box.length);
-        //self.debug_renderer.render_cube(debug_entity, camera);
+        if let Some(layer) = self.layer_pass {
+            self.do_render_layer_pass(layer, lights, camera, entities, &lods, framebuffers, display);
+        }
+    }
+
+    fn camera_overlay_passes(has_wireframe_overlay: bool, shows_shadow_box: bool, has_debug_vector_lines: bool) -> Vec<CameraOverlayPass> {
+        let mut passes = Vec::new();
+        if has_wireframe_overlay {
+            passes.push(CameraOverlayPass::WireframeEdges);
+        }
+        passes.extend_from_slice(&[CameraOverlayPass::Water, CameraOverlayPass::EnvMappedEntities, CameraOverlayPass::Outline, CameraOverlayPass::Particles]);
+        // the debug overlays are drawn over the particles
+        if shows_shadow_box {
+            passes.push(CameraOverlayPass::ShadowBox);
+        }
+        if has_debug_vector_lines {
+            passes.push(CameraOverlayPass::DebugVectorLines);
+        }
+        passes
+    }
+
+    // the camera fbo is bound and stays bound afterwards
+    fn render_particles(&mut self, camera: &Camera, framebuffers: &mut FboMap, particle_master: &mut ParticleMaster) {
+        if self.particle_projection_outdated {
+            particle_master.update_projection_matrix(&self.projection_matrix);
+            self.particle_projection_outdated = false;
+        }
+        let mut scene_depth = ParticleSceneDepth::depth_buffer(self.depth_mode);
+        scene_depth.depth_test = self.particle_depth_test;
+        if self.particle_depth_test == ParticleDepthTest::ResolvedSceneDepth {
+            scene_depth.depth_texture = self.resolve_scene_depth(framebuffers);
+        }
+        particle_master.render(camera, &scene_depth);
+    }
+
+    // copies the depth of the camera fbo, which has everything that writes depth by now, into a texture the particle shader can read
+    fn resolve_scene_depth(&self, framebuffers: &mut FboMap) -> Option<u32> {
+        let scene_depth_fbo = framebuffers.fbos.get(FboMap::SCENE_DEPTH_FBO)?;
+        let camera_fbo = framebuffers.fbos.get(FboMap::CAMERA_TEXTURE_FBO_MULTI).expect("Must have a camera fbo to resolve its depth");
+        camera_fbo.blit_depth_to(scene_depth_fbo);
+        let depth_texture = scene_depth_fbo.depth_texture;
+        self.bind_camera_fbo(framebuffers);
+        depth_texture
+    }
+
+    fn select_lods(&mut self, entities: &[Entity], camera: &Camera) -> Vec<LodSelection> {
+        let settled_levels = self.settled_lod_levels.entry(self.viewport).or_insert_with(Vec::new);
+        MasterRenderer::select_entity_lods(entities, &camera.position, self.lod_fade_band, self.lod_hysteresis, settled_levels)
+    }
+
+    // the settled levels are only moved here so the hysteresis of a view isn't undone by the other passes or views
+    // they are kept by index so they start over for the entities after one that is added or removed
+    fn select_entity_lods(entities: &[Entity], camera_pos: &Vector3f, lod_fade_band: f32, lod_hysteresis: f32, settled_levels: &mut Vec<usize>) -> Vec<LodSelection> {
+        settled_levels.resize(entities.len(), 0);
+        entities.iter().zip(settled_levels.iter_mut()).map(|(entity, settled_level)| {
+            let selection = entity.select_lod(distance(camera_pos, &entity.position), lod_fade_band, lod_hysteresis, *settled_level);
+            *settled_level = selection.settled_level(*settled_level);
+            selection
+        }).collect()
+    }
+
+    // the entities the camera pass drew with the model of the vao, a lod level replaces the entity model and normal mapped entities have none
+    // an entity crossing a switch distance counts for the level it fades out of
+    fn entities_drawn_with<'e>(vao_id: u32, entities: &'e [Entity], lods: &[LodSelection], normal_mapped_entities: &'e [Entity]) -> Vec<&'e Entity> {
+        entities.iter().zip(lods).filter(|(entity, lod)| entity.visible && entity.lod_models(**lod).0.raw_model.vao_id == vao_id).map(|(entity, _)| entity)
+            .chain(normal_mapped_entities.iter().filter(|entity| entity.visible && entity.model.raw_model.vao_id == vao_id))
+            .collect()
+    }
+
+    // the box is drawn as it was used for the shadow map of this frame
+    fn render_shadow_box(&mut self, debug_entity: &mut DebugEntity, camera: &Camera) {
+        if debug_entity.model.raw_model.vertex_count == 0 {
+            return;
+        }
+        let shadow_box = &self.shadowmap_renderer.shadow_box;
+        MasterRenderer::track_shadow_box(debug_entity, shadow_box);
+        self.debug_renderer.render(debug_entity, camera, &shadow_box.corners_around_center());
+    }
+
+    // the corners are relative to the center and already have the box size so the entity isn't scaled
+    fn track_shadow_box(debug_entity: &mut DebugEntity, shadow_box: &ShadowBox) {
+        debug_entity.position = shadow_box.world_space_center.clone();
+        debug_entity.rotation = Vector3f::zero();
+        debug_entity.scale = Vector3f::new(1.0, 1.0, 1.0);
     }
 
     fn do_shadowmap_render_passes(&mut self, camera: &mut Camera, framebuffers: &mut FboMap, entities: &Vec<Entity>, 
                 normal_mapped_entities: &Vec<Entity>, player: &Player, lights: &Vec<Light>, terrains: &Vec<Terrain>) {
         
+        // without a sun nothing casts shadows and the shaders only get their minimum ambient light
+        let sun = match lights.first() {
+            Some(sun) => sun,
+            None => return,
+        };
+        if !self.shadowmap_renderer.needs_rerender(camera, sun) {
+            return;
+        }
+
         gl::helper::push_debug_group(RenderGroup::SHADOW_MAP_PASS.id, RenderGroup::SHADOW_MAP_PASS.name);
+        // the shadow map uses an ortho projection and is sampled with a [0, 1] bias so it always stays with standard depth
+        // the following render passes switch back to our depth mode in prepare
+        DepthMode::Standard.apply();
 
+        let shadowmap_fbo = framebuffers.fbos.get(FboMap::SHADOW_MAP_FBO).expect("Must have shadowmap fbo to render shadowmaps");
+        if let Some(shadow_map_size) = self.shadowmap_renderer.shadow_params.shadow_map_rebuild_size(shadowmap_fbo.viewport_width, shadowmap_fbo.viewport_height) {
+            framebuffers.replace(FboMap::SHADOW_MAP_FBO, || FboMap::new_shadow_map_fbo(shadow_map_size));
+        }
         let shadowmap_fbo = framebuffers.fbos.get_mut(FboMap::SHADOW_MAP_FBO).expect("Must have shadowmap fbo to render shadowmaps");
         shadowmap_fbo.bind();
-        self.shadowmap_renderer.start_render(camera, &lights[0]);
+        self.shadowmap_renderer.start_render(camera, sun);
         self.shadowmap_renderer.shadow_params.shadow_map_texture = shadowmap_fbo.depth_texture.expect("A shadowmup must have a depth texture or crash");
+        self.shadowmap_renderer.shadow_params.shadow_map_sampler = shadowmap_fbo.shadow_sampler.expect("A shadowmap fbo creates a sampler for its depth texture");
 
         // render into the shadowmap depth buffer all the entities that we want to cast shadows
-        let entity_by_tex = MasterRenderer::group_entities_by_tex(entities);
+        let casters = self.shadowmap_renderer.shadow_casters(entities, camera);
+        self.stats.record_shadow_caster_draws(entities, &casters);
+        let entity_by_tex = MasterRenderer::group_entities_by_tex(casters);
         for (tex_model, entity_group) in entity_by_tex {
             self.shadowmap_renderer.prepare_textured_model(tex_model);
             self.shadowmap_renderer.render(&entity_group);
             self.shadowmap_renderer.cleanup_textured_model();
         }
 
-        let norm_entity_by_tex = MasterRenderer::group_entities_by_tex(normal_mapped_entities);
+        let norm_casters = self.shadowmap_renderer.shadow_casters(normal_mapped_entities, camera);
+        self.stats.record_shadow_caster_draws(normal_mapped_entities, &norm_casters);
+        let norm_entity_by_tex = MasterRenderer::group_entities_by_tex(norm_casters);
         for (tex_model, entity_group) in norm_entity_by_tex {
             self.shadowmap_renderer.prepare_textured_model(tex_model);
             self.shadowmap_renderer.render(&entity_group);
@@ -143,75 +706,251 @@ impl MasterRenderer {
             self.shadowmap_renderer.prepare_textured_model(&entity.model);
             self.shadowmap_renderer.render_entity(entity);
             self.shadowmap_renderer.cleanup_textured_model();
+            self.stats.record_draw(entity.model.raw_model.vertex_count);
         }
 
         self.shadowmap_renderer.render_terrain(terrains);
+        for terrain in terrains.iter() {
+            self.stats.record_draw(terrain.model.raw_model.vertex_count);
+        }
 
         self.shadowmap_renderer.stop_render();
 
         gl::helper::pop_debug_group();
     }
 
-    fn do_water_render_passes(&mut self, water_tiles: &Vec<WaterTile>, camera: &mut Camera, framebuffers: &mut FboMap,
-                entities: &Vec<Entity>, normal_mapped_entities: &Vec<Entity>, terrains: &Vec<Terrain>, player: &Player, lights: &Vec<Light>,
-                skybox: &Skybox, display: &Display) {
+    fn bind_camera_fbo(&self, framebuffers: &mut FboMap) {
+        let camera_tex_fbo = framebuffers.fbos.get_mut(FboMap::CAMERA_TEXTURE_FBO_MULTI).expect("Must have a camera output fbo to which to render the scene for post processing");
+        camera_tex_fbo.bind();
+        self.apply_pass_scissor(ScenePass::Camera);
+    }
 
-        if water_tiles.is_empty() {
-            return;
+    fn apply_pass_scissor(&self, pass: ScenePass) {
+        match MasterRenderer::pass_scissor(pass, self.viewport) {
+            Some(viewport) => viewport.apply(),
+            None => Viewport::reset_scissor(),
         }
+    }
+
+    fn do_water_render_passes(&mut self, water_group: &WaterHeightGroup, camera: &mut Camera, framebuffers: &mut FboMap,
+                entities: &Vec<Entity>, lods: &[LodSelection], normal_mapped_entities: &Vec<Entity>, terrains: &Vec<Terrain>, player: &Player, lights: &Vec<Light>,
+                skybox: &Skybox, display: &Display, particle_master: &mut ParticleMaster) {
 
         gl::helper::push_debug_group(RenderGroup::REFLECT_REFRACT_PASS.id, RenderGroup::REFLECT_REFRACT_PASS.name);
         // enable clip plane                    
         gl::enable(gl::CLIP_DISTANCE0);
 
-        let water_height = WaterTile::get_water_height(water_tiles);
-        let tiny_overlap = 0.07; // to prevent glitches near the edge of the water
-        let above_water_clip_plane = Vector4f::new(0.0, -1.0, 0.0, water_height + tiny_overlap);
-        let below_water_clip_plane = Vector4f::new(0.0, 1.0, 0.0, -water_height + tiny_overlap);        
+        let (above_water_clip_plane, below_water_clip_plane) = water_group.clip_planes();
+        let reflection_plane_height = water_group.reflection_plane_height();
         
-        camera.set_to_reflected_ray_camera_origin(water_height);
-        let reflection_fbo = framebuffers.fbos.get_mut(FboMap::REFLECTION_FBO).expect("Must have reflection fbo for water render");
+        camera.set_to_reflected_ray_camera_origin(reflection_plane_height);
+        let reflection_fbo = framebuffers.fbos.get_mut(WaterPass::Reflection.fbo_name()).expect("Must have reflection fbo for water render");
         reflection_fbo.bind();
-        self.render_pass(lights, camera, entities, normal_mapped_entities, terrains, player, skybox, &display.wall_clock, &below_water_clip_plane);
-        camera.set_to_reflected_ray_camera_origin(water_height);
+        self.apply_pass_scissor(ScenePass::Water(WaterPass::Reflection));
+        let reflection_colors = MasterRenderer::scene_pass_colors(ScenePass::Water(WaterPass::Reflection), &self.sky_colors, self.background, &self.water_clear_colors);
+        let mut reflection_projection = MasterRenderer::pass_projection(&self.projection_matrix, self.depth_mode, self.reflection_far_distance);
+        let oblique_view_plane = self.reflection_clip_mode.oblique_view_plane(&Matrix4f::create_view_matrix(camera), &below_water_clip_plane);
+        if let Some(view_clip_plane) = &oblique_view_plane {
+            let projection = reflection_projection.unwrap_or_else(|| self.projection_matrix.clone());
+            reflection_projection = Some(self.depth_mode.oblique_clip(&projection, view_clip_plane));
+            gl::disable(gl::CLIP_DISTANCE0);
+        }
+        if let Some(reflection_projection) = &reflection_projection {
+            self.load_pass_projection(reflection_projection);
+        }
+        self.render_pass(lights, camera, entities, lods, normal_mapped_entities, terrains, player, skybox, &display.wall_clock, &below_water_clip_plane, ScenePass::Water(WaterPass::Reflection), &reflection_colors);
+        if self.reflects_particles {
+            // the camera pass loads the camera projection back into the particle renderer
+            particle_master.update_projection_matrix(reflection_projection.as_ref().unwrap_or(&self.projection_matrix));
+            self.particle_projection_outdated = true;
+            particle_master.render_clipped(camera, &below_water_clip_plane, &ParticleSceneDepth::depth_buffer(self.depth_mode));
+        }
+        if reflection_projection.is_some() {
+            let camera_projection = self.projection_matrix.clone();
+            self.load_pass_projection(&camera_projection);
+        }
+        if oblique_view_plane.is_some() {
+            gl::enable(gl::CLIP_DISTANCE0);
+        }
+        camera.set_to_reflected_ray_camera_origin(reflection_plane_height);
 
+        // the refraction keeps the camera far plane because the water shader compares its depth with the depth of the water surface
         // we should also move camera before refraction to account for refracted angle?
-        let refraction_fbo = framebuffers.fbos.get_mut(FboMap::REFRACTION_FBO).expect("Must have refraction fbo for water render");
+        let refraction_fbo = framebuffers.fbos.get_mut(WaterPass::Refraction.fbo_name()).expect("Must have refraction fbo for water render");
         refraction_fbo.bind();
-        self.render_pass(lights, camera, entities, normal_mapped_entities, terrains, player, skybox, &display.wall_clock, &above_water_clip_plane);
+        self.apply_pass_scissor(ScenePass::Water(WaterPass::Refraction));
+        let refraction_colors = MasterRenderer::scene_pass_colors(ScenePass::Water(WaterPass::Refraction), &self.sky_colors, self.background, &self.water_clear_colors);
+        match MasterRenderer::plan_refraction_pass(self.refraction_mode, entities, normal_mapped_entities, player) {
+            RefractionPassPlan::Shaded => {
+                self.render_pass(lights, camera, entities, lods, normal_mapped_entities, terrains, player, skybox, &display.wall_clock, &above_water_clip_plane, ScenePass::Water(WaterPass::Refraction), &refraction_colors);
+            },
+            RefractionPassPlan::DepthOnly(entities_by_tex) => {
+                self.render_depth_only_pass(camera, &entities_by_tex, terrains, &above_water_clip_plane, &refraction_colors.clear);
+            },
+        }
 
         gl::disable(gl::CLIP_DISTANCE0); // apparently this doesnt work on all drivers?   
 
         gl::helper::pop_debug_group();     
     }
 
-    fn render_pass(&mut self, lights: &Vec<Light>, camera: &Camera, entities: &Vec<Entity>, normal_mapped_entities: &Vec<Entity>, terrains: &Vec<Terrain>, 
-                player: &Player, skybox: &Skybox, wall_clock: &WallClock, clip_plane: &Vector4f) {
+    // draws the model on its own into a new offscreen texture e.g. for inventory icons or editor previews
+    pub fn render_thumbnail(&mut self, model: &TexturedModel, params: &ThumbnailParams, display: &Display) -> Thumbnail {
+        gl::helper::push_debug_group(RenderGroup::THUMBNAIL_PASS.id, RenderGroup::THUMBNAIL_PASS.name);
+        let mut thumbnail = Thumbnail::new(params.resolution);
+        let camera = Thumbnail::camera(model, Display::FOV_HORIZONTAL, params);
+        let entity = Entity::new(model.clone(), Vector3f::zero(), Vector3f::zero(), 1.0);
+        // fixed light from above the camera so all icons are lit the same
+        let lights = vec![Light::new_directional(Vector3f::new(0.5, 1.0, 0.8), Color::WHITE)];
+        // the model is not in the shadow map, a shadow distance of 0 fades out all shadows
+        let mut shadow_params = self.shadowmap_renderer.shadow_params.clone();
+        shadow_params.shadow_distance = 0.0;
+        let no_clip_plane = Vector4f::new(0.0, -1.0, 0.0, 10_000.0);
 
-        gl::helper::push_debug_group(RenderGroup::DRAW_ENTITIES.id, RenderGroup::DRAW_ENTITIES.name);
-        self.prepare();
+        // the icon is square so it gets its own projection
+        self.entity_renderer.update_projection_matrix(&self.depth_mode.projection_matrix(1.0));
+        thumbnail.fbo().bind();
+        self.prepare(&params.background);
+        self.entity_renderer.start_render(&lights, &camera, &params.background, &self.shadowmap_renderer.get_to_shadow(), &shadow_params);
+        self.entity_renderer.prepare_textured_model(&entity.model, &no_clip_plane);
+        self.entity_renderer.render(&entity);
+        self.entity_renderer.unprepare_textured_model(&entity.model);
+        self.entity_renderer.stop_render();
+        self.entity_renderer.update_projection_matrix(&self.projection_matrix);
 
-        // render entites
-        self.entity_renderer.start_render(lights, camera, &MasterRenderer::SKY_COLOR, &self.shadowmap_renderer.get_to_shadow(), &self.shadowmap_renderer.shadow_params);
-        let groups_by_tex = MasterRenderer::group_entities_by_tex(entities);
-        for (textured_model, entity_vec) in groups_by_tex.iter() {
-            self.entity_renderer.prepare_textured_model(textured_model, clip_plane);
-            for entity in entity_vec {
-                // load transform matrix into shader
-                self.entity_renderer.render(entity);
+        display.restore_default_framebuffer();
+        gl::helper::pop_debug_group();
+        thumbnail
+    }
+
+    // cheap extra pass which only draws the entities on the layer, depth tested against the camera depth so the scene still hides them
+    // the entities were already counted in the camera pass so this pass is left out of the stats
+    fn do_render_layer_pass(&mut self, layer: RenderLayers, lights: &Vec<Light>, camera: &Camera, entities: &Vec<Entity>, lods: &[LodSelection], framebuffers: &mut FboMap, display: &Display) {
+        let (camera_fbo, layer_fbo) = match (framebuffers.fbos.get(FboMap::CAMERA_TEXTURE_FBO_MULTI), framebuffers.fbos.get(FboMap::RENDER_LAYER_FBO)) {
+            (Some(camera_fbo), Some(layer_fbo)) => (camera_fbo, layer_fbo),
+            _ => return,
+        };
+        gl::helper::push_debug_group(RenderGroup::DRAW_RENDER_LAYER.id, RenderGroup::DRAW_RENDER_LAYER.name);
+        camera_fbo.blit_depth_to(layer_fbo);
+        framebuffers.fbos.get_mut(FboMap::RENDER_LAYER_FBO).expect("Checked above").bind();
+        // the layer is drawn into the same part of its fbo as the camera pass and only that part gets cleared
+        self.apply_pass_scissor(ScenePass::Layer);
+        // like prepare but the copied depth is kept and the entities pass where they are the closest surface
+        gl::helper::enable_backface_culling();
+        gl::enable(gl::DEPTH_TEST);
+        self.depth_mode.apply();
+        gl::depth_func(self.depth_mode.depth_func_or_equal());
+        let colors = MasterRenderer::scene_pass_colors(ScenePass::Layer, &self.sky_colors, self.background, &self.water_clear_colors);
+        let Color { r, g, b, a } = colors.clear;
+        gl::clear_color(r, g, b, a);
+        gl::clear(gl::COLOR_BUFFER_BIT);
+
+        let no_clip_plane = Vector4f::new(0.0, -1.0, 0.0, 10_000.0);
+        self.entity_renderer.start_render(lights, camera, &colors.fog, &self.shadowmap_renderer.get_to_shadow(), &self.shadowmap_renderer.shadow_params);
+        for (textured_model, entity_vec) in MasterRenderer::layer_entity_groups(entities, lods, layer).iter() {
+            self.entity_renderer.prepare_textured_model(textured_model, &no_clip_plane);
+            for &(entity, fade) in entity_vec.iter() {
+                self.entity_renderer.render_lod(entity, textured_model, fade);
             }
             self.entity_renderer.unprepare_textured_model(textured_model);
-        }        
+        }
+        self.entity_renderer.stop_render();
+        gl::depth_func(self.depth_mode.depth_func());
+        if self.viewport.is_some() {
+            Viewport::reset_scissor();
+        }
+
+        display.restore_default_framebuffer();
+        gl::helper::pop_debug_group();
+    }
+
+    // the color buffer only gets cleared, everything else just writes depth
+    // the fog stays the same in every pass, only the clear color depends on the pass
+    // only the camera fbo is composited over other layers, the water still reflects and refracts the sky behind a transparent background
+    // the layer pass draws nothing but the entities on the layer
+    fn pass_draws_skybox(pass: ScenePass, background: SceneBackground) -> bool {
+        match pass {
+            ScenePass::Camera => background.draws_skybox(),
+            ScenePass::Water(_) => true,
+            ScenePass::Layer => false,
+        }
+    }
+
+    fn scene_pass_colors(pass: ScenePass, sky_colors: &SkyColors, background: SceneBackground, water_clear_colors: &WaterPassClearColors) -> ScenePassColors {
+        let clear = match pass {
+            ScenePass::Camera => sky_colors.camera_clear_color(background),
+            ScenePass::Water(water_pass) => water_clear_colors.clear_color(water_pass).clone(),
+            // only the layer entities are drawn, everything else stays transparent for compositing
+            ScenePass::Layer => SceneBackground::TRANSPARENT_CLEAR_COLOR,
+        };
+        ScenePassColors { clear, fog: sky_colors.fog.clone() }
+    }
+
+    // the camera and layer passes draw into their part of the screen, the water fbos are always drawn and cleared whole
+    // and the camera viewport is applied again when the water tiles are drawn into the camera fbo
+    fn pass_scissor(pass: ScenePass, viewport: Option<Viewport>) -> Option<Viewport> {
+        match pass {
+            ScenePass::Camera | ScenePass::Layer => viewport,
+            ScenePass::Water(_) => None,
+        }
+    }
+
+    // the depth only shader ignores normal maps so the normal mapped entities and the player share the groups of the other entities
+    fn plan_refraction_pass<'e>(refraction_mode: RefractionMode, entities: &'e [Entity], normal_mapped_entities: &'e [Entity], player: &'e Player) -> RefractionPassPlan<'e> {
+        if refraction_mode.uses_color_shaders() {
+            return RefractionPassPlan::Shaded;
+        }
+        let player_entity = match &player.entity {
+            player::PlayerEntityType::StaticModelEntity(entity) if !player.is_invisible_immovable => Some(entity),
+            _ => None,
+        };
+        RefractionPassPlan::DepthOnly(MasterRenderer::group_entities_by_tex(entities.iter().chain(normal_mapped_entities.iter()).chain(player_entity)))
+    }
+
+    fn render_depth_only_pass(&mut self, camera: &Camera, entities_by_tex: &HashMap<&TexturedModel, Vec<&Entity>>, terrains: &Vec<Terrain>, clip_plane: &Vector4f, clear_color: &Color) {
+        gl::helper::push_debug_group(RenderGroup::DEPTH_ONLY_PASS.id, RenderGroup::DEPTH_ONLY_PASS.name);
+        self.prepare(clear_color);
+
+        self.depth_only_renderer.start_render(camera, clip_plane);
+        self.depth_only_renderer.render_entities(entities_by_tex);
+        self.depth_only_renderer.render_terrain(terrains);
+        self.depth_only_renderer.stop_render();
+
+        gl::helper::pop_debug_group();
+    }
+
+    // the entities on the layer with the lod models the camera pass drew them with
+    fn layer_entity_groups<'b>(entities: &'b [Entity], lods: &[LodSelection], layer: RenderLayers) -> HashMap<&'b TexturedModel, Vec<(&'b Entity, LodFade)>> {
+        let mut groups_by_tex = MasterRenderer::group_entities_by_lod_model(entities, lods);
+        for entity_vec in groups_by_tex.values_mut() {
+            entity_vec.retain(|(entity, _)| entity.render_layers.intersects(layer));
+        }
+        groups_by_tex.retain(|_, entity_vec| !entity_vec.is_empty());
+        groups_by_tex
+    }
+
+    fn render_pass(&mut self, lights: &Vec<Light>, camera: &Camera, entities: &Vec<Entity>, lods: &[LodSelection], normal_mapped_entities: &Vec<Entity>, terrains: &Vec<Terrain>, 
+                player: &Player, skybox: &Skybox, wall_clock: &WallClock, clip_plane: &Vector4f, pass: ScenePass, colors: &ScenePassColors) {
+
+        gl::helper::push_debug_group(RenderGroup::DRAW_ENTITIES.id, RenderGroup::DRAW_ENTITIES.name);
+        self.prepare(&colors.clear);
+
+        // render opaque entites
+        self.entity_renderer.start_render(lights, camera, &colors.fog, &self.shadowmap_renderer.get_to_shadow(), &self.shadowmap_renderer.shadow_params);
+        self.render_entity_groups(entities, lods, clip_plane, false);
         // render player
         if !player.is_invisible_immovable {
             match &player.entity {
                 player::PlayerEntityType::StaticModelEntity(entity) => {
                     self.entity_renderer.prepare_textured_model(&entity.model, clip_plane); 
                     self.entity_renderer.render(entity);
+                    self.stats.record_entity_draw(entity);
                     self.entity_renderer.unprepare_textured_model(&entity.model);
                 },
                 player::PlayerEntityType::AnimatedModelEntity(entity) => {
                     self.animated_entity_renderer.render(entity, camera);
+                    self.stats.entities_drawn += 1;
+                    self.stats.record_draw(entity.model.raw_model.vertex_count);
                 },
             }
         }
@@ -221,13 +960,14 @@ impl MasterRenderer {
 
         gl::helper::push_debug_group(RenderGroup::DRAW_NORMAL_MAP_ENTITIES.id, RenderGroup::DRAW_NORMAL_MAP_ENTITIES.name);
         // render normal mapped entites
-        self.normal_map_entity_renderer.start_render(lights, camera, &MasterRenderer::SKY_COLOR);
+        self.normal_map_entity_renderer.start_render(lights, camera, &colors.fog);
         let groups_by_tex = MasterRenderer::group_entities_by_tex(normal_mapped_entities);
         for (textured_model, entity_vec) in groups_by_tex.iter() {
             self.normal_map_entity_renderer.prepare_textured_model(textured_model, clip_plane);
             for entity in entity_vec {
                 // load transform matrix into shader
                 self.normal_map_entity_renderer.render(entity);
+                self.stats.record_entity_draw(entity);
             }
             self.normal_map_entity_renderer.unprepare_textured_model(textured_model);
         }
@@ -236,36 +976,288 @@ impl MasterRenderer {
 
         // render terrain
         gl::helper::push_debug_group(RenderGroup::DRAW_TERRAIN.id, RenderGroup::DRAW_TERRAIN.name);
-        self.terrain_renderer.start_render(lights, camera, &MasterRenderer::SKY_COLOR, &self.shadowmap_renderer.get_to_shadow(), &self.shadowmap_renderer.shadow_params);
+        self.terrain_renderer.start_render(lights, camera, &colors.fog, &self.shadowmap_renderer.get_to_shadow(), &self.shadowmap_renderer.shadow_params);
         for terrain in terrains.iter() {
             self.terrain_renderer.prepare_terrain(terrain, clip_plane);
             self.terrain_renderer.render(terrain);
+            self.stats.record_draw(terrain.model.raw_model.vertex_count);
             self.terrain_renderer.unprepare_terrain();
         }
         self.terrain_renderer.stop_render();
         gl::helper::pop_debug_group();
 
-        gl::helper::push_debug_group(RenderGroup::DRAW_SKYBOX.id, RenderGroup::DRAW_SKYBOX.name);
-        self.skybox_renderer.render(camera, skybox, &MasterRenderer::SKY_COLOR, wall_clock, clip_plane);
+        // after all the opaque geometry so the far plane skybox only shades the uncovered pixels, but before the transparent entities that blend over it
+        if MasterRenderer::pass_draws_skybox(pass, self.background) {
+            gl::helper::push_debug_group(RenderGroup::DRAW_SKYBOX.id, RenderGroup::DRAW_SKYBOX.name);
+            // the first light is the sun
+            self.skybox_renderer.render(camera, skybox, &colors.fog, wall_clock, clip_plane, lights.first());
+            gl::helper::pop_debug_group();
+        }
+
+        gl::helper::push_debug_group(RenderGroup::DRAW_TRANSPARENT_ENTITIES.id, RenderGroup::DRAW_TRANSPARENT_ENTITIES.name);
+        self.entity_renderer.start_render(lights, camera, &colors.fog, &self.shadowmap_renderer.get_to_shadow(), &self.shadowmap_renderer.shadow_params);
+        self.render_entity_groups(entities, lods, clip_plane, true);
+        self.entity_renderer.stop_render();
         gl::helper::pop_debug_group();
     }
-    
-    fn prepare(&self) {
+
+    // draws either only the opaque or only the transparent entities, start_render has to be called before
+    fn render_entity_groups(&mut self, entities: &Vec<Entity>, lods: &[LodSelection], clip_plane: &Vector4f, transparent: bool) {
+        let groups_by_tex = MasterRenderer::group_entities_by_lod_model(entities, lods);
+        for (textured_model, entity_vec) in groups_by_tex.iter() {
+            if textured_model.is_transparent() != transparent {
+                continue;
+            }
+            self.entity_renderer.prepare_textured_model(textured_model, clip_plane);
+            // entities fading between lod levels need their own fade uniform so they can't be part of an instanced draw
+            let (steady, fading): (Vec<&(&Entity, LodFade)>, Vec<_>) = entity_vec.iter().partition(|(_, fade)| *fade == LodFade::None);
+            let steady_entities = match EntityDrawPath::for_group_size(steady.len(), self.instancing_threshold) {
+                EntityDrawPath::Instanced => {
+                    let entities: Vec<&Entity> = steady.iter().map(|(entity, _)| *entity).collect();
+                    let draw_calls = self.entity_renderer.render_instanced(&entities, textured_model);
+                    self.stats.record_instanced_draw(textured_model.raw_model.vertex_count, entities.len(), draw_calls);
+                    Vec::new()
+                },
+                EntityDrawPath::PerEntity => steady,
+            };
+            for &(entity, fade) in steady_entities.into_iter().chain(fading) {
+                // load transform matrix into shader
+                self.entity_renderer.render_lod(entity, textured_model, fade);
+                // an entity crossing a switch distance is drawn with both levels but only counted once
+                if !fade.is_incoming() {
+                    self.stats.entities_drawn += 1;
+                }
+                self.stats.record_draw(textured_model.raw_model.vertex_count);
+            }
+            self.entity_renderer.unprepare_textured_model(textured_model);
+        }
+    }
+
+    fn prepare(&self, clear_color: &Color) {
         gl::helper::enable_backface_culling();
         gl::enable(gl::DEPTH_TEST);
-        let (Vector3f{x : r, y : g, z : b}, a) = (MasterRenderer::SKY_COLOR, 1.0);
+        self.depth_mode.apply();
+        let Color { r, g, b, a } = *clear_color;
         gl::clear_color(r, g, b, a);
         gl::clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
     }
 
-    fn group_entities_by_tex<'b>(entities: &'b Vec<Entity>) -> HashMap<&'b TexturedModel, Vec<&'b Entity>> {
+    // hidden entities are left out so none of the passes draw them
+    // entities crossing a lod switch distance end up in the groups of both levels
+    fn group_entities_by_lod_model<'b>(entities: &'b [Entity], lods: &[LodSelection]) -> HashMap<&'b TexturedModel, Vec<(&'b Entity, LodFade)>> {
         let mut groups_by_tex = HashMap::new();
 
-        for entity in entities.iter() {
+        for (entity, lod) in entities.iter().zip(lods).filter(|(entity, _)| entity.visible) {
+            match entity.lod_models(*lod) {
+                (model, None) => groups_by_tex.entry(model).or_insert(Vec::new()).push((entity, LodFade::None)),
+                (outgoing, Some((incoming, factor))) => {
+                    groups_by_tex.entry(outgoing).or_insert(Vec::new()).push((entity, LodFade::Out(factor)));
+                    groups_by_tex.entry(incoming).or_insert(Vec::new()).push((entity, LodFade::In(factor)));
+                },
+            }
+        }
+
+        groups_by_tex
+    }
+
+    fn group_entities_by_tex<'b>(entities: impl IntoIterator<Item = &'b Entity>) -> HashMap<&'b TexturedModel, Vec<&'b Entity>> {
+        let mut groups_by_tex = HashMap::new();
+
+        for entity in entities.into_iter().filter(|entity| entity.visible) {
             let group = groups_by_tex.entry(&entity.model).or_insert(Vec::new());
             group.push(entity);
         }
 
         groups_by_tex
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::RawModel;
+
+    #[test]
+    fn reflection_pass_projection_uses_overridden_far_distance() {
+        let projection = DepthMode::Standard.projection_matrix(16.0 / 9.0);
+        assert!(MasterRenderer::pass_projection(&projection, DepthMode::Standard, None).is_none());
+
+        let reflection = MasterRenderer::pass_projection(&projection, DepthMode::Standard, Some(200.0)).unwrap();
+        let far_clip = reflection.transform(&Vector4f::new(0.0, 0.0, -200.0, 1.0));
+        assert!((far_clip.z / far_clip.w - 1.0).abs() < 1e-4);
+        let near_clip = reflection.transform(&Vector4f::new(0.0, 0.0, Display::NEAR, 1.0));
+        assert!((near_clip.z / near_clip.w + 1.0).abs() < 1e-4);
+        // field of view and aspect stay the same
+        assert_eq!(reflection[0][0], projection[0][0]);
+        assert_eq!(reflection[1][1], projection[1][1]);
+    }
+
+    #[test]
+    fn layer_pass_only_draws_entities_on_the_layer() {
+        use crate::entities::LodLevel;
+        let model = |vao_id| TexturedModel { raw_model: RawModel::new(vao_id, 3), ..TexturedModel::default() };
+        let mut entities = Entity::spawn_many(&model(1), &[
+            (Vector3f::new(1.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(2.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(100.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+        ]);
+        entities[1].render_layers = RenderLayers::DEFAULT | RenderLayers::GLOW;
+        entities[2].render_layers = RenderLayers::GLOW;
+        entities[2].lod_levels = vec![LodLevel { model: model(2), switch_distance: 50.0 }];
+        let lods = MasterRenderer::select_entity_lods(&entities, &Vector3f::zero(), 0.0, 0.0, &mut Vec::new());
+
+        let glowing = MasterRenderer::layer_entity_groups(&entities, &lods, RenderLayers::GLOW);
+        let glowing_x = |vao_id| glowing.iter().filter(|(model, _)| model.raw_model.vao_id == vao_id)
+            .flat_map(|(_, entity_vec)| entity_vec.iter().map(|(entity, _)| entity.position.x)).collect::<Vec<f32>>();
+        assert_eq!(glowing_x(1), vec![2.0]);
+        // the far entity is drawn with the same lod level as in the camera pass
+        assert_eq!(glowing_x(2), vec![100.0]);
+        assert_eq!(MasterRenderer::layer_entity_groups(&entities, &lods, RenderLayers::DEFAULT).values().flatten().count(), 2);
+    }
+
+    #[test]
+    fn transparent_background_only_drops_the_sky_of_the_camera_pass() {
+        let sky_colors = SkyColors::default();
+        let water_clear_colors = WaterPassClearColors::with_sky_color(&sky_colors.sky);
+        let passes = [ScenePass::Camera, ScenePass::Water(WaterPass::Reflection), ScenePass::Water(WaterPass::Refraction), ScenePass::Layer];
+        let drawn_sky = |background| passes.iter().map(|&pass| {
+            (MasterRenderer::pass_draws_skybox(pass, background), MasterRenderer::scene_pass_colors(pass, &sky_colors, background, &water_clear_colors).clear.a)
+        }).collect::<Vec<(bool, f32)>>();
+
+        // the layer pass is always composited over the camera output
+        assert_eq!(drawn_sky(SceneBackground::Skybox), vec![(true, 1.0), (true, 1.0), (true, 1.0), (false, 0.0)]);
+        assert_eq!(drawn_sky(SceneBackground::Transparent), vec![(false, 0.0), (true, 1.0), (true, 1.0), (false, 0.0)]);
+    }
+
+    #[test]
+    fn hidden_entities_are_not_drawn_or_cast_shadows() {
+        let model = TexturedModel::default();
+        let mut entities = Entity::spawn_many(&model, &[
+            (Vector3f::new(1.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(2.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+        ]);
+        entities[1].visible = false;
+        let drawn_x = |groups: HashMap<&TexturedModel, Vec<&Entity>>| groups.values().flatten().map(|entity| entity.position.x).collect::<Vec<f32>>();
+
+        assert_eq!(drawn_x(MasterRenderer::group_entities_by_tex(&entities)), vec![1.0]);
+        let shadow_box = ShadowBox::new(1.5, 70.0, -0.1, -100.0);
+        assert_eq!(drawn_x(MasterRenderer::group_entities_by_tex(shadow_box.shadow_casters(&entities))), vec![1.0]);
+
+        entities[1].visible = true;
+        assert_eq!(MasterRenderer::group_entities_by_tex(&entities).values().flatten().count(), 2);
+    }
+
+    #[test]
+    fn fog_color_is_independent_of_the_clear_color() {
+        let sky = Color::rgb(0.2, 0.4, 0.9);
+        let fog = Color::rgb(0.5, 0.5, 0.5);
+        assert_eq!(SkyColors::new(&sky).fog, sky);
+
+        let sky_colors = SkyColors::new(&sky).with_fog_color(&fog);
+        let water_clear_colors = WaterPassClearColors::with_sky_color(&sky);
+        let colors = |pass, background| MasterRenderer::scene_pass_colors(pass, &sky_colors, background, &water_clear_colors);
+        // every shader of a pass is loaded with the fog color of the pass
+        assert_eq!(colors(ScenePass::Camera, SceneBackground::Skybox), ScenePassColors { clear: sky.clone(), fog: fog.clone() });
+        assert_eq!(colors(ScenePass::Camera, SceneBackground::Transparent), ScenePassColors { clear: SceneBackground::TRANSPARENT_CLEAR_COLOR, fog: fog.clone() });
+        assert_eq!(colors(ScenePass::Water(WaterPass::Reflection), SceneBackground::Skybox), ScenePassColors { clear: sky.clone(), fog: fog.clone() });
+        assert_eq!(colors(ScenePass::Water(WaterPass::Refraction), SceneBackground::Skybox),
+            ScenePassColors { clear: WaterPassClearColors::DEFAULT_REFRACTION_COLOR, fog: fog.clone() });
+        assert_eq!(colors(ScenePass::Layer, SceneBackground::Skybox), ScenePassColors { clear: SceneBackground::TRANSPARENT_CLEAR_COLOR, fog: fog.clone() });
+    }
+
+    #[test]
+    fn overlay_targets_follow_the_drawn_lod_level() {
+        use crate::entities::LodLevel;
+        let model = |vao_id| TexturedModel { raw_model: RawModel::new(vao_id, 3), ..TexturedModel::default() };
+        let mut near = Entity::new(model(1), Vector3f::zero(), Vector3f::zero(), 1.0);
+        near.lod_levels = vec![LodLevel { model: model(2), switch_distance: 50.0 }];
+        let mut far = near.clone();
+        far.position = Vector3f::new(100.0, 0.0, 0.0);
+        let entities = vec![near, far];
+        let normal_mapped_entities = vec![Entity::new(model(1), Vector3f::new(100.0, 0.0, 0.0), Vector3f::zero(), 1.0)];
+
+        let lods = MasterRenderer::select_entity_lods(&entities, &Vector3f::zero(), 0.0, 0.0, &mut Vec::new());
+        let drawn_with = |vao_id| MasterRenderer::entities_drawn_with(vao_id, &entities, &lods, &normal_mapped_entities)
+            .iter().map(|entity| entity.position.x).collect::<Vec<f32>>();
+        assert_eq!(drawn_with(1), vec![0.0, 100.0]);
+        assert_eq!(drawn_with(2), vec![100.0]);
+    }
+
+    #[test]
+    fn depth_only_refraction_writes_the_depth_of_every_visible_entity() {
+        let model = TexturedModel::default();
+        let normal_mapped_model = TexturedModel { raw_model: RawModel::new(2, 6), ..TexturedModel::default() };
+        let mut entities = Entity::spawn_many(&model, &[
+            (Vector3f::new(1.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(2.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+        ]);
+        entities[1].visible = false;
+        let normal_mapped_entities = Entity::spawn_many(&normal_mapped_model, &[(Vector3f::new(3.0, 0.0, 0.0), Vector3f::zero(), 1.0)]);
+        let mut player = Player::new(Entity::new(model.clone(), Vector3f::new(4.0, 0.0, 0.0), Vector3f::zero(), 1.0));
+        let depth_written_x = |player: &Player| match MasterRenderer::plan_refraction_pass(RefractionMode::DepthOnly, &entities, &normal_mapped_entities, player) {
+            RefractionPassPlan::DepthOnly(groups) => {
+                let mut xs: Vec<f32> = groups.values().flatten().map(|entity| entity.position.x).collect();
+                xs.sort_by(|a, b| a.partial_cmp(b).unwrap());
+                xs
+            },
+            RefractionPassPlan::Shaded => panic!("The depth only refraction must not use the color shaders"),
+        };
+
+        assert_eq!(depth_written_x(&player), vec![1.0, 3.0, 4.0]);
+        player.is_invisible_immovable = true;
+        assert_eq!(depth_written_x(&player), vec![1.0, 3.0]);
+        match MasterRenderer::plan_refraction_pass(RefractionMode::Full, &entities, &normal_mapped_entities, &player) {
+            RefractionPassPlan::Shaded => (),
+            RefractionPassPlan::DepthOnly(_) => panic!("The full refraction shades the scene"),
+        }
+    }
+
+    #[test]
+    fn water_passes_are_not_scissored_to_the_viewport() {
+        let (left, _) = Viewport::full(1280, 720).split_side_by_side();
+        assert_eq!(MasterRenderer::pass_scissor(ScenePass::Camera, Some(left)), Some(left));
+        assert_eq!(MasterRenderer::pass_scissor(ScenePass::Camera, None), None);
+        for &water_pass in [WaterPass::Reflection, WaterPass::Refraction].iter() {
+            assert_eq!(MasterRenderer::pass_scissor(ScenePass::Water(water_pass), Some(left)), None);
+        }
+        // the layer is composited over the camera image so it covers the same part of the screen
+        assert_eq!(MasterRenderer::pass_scissor(ScenePass::Layer, Some(left)), Some(left));
+    }
+
+    #[test]
+    fn lod_hysteresis_is_kept_per_view() {
+        use crate::entities::LodLevel;
+        let model = |vao_id| TexturedModel { raw_model: RawModel::new(vao_id, 3), ..TexturedModel::default() };
+        let mut entity = Entity::new(model(1), Vector3f::zero(), Vector3f::zero(), 1.0);
+        entity.lod_levels = vec![LodLevel { model: model(2), switch_distance: 50.0 }];
+        let entities = vec![entity];
+        let drawn_level = |camera_x, settled_levels: &mut Vec<usize>| {
+            MasterRenderer::select_entity_lods(&entities, &Vector3f::new(camera_x, 0.0, 0.0), 0.0, 4.0, settled_levels)[0].level
+        };
+        let mut left_view = Vec::new();
+        let mut right_view = Vec::new();
+
+        assert_eq!(drawn_level(52.0, &mut left_view), 1);
+        // the other view is closer but doesn't move the level the left view settled on
+        assert_eq!(drawn_level(48.5, &mut right_view), 0);
+        assert_eq!(drawn_level(49.0, &mut left_view), 1);
+        assert_eq!(drawn_level(47.9, &mut left_view), 0);
+        assert_eq!(drawn_level(51.0, &mut left_view), 0);
+    }
+
+    #[test]
+    fn particles_are_drawn_after_the_water_and_before_the_debug_overlays() {
+        for &(has_wireframe_overlay, shows_shadow_box, has_debug_vector_lines) in [(false, false, false), (true, true, true), (false, true, false), (true, false, true)].iter() {
+            let passes = MasterRenderer::camera_overlay_passes(has_wireframe_overlay, shows_shadow_box, has_debug_vector_lines);
+            let index = |pass: CameraOverlayPass| passes.iter().position(|&p| p == pass);
+            let particles = index(CameraOverlayPass::Particles).expect("Particles are always drawn");
+
+            for &depth_writing_pass in [CameraOverlayPass::Water, CameraOverlayPass::EnvMappedEntities, CameraOverlayPass::Outline].iter() {
+                assert!(index(depth_writing_pass).unwrap() < particles, "{:?}", depth_writing_pass);
+            }
+            assert_eq!(index(CameraOverlayPass::WireframeEdges).map(|edges| edges < particles), Some(true).filter(|_| has_wireframe_overlay));
+            assert_eq!(index(CameraOverlayPass::ShadowBox).map(|shadow_box| shadow_box > particles), Some(true).filter(|_| shows_shadow_box));
+            assert_eq!(index(CameraOverlayPass::DebugVectorLines).map(|lines| lines > particles), Some(true).filter(|_| has_debug_vector_lines));
+        }
+    }
+}
@@ -10,6 +10,11 @@ pub mod shadowmap_renderer;
 pub mod debug_renderer;
 pub mod env_map_renderer;
 pub mod animated_entity_renderer;
+pub mod outline_renderer;
+pub mod depth_only_renderer;
+pub mod attrib_array_scope;
+pub mod render_stats;
+pub mod thumbnail;
 
 pub mod particle_renderer;
 pub mod particle_renderer_gpu_instanced;
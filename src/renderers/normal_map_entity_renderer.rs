@@ -2,21 +2,27 @@ use crate::gl;
 use crate::entities::{
     Entity,
     Camera,
+    FogCutoff,
     Light,
 };
 use crate::shaders::NormalMapStaticShader;
 use crate::math::{
+    Color,
     Matrix4f,
-    Vector3f,
     Vector4f,
 };
 use crate::models::{
     TexturedModel,
     RawModel,
 };
+use super::attrib_array_scope::AttribArrayScope;
 
 pub struct NormalMapEntityRenderer {
     shader: NormalMapStaticShader,
+    // reused for every entity instead of creating a new matrix
+    transform_mat: Matrix4f,
+    // enabled between prepare and unprepare so they are always disabled again on the same vao
+    attrib_arrays: Option<AttribArrayScope>,
 }
 
 impl NormalMapEntityRenderer {    
@@ -29,10 +35,24 @@ impl NormalMapEntityRenderer {
         shader.stop();
         NormalMapEntityRenderer {
             shader,
+            transform_mat: Matrix4f::identity(),
+            attrib_arrays: None,
         }
     }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.shader.start();
+        self.shader.load_projection_matrix(projection_matrix);
+        self.shader.stop();
+    }
+
+    pub fn update_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.shader.start();
+        self.shader.load_fog_cutoff(fog_cutoff);
+        self.shader.stop();
+    }
     
-    pub fn start_render(&mut self, lights: &Vec<Light>, camera: &Camera, sky_color: &Vector3f) {
+    pub fn start_render(&mut self, lights: &Vec<Light>, camera: &Camera, sky_color: &Color) {
         self.shader.start();
         self.shader.load_lights(lights);
         self.shader.load_view_matrix(camera);
@@ -48,15 +68,16 @@ impl NormalMapEntityRenderer {
             gl::helper::disable_culling();
         }
 
+        // a scope left over from the previous model has to be disabled while its own vao is still bound
+        drop(self.attrib_arrays.take());
         gl::bind_vertex_array(textured_model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TANGENT_ATTRIB);
+        self.attrib_arrays = Some(AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB, RawModel::NORMAL_ATTRIB, RawModel::TANGENT_ATTRIB]));
 
         self.shader.load_shine_variables(textured_model.texture.shine_damper, textured_model.texture.reflectivity);
         self.shader.load_uses_fake_lighting(textured_model.texture.uses_fake_lighting);
+        self.shader.load_normal_strength(textured_model.texture.normal_strength);
         self.shader.load_atlas_number_of_rows(textured_model.texture.number_of_rows_in_atlas);
+        self.shader.load_uv_transform(&textured_model.texture.uv_scale, &textured_model.texture.uv_offset);
 
         // clip plane for water 
         self.shader.load_clip_plane(clip_plane);
@@ -69,21 +90,18 @@ impl NormalMapEntityRenderer {
 
     pub fn render(&mut self, entity: &Entity) {
         // load transform matrix into shader
-        let transform_mat = Matrix4f::create_transform_matrix(&entity.position, &entity.rotation_deg, entity.scale);
-        self.shader.load_transformation_matrix(&transform_mat);
+        Matrix4f::create_transform_matrix_into(&mut self.transform_mat, &entity.position, &entity.rotation_deg, entity.scale);
+        self.shader.load_transformation_matrix(&self.transform_mat);
         self.shader.load_atlas_offset(&entity.get_atlas_offset());
         
         gl::draw_elements(gl::TRIANGLES, entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);
     }
 
-    pub fn unprepare_textured_model(&self, textured_model: &TexturedModel) {
+    pub fn unprepare_textured_model(&mut self, textured_model: &TexturedModel) {
         if textured_model.texture.has_transparency {
             gl::helper::enable_backface_culling(); // restore backbace culling for next model
         }
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TANGENT_ATTRIB);
+        self.attrib_arrays = None;
 
         gl::bind_vertex_array(0);
         gl::bind_texture(gl::TEXTURE_2D, 0);
@@ -0,0 +1,162 @@
+use crate::gl;
+use crate::entities::{
+    Entity,
+    Camera,
+};
+use crate::math::{
+    Color,
+    Matrix4f,
+};
+use crate::models::RawModel;
+use crate::shaders::OutlineShader;
+use super::attrib_array_scope::AttribArrayScope;
+use super::entity_renderer::EntityRenderer;
+
+#[derive(Clone, Debug)]
+pub struct OutlineParams {
+    pub color: Color,
+    // width of the outline in world units
+    pub thickness: f32,
+}
+
+impl Default for OutlineParams {
+    fn default() -> Self {
+        OutlineParams {
+            color: Color::rgb(1.0, 0.6, 0.0),
+            thickness: 0.1,
+        }
+    }
+}
+
+pub struct OutlineRenderer {
+    shader: OutlineShader,
+    proj_mat: Matrix4f,
+    transform_mat: Matrix4f,
+}
+
+impl OutlineRenderer {
+
+    pub fn new(projection_matrix: &Matrix4f) -> Self {
+        OutlineRenderer {
+            shader: OutlineShader::new(),
+            proj_mat: projection_matrix.clone(),
+            transform_mat: Matrix4f::identity(),
+        }
+    }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.proj_mat = projection_matrix.clone();
+    }
+
+    // expects the bound framebuffer to have a stencil attachment
+    // ids that are not valid indices into entities are ignored
+    pub fn render(&mut self, entities: &Vec<Entity>, entity_ids: &[usize], camera: &Camera, params: &OutlineParams) {
+        let selected = OutlineRenderer::selected_entities(entities, entity_ids);
+        if selected.is_empty() {
+            return;
+        }
+        self.shader.start();
+        self.shader.load_outline_color(&params.color);
+        let view_mat = Matrix4f::create_view_matrix(camera);
+        let vp_mat = &self.proj_mat * view_mat;
+
+        // the outline is drawn on top of everything so that selected entities remain visible behind other geometry
+        gl::disable(gl::DEPTH_TEST);
+        gl::enable(gl::STENCIL_TEST);
+        gl::stencil_mask(0xFF);
+        gl::clear(gl::STENCIL_BUFFER_BIT);
+
+        // first write 1 into the stencil wherever the selected entities are without touching the color buffer
+        gl::color_mask(false);
+        gl::stencil_func(gl::ALWAYS, 1, 0xFF);
+        gl::stencil_op(gl::KEEP, gl::KEEP, gl::REPLACE);
+        for entity in selected.iter() {
+            self.render_entity(entity, camera, &vp_mat, 0.0);
+        }
+
+        // then draw the grown silhouette only where the stencil wasn't set which leaves just the rim around the entity
+        gl::color_mask(true);
+        gl::stencil_func(gl::NOTEQUAL, 1, 0xFF);
+        gl::stencil_mask(0x00);
+        for entity in selected.iter() {
+            self.render_entity(entity, camera, &vp_mat, Self::silhouette_offset(params.thickness, entity.scale));
+        }
+
+        gl::stencil_mask(0xFF);
+        gl::disable(gl::STENCIL_TEST);
+        gl::enable(gl::DEPTH_TEST);
+        self.shader.stop();
+    }
+
+    // the silhouette is grown by pushing the vertices out along their normals before the entity transform scales them
+    // so the offset in model space is the thickness in world units divided by the entity scale
+    pub fn silhouette_offset(thickness: f32, entity_scale: f32) -> f32 {
+        thickness / entity_scale
+    }
+
+    // a hidden entity stays selected but gets no outline until it is shown again
+    fn selected_entities<'a>(entities: &'a [Entity], entity_ids: &[usize]) -> Vec<&'a Entity> {
+        entity_ids.iter()
+            .filter_map(|id| entities.get(*id))
+            .filter(|entity| entity.visible)
+            .collect()
+    }
+
+    fn render_entity(&mut self, entity: &Entity, camera: &Camera, vp_mat: &Matrix4f, outline_offset: f32) {
+        gl::bind_vertex_array(entity.model.raw_model.vao_id);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::NORMAL_ATTRIB]);
+
+        // billboards are outlined where the entity renderer turned them to the camera
+        EntityRenderer::entity_transform_into(&mut self.transform_mat, entity, camera.pitch, camera.yaw);
+        self.shader.load_mvp_matrix(&(vp_mat * &self.transform_mat));
+        self.shader.load_outline_offset(outline_offset);
+        gl::draw_elements(gl::TRIANGLES, entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);
+
+        drop(attrib_arrays);
+        gl::bind_vertex_array(0);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{
+        Vector3f,
+        Vector4f,
+    };
+
+    fn to_world(transform: &Matrix4f, model_pos: &Vector3f) -> Vector3f {
+        let world = transform.transform(&Vector4f::new(model_pos.x, model_pos.y, model_pos.z, 1.0));
+        Vector3f::new(world.x, world.y, world.z)
+    }
+
+    #[test]
+    fn silhouette_is_grown_by_thickness_in_world_units() {
+        let thickness = 0.25;
+        for &scale in [0.5, 1.0, 4.0].iter() {
+            let transform = Matrix4f::create_transform_matrix(&Vector3f::new(10.0, -3.0, 7.0), &Vector3f::new(30.0, 45.0, 0.0), scale);
+            let vertex = Vector3f::new(1.0, 2.0, -1.0);
+            let normal = Vector3f::new(0.0, 0.6, 0.8);
+            let offset = OutlineRenderer::silhouette_offset(thickness, scale);
+            let grown_vertex = Vector3f::new(vertex.x + normal.x * offset, vertex.y + normal.y * offset, vertex.z + normal.z * offset);
+
+            let world_dist = (to_world(&transform, &grown_vertex) - to_world(&transform, &vertex)).length();
+            assert!((world_dist - thickness).abs() < 1e-4, "scale {} grew silhouette by {}", scale, world_dist);
+        }
+    }
+
+    #[test]
+    fn hidden_and_unknown_entities_are_not_outlined() {
+        let model = crate::models::TexturedModel::default();
+        let mut entities = Entity::spawn_many(&model, &[
+            (Vector3f::new(0.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(1.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(2.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+        ]);
+        entities[1].visible = false;
+
+        let selected = OutlineRenderer::selected_entities(&entities, &[0, 1, 2, 7]);
+        let selected_x: Vec<f32> = selected.iter().map(|entity| entity.position.x).collect();
+        assert_eq!(selected_x, vec![0.0, 2.0]);
+    }
+}
@@ -1,17 +1,91 @@
 use std::collections::HashMap;
+use crate::display::DepthMode;
 use crate::entities::Camera;
+use crate::math::{
+    Matrix4f,
+    Vector4f,
+};
 use crate::models::{
     ParticleTexturedModel,
 };
 use crate::particles::Particle;
 
+// how particles are occluded by the scene
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ParticleDepthTest {
+    // test against the depth buffer which by then contains the opaque and the transparent geometry so foliage hides smoke behind it
+    SceneDepth,
+    // the depth of the camera fbo is resolved into a texture (FboMap::SCENE_DEPTH_FBO) which the particle shader tests against
+    // instead of the depth buffer, this is the depth soft particles fade against
+    ResolvedSceneDepth,
+    // particles are drawn over everything
+    Disabled,
+}
+
+impl Default for ParticleDepthTest {
+    fn default() -> Self {
+        ParticleDepthTest::SceneDepth
+    }
+}
+
+// what the particles of a pass are tested against
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ParticleSceneDepth {
+    pub depth_test: ParticleDepthTest,
+    // the resolved depth texture, only set for ParticleDepthTest::ResolvedSceneDepth
+    pub depth_texture: Option<u32>,
+    // reversed z has the near plane at 1 so the shader compares the other way around
+    pub depth_mode: DepthMode,
+}
+
+impl ParticleSceneDepth {
+    // e.g. for the water fbos which have their own depth buffer
+    pub fn depth_buffer(depth_mode: DepthMode) -> Self {
+        ParticleSceneDepth {
+            depth_test: ParticleDepthTest::SceneDepth,
+            depth_texture: None,
+            depth_mode,
+        }
+    }
+
+    // the hardware depth test is replaced by the shader when it gets the resolved depth
+    pub fn uses_depth_buffer(&self) -> bool {
+        self.depth_test == ParticleDepthTest::SceneDepth || (self.depth_test == ParticleDepthTest::ResolvedSceneDepth && self.depth_texture.is_none())
+    }
+}
+
 pub trait ParticleRenderer {
-    fn render(&mut self, particles: &HashMap<ParticleTexturedModel, Vec<Particle>>, camera: &Camera);
+    // particles completely on the negative side of the clip plane are skipped
+    fn render(&mut self, particles: &HashMap<ParticleTexturedModel, Vec<Particle>>, camera: &Camera, clip_plane: Option<&Vector4f>, scene_depth: &ParticleSceneDepth);
+    fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f);
+}
+
+pub fn is_rendered(particle: &Particle, clip_plane: Option<&Vector4f>) -> bool {
+    !particle.is_culled && !clip_plane.map_or(false, |clip_plane| particle.is_below_clip_plane(clip_plane))
 }
 
-pub fn update_vbo(vbo: u32, particle_data: &Vec<f32>) {        
+// orphan the stream vbo and then write only the part of it that we use this frame
+// we always orphan with the full capacity of the vbo so that the driver can hand us back a store of the same size
+// instead of reallocating it each frame because the particle count changed
+pub fn update_vbo(vbo: u32, vbo_float_capacity: usize, particle_data: &Vec<f32>) {
+    assert!(particle_data.len() <= vbo_float_capacity, "Particle data doesn't fit into the stream vbo");
     gl::bind_buffer(gl::ARRAY_BUFFER, vbo);
-    gl::buffer_data_unitialized::<f32>(gl::ARRAY_BUFFER, particle_data.len(), gl::STREAM_DRAW);
+    gl::buffer_data_unitialized::<f32>(gl::ARRAY_BUFFER, vbo_float_capacity, gl::STREAM_DRAW);
     gl::buffer_sub_data(gl::ARRAY_BUFFER, 0, particle_data);
     gl::bind_buffer(gl::ARRAY_BUFFER, 0);
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn resolved_depth_replaces_the_depth_buffer_test() {
+        let scene_depth = |depth_test, depth_texture| ParticleSceneDepth { depth_test, depth_texture, depth_mode: DepthMode::Standard };
+        assert!(scene_depth(ParticleDepthTest::SceneDepth, None).uses_depth_buffer());
+        assert!(!scene_depth(ParticleDepthTest::ResolvedSceneDepth, Some(3)).uses_depth_buffer());
+        // without the resolved texture the particles still get occluded
+        assert!(scene_depth(ParticleDepthTest::ResolvedSceneDepth, None).uses_depth_buffer());
+        assert!(!scene_depth(ParticleDepthTest::Disabled, None).uses_depth_buffer());
+    }
+}
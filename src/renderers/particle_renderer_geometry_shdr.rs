@@ -3,6 +3,7 @@ use crate::entities::Camera;
 use crate::gl;
 use crate::math::{
     Matrix4f,
+    Vector4f,
 };
 use crate::models::{
     RawModel,
@@ -14,8 +15,11 @@ use crate::shaders::particle_using_geometry_shader::ParticleUsingGeometryShader;
 use super::master_renderer::RenderGroup;
 use super::particle_renderer::{
     update_vbo,
+    is_rendered,
     ParticleRenderer,
+    ParticleSceneDepth,
 };
+use super::attrib_array_scope::AttribArrayScope;
 
 pub struct ParticleRendererGeometryShader {
     shader: ParticleUsingGeometryShader,
@@ -24,7 +28,8 @@ pub struct ParticleRendererGeometryShader {
 }
 
 impl ParticleRenderer for ParticleRendererGeometryShader {
-    fn render(&mut self, particles: &HashMap<ParticleTexturedModel, Vec<Particle>>, camera: &Camera) {
+    // its shader has no scene depth input so the points are always tested against the depth buffer
+    fn render(&mut self, particles: &HashMap<ParticleTexturedModel, Vec<Particle>>, camera: &Camera, clip_plane: Option<&Vector4f>, _scene_depth: &ParticleSceneDepth) {
         gl::helper::push_debug_group(RenderGroup::PARTICLE_EFFECTS_PASS.id, RenderGroup::PARTICLE_EFFECTS_PASS.name);
         self.prepare();
 
@@ -36,33 +41,42 @@ impl ParticleRenderer for ParticleRendererGeometryShader {
         let mut particle_num = 0;
         self.shader.start();
         for (model, particle_vec) in particles {
-            particle_num = particle_vec.len();
+            particle_num = 0;
             model_vao = model.model.raw_model.vao_id;                
-            for i in 0..particle_num {
-                self.particle_data.push(particle_vec[i].position.x);
-                self.particle_data.push(particle_vec[i].position.y);
-                self.particle_data.push(particle_vec[i].position.z);
+            for particle in particle_vec.iter().filter(|particle| is_rendered(particle, clip_plane)).take(ParticleModel::MAX_INSTANCES) {
+                self.particle_data.push(particle.position.x);
+                self.particle_data.push(particle.position.y);
+                self.particle_data.push(particle.position.z);
+                particle_num += 1;
             }
-            update_vbo(model.model.stream_draw_vbo, &self.particle_data);
+            update_vbo(model.model.stream_draw_vbo, ParticleModel::MAX_INSTANCES * 3, &self.particle_data);
         }
 
         let vp_matrix = &self.projection_matrix * Matrix4f::create_view_matrix(camera);
         self.shader.load_vp_matrix(&vp_matrix);
+        self.shader.load_clip_plane(clip_plane.unwrap_or(&ParticleRendererGeometryShader::NO_CLIP_PLANE));
 
         gl::bind_vertex_array(model_vao);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
         // drawing points
         gl::draw_arrays(gl::POINTS, 0, particle_num);
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        drop(attrib_arrays);
         gl::bind_vertex_array(0);
 
         self.shader.stop();
 
         gl::helper::pop_debug_group();
     }
+
+    fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.projection_matrix = projection_matrix.clone();
+    }
 }
 
 impl ParticleRendererGeometryShader {
+    // every point is on the positive side so nothing is clipped
+    const NO_CLIP_PLANE: Vector4f = Vector4f {x: 0.0, y: 0.0, z: 0.0, w: 1.0};
+
     pub fn new(projection_matrix: &Matrix4f) -> Self {
         let shader = ParticleUsingGeometryShader::new();        
         ParticleRendererGeometryShader {
@@ -3,6 +3,7 @@ use crate::entities::Camera;
 use crate::gl;
 use crate::math::{
     Matrix4f,
+    Vector4f,
 };
 use crate::models::{
     RawModel,
@@ -15,8 +16,11 @@ use crate::shaders::ParticleShader;
 use super::master_renderer::RenderGroup;
 use super::particle_renderer::{
     update_vbo,
+    is_rendered,
     ParticleRenderer,
+    ParticleSceneDepth,
 };
+use super::attrib_array_scope::AttribArrayScope;
 
 pub struct ParticleRendererGpuInstanced {
     shader: ParticleShader,
@@ -24,56 +28,62 @@ pub struct ParticleRendererGpuInstanced {
 }
 
 impl ParticleRenderer for ParticleRendererGpuInstanced {
-    fn render(&mut self, particles: &HashMap<ParticleTexturedModel, Vec<Particle>>, camera: &Camera) {
+    fn render(&mut self, particles: &HashMap<ParticleTexturedModel, Vec<Particle>>, camera: &Camera, clip_plane: Option<&Vector4f>, scene_depth: &ParticleSceneDepth) {
         gl::helper::push_debug_group(RenderGroup::PARTICLE_EFFECTS_PASS.id, RenderGroup::PARTICLE_EFFECTS_PASS.name);
-        self.prepare();
+        self.prepare(scene_depth);
 
         let view_mat = Matrix4f::create_view_matrix(camera);
+        // the shader gets model view positions so the plane is moved into view space, without a plane nothing is clipped
+        let view_clip_plane = clip_plane.map_or(Self::NO_CLIP_PLANE, |clip_plane| view_mat.transform_plane(clip_plane));
+        self.shader.load_view_clip_plane(&view_clip_plane);
 
         for (model_texture, particles) in particles {
+            // the particle master keeps empty groups around so that their storage can be reused next frame
+            if particles.is_empty() {
+                continue;
+            }
             gl::bind_vertex_array(model_texture.model.raw_model.vao_id);
-            gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-            gl::enable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN1);
-            gl::enable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN2);
-            gl::enable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN3);
-            gl::enable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN4);
-            gl::enable_vertex_attrib_array(ParticleModel::TEX_OFFSET);
-            gl::enable_vertex_attrib_array(ParticleModel::BLEND);
+            let attrib_arrays = AttribArrayScope::enable(&[
+                RawModel::POS_ATTRIB,
+                ParticleModel::MODELVIEW_COLUMN1,
+                ParticleModel::MODELVIEW_COLUMN2,
+                ParticleModel::MODELVIEW_COLUMN3,
+                ParticleModel::MODELVIEW_COLUMN4,
+                ParticleModel::TEX_OFFSET,
+                ParticleModel::BLEND,
+            ]);
             self.bind_texture(&model_texture.texture);
 
-            self.particle_data.clear();
-
-            for particle in particles {
-                //ParticleRenderer::create_always_camera_facing_model_view_mat(particle, &view_mat, &mut self.particle_data);
-                Self::create_always_camera_facing_model_view_mat(particle, &view_mat, camera, &mut self.particle_data);
-                Self::update_texture_data(particle, &mut self.particle_data);
-            }
-            update_vbo(model_texture.model.stream_draw_vbo, &self.particle_data);
+            let instance_count = Self::fill_instance_data(particles, &view_mat, camera, clip_plane, &mut self.particle_data);
+            update_vbo(model_texture.model.stream_draw_vbo, ParticleModel::MAX_INSTANCES * ParticleModel::INSTANCED_DATA_LENGTH, &self.particle_data);
 
             self.shader.load_particle_texture_data(&model_texture.texture);
             
-            gl::draw_arrays_instanced(gl::TRIANGLE_STRIP, 0, model_texture.model.raw_model.vertex_count, particles.len());
-
-            gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-            gl::disable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN1);
-            gl::disable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN2);
-            gl::disable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN3);
-            gl::disable_vertex_attrib_array(ParticleModel::MODELVIEW_COLUMN4);
-            gl::disable_vertex_attrib_array(ParticleModel::TEX_OFFSET);
-            gl::disable_vertex_attrib_array(ParticleModel::BLEND);
+            gl::draw_arrays_instanced(gl::TRIANGLE_STRIP, 0, model_texture.model.raw_model.vertex_count, instance_count);
+
+            drop(attrib_arrays);
             gl::bind_vertex_array(0);
         }
 
-        self.finish_rendering();
+        self.finish_rendering(scene_depth);
         gl::helper::pop_debug_group();
     }
+
+    fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.shader.start();
+        self.shader.load_projection_matrix(projection_matrix);
+        self.shader.stop();
+    }
 }
 
 impl ParticleRendererGpuInstanced {
+    const NO_CLIP_PLANE: Vector4f = Vector4f {x: 0.0, y: 0.0, z: 0.0, w: 1.0};
+
     pub fn new(projection_matrix: &Matrix4f) -> Self {
         let mut shader = ParticleShader::new();
         shader.start();
         shader.load_projection_matrix(projection_matrix);
+        shader.connect_texture_units();
         shader.stop();
         Self {
             shader,
@@ -81,22 +91,38 @@ impl ParticleRendererGpuInstanced {
         }
     }
 
+    // writes the per instance data of all particles into the storage buffer which is cleared first
+    // the buffer keeps its capacity between calls so once it has grown to fit the particles no more allocation happens
+    // culled particles and the ones below the clip plane are skipped and particles beyond what fits into the stream vbo are dropped, returns the number of instances that were written
+    pub fn fill_instance_data(particles: &[Particle], view_matrix: &Matrix4f, camera: &Camera, clip_plane: Option<&Vector4f>, storage_buffer: &mut Vec<f32>) -> usize {
+        storage_buffer.clear();
+        let mut instance_count = 0;
+        for particle in particles.iter().filter(|particle| is_rendered(particle, clip_plane)).take(ParticleModel::MAX_INSTANCES) {
+            Self::create_always_camera_facing_model_view_mat(particle, view_matrix, camera, storage_buffer);
+            Self::update_texture_data(particle, storage_buffer);
+            instance_count += 1;
+        }
+        instance_count
+    }
+
     fn bind_texture(&mut self, texture: &ParticleTexture) {
          gl::active_texture(gl::TEXTURE0);
         gl::bind_texture(gl::TEXTURE_2D, texture.tex_id.unwrap());
 
-        if texture.additive {
-            // use additive blending where the colors are always combined
-            // this is achieved by always using 1.0 for the destination (already rendered) unlike gl::ONE_MINUS_SRC_ALPHA in alpha blending
-            // additive blending is good for effects like magic where we want it to be shinier when there is overlap of particles
-            gl::blend_func(gl::SRC_ALPHA, gl::ONE);
-        } else {
-            gl::blend_func(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
-        }
+        let (src_factor, dst_factor) = texture.blend_mode.blend_factors();
+        gl::blend_func(src_factor, dst_factor);
     }
 
-    fn prepare(&mut self) {
+    fn prepare(&mut self, scene_depth: &ParticleSceneDepth) {
         self.shader.start();
+        self.shader.load_scene_depth(scene_depth);
+        if let Some(depth_texture) = scene_depth.depth_texture {
+            gl::active_texture(gl::TEXTURE1);
+            gl::bind_texture(gl::TEXTURE_2D, depth_texture);
+        }
+        if !scene_depth.uses_depth_buffer() {
+            gl::disable(gl::DEPTH_TEST);
+        }
         // we don't want depth tests to prevent particles from being drawn because they are behind other particles -> draw them on top of each other (overdraw?)        
         // however if we were to disable depth testing completely with disable(gl::DEPTH_TEST) then particles will be drawn on top of everything including terrain
         // we want them not to write into depth buffer (depth_mask(false)) but still get tested
@@ -104,7 +130,10 @@ impl ParticleRendererGpuInstanced {
         gl::enable(gl::BLEND);        
     }
     
-    fn finish_rendering(&mut self) {
+    fn finish_rendering(&mut self, scene_depth: &ParticleSceneDepth) {
+        if !scene_depth.uses_depth_buffer() {
+            gl::enable(gl::DEPTH_TEST);
+        }
         gl::depth_mask(true);
         gl::disable(gl::BLEND);
         self.shader.stop();
@@ -128,4 +157,59 @@ impl ParticleRendererGpuInstanced {
         storage_buffer.push(particle.texture_offset2.y);
         storage_buffer.push(particle.blend);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::Vector3f;
+
+    fn create_particles(count: usize) -> Vec<Particle> {
+        (0..count).map(|i| {
+            Particle::new(ParticleTexturedModel::default(), Vector3f::new(i as f32, 0.0, -10.0), Vector3f::zero(), 0.0, 0.0, 1.0, 1.0)
+        }).collect()
+    }
+
+    #[test]
+    fn fill_instance_data_writes_all_floats() {
+        let particles = create_particles(5);
+        let camera = Camera::default();
+        let view_mat = Matrix4f::create_view_matrix(&camera);
+        let mut storage = Vec::new();
+        let instance_count = ParticleRendererGpuInstanced::fill_instance_data(&particles, &view_mat, &camera, None, &mut storage);
+        assert_eq!(instance_count, 5);
+        // 16 floats of the model view matrix and 5 of the texture data
+        assert_eq!(storage.len(), 5 * ParticleModel::INSTANCED_DATA_LENGTH);
+    }
+
+    #[test]
+    fn instance_buffer_capacity_does_not_grow_at_steady_count() {
+        let particles = create_particles(100);
+        let camera = Camera::default();
+        let view_mat = Matrix4f::create_view_matrix(&camera);
+        let mut storage = Vec::new();
+        ParticleRendererGpuInstanced::fill_instance_data(&particles, &view_mat, &camera, None, &mut storage);
+        let capacity_after_first_frame = storage.capacity();
+        let data_ptr = storage.as_ptr();
+        for _ in 0..10 {
+            ParticleRendererGpuInstanced::fill_instance_data(&particles, &view_mat, &camera, None, &mut storage);
+            assert_eq!(storage.capacity(), capacity_after_first_frame);
+            assert_eq!(storage.as_ptr(), data_ptr);
+        }
+    }
+
+    #[test]
+    fn particles_below_clip_plane_are_discarded() {
+        let mut particles = create_particles(2);
+        particles[0].position.y = 5.0;
+        particles[1].position.y = -5.0;
+        let camera = Camera::default();
+        let view_mat = Matrix4f::create_view_matrix(&camera);
+        // keeps what is above y = 1 like the reflection pass of water at height 1
+        let reflection_clip_plane = Vector4f::new(0.0, 1.0, 0.0, -1.0);
+        let mut storage = Vec::new();
+        let instance_count = ParticleRendererGpuInstanced::fill_instance_data(&particles, &view_mat, &camera, Some(&reflection_clip_plane), &mut storage);
+        assert_eq!(instance_count, 1);
+        assert_eq!(storage.len(), ParticleModel::INSTANCED_DATA_LENGTH);
+    }
+}
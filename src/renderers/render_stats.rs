@@ -0,0 +1,133 @@
+use crate::entities::Entity;
+
+// work submitted by the master renderer during one frame, reset by the caller at the start of every frame (MasterRenderer::begin_frame)
+// so a split screen frame adds up both viewports
+// the water reflection/refraction and shadow map passes draw the scene again so their draws are counted too
+// entities are only culled against the shadow box, the camera passes draw every visible entity
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct RenderStats {
+    pub draw_calls: usize,
+    pub triangles: usize,
+    pub entities_drawn: usize,
+    pub entities_culled: usize,
+}
+
+impl RenderStats {
+    pub fn reset(&mut self) {
+        *self = RenderStats::default();
+    }
+
+    // vertex count of an indexed draw of a triangle list
+    pub fn record_draw(&mut self, vertex_count: usize) {
+        self.draw_calls += 1;
+        self.triangles += vertex_count / 3;
+    }
+
+    pub fn record_entity_draw(&mut self, entity: &Entity) {
+        self.entities_drawn += 1;
+        self.record_draw(entity.model.raw_model.vertex_count);
+    }
+
+    // the instances of a group can take several draw calls when they don't fit into one instance buffer
+    pub fn record_instanced_draw(&mut self, vertex_count: usize, instance_count: usize, draw_calls: usize) {
+        self.entities_drawn += instance_count;
+        self.draw_calls += draw_calls;
+        self.triangles += instance_count * (vertex_count / 3);
+    }
+
+    // the casters were already counted as drawn entities in the camera pass, only their draw calls are added
+    pub fn record_shadow_caster_draws(&mut self, entities: &[Entity], casters: &[&Entity]) {
+        self.record_culled_entities(entities, casters);
+        for caster in casters {
+            self.record_draw(caster.model.raw_model.vertex_count);
+        }
+    }
+
+    // hidden entities are skipped by every pass so they don't count as culled
+    pub fn record_culled_entities(&mut self, entities: &[Entity], kept: &[&Entity]) {
+        let visible = entities.iter().filter(|entity| entity.visible).count();
+        self.entities_culled += visible.saturating_sub(kept.len());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::math::{
+        Matrix4f,
+        Vector3f,
+    };
+    use crate::models::{
+        RawModel,
+        TexturedModel,
+    };
+    use crate::shadows::shadow_box::ShadowBox;
+
+    fn entity_with_vertex_count(vertex_count: usize) -> Entity {
+        let raw_model = RawModel::new(0, vertex_count);
+        let model = TexturedModel { raw_model, ..TexturedModel::default() };
+        Entity::new(model, Vector3f::zero(), Vector3f::zero(), 1.0)
+    }
+
+    #[test]
+    fn entity_draws_add_up_draw_calls_and_triangles() {
+        // a cube and two quads
+        let entities = vec![entity_with_vertex_count(36), entity_with_vertex_count(6), entity_with_vertex_count(6)];
+        let mut stats = RenderStats::default();
+        for entity in entities.iter() {
+            stats.record_entity_draw(entity);
+        }
+
+        assert_eq!(stats, RenderStats { draw_calls: 3, triangles: 16, entities_drawn: 3, entities_culled: 0 });
+        stats.reset();
+        assert_eq!(stats, RenderStats::default());
+    }
+
+    #[test]
+    fn entities_outside_the_shadow_box_are_counted_as_culled() {
+        let mut shadow_box = ShadowBox::new(1.5, 70.0, -0.1, -100.0);
+        let corner = |x, y, z| Vector3f::new(x, y, z);
+        shadow_box.frustum_corners = [
+            corner(-10.0, -10.0, -10.0), corner(10.0, -10.0, -10.0), corner(10.0, 10.0, -10.0), corner(-10.0, 10.0, -10.0),
+            corner(-10.0, -10.0, 10.0), corner(10.0, -10.0, 10.0), corner(10.0, 10.0, 10.0), corner(-10.0, 10.0, 10.0),
+        ];
+        shadow_box.update_shadow_box_size(&Matrix4f::identity());
+
+        let model = TexturedModel { bounding_radius: Some(1.0), ..TexturedModel::default() };
+        let mut entities = Entity::spawn_many(&model, &[
+            (Vector3f::new(0.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(50.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(0.0, 50.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(1.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+        ]);
+        entities[3].visible = false;
+
+        let mut stats = RenderStats::default();
+        let casters = shadow_box.shadow_casters(&entities);
+        stats.record_culled_entities(&entities, &casters);
+        assert_eq!(casters.len(), 1);
+        assert_eq!(stats.entities_culled, 2);
+    }
+
+    #[test]
+    fn split_screen_frame_adds_up_both_viewports_and_their_shadow_passes() {
+        let entities = vec![entity_with_vertex_count(36), entity_with_vertex_count(6)];
+        // only the cube casts a shadow in the left viewport, the right one reuses the shadow map
+        let render_viewport = |stats: &mut RenderStats, casters: Option<Vec<&Entity>>| {
+            if let Some(casters) = casters {
+                stats.record_shadow_caster_draws(&entities, &casters);
+            }
+            for entity in entities.iter() {
+                stats.record_entity_draw(entity);
+            }
+        };
+
+        let mut stats = RenderStats { draw_calls: 100, ..RenderStats::default() };
+        // once per frame, before the viewports are rendered
+        stats.reset();
+        render_viewport(&mut stats, Some(vec![&entities[0]]));
+        render_viewport(&mut stats, None);
+
+        assert_eq!(stats, RenderStats { draw_calls: 5, triangles: 40, entities_drawn: 4, entities_culled: 1 });
+    }
+}
@@ -11,6 +11,7 @@ use crate::entities::{
 use crate::gl;
 use crate::math::{
     Matrix4f,
+    Vector2f,
     Vector3f,
 };
 use crate::models::{
@@ -18,10 +19,19 @@ use crate::models::{
     TexturedModel,
 };
 use crate::shadows::{
-    shadow_params::ShadowParams,
+    shadow_params::{
+        ShadowParams,
+        ShadowMapResolution,
+    },
     shadow_box::ShadowBox,
+    shadow_map_freeze::{
+        ShadowMapFreezeParams,
+        ShadowMapSnapshot,
+    },
 };
 use crate::shaders::ShadowShader;
+use super::attrib_array_scope::AttribArrayScope;
+use super::entity_renderer::EntityRenderer;
 
 
 pub struct ShadowMapRenderer {
@@ -32,6 +42,16 @@ pub struct ShadowMapRenderer {
     bias: Matrix4f,
     vp_matrix: Matrix4f,
     mvp_matrix: Matrix4f,    
+    // scratch for the per entity transform
+    transform_mat: Matrix4f,
+    // billboards are turned towards the camera like in the camera pass, not towards the sun
+    camera_pitch: f32,
+    camera_yaw: f32,
+    // when set the shadow map is only rendered again once the camera or sun moved past the thresholds
+    freeze_params: Option<ShadowMapFreezeParams>,
+    last_snapshot: Option<ShadowMapSnapshot>,
+    // enabled between prepare_textured_model and cleanup_textured_model
+    attrib_arrays: Option<AttribArrayScope>,
 }
 
 impl ShadowMapRenderer {
@@ -46,8 +66,15 @@ impl ShadowMapRenderer {
 
         let shadow_params = ShadowParams {
             shadow_map_texture: 0,
+            shadow_map_sampler: 0,
             shadow_distance: ShadowBox::SHADOW_DISTANCE,
             shadow_map_size: FboMap::SHADOW_MAP_SIZE,
+            constant_bias: ShadowParams::DEFAULT_CONSTANT_BIAS,
+            slope_bias: ShadowParams::DEFAULT_SLOPE_BIAS,
+            max_bias: ShadowParams::DEFAULT_MAX_BIAS,
+            snaps_to_texels: true,
+            culls_to_shadow_box: true,
+            max_caster_distance: None,
         };
 
         ShadowMapRenderer {
@@ -57,15 +84,70 @@ impl ShadowMapRenderer {
             bias,
             vp_matrix,
             mvp_matrix,
+            transform_mat: Matrix4f::identity(),
+            camera_pitch: 0.0,
+            camera_yaw: 0.0,
             shadow_params,
+            freeze_params: None,
+            last_snapshot: None,
+            attrib_arrays: None,
         }
     }
 
+    pub fn set_shadow_distance(&mut self, shadow_distance: f32) {
+        self.shadow_box.set_shadow_distance(shadow_distance);
+        // the shaders fade the shadows out towards this distance
+        self.shadow_params.shadow_distance = shadow_distance;
+        self.last_snapshot = None;
+    }
+
+    pub fn set_resolution(&mut self, resolution: ShadowMapResolution) {
+        self.shadow_params.set_resolution(resolution);
+        self.last_snapshot = None;
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.shadow_box.set_aspect_ratio(aspect_ratio);
+        self.last_snapshot = None;
+    }
+
+    pub fn set_freeze_params(&mut self, freeze_params: Option<ShadowMapFreezeParams>) {
+        self.freeze_params = freeze_params;
+        // make sure the next frame renders a fresh shadow map
+        self.last_snapshot = None;
+    }
+
+    // when this returns false the previous shadow map and to shadow space matrix can be reused
+    pub fn needs_rerender(&mut self, camera: &Camera, sun: &Light) -> bool {
+        let freeze_params = match &self.freeze_params {
+            Some(freeze_params) => freeze_params,
+            None => return true,
+        };
+        let (sun_pitch, sun_yaw) = Self::calc_light_pitch_yaw_dg(&sun.to_light_direction());
+        let current = ShadowMapSnapshot {
+            camera_position: camera.position.clone(),
+            camera_pitch: camera.pitch,
+            camera_yaw: camera.yaw,
+            sun_pitch,
+            sun_yaw,
+        };
+        let needs_rerender = match &self.last_snapshot {
+            Some(last_snapshot) => last_snapshot.needs_rerender(&current, freeze_params),
+            None => true,
+        };
+        if needs_rerender {
+            self.last_snapshot = Some(current);
+        }
+        needs_rerender
+    }
+
     pub fn start_render(&mut self, camera: &Camera, sun: &Light) {                
-        let (pitch, yaw) = Self::calc_light_pitch_yaw_dg(&sun.position);
+        let (pitch, yaw) = Self::calc_light_pitch_yaw_dg(&sun.to_light_direction());
         let world_to_lightspace_non_moving = Matrix4f::create_fps_view_matrix(&Vector3f::ZERO, pitch, yaw);
         self.shadow_box.update(camera, &world_to_lightspace_non_moving);        
         self.update_world_to_lightspace(pitch, yaw);
+        self.camera_pitch = camera.pitch;
+        self.camera_yaw = camera.yaw;
         
         gl::enable(gl::DEPTH_TEST);
         gl::clear(gl::DEPTH_BUFFER_BIT);
@@ -76,12 +158,25 @@ impl ShadowMapRenderer {
         self.vp_matrix.pre_multiply_in_place(&self.shadow_box.ortho_proj_mat);
     }
 
+    // call after start_render so the shadow box is up to date
+    pub fn shadow_casters<'a>(&self, entities: &'a [Entity], camera: &Camera) -> Vec<&'a Entity> {
+        let casters = if self.shadow_params.culls_to_shadow_box {
+            self.shadow_box.shadow_casters(entities)
+        } else {
+            entities.iter().filter(|entity| entity.visible).collect()
+        };
+        self.shadow_params.cull_distant_casters(casters, &camera.position)
+    }
+
     pub fn prepare_textured_model(&mut self, model: &TexturedModel) {
         gl::active_texture(gl::TEXTURE0);        
         gl::bind_texture(gl::TEXTURE_2D, model.texture.tex_id.unwrap());
+        self.shadow_shader.load_atlas_number_of_rows(model.texture.number_of_rows_in_atlas);
+        self.shadow_shader.load_uv_transform(&model.texture.uv_scale, &model.texture.uv_offset);
+        // a scope left over from the previous model has to be disabled while its own vao is still bound
+        drop(self.attrib_arrays.take());
         gl::bind_vertex_array(model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);        
+        self.attrib_arrays = Some(AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB]));
     }
 
     pub fn render(&mut self, entities: &Vec<&Entity>) {        
@@ -93,36 +188,40 @@ impl ShadowMapRenderer {
     pub fn render_entity(&mut self, entity: &Entity) {
         self.mvp_matrix.make_identity();
         self.mvp_matrix.post_multiply_in_place(&self.vp_matrix);
-        let transform_mat = Matrix4f::create_transform_matrix(&entity.position, &entity.rotation_deg, entity.scale);
-        self.mvp_matrix.post_multiply_in_place(&transform_mat);
+        EntityRenderer::entity_transform_into(&mut self.transform_mat, entity, self.camera_pitch, self.camera_yaw);
+        self.mvp_matrix.post_multiply_in_place(&self.transform_mat);
         self.shadow_shader.load_mvp_matrix(&self.mvp_matrix);
+        self.shadow_shader.load_atlas_offset(&entity.get_atlas_offset());
 
         gl::draw_elements(gl::TRIANGLES, entity.model.raw_model.vertex_count, gl::UNSIGNED_INT);            
     }
 
     pub fn cleanup_textured_model(&mut self) {        
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
+        self.attrib_arrays = None;
         gl::bind_vertex_array(0);
     }
 
     pub fn render_terrain(&mut self, terrains: &Vec<Terrain>) {
+        // terrains have no texture coordinates bound, this only keeps the lookup away from the previous model's atlas transform
+        self.shadow_shader.load_atlas_number_of_rows(1);
+        self.shadow_shader.load_atlas_offset(&Vector2f::zero());
+        self.shadow_shader.load_uv_transform(&Vector2f::new(1.0, 1.0), &Vector2f::zero());
         for terrain in terrains.iter() {
             gl::bind_vertex_array(terrain.model.raw_model.vao_id);
-            gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);            
+            let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
             
             let terrain_pos = Vector3f::new(terrain.x as f32, 0.0, terrain.z as f32);
             let terrain_rot = Vector3f::new(0.0, 0.0, 0.0);
-            let transform_mat = Matrix4f::create_transform_matrix(&terrain_pos, &terrain_rot, 1.0);
+            Matrix4f::create_transform_matrix_into(&mut self.transform_mat, &terrain_pos, &terrain_rot, 1.0);
 
             self.mvp_matrix.make_identity();
-            self.mvp_matrix.pre_multiply_in_place(&transform_mat);
+            self.mvp_matrix.pre_multiply_in_place(&self.transform_mat);
             self.mvp_matrix.pre_multiply_in_place(&self.vp_matrix);
 
             self.shadow_shader.load_mvp_matrix(&self.mvp_matrix);
             gl::draw_elements(gl::TRIANGLES, terrain.model.raw_model.vertex_count, gl::UNSIGNED_INT);
 
-            gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);            
+            drop(attrib_arrays);
         }
         gl::bind_vertex_array(0);
     }
@@ -152,6 +251,11 @@ impl ShadowMapRenderer {
         // if the dependency is there then the frustum bounding box (shadow box) jumps around too much which seems to causes it to not correctly center on the player        
         let center = &self.shadow_box.world_space_center;
         self.world_to_lightspace = Matrix4f::create_fps_view_matrix(center, pitch, yaw);
+        if self.shadow_params.snaps_to_texels {
+            // the translation column is the lightspace position of the world origin, rounding it moves the whole box in texel steps
+            self.world_to_lightspace[0][3] = self.shadow_params.snap_to_texel(self.world_to_lightspace[0][3], self.shadow_box.width);
+            self.world_to_lightspace[1][3] = self.shadow_params.snap_to_texel(self.world_to_lightspace[1][3], self.shadow_box.height);
+        }
     }
     // we want to use the lightspace transform in a shader to sample from the depth map
     // the projection to lightspace ndc coords will leave us in the unit cube [-1,1]
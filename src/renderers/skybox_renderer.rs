@@ -1,12 +1,15 @@
 use crate::display::{
+    DepthMode,
     WallClock,
 };
 use crate::entities::{
     Camera,
+    Light,
     Skybox,
 };
 use crate::gl;
 use crate::math::{
+    Color,
     Matrix4f,
     Vector3f,
     Vector4f,
@@ -15,9 +18,46 @@ use crate::models::{
     RawModel,
 };
 use crate::shaders::SkyboxShader;
+use super::attrib_array_scope::AttribArrayScope;
+
+// how the skybox is tested against the depth of the geometry drawn before it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SkyboxDepth {
+    // the cube writes depth like any other geometry
+    Geometry,
+    // the cube is moved onto the far plane and doesn't write depth so it only fills the pixels no opaque geometry covers
+    FarPlane,
+}
+
+impl Default for SkyboxDepth {
+    fn default() -> Self {
+        SkyboxDepth::FarPlane
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SkyboxDepthState {
+    pub depth_func: gl::types::GLenum,
+    pub writes_depth: bool,
+    // ndc depth of the far plane the cube is moved to, None keeps the depth of the cube
+    pub far_plane_depth: Option<f32>,
+}
+
+impl SkyboxDepth {
+    // the sky lands exactly on the cleared depth so the comparison has to include equality
+    pub fn depth_state(&self, depth_mode: DepthMode) -> SkyboxDepthState {
+        match (self, depth_mode) {
+            (SkyboxDepth::Geometry, _) => SkyboxDepthState { depth_func: depth_mode.depth_func(), writes_depth: true, far_plane_depth: None },
+            (SkyboxDepth::FarPlane, DepthMode::Standard) => SkyboxDepthState { depth_func: gl::LEQUAL, writes_depth: false, far_plane_depth: Some(1.0) },
+            (SkyboxDepth::FarPlane, DepthMode::ReversedZ) => SkyboxDepthState { depth_func: gl::GEQUAL, writes_depth: false, far_plane_depth: Some(0.0) },
+        }
+    }
+}
 
 pub struct SkyboxRenderer {
     shader: SkyboxShader,    
+    depth: SkyboxDepth,
+    depth_mode: DepthMode,
 }
 
 impl SkyboxRenderer {
@@ -28,28 +68,55 @@ impl SkyboxRenderer {
         skybox_shader.stop();
         SkyboxRenderer {
             shader: skybox_shader,
+            depth: SkyboxDepth::default(),
+            depth_mode: DepthMode::default(),
         }
     }
 
-    pub fn render(&mut self, camera: &Camera, skybox: &Skybox, sky_color: &Vector3f, wall_clock: &WallClock, clip_plane: &Vector4f) {
+    pub fn set_depth(&mut self, depth: SkyboxDepth) {
+        self.depth = depth;
+    }
+
+    pub fn update_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.depth_mode = depth_mode;
+    }
+
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.shader.start();
+        self.shader.load_projection_matrix(projection_matrix);
+        self.shader.stop();
+    }
+
+    // the sun is the directional light which drives the analytic sky if the skybox uses one
+    pub fn render(&mut self, camera: &Camera, skybox: &Skybox, sky_color: &Color, wall_clock: &WallClock, clip_plane: &Vector4f, sun: Option<&Light>) {
         if skybox.invisible {
             return;
         }
         self.shader.start();        
-        self.shader.load_view_matrix(camera, skybox.rotation_yaw_deg);
+        // the analytic sky is computed in world space so it must not be rotated
+        let rotation_deg = if skybox.atmosphere.is_some() { 0.0 } else { skybox.rotation_deg };
+        self.shader.load_view_matrix(camera, rotation_deg, &skybox.rotation_axis);
+        let sun_direction = sun.map_or(Vector3f::POS_Y_AXIS, |light| light.to_light_direction());
+        self.shader.load_atmosphere(skybox.atmosphere.as_ref(), &sun_direction);
         self.shader.load_sky_color(sky_color, skybox.uses_fog); // due to day night this color needs to be set every frame
         // water stuff (every frame?)
         self.shader.load_clip_plane(clip_plane);
+        let depth_state = self.depth.depth_state(self.depth_mode);
+        self.shader.load_far_plane_depth(depth_state.far_plane_depth);
+        gl::depth_func(depth_state.depth_func);
+        gl::depth_mask(depth_state.writes_depth);
         
         self.bind_textures(skybox, wall_clock);        
 
         gl::bind_vertex_array(skybox.model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
         gl::draw_arrays(gl::TRIANGLES, 0, skybox.model.raw_model.vertex_count);
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
+        drop(attrib_arrays);
         gl::bind_vertex_array(0);
 
         gl::bind_texture(gl::TEXTURE_CUBE_MAP, 0);
+        gl::depth_mask(true);
+        gl::depth_func(self.depth_mode.depth_func());
 
         self.shader.stop();
     }
@@ -66,4 +133,20 @@ impl SkyboxRenderer {
         self.shader.load_blend_factor(blend_factor);
         self.shader.connect_texture_units();   
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn far_plane_skybox_is_tested_with_equality_and_writes_no_depth() {
+        let state = SkyboxDepth::FarPlane.depth_state(DepthMode::Standard);
+        assert_eq!(state, SkyboxDepthState { depth_func: gl::LEQUAL, writes_depth: false, far_plane_depth: Some(1.0) });
+        let state = SkyboxDepth::FarPlane.depth_state(DepthMode::ReversedZ);
+        assert_eq!(state, SkyboxDepthState { depth_func: gl::GEQUAL, writes_depth: false, far_plane_depth: Some(0.0) });
+
+        let state = SkyboxDepth::Geometry.depth_state(DepthMode::Standard);
+        assert_eq!(state, SkyboxDepthState { depth_func: gl::LESS, writes_depth: true, far_plane_depth: None });
+    }
+}
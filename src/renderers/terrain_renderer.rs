@@ -1,12 +1,14 @@
 use crate::gl;
 use crate::entities::{
     Camera,
+    FogCutoff,
     Light,
     Terrain,
 };
 use crate::shaders::TerrainShader;
 use crate::shadows::shadow_params::ShadowParams;
 use crate::math::{
+    Color,
     Matrix4f,
     Vector3f,
     Vector4f,    
@@ -14,63 +16,173 @@ use crate::math::{
 use crate::models::{
     RawModel,
 };
+use super::attrib_array_scope::AttribArrayScope;
+
+#[derive(Clone, Debug)]
+pub struct TerrainTessellationParams {
+    // only takes effect when the context supports tessellation (gl 4.0+)
+    pub enabled: bool,
+    pub min_level: f32,
+    pub max_level: f32,
+    // edges closer than near_distance get max_level, the level drops linearly to min_level at far_distance
+    pub near_distance: f32,
+    pub far_distance: f32,
+    // detail noise relief in world units, fades out with distance the same way the level does
+    // anything but 0 moves the drawn surface away from Ground::height_at_xz so things placed on it float or sink
+    pub detail_amplitude: f32,
+    pub detail_frequency: f32,
+}
+
+impl Default for TerrainTessellationParams {
+    fn default() -> Self {
+        TerrainTessellationParams {
+            enabled: false,
+            min_level: 1.0,
+            max_level: 16.0,
+            near_distance: 10.0,
+            far_distance: 120.0,
+            detail_amplitude: 0.0,
+            detail_frequency: 0.5,
+        }
+    }
+}
+
+impl TerrainTessellationParams {
+    // mirrors tessellation_level in terrainTessControlShader
+    pub fn tessellation_level(&self, distance: f32) -> f32 {
+        let distance_range = (self.far_distance - self.near_distance).max(0.0001);
+        let far_factor = ((distance - self.near_distance) / distance_range).max(0.0).min(1.0);
+        self.max_level + (self.min_level - self.max_level) * far_factor
+    }
+}
 
 pub struct TerrainRenderer {
     shader: TerrainShader,
+    // None when the context can't tessellate, we then always fall back to the fixed resolution mesh
+    tessellated_shader: Option<TerrainShader>,
+    tessellation: TerrainTessellationParams,
+    // enabled between prepare and unprepare so they are always disabled again on the same vao
+    attrib_arrays: Option<AttribArrayScope>,
 }
 
 impl TerrainRenderer {    
     
     pub fn new(projection_matrix: &Matrix4f) -> TerrainRenderer {     
-        let mut shader = TerrainShader::new();
+        let shader = TerrainRenderer::init_shader(TerrainShader::new(), projection_matrix);
+        let tessellated_shader = if gl::helper::supports_tessellation() {
+            Some(TerrainRenderer::init_shader(TerrainShader::new_tessellated(), projection_matrix))
+        } else {
+            None
+        };
+        TerrainRenderer {
+            shader,
+            tessellated_shader,
+            tessellation: TerrainTessellationParams::default(),
+            attrib_arrays: None,
+        }
+    }
+
+    fn init_shader(mut shader: TerrainShader, projection_matrix: &Matrix4f) -> TerrainShader {
         shader.start();
         shader.load_projection_matrix(projection_matrix);
         shader.connect_texture_units();
         shader.stop();
-        TerrainRenderer {
-            shader,
+        shader
+    }
+
+    pub fn set_tessellation_params(&mut self, tessellation: TerrainTessellationParams) {
+        self.tessellation = tessellation;
+    }
+
+    pub fn tessellation_params(&self) -> &TerrainTessellationParams {
+        &self.tessellation
+    }
+
+    pub fn uses_tessellation(&self) -> bool {
+        self.tessellation.enabled && self.tessellated_shader.is_some()
+    }
+
+    fn active_shader(&mut self) -> &mut TerrainShader {
+        match self.tessellated_shader.as_mut() {
+            Some(tessellated_shader) if self.tessellation.enabled => tessellated_shader,
+            _ => &mut self.shader,
         }
     }
 
-    pub fn start_render(&mut self, lights: &Vec<Light>, camera: &Camera, sky_color: &Vector3f, to_shadow_space: &Matrix4f, shadow_params: &ShadowParams) {
-        self.shader.start();
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        for shader in std::iter::once(&mut self.shader).chain(self.tessellated_shader.as_mut()) {
+            shader.start();
+            shader.load_projection_matrix(projection_matrix);
+            shader.stop();
+        }
+    }
+
+    pub fn update_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        for shader in std::iter::once(&mut self.shader).chain(self.tessellated_shader.as_mut()) {
+            shader.start();
+            shader.load_fog_cutoff(fog_cutoff);
+            shader.stop();
+        }
+    }
+
+    pub fn start_render(&mut self, lights: &Vec<Light>, camera: &Camera, sky_color: &Color, to_shadow_space: &Matrix4f, shadow_params: &ShadowParams) {
+        let tessellation = self.tessellation.clone();
+        let uses_tessellation = self.uses_tessellation();
+        let shader = self.active_shader();
+        shader.start();
         // we do this more than once because we may want to change the light, view, sky color
         // but we do them once per model type, because the type has one shader
-        self.shader.load_lights(lights);
-        self.shader.load_view_matrix(camera);  
-        self.shader.load_sky_color(sky_color);
+        shader.load_lights(lights);
+        shader.load_view_matrix(camera);  
+        shader.load_sky_color(sky_color);
         
-        self.shader.load_to_shadowmap_space(to_shadow_space);
-        self.shader.load_shadow_params(shadow_params);
+        shader.load_to_shadowmap_space(to_shadow_space);
+        shader.load_shadow_params(shadow_params);
+
+        if uses_tessellation {
+            shader.load_tessellation_levels(tessellation.min_level, tessellation.max_level, tessellation.near_distance, tessellation.far_distance);
+            shader.load_detail_relief(tessellation.detail_amplitude, tessellation.detail_frequency);
+            // every terrain triangle is one patch
+            gl::patch_parameter_i(gl::PATCH_VERTICES, 3);
+        }
 
         gl::active_texture(gl::TEXTURE5);
         gl::bind_texture(gl::TEXTURE_2D, shadow_params.shadow_map_texture);
+        gl::bind_sampler(5, shadow_params.shadow_map_sampler);
     }
 
     pub fn stop_render(&mut self) {          
-        self.shader.stop();
+        // other textures on the unit are sampled with their own parameters again
+        gl::bind_sampler(5, 0);
+        self.active_shader().stop();
     }
 
     pub fn prepare_terrain(&mut self, terrain: &Terrain, clip_plane: &Vector4f) {
+        // a scope left over from the previous model has to be disabled while its own vao is still bound
+        drop(self.attrib_arrays.take());
         gl::bind_vertex_array(terrain.model.raw_model.vao_id);
-        gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::enable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
-
-        self.shader.load_shine_variables(1.0, 0.0);
-        self.shader.load_clip_plane(clip_plane);
-
-        // configure texture units
-        gl::active_texture(gl::TEXTURE0); 
-        gl::bind_texture(gl::TEXTURE_2D, terrain.texture_pack.background_texture.tex_id.unwrap());
-        gl::active_texture(gl::TEXTURE1); 
-        gl::bind_texture(gl::TEXTURE_2D, terrain.texture_pack.r_texture.tex_id.unwrap());
-        gl::active_texture(gl::TEXTURE2); 
-        gl::bind_texture(gl::TEXTURE_2D, terrain.texture_pack.g_texture.tex_id.unwrap());
-        gl::active_texture(gl::TEXTURE3); 
-        gl::bind_texture(gl::TEXTURE_2D, terrain.texture_pack.b_texture.tex_id.unwrap());
-        gl::active_texture(gl::TEXTURE4); 
-        gl::bind_texture(gl::TEXTURE_2D, terrain.blend_texture.tex_id.unwrap());
+        self.attrib_arrays = Some(AttribArrayScope::enable(&[RawModel::POS_ATTRIB, RawModel::TEX_COORD_ATTRIB, RawModel::NORMAL_ATTRIB]));
+
+        let shader = self.active_shader();
+        shader.load_shine_variables(1.0, 0.0);
+        shader.load_clip_plane(clip_plane);
+
+        // configure texture units, every terrain tile binds its own texture pack and blend map
+        for (texture_unit, tex_id) in Self::terrain_texture_bindings(terrain).iter() {
+            gl::active_texture(*texture_unit);
+            gl::bind_texture(gl::TEXTURE_2D, *tex_id);
+        }
+    }
+
+    // texture unit and texture id pairs in the order expected by the terrain shader samplers (see TerrainShader::connect_texture_units)
+    pub fn terrain_texture_bindings(terrain: &Terrain) -> [(gl::types::GLenum, u32); 5] {
+        [
+            (gl::TEXTURE0, terrain.texture_pack.background_texture.tex_id.unwrap()),
+            (gl::TEXTURE1, terrain.texture_pack.r_texture.tex_id.unwrap()),
+            (gl::TEXTURE2, terrain.texture_pack.g_texture.tex_id.unwrap()),
+            (gl::TEXTURE3, terrain.texture_pack.b_texture.tex_id.unwrap()),
+            (gl::TEXTURE4, terrain.blend_texture.tex_id.unwrap()),
+        ]
     }
 
     pub fn render(&mut self, terrain: &Terrain) {        
@@ -78,17 +190,71 @@ impl TerrainRenderer {
         let terrain_pos = Vector3f::new(terrain.x as f32, 0.0, terrain.z as f32);
         let terrain_rot = Vector3f::new(0.0, 0.0, 0.0);
         let transform_mat = Matrix4f::create_transform_matrix(&terrain_pos, &terrain_rot, 1.0);
-        self.shader.load_transformation_matrix(&transform_mat);
+        self.active_shader().load_transformation_matrix(&transform_mat);
         
-        gl::draw_elements(gl::TRIANGLES, terrain.model.raw_model.vertex_count, gl::UNSIGNED_INT);
+        let draw_mode = if self.uses_tessellation() { gl::PATCHES } else { gl::TRIANGLES };
+        gl::draw_elements(draw_mode, terrain.model.raw_model.vertex_count, gl::UNSIGNED_INT);
     }
 
-    pub fn unprepare_terrain(&self) {
-        gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::TEX_COORD_ATTRIB);
-        gl::disable_vertex_attrib_array(RawModel::NORMAL_ATTRIB);
+    pub fn unprepare_terrain(&mut self) {
+        self.attrib_arrays = None;
 
         gl::bind_vertex_array(0);
         gl::bind_texture(gl::TEXTURE_2D, 0);
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Arc;
+    use crate::models::{
+        TerrainModel,
+        TerrainTexture,
+        TerrainTexturePack,
+        TextureId,
+    };
+
+    fn texture(id: u32) -> TerrainTexture {
+        TerrainTexture { tex_id: TextureId::Loaded(id) }
+    }
+
+    fn create_terrain(grid_x: i32, first_tex_id: u32) -> Terrain {
+        let texture_pack = TerrainTexturePack {
+            background_texture: texture(first_tex_id),
+            r_texture: texture(first_tex_id + 1),
+            g_texture: texture(first_tex_id + 2),
+            b_texture: texture(first_tex_id + 3),
+        };
+        let model = TerrainModel { raw_model: RawModel::default(), height_map: Arc::new(Vec::new()) };
+        Terrain::new(grid_x, 0, texture_pack, texture(first_tex_id + 4), model)
+    }
+
+    #[test]
+    fn terrains_bind_their_own_texture_packs() {
+        let grassland = create_terrain(0, 1);
+        let desert = create_terrain(1, 10);
+
+        let grassland_bindings = TerrainRenderer::terrain_texture_bindings(&grassland);
+        let desert_bindings = TerrainRenderer::terrain_texture_bindings(&desert);
+
+        assert_eq!(grassland_bindings, [(gl::TEXTURE0, 1), (gl::TEXTURE1, 2), (gl::TEXTURE2, 3), (gl::TEXTURE3, 4), (gl::TEXTURE4, 5)]);
+        assert_eq!(desert_bindings, [(gl::TEXTURE0, 10), (gl::TEXTURE1, 11), (gl::TEXTURE2, 12), (gl::TEXTURE3, 13), (gl::TEXTURE4, 14)]);
+    }
+
+    #[test]
+    fn tessellation_level_drops_with_distance() {
+        let params = TerrainTessellationParams {
+            min_level: 2.0,
+            max_level: 16.0,
+            near_distance: 10.0,
+            far_distance: 80.0,
+            ..TerrainTessellationParams::default()
+        };
+        assert_eq!(params.tessellation_level(0.0), 16.0);
+        assert_eq!(params.tessellation_level(10.0), 16.0);
+        assert_eq!(params.tessellation_level(45.0), 9.0);
+        assert_eq!(params.tessellation_level(80.0), 2.0);
+        assert_eq!(params.tessellation_level(500.0), 2.0);
+    }
+}
@@ -0,0 +1,138 @@
+use crate::display::framebuffers::{
+    FramebufferObject,
+    FboFlags,
+};
+use crate::entities::Camera;
+use crate::models::TexturedModel;
+use crate::math::{
+    Color,
+    Vector3f,
+};
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct ThumbnailParams {
+    // width and height of the square icon in pixels
+    pub resolution: usize,
+    // the camera looks at the center of the model bounds from this pitch and yaw
+    pub pitch_deg: f32,
+    pub yaw_deg: f32,
+    // fraction of the icon size left empty on each side of the model
+    pub margin: f32,
+    pub background: Color,
+}
+
+impl Default for ThumbnailParams {
+    fn default() -> Self {
+        ThumbnailParams {
+            resolution: 128,
+            pitch_deg: 20.0,
+            yaw_deg: 30.0,
+            margin: 0.1,
+            background: Color::rgb(0.5, 0.5, 0.5),
+        }
+    }
+}
+
+// the fbo owns the texture so the thumbnail has to be kept around as long as the icon is shown
+pub struct Thumbnail {
+    fbo: FramebufferObject,
+}
+
+impl Thumbnail {
+    // models without a bounding radius are framed as if they were a unit sphere
+    const DEFAULT_BOUNDING_RADIUS: f32 = 1.0;
+    // the margins can't take up the whole icon
+    const MAX_MARGIN: f32 = 0.45;
+
+    pub fn new(resolution: usize) -> Self {
+        Thumbnail {
+            fbo: FramebufferObject::new(resolution, resolution, FboFlags::COLOR_TEX | FboFlags::DEPTH_RENDERBUF, 1),
+        }
+    }
+
+    pub fn fbo(&mut self) -> &mut FramebufferObject {
+        &mut self.fbo
+    }
+
+    pub fn texture_id(&self) -> u32 {
+        self.fbo.color_texture(0).expect("Thumbnail fbo must have a color texture")
+    }
+
+    // how far the camera has to be from the center of the bounding sphere so the sphere just fits between the margins
+    pub fn framing_distance(bounding_radius: Option<f32>, fov_deg: f32, margin: f32) -> f32 {
+        let radius = bounding_radius.unwrap_or(Thumbnail::DEFAULT_BOUNDING_RADIUS);
+        let margin = margin.max(0.0).min(Thumbnail::MAX_MARGIN);
+        // the sphere only covers the part of the view that is left after the margins
+        let framed_radius = radius / (1.0 - 2.0 * margin);
+        framed_radius / (fov_deg.to_radians() / 2.0).sin()
+    }
+
+    // camera looking at the origin of the model, where its bounding sphere is centered
+    pub fn camera(model: &TexturedModel, fov_deg: f32, params: &ThumbnailParams) -> Camera {
+        let (center, radius) = Thumbnail::framed_sphere(model);
+        let mut camera = Camera::default();
+        camera.pitch = params.pitch_deg;
+        camera.yaw = params.yaw_deg;
+        let (forward, _right) = camera.view_directions();
+        let distance = Thumbnail::framing_distance(radius, fov_deg, params.margin);
+        camera.position = &center - &(forward * distance);
+        camera.looking_at = center;
+        camera
+    }
+
+    // the sphere around the model bounds, models without bounds are assumed to be centered on their origin
+    fn framed_sphere(model: &TexturedModel) -> (Vector3f, Option<f32>) {
+        match &model.bounds {
+            Some((min, max)) => ((min + max) * 0.5, Some((max - min).length() / 2.0)),
+            None => (Vector3f::zero(), model.bounding_radius),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn framing_distance_fits_the_bounding_sphere_between_the_margins() {
+        // sin(30) = 0.5 so without margins the camera is twice the radius away
+        assert!((Thumbnail::framing_distance(Some(2.0), 60.0, 0.0) - 4.0).abs() < 1e-5);
+        // a quarter margin on each side leaves half the view for the model
+        assert!((Thumbnail::framing_distance(Some(2.0), 60.0, 0.25) - 8.0).abs() < 1e-5);
+        assert!((Thumbnail::framing_distance(None, 60.0, 0.0) - 2.0).abs() < 1e-5);
+        // margins are clamped so the distance stays finite
+        assert!(Thumbnail::framing_distance(Some(2.0), 60.0, 0.5).is_finite());
+    }
+
+    #[test]
+    fn thumbnail_camera_looks_at_the_model_origin() {
+        let params = ThumbnailParams::default();
+        let model = TexturedModel { bounding_radius: Some(2.0), ..TexturedModel::default() };
+        let camera = Thumbnail::camera(&model, 60.0, &params);
+        let distance = Thumbnail::framing_distance(Some(2.0), 60.0, params.margin);
+        assert!((camera.position.length() - distance).abs() < 1e-4);
+        let (forward, _right) = camera.view_directions();
+        let mut to_origin = -camera.position.clone();
+        to_origin.normalize();
+        assert!(forward.dot_product(&to_origin) > 0.9999);
+        // looking down onto the model from above
+        assert!(camera.position.y > 0.0);
+    }
+
+    #[test]
+    fn thumbnail_camera_frames_the_bounds_of_an_off_origin_model() {
+        let params = ThumbnailParams::default();
+        // a 4 x 2 x 4 box centered on (12, 1, -2) so half of its diagonal is 3
+        let model = TexturedModel { bounding_radius: Some(14.3), bounds: Some((Vector3f::new(10.0, 0.0, -4.0), Vector3f::new(14.0, 2.0, 0.0))), ..TexturedModel::default() };
+        let camera = Thumbnail::camera(&model, 60.0, &params);
+
+        let center = Vector3f::new(12.0, 1.0, -2.0);
+        let mut to_center = &center - &camera.position;
+        let distance = to_center.length();
+        assert!((distance - Thumbnail::framing_distance(Some(3.0), 60.0, params.margin)).abs() < 1e-4);
+        to_center.normalize();
+        let (forward, _right) = camera.view_directions();
+        assert!(forward.dot_product(&to_center) > 0.9999);
+        assert!((&camera.looking_at - &center).length() < 1e-5);
+    }
+}
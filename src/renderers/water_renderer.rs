@@ -1,22 +1,113 @@
 use crate::display::{
-    Display,
+    DepthMode,
     framebuffers::FboMap,
 };
 use crate::entities::{
     Camera,
+    FogCutoff,
     Light,
     WaterTile,
 };
 use crate::gl;
-use crate::models::{
-    RawModel,
-};
+use crate::models::RawModel;
 use crate::math::{
+    Color,
     Matrix4f,
-    Vector3f,
+    Vector4f,
 };
 use crate::shaders::WaterShader;
 use super::master_renderer::RenderGroup;
+use super::attrib_array_scope::AttribArrayScope;
+
+// the offscreen passes the water surface samples from
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum WaterPass {
+    Reflection,
+    Refraction,
+}
+
+impl WaterPass {
+    pub fn fbo_name(&self) -> &'static str {
+        match self {
+            WaterPass::Reflection => FboMap::REFLECTION_FBO,
+            WaterPass::Refraction => FboMap::REFRACTION_FBO,
+        }
+    }
+}
+
+// colors the reflection and refraction fbos get cleared to before the water passes
+// wherever nothing is rendered in them this is what shows up on the water surface
+#[derive(Clone, Debug)]
+pub struct WaterPassClearColors {
+    pub reflection: Color,
+    pub refraction: Color,
+}
+
+impl WaterPassClearColors {
+    // murky water tint so that areas below the water which have nothing rendered in them don't show the sky
+    pub const DEFAULT_REFRACTION_COLOR: Color = Color::rgb(0.1, 0.25, 0.3);
+
+    pub fn with_sky_color(sky_color: &Color) -> Self {
+        WaterPassClearColors {
+            reflection: sky_color.clone(),
+            refraction: WaterPassClearColors::DEFAULT_REFRACTION_COLOR,
+        }
+    }
+
+    pub fn clear_color(&self, pass: WaterPass) -> &Color {
+        match pass {
+            WaterPass::Reflection => &self.reflection,
+            WaterPass::Refraction => &self.refraction,
+        }
+    }
+}
+
+// what gets rendered into the refraction fbo, the water shader always needs its depth to fade the water out at the shore
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RefractionMode {
+    // the scene below the water is fully shaded and shows through the water surface
+    Full,
+    // only depth is rendered, the water then shows the refraction clear color faded by how deep the water is
+    DepthOnly,
+}
+
+impl Default for RefractionMode {
+    fn default() -> Self {
+        RefractionMode::Full
+    }
+}
+
+impl RefractionMode {
+    pub fn uses_color_shaders(&self) -> bool {
+        *self == RefractionMode::Full
+    }
+}
+
+// how the reflection pass cuts away what is below the water
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReflectionClipMode {
+    // gl_ClipDistance in the shaders, not honored by all drivers
+    ClipDistance,
+    // the near plane of the reflection projection is moved onto the water surface so clipping needs no shader support
+    ObliqueProjection,
+}
+
+impl Default for ReflectionClipMode {
+    fn default() -> Self {
+        ReflectionClipMode::ClipDistance
+    }
+}
+
+impl ReflectionClipMode {
+    // the oblique near plane only works when the camera is on the clipped side of the plane, otherwise it would be behind the camera
+    pub fn oblique_view_plane(&self, view_matrix: &Matrix4f, clip_plane: &Vector4f) -> Option<Vector4f> {
+        if *self != ReflectionClipMode::ObliqueProjection {
+            return None;
+        }
+        let view_clip_plane = view_matrix.transform_plane(clip_plane);
+        if view_clip_plane.w < 0.0 { Some(view_clip_plane) } else { None }
+    }
+}
 
 pub struct WaterRenderer {
     shader: WaterShader,
@@ -26,11 +117,11 @@ pub struct WaterRenderer {
 impl WaterRenderer {
     const WATER_SPEED: f32 = 0.03;
 
-    pub fn new(projection_mat: &Matrix4f, sky_color: &Vector3f) -> Self {
+    pub fn new(projection_mat: &Matrix4f, fog_color: &Color) -> Self {
         let mut shader = WaterShader::new();
         shader.start();
         shader.load_projection_matrix(projection_mat);
-        shader.load_sky_color(sky_color);
+        shader.load_sky_color(fog_color);
         shader.connect_texture_units();
         shader.stop();        
         WaterRenderer {
@@ -39,13 +130,36 @@ impl WaterRenderer {
         }
     }
 
-    pub fn render(&mut self, water_tiles: &Vec<WaterTile>, framebuffers: &FboMap, camera: &Camera, display: &Display, lights: &Vec<Light>) {
+    pub fn update_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
+        self.shader.start();
+        self.shader.load_projection_matrix(projection_matrix);
+        self.shader.stop();
+    }
+
+    pub fn update_fog_color(&mut self, fog_color: &Color) {
+        self.shader.start();
+        self.shader.load_sky_color(fog_color);
+        self.shader.stop();
+    }
+
+    pub fn update_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.shader.start();
+        self.shader.load_fog_cutoff(fog_cutoff);
+        self.shader.stop();
+    }
+
+    pub fn update_depth_mode(&mut self, depth_mode: DepthMode) {
+        self.shader.start();
+        self.shader.load_depth_mode(depth_mode);
+        self.shader.stop();
+    }
+
+    pub fn render<'a>(&mut self, water_tiles: impl IntoIterator<Item = &'a WaterTile>, framebuffers: &FboMap, camera: &Camera, lights: &Vec<Light>) {
         gl::helper::push_debug_group(RenderGroup::DRAW_WATER.id, RenderGroup::DRAW_WATER.name);
 
         self.shader.start();
         self.shader.load_camera(camera);
         
-        self.update_wave_factor(display);
         self.shader.load_wave_factor(self.wave_factor);
 
         self.shader.load_lights(lights);
@@ -68,9 +182,10 @@ impl WaterRenderer {
         for water_tile in water_tiles {
             let transform_matrix = &water_tile.transform;
             self.shader.load_transform_matrix(transform_matrix);
+            self.shader.load_water_props(&water_tile.model.props);
 
             gl::bind_vertex_array(water_tile.model.raw_model.vao_id);
-            gl::enable_vertex_attrib_array(RawModel::POS_ATTRIB);
+            let attrib_arrays = AttribArrayScope::enable(&[RawModel::POS_ATTRIB]);
             
             gl::active_texture(gl::TEXTURE2);
             gl::bind_texture(gl::TEXTURE_2D, water_tile.model.dudv_tex_id.unwrap());
@@ -79,7 +194,7 @@ impl WaterRenderer {
 
             gl::draw_arrays(gl::TRIANGLE_STRIP, 0, water_tile.model.raw_model.vertex_count);
 
-            gl::disable_vertex_attrib_array(RawModel::POS_ATTRIB);
+            drop(attrib_arrays);
             gl::bind_vertex_array(0);
         }
 
@@ -89,8 +204,56 @@ impl WaterRenderer {
         gl::helper::pop_debug_group();
     }
 
-    fn update_wave_factor(&mut self, display: &Display) {
-        self.wave_factor += WaterRenderer::WATER_SPEED * display.frame_time_sec;
+    // once per frame, render may be called once per water height
+    pub fn update_wave_factor(&mut self, frame_time_sec: f32) {
+        self.wave_factor += WaterRenderer::WATER_SPEED * frame_time_sec;
         self.wave_factor %= 1.0;
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::framebuffers::FboFlags;
+
+    #[test]
+    fn each_water_fbo_gets_its_configured_clear_color() {
+        let clear_colors = WaterPassClearColors {
+            reflection: Color::rgb(0.5, 0.6, 0.7),
+            refraction: Color::rgb(0.1, 0.2, 0.3),
+        };
+        assert_eq!(clear_colors.clear_color(WaterPass::Reflection), &Color::rgb(0.5, 0.6, 0.7));
+        assert_eq!(clear_colors.clear_color(WaterPass::Refraction), &Color::rgb(0.1, 0.2, 0.3));
+        assert_eq!(WaterPass::Reflection.fbo_name(), FboMap::REFLECTION_FBO);
+        assert_eq!(WaterPass::Refraction.fbo_name(), FboMap::REFRACTION_FBO);
+    }
+
+    #[test]
+    fn default_reflection_clears_to_sky_color() {
+        let sky_color = Color::rgb(0.5444, 0.62, 0.69);
+        let clear_colors = WaterPassClearColors::with_sky_color(&sky_color);
+        assert_eq!(clear_colors.reflection, sky_color);
+        assert_eq!(clear_colors.refraction, WaterPassClearColors::DEFAULT_REFRACTION_COLOR);
+    }
+
+    #[test]
+    fn refraction_fbo_keeps_the_depth_the_water_shader_samples() {
+        // the depth only refraction draws into this attachment
+        assert!(FboMap::refraction_fbo_flags().contains(FboFlags::DEPTH_TEX));
+    }
+
+    #[test]
+    fn oblique_reflection_clip_needs_camera_on_clipped_side() {
+        // clip plane keeping everything above y = 0
+        let clip_plane = Vector4f::new(0.0, 1.0, 0.0, 0.0);
+        let mut above = Matrix4f::identity();
+        above.translate(&crate::math::Vector3f::new(0.0, -5.0, 0.0));
+        let mut below = Matrix4f::identity();
+        below.translate(&crate::math::Vector3f::new(0.0, 5.0, 0.0));
+
+        assert!(ReflectionClipMode::ClipDistance.oblique_view_plane(&below, &clip_plane).is_none());
+        assert!(ReflectionClipMode::ObliqueProjection.oblique_view_plane(&above, &clip_plane).is_none());
+        let view_plane = ReflectionClipMode::ObliqueProjection.oblique_view_plane(&below, &clip_plane).unwrap();
+        assert!(view_plane.w < 0.0);
+    }
+}
@@ -12,6 +12,7 @@ use crate::entities::{
     Light,
     Player,
     Ground,
+    TerrainPlacement,
     Skybox,
     Terrain,
     DebugEntity,
@@ -21,16 +22,21 @@ use crate::guis::{
     GuiPanel,
     TextMaterial,
 };
-use crate::math::{Matrix4f, Vector3f, Vector2f};
+use crate::math::{Color, Matrix4f, Vector3f, Vector2f};
+use crate::renderers::master_renderer::SkyColors;
+use crate::renderers::gui_renderer::GuiRenderTarget;
 use crate::models::{
     ResourceManager,
     Models,
     ModelType,
     CorrectionTransform,
+    ParticleBlendMode,
 };
 use crate::particles::{
     AdvancedParticleSystem,
     ParticleSystemProps,
+    TerrainCollision,
+    ParticleRotationMode,
 }; 
 
 pub fn init_scene_resources(resource_manager: &mut ResourceManager) {
@@ -61,7 +67,7 @@ pub fn init_scene_resources(resource_manager: &mut ResourceManager) {
     resource_manager.init_fonts();
 
     resource_manager.init_particle_model();
-    resource_manager.init_particle_textures();
+    resource_manager.init_particle_textures(&[ResourceManager::PARTICLE_ATLAS, ResourceManager::FIRE_ATLAS, ResourceManager::SMOKE_ATLAS]);
     // debug entity
     resource_manager.init_debug_cuboid_model();
 }
@@ -80,15 +86,17 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         }
     }
     let ground = Ground { terrains };
+    let tree_placement = TerrainPlacement::default();
+    let rock_placement = TerrainPlacement { max_slope_deg: 45.0, aligns_to_normal: true, ..TerrainPlacement::default() };
 
     for _ in 0..100 {
         // let r_pos = ground.create_pos_on_terrain(rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH);
         // let r_rot = Vector3f::new(0.0, 0.0, 0.0);
         // entities.push(Entity::new(resource_manager.model(ModelType::Tree), r_pos, r_rot, 3.0));
 
-        let r_pos = ground.create_pos_on_terrain(rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH);
-        let r_rot = Vector3f::new(0.0, 0.0, 0.0);
-        entities.push(Entity::new(resource_manager.model(ModelType::LowPolyTree), r_pos, r_rot, 0.5));
+        if let Some(spot) = ground.find_spot_on_terrain(&tree_placement, || (rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH)) {
+            entities.push(Entity::new(resource_manager.model(ModelType::LowPolyTree), spot.position, spot.rotation_deg, 0.5));
+        }
 
         let r_pos = ground.create_pos_on_terrain(rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH);
         let r_rot = Vector3f::new(0.0, rng.gen::<f32>() * 180.0, 0.0);
@@ -104,13 +112,15 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         // let r_rot = Vector3f::new(0.0, rng.gen::<f32>() * 180.0, 0.0);
         // entities.push(Entity::new(resource_manager.model(ModelType::Flowers), r_pos, r_rot, 1.0));
 
-        let r_pos = ground.create_pos_on_terrain(rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH);
-        let r_rot = Vector3f::new(0.0, rng.gen::<f32>() * 180.0, 0.0);
-        entities.push(Entity::new(resource_manager.model(ModelType::BobbleTree), r_pos, r_rot, 0.5));
+        if let Some(spot) = ground.find_spot_on_terrain(&tree_placement, || (rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH)) {
+            let r_rot = Vector3f::new(0.0, rng.gen::<f32>() * 180.0, 0.0);
+            entities.push(Entity::new(resource_manager.model(ModelType::BobbleTree), spot.position, r_rot, 0.5));
+        }
 
-        let r_pos = ground.create_pos_on_terrain(rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH);
-        let r_rot = Vector3f::new(0.0, rng.gen::<f32>() * 180.0, 0.0);
-        entities.push(Entity::new(resource_manager.model(ModelType::ToonRocks), r_pos, r_rot, 1.0));
+        if let Some(spot) = ground.find_spot_on_terrain(&rock_placement, || (rng.gen::<f32>() * X_WIDTH - X_WIDTH/2.0, rng.gen::<f32>() * Z_WIDTH)) {
+            let r_rot = spot.rotation_with_yaw_deg(rng.gen::<f32>() * 180.0);
+            entities.push(Entity::new(resource_manager.model(ModelType::ToonRocks), spot.position, r_rot, 1.0));
+        }
     }    
 
     ///////////// animated player but without all the fancy shaders ///////////////////////
@@ -146,7 +156,7 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         resource_manager.create_gui_text("hello\nworld", 
             ResourceManager::COPPER_SDF_FONT_TYPE, 4, Vector2f::new(-0.8, -0.60), 
             TextMaterial {
-                color: Vector3f::new(1.0, 0.0, 0.0), 
+                color: Color::rgb(1.0, 0.0, 0.0), 
                 width: 0.5, edge: 0.3,
                 outline_width: 0.5, outline_edge: 0.4,
                 ..TextMaterial::default()
@@ -155,8 +165,8 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         resource_manager.create_gui_text("Made with Rust", 
             ResourceManager::COPPER_SDF_FONT_TYPE, 4, Vector2f::new(0.3, -0.95), 
             TextMaterial {
-                color: Vector3f::new(0.0, 0.0, 1.0), 
-                outline_color: Vector3f::new(0.0, 0.0, 0.0),
+                color: Color::rgb(0.0, 0.0, 1.0), 
+                outline_color: Color::BLACK,
                 offset: Vector2f::new(-0.002, -0.002),
                 outline_width: 0.5, outline_edge: 0.4,
                 ..TextMaterial::default()
@@ -177,15 +187,18 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
     entities.push(Entity::new(resource_manager.model(ModelType::Lantern), ground.create_pos_on_terrain(130.0, -190.0), Vector3f::new(0.0, 0.0, 0.0), 1.0));
 
     let lights = vec!{        
-        Light::new_infinite(Vector3f::new(5000.0, 10000.0, 5000.0), Vector3f::new(1.0, 1.0, 1.0)), // sunlight, no attenuation
-        Light::new_point(ground.create_pos_above_terrain(185.0,12.5,-293.0), Vector3f::new(2.0, 0.0, 0.0), Vector3f::new(1.0, 0.01, 0.002)),
-        Light::new_point(ground.create_pos_above_terrain(370.0,14.0,-300.0), Vector3f::new(0.0, 2.0, 2.0), Vector3f::new(1.0, 0.01, 0.002)),
-        Light::new_point(ground.create_pos_above_terrain(120.0,14.0,-240.0), Vector3f::new(2.0, 2.0, 0.0), Vector3f::new(1.0, 0.01, 0.002)),        
+        Light::new_infinite(Vector3f::new(5000.0, 10000.0, 5000.0), Color::rgb(1.0, 1.0, 1.0)), // sunlight, no attenuation
+        Light::new_point(ground.create_pos_above_terrain(185.0,12.5,-293.0), Color::rgb(2.0, 0.0, 0.0), Vector3f::new(1.0, 0.01, 0.002)),
+        Light::new_point(ground.create_pos_above_terrain(370.0,14.0,-300.0), Color::rgb(0.0, 2.0, 2.0), Vector3f::new(1.0, 0.01, 0.002)),
+        Light::new_point(ground.create_pos_above_terrain(120.0,14.0,-240.0), Color::rgb(2.0, 2.0, 0.0), Vector3f::new(1.0, 0.01, 0.002)),        
     };
     // add lamps 
-    entities.push(Entity::new(resource_manager.model(ModelType::Lamp), ground.create_pos_on_terrain(185.0, -293.0), Vector3f::new(0.0, 0.0, 0.0), 1.0));
-    entities.push(Entity::new(resource_manager.model(ModelType::Lamp), ground.create_pos_on_terrain(370.0, -300.0), Vector3f::new(0.0, 0.0, 0.0), 1.0));
-    entities.push(Entity::new(resource_manager.model(ModelType::Lamp), ground.create_pos_on_terrain(120.0, -240.0), Vector3f::new(0.0, 0.0, 0.0), 1.0));
+    let lamp_transforms = [
+        (ground.create_pos_on_terrain(185.0, -293.0), Vector3f::new(0.0, 0.0, 0.0), 1.0),
+        (ground.create_pos_on_terrain(370.0, -300.0), Vector3f::new(0.0, 0.0, 0.0), 1.0),
+        (ground.create_pos_on_terrain(120.0, -240.0), Vector3f::new(0.0, 0.0, 0.0), 1.0),
+    ];
+    entities.extend(resource_manager.spawn_many(ModelType::Lamp, &lamp_transforms));
 
     // particle effect systems
     let mut particle_spawn_point = player.position().clone();
@@ -196,8 +209,11 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
             particles_per_sec: 60.0, speed: 15.0, scale: 2.5, 
             gravity_effect: 0.5, life_length: 1.5, 
             speed_error: 0.3, life_error: 0.3, scale_error: 0.3, 
-            randomize_rotation: true, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 45.0)),
-            additive_blending: false,
+            rotation_mode: ParticleRotationMode::Random, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 45.0)),
+            blend_mode: ParticleBlendMode::Alpha,
+            attractors: Vec::new(),
+            max_particles: None,
+            terrain_collision: TerrainCollision::Bounce { restitution: 0.5 },
         }
     );
     let mut particle_spawn_point_fire = player.position().clone();
@@ -208,8 +224,11 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
             particles_per_sec: 60.0, speed: 15.0, scale: 7.0, 
             gravity_effect: 0.0, life_length: 1.0, 
             speed_error: 0.3, life_error: 0.7, scale_error: 0.5, 
-            randomize_rotation: true, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 65.0)),
-            additive_blending: true,
+            rotation_mode: ParticleRotationMode::Random, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 65.0)),
+            blend_mode: ParticleBlendMode::Additive,
+            attractors: Vec::new(),
+            max_particles: None,
+            terrain_collision: TerrainCollision::None,
         }
     );
     let mut particle_spawn_point_smoke = player.position().clone();
@@ -220,8 +239,11 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
             particles_per_sec: 30.0, speed: 15.0, scale: 6.5, 
             gravity_effect: 0.05, life_length: 1.5, 
             speed_error: 0.3, life_error: 0.3, scale_error: 0.1, 
-            randomize_rotation: true, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 50.0)),
-            additive_blending: false,
+            rotation_mode: ParticleRotationMode::Random, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 50.0)),
+            blend_mode: ParticleBlendMode::Alpha,
+            attractors: Vec::new(),
+            max_particles: None,
+            terrain_collision: TerrainCollision::None,
         }
     );
     let particle_systems = vec![
@@ -246,5 +268,8 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         particle_systems,
         uses_post_processing: true,
         entities_with_env_map: Vec::new(),
+        sky_colors: SkyColors::default(),
+        gui_target: GuiRenderTarget::default(),
+        terrain_streaming: None,
     }
 }
\ No newline at end of file
@@ -10,7 +10,9 @@ use crate::entities::{
     Skybox,
     DebugEntity,
 };
-use crate::math::Vector3f;
+use crate::math::{Color, Vector3f};
+use crate::renderers::master_renderer::SkyColors;
+use crate::renderers::gui_renderer::GuiRenderTarget;
 use crate::models::{
     ResourceManager,
     Models,
@@ -60,8 +62,8 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
     let texts = Vec::new();
     
     let lights = vec!{
-        //Light::new_infinite(Vector3f::new(0.0, 10000.0, 0.0), Vector3f::new(0.8, 0.8, 0.8)), // sunlight, no attenuation
-        Light::new_infinite(Vector3f::new(5000.0, 10000.0, -5000.0), Vector3f::new(0.8, 0.8, 0.8)), // sunlight, no attenuation
+        //Light::new_infinite(Vector3f::new(0.0, 10000.0, 0.0), Color::rgb(0.8, 0.8, 0.8)), // sunlight, no attenuation
+        Light::new_infinite(Vector3f::new(5000.0, 10000.0, -5000.0), Color::rgb(0.8, 0.8, 0.8)), // sunlight, no attenuation
     };
 
     let particle_systems = Vec::new();
@@ -90,5 +92,8 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         particle_systems,
         uses_post_processing: false,
         entities_with_env_map,
+        sky_colors: SkyColors::default(),
+        gui_target: GuiRenderTarget::default(),
+        terrain_streaming: None,
     }
 }
\ No newline at end of file
@@ -10,7 +10,9 @@ use crate::entities::{
     Skybox,
     DebugEntity,
 };
-use crate::math::{Vector3f};
+use crate::math::{Color, Vector3f};
+use crate::renderers::master_renderer::SkyColors;
+use crate::renderers::gui_renderer::GuiRenderTarget;
 use crate::models::{
     ResourceManager,
     Models,
@@ -18,10 +20,13 @@ use crate::models::{
     SkyboxModel,
     RawModel,
     TextureId,
+    ParticleBlendMode,
 };
 use crate::particles::{
     AdvancedParticleSystem,
     ParticleSystemProps,
+    TerrainCollision,
+    ParticleRotationMode,
 };
 
 pub fn init_scene_resources(resource_manager: &mut ResourceManager) {
@@ -34,7 +39,7 @@ pub fn init_scene_resources(resource_manager: &mut ResourceManager) {
 
     resource_manager.init_simple_point_particle_model();
     resource_manager.init_particle_model();
-    resource_manager.init_particle_textures();
+    resource_manager.init_particle_textures(&[ResourceManager::SMOKE_ATLAS]);
 }
 
 // to use the particles in this example you need to use the correct renderer in the ParticleMaster
@@ -59,13 +64,13 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
     let mut camera = Camera::new(20.0, 30.0);
     camera.position = Vector3f::new(0.0, 0.0, 0.0);
 
-    let skybox = Skybox::new(SkyboxModel {raw_model: RawModel {vao_id: 0, vertex_count: 0}, day_texture_id: TextureId::Empty, night_texture_id: TextureId::Empty, cycles_day_night: false}, 0.0);
+    let skybox = Skybox::new(SkyboxModel {raw_model: RawModel::new(0, 0), day_texture_id: TextureId::Empty, night_texture_id: TextureId::Empty, cycles_day_night: false}, 0.0);
 
     let texts = Vec::new();
     
     let lights = vec!{
-        //Light::new_infinite(Vector3f::new(0.0, 10000.0, 0.0), Vector3f::new(0.8, 0.8, 0.8)), // sunlight, no attenuation
-        Light::new_infinite(Vector3f::new(5000.0, 10000.0, -5000.0), Vector3f::new(0.8, 0.8, 0.8)), // sunlight, no attenuation
+        //Light::new_infinite(Vector3f::new(0.0, 10000.0, 0.0), Color::rgb(0.8, 0.8, 0.8)), // sunlight, no attenuation
+        Light::new_infinite(Vector3f::new(5000.0, 10000.0, -5000.0), Color::rgb(0.8, 0.8, 0.8)), // sunlight, no attenuation
     };
 
     let particle_spawn = player.position().clone();    
@@ -74,8 +79,11 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
             particles_per_sec: 50.0, speed: 15.0, scale: 6.5, 
             gravity_effect: 0.5, life_length: 1.5, 
             speed_error: 0.3, life_error: 0.3, scale_error: 0.1, 
-            randomize_rotation: true, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 150.0)),
-            additive_blending: false,
+            rotation_mode: ParticleRotationMode::Random, direction: Some((Vector3f::new(0.0, 1.0, 0.0), 150.0)),
+            blend_mode: ParticleBlendMode::Alpha,
+            attractors: Vec::new(),
+            max_particles: None,
+            terrain_collision: TerrainCollision::None,
         }
     );
 
@@ -101,5 +109,8 @@ pub fn create_scene(resource_manager: &mut ResourceManager, _framebuffers: &FboM
         particle_systems,
         uses_post_processing: false,
         entities_with_env_map: Vec::new(),
+        sky_colors: SkyColors::default(),
+        gui_target: GuiRenderTarget::default(),
+        terrain_streaming: None,
     }
 }
\ No newline at end of file
@@ -3,8 +3,8 @@ use crate::models::{
     QuadModel,
 };
 use crate::math::{
+    Color,
     Vector2f,
-    Vector3f,
 };
 use crate::guis::{
     GuiPanel,
@@ -12,6 +12,33 @@ use crate::guis::{
     TextMaterial,
 };
 
+// where the startup is at, advanced once per rendered frame so that the window keeps processing events while loading
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LoadStage {
+    // waiting for the textures the load screen itself needs
+    LoadScreen,
+    // showing the load screen while the scene resources load
+    Scene,
+    Done,
+}
+
+impl Default for LoadStage {
+    fn default() -> Self {
+        LoadStage::LoadScreen
+    }
+}
+
+impl LoadStage {
+    // textures_loading is the result of pumping ResourceManager::are_textures_loading for this frame
+    pub fn next(self, textures_loading: bool) -> LoadStage {
+        match self {
+            LoadStage::LoadScreen if !textures_loading => LoadStage::Scene,
+            LoadStage::Scene if !textures_loading => LoadStage::Done,
+            stage => stage,
+        }
+    }
+}
+
 pub struct LoadScreen {
     pub guis: Vec<GuiPanel>,
     pub texts: Vec<GuiText>,
@@ -36,7 +63,7 @@ pub fn create_load_screen(resource_manager: &mut ResourceManager) -> LoadScreen
         resource_manager.create_gui_text("Loading the world...", 
             ResourceManager::COPPER_SDF_FONT_TYPE, 4, Vector2f::new(-0.25, 0.0), 
             TextMaterial {
-                color: Vector3f::new(1.0, 0.0, 0.0), 
+                color: Color::rgb(1.0, 0.0, 0.0), 
                 width: 0.5, edge: 0.3,
                 outline_width: 0.5, outline_edge: 0.4,
                 ..TextMaterial::default()
@@ -49,4 +76,39 @@ pub fn create_load_screen(resource_manager: &mut ResourceManager) -> LoadScreen
         texts,
         gui_model: resource_manager.quad_model(),
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // like ResourceManager::are_textures_loading with an upload budget of one texture per call
+    fn pump(pending_textures: &mut usize) -> bool {
+        if *pending_textures == 0 {
+            return false;
+        }
+        *pending_textures -= 1;
+        true
+    }
+
+    #[test]
+    fn loading_completes_after_all_textures_are_pumped() {
+        let load_screen_textures = 2;
+        let scene_textures = 3;
+        let mut pending_textures = load_screen_textures;
+
+        let mut stage = LoadStage::default();
+        let mut pump_calls = 0;
+        while stage != LoadStage::Done {
+            let next_stage = stage.next(pump(&mut pending_textures));
+            pump_calls += 1;
+            if stage == LoadStage::LoadScreen && next_stage == LoadStage::Scene {
+                pending_textures = scene_textures;
+            }
+            stage = next_stage;
+            assert!(pump_calls < 100, "Loading never finished");
+        }
+        // every texture takes one call and each stage needs one more call to see that nothing is loading anymore
+        assert_eq!(pump_calls, load_screen_textures + scene_textures + 2);
+    }
+}
@@ -4,4 +4,5 @@ pub mod test_scene;
 pub mod simple_floor_scene;
 pub mod geometry_shader_particles_test_scene;
 pub mod environment_map_scene;
-pub mod load_screen;
\ No newline at end of file
+pub mod load_screen;
+pub mod render_snapshot;
\ No newline at end of file
@@ -0,0 +1,33 @@
+use crate::entities::{
+    Entity,
+    Camera,
+    DebugEntity,
+    Light,
+};
+use super::scene::Scene;
+
+// copy of the scene state the update phase mutates and the render phase reads
+// the renderer only ever sees the snapshot so it can't change the scene (e.g. the camera flips for the water reflection
+// and the debug entity gets moved to the shadow box) and the next update could run while the snapshot is still being drawn
+#[derive(Clone)]
+pub struct RenderSnapshot {
+    pub camera: Camera,
+    pub lights: Vec<Light>,
+    pub entities: Vec<Entity>,
+    pub normal_mapped_entities: Vec<Entity>,
+    pub entities_with_env_map: Vec<Entity>,
+    pub debug_entity: DebugEntity,
+}
+
+impl RenderSnapshot {
+    pub fn capture(scene: &Scene) -> RenderSnapshot {
+        RenderSnapshot {
+            camera: scene.camera.clone(),
+            lights: scene.lights.clone(),
+            entities: scene.entities.clone(),
+            normal_mapped_entities: scene.normal_mapped_entities.clone(),
+            entities_with_env_map: scene.entities_with_env_map.clone(),
+            debug_entity: scene.debug_entity.clone(),
+        }
+    }
+}
@@ -1,3 +1,9 @@
+use crate::animations::animator::Animator;
+use crate::display::{
+    Display,
+    Key,
+    Keyboard,
+};
 use crate::entities::{
     Entity,
     Camera,
@@ -7,14 +13,35 @@ use crate::entities::{
     Skybox,
     WaterTile,
     DebugEntity,
+    CameraMode,
+    TerrainTileManager,
+    player::PlayerEntityType,
 };
 use crate::math::Vector3f;
-use crate::models::QuadModel;
+use crate::renderers::master_renderer::SkyColors;
+use crate::renderers::gui_renderer::GuiRenderTarget;
+use crate::mouse_picker::MousePicker;
+use crate::models::{
+    QuadModel,
+    HeightsGenerator,
+};
 use crate::guis::{
     GuiPanel,
     GuiText,
 };
 use crate::particles::AdvancedParticleSystem;
+use super::render_snapshot::RenderSnapshot;
+
+struct NoInput;
+
+impl Keyboard for NoInput {
+    fn is_pressed(&self, _key: Key) -> bool {
+        false
+    }
+    fn is_mouse_select_active(&self) -> bool {
+        false
+    }
+}
 
 pub struct Scene {
     pub entities: Vec<Entity>, 
@@ -33,4 +60,148 @@ pub struct Scene {
     pub particle_systems: Vec<(AdvancedParticleSystem, Vector3f)>,
     pub uses_post_processing: bool,
     pub entities_with_env_map: Vec<Entity>,
-}
\ No newline at end of file
+    // clear and fog colors the master renderer uses for this scene
+    pub sky_colors: SkyColors,
+    // the gui renderer draws the guis and texts straight to the screen or composites them from their own texture
+    pub gui_target: GuiRenderTarget,
+    // for worlds made of generated tiles that are loaded around the player (ResourceManager::stream_terrain_tiles)
+    pub terrain_streaming: Option<(TerrainTileManager, HeightsGenerator)>,
+}
+
+impl Scene {
+    const NORMAL_MAPPED_SPIN_SPEED: f32 = 20.0;
+
+    pub fn update(&mut self, display: &Display, animator: &Animator, mouse_picker: &mut MousePicker) {
+        self.update_animations(animator, display.frame_time_sec);
+        self.camera.move_camera(display, &self.player, &self.ground);
+        self.update_mouse_picker_and_move_lamp(mouse_picker, display);
+        self.update_world(display, display.frame_time_sec);
+    }
+
+    // taken after the update, everything the renderer needs to draw this frame
+    pub fn create_render_snapshot(&self) -> RenderSnapshot {
+        RenderSnapshot::capture(self)
+    }
+
+    // the part of the update that only needs input and time so it can also run without a window
+    pub fn update_world(&mut self, keyboard: &dyn Keyboard, frame_time_sec: f32) {
+        self.spin_around_normal_mapped_entities(frame_time_sec);
+        // the free fly camera uses the movement keys itself
+        let player_keyboard = if self.camera.mode == CameraMode::FreeFly { &NoInput as &dyn Keyboard } else { keyboard };
+        self.player.move_player(player_keyboard, frame_time_sec, &self.ground);
+        self.skybox.increase_rotation(frame_time_sec);
+    }
+
+    fn update_animations(&mut self, animator: &Animator, frame_time_sec: f32) {
+        let moving = self.player.is_moving();
+        if let PlayerEntityType::AnimatedModelEntity(animated_model) = &mut self.player.entity {
+            if moving {
+                animated_model.model.animation.play();
+            } else {
+                animated_model.model.animation.stop();
+            }
+            animator.update_animation(animated_model, frame_time_sec);
+        }
+    }
+
+    fn update_mouse_picker_and_move_lamp(&mut self, mouse_picker: &mut MousePicker, display: &Display) {
+        if let Some(selected_pos) = mouse_picker.update(display, &display.projection_matrix, &self.camera, &self.ground) {
+            // the lamp is expected to be the last entity and its light the fourth one
+            if self.entities.is_empty() || self.lights.len() < 4 {
+                return;
+            }
+            let last_pos = self.entities.len()-1;
+            self.entities[last_pos].set_position(&selected_pos);
+            self.lights[3].position = selected_pos;
+            self.lights[3].position.y += 14.0; 
+        }
+    }
+
+    fn spin_around_normal_mapped_entities(&mut self, frame_time_sec: f32) {
+        for entity in self.normal_mapped_entities.iter_mut() {
+            entity.increase_rotation(0.0, 0.0, Scene::NORMAL_MAPPED_SPIN_SPEED * frame_time_sec);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::display::Key;
+    use crate::models::{
+        DynamicVertexIndexedModel,
+        RawModel,
+        SkyboxModel,
+        TexturedModel,
+        TextureId,
+    };
+
+    struct ForwardKeyboard;
+
+    impl Keyboard for ForwardKeyboard {
+        fn is_pressed(&self, key: Key) -> bool {
+            key == Key::W
+        }
+        fn is_mouse_select_active(&self) -> bool {
+            false
+        }
+    }
+
+    fn create_test_scene() -> Scene {
+        let textured_model = TexturedModel::default();
+        let skybox_model = SkyboxModel { raw_model: RawModel::default(), day_texture_id: TextureId::Empty, night_texture_id: TextureId::Empty, cycles_day_night: false };
+        Scene {
+            entities: Vec::new(),
+            normal_mapped_entities: Vec::new(),
+            ground: Ground { terrains: Vec::new() },
+            player: Player::new(Entity::new(textured_model, Vector3f::zero(), Vector3f::zero(), 1.0)),
+            quad_model: QuadModel { raw_model: RawModel::default() },
+            water: Vec::new(),
+            debug_entity: DebugEntity::new(DynamicVertexIndexedModel { raw_model: RawModel::default(), stream_draw_vbo: 0 }),
+            camera: Camera::default(),
+            skybox: Skybox::new(skybox_model, 0.0),
+            texts: Vec::new(),
+            guis: Vec::new(),
+            lights: Vec::new(),
+            particle_systems: Vec::new(),
+            uses_post_processing: false,
+            entities_with_env_map: Vec::new(),
+            sky_colors: SkyColors::default(),
+            gui_target: GuiRenderTarget::default(),
+            terrain_streaming: None,
+        }
+    }
+
+    #[test]
+    fn update_world_advances_skybox_and_player() {
+        let mut scene = create_test_scene();
+        let frame_time = 0.1;
+        scene.update_world(&ForwardKeyboard, frame_time);
+
+        assert!((scene.skybox.rotation_deg - scene.skybox.rotate_speed * frame_time).abs() < 1e-6);
+        let expected_z = Player::RUN_SPEED * frame_time;
+        assert!((scene.player.position().z - expected_z).abs() < 1e-5, "Player z was {}, expected {}", scene.player.position().z, expected_z);
+        assert_eq!(scene.player.position().x, 0.0);
+        assert_eq!(scene.player.position().y, 0.0);
+    }
+
+    #[test]
+    fn render_snapshot_is_not_affected_by_later_updates() {
+        let mut scene = create_test_scene();
+        scene.normal_mapped_entities.push(Entity::new(TexturedModel::default(), Vector3f::new(1.0, 2.0, 3.0), Vector3f::zero(), 1.0));
+        scene.camera.pitch = 20.0;
+
+        let snapshot = scene.create_render_snapshot();
+        scene.update_world(&ForwardKeyboard, 0.5);
+        scene.normal_mapped_entities[0].set_position(&Vector3f::new(5.0, 5.0, 5.0));
+        scene.camera.pitch = -20.0;
+        scene.debug_entity.position = Vector3f::new(7.0, 7.0, 7.0);
+
+        let captured = &snapshot.normal_mapped_entities[0];
+        assert_eq!((captured.position.x, captured.position.y, captured.position.z), (1.0, 2.0, 3.0));
+        assert_eq!(captured.rotation_deg.z, 0.0);
+        assert!(scene.normal_mapped_entities[0].rotation_deg.z != 0.0);
+        assert_eq!(snapshot.camera.pitch, 20.0);
+        assert_eq!(snapshot.debug_entity.position.x, 0.0);
+    }
+}
@@ -1,6 +1,9 @@
 use super::scene::Scene;
 
-use crate::display::framebuffers::FboMap;
+use crate::display::framebuffers::{
+    FboMap,
+    FboTexture,
+};
 use crate::entities::{
     Entity,
     Camera,
@@ -11,12 +14,13 @@ use crate::entities::{
     DebugEntity,
 };
 use crate::guis::GuiPanel;
-use crate::math::{Vector3f, Vector2f};
+use crate::math::{Color, Vector3f, Vector2f};
+use crate::renderers::master_renderer::SkyColors;
+use crate::renderers::gui_renderer::GuiRenderTarget;
 use crate::models::{
     ResourceManager,
     Models,
     ModelType,
-    TextureId,
 };
 
 pub fn init_scene_resources(resource_manager: &mut ResourceManager) {
@@ -67,16 +71,17 @@ pub fn create_scene(resource_manager: &mut ResourceManager, framebuffers: &FboMa
     let texts = Vec::new();
     
     let lights = vec!{
-        //Light::new_infinite(Vector3f::new(0.0, 10000.0, 0.0), Vector3f::new(0.8, 0.8, 0.8)), // sunlight, no attenuation
-        Light::new_infinite(Vector3f::new(5000.0, 10000.0, -5000.0), Vector3f::new(0.8, 0.8, 0.8)), // sunlight, no attenuation
+        //Light::new_infinite(Vector3f::new(0.0, 10000.0, 0.0), Color::rgb(0.8, 0.8, 0.8)), // sunlight, no attenuation
+        Light::new_infinite(Vector3f::new(5000.0, 10000.0, -5000.0), Color::rgb(0.8, 0.8, 0.8)), // sunlight, no attenuation
     };
 
     let particle_systems = Vec::new();
 
-    let shadow_map = framebuffers.fbos[FboMap::SHADOW_MAP_FBO].depth_texture.expect("Must have shadowmap to show it in gui");
-    let guis = vec!{
-        GuiPanel::new(TextureId::FboTexture(shadow_map), Vector2f::new(0.6, 0.6), Vector2f::new(0.4, 0.4)),
+    let mut guis = vec!{
+        GuiPanel::for_fbo_texture(FboMap::SHADOW_MAP_FBO, FboTexture::Depth, Vector2f::new(0.6, 0.6), Vector2f::new(0.4, 0.4)),
     };
+    framebuffers.resolve_gui_panels(&mut guis);
+    assert!(guis[0].texture_id.is_ready(), "Must have shadowmap to show it in gui");
 
     Scene {
         entities, 
@@ -94,5 +99,8 @@ pub fn create_scene(resource_manager: &mut ResourceManager, framebuffers: &FboMa
         particle_systems,
         uses_post_processing: false,
         entities_with_env_map: Vec::new(),
+        sky_colors: SkyColors::default(),
+        gui_target: GuiRenderTarget::default(),
+        terrain_streaming: None,
     }
 }
\ No newline at end of file
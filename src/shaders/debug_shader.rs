@@ -1,16 +1,23 @@
 use super::shader_program::ShaderProgram;
 use crate::models::RawModel;
-use crate::math::Matrix4f;
+use crate::math::{
+    Matrix4f,
+    Vector4f,
+};
 
 pub struct DebugShader {
     shader_program: ShaderProgram,
     location_mvp_matrix: i32,
+    location_uses_line_color: i32,
+    location_line_color: i32,
 }
 
 impl DebugShader {
 
     pub fn new() -> Self {
         let mut location_mvp_matrix = 0;
+        let mut location_uses_line_color = 0;
+        let mut location_line_color = 0;
         let shader_program = ShaderProgram::new(
             "res/shaders/test/debugVertShader.glsl",
             None,
@@ -20,11 +27,15 @@ impl DebugShader {
             },
             |shader_prog| {
                 location_mvp_matrix = shader_prog.get_uniform_location("mvp_matrix");
+                location_uses_line_color = shader_prog.get_uniform_location("uses_line_color");
+                location_line_color = shader_prog.get_uniform_location("line_color");
             }
         );
         DebugShader {
             shader_program,
             location_mvp_matrix,
+            location_uses_line_color,
+            location_line_color,
         }
     }
 
@@ -39,4 +50,12 @@ impl DebugShader {
     pub fn load_mvp_matrix(&mut self, mvp: &Matrix4f) {
         ShaderProgram::load_matrix(self.location_mvp_matrix, mvp);
     }
+
+    // None goes back to the per vertex colors of the cube
+    pub fn load_line_color(&mut self, line_color: Option<&Vector4f>) {
+        ShaderProgram::load_bool(self.location_uses_line_color, line_color.is_some());
+        if let Some(line_color) = line_color {
+            ShaderProgram::load_vector4d(self.location_line_color, line_color);
+        }
+    }
 }
\ No newline at end of file
@@ -0,0 +1,104 @@
+use super::shader_program::ShaderProgram;
+use crate::models::RawModel;
+use crate::math::{
+    Matrix4f,
+    Vector2f,
+    Vector4f,
+};
+
+// writes nothing but depth, used by passes that only need the depth buffer
+pub struct DepthOnlyShader {
+    shader_program: ShaderProgram,
+    location_transform: i32,
+    location_vp_matrix: i32,
+    location_clip_plane: i32,
+    location_uses_alpha_test: i32,
+    location_number_of_rows: i32,
+    location_texture_offset: i32,
+    location_uv_scale: i32,
+    location_uv_offset: i32,
+}
+
+impl DepthOnlyShader {
+    pub fn new() -> Self {
+        let (
+            mut location_transform,
+            mut location_vp_matrix,
+            mut location_clip_plane,
+            mut location_uses_alpha_test,
+            mut location_number_of_rows,
+            mut location_texture_offset,
+            mut location_uv_scale,
+            mut location_uv_offset,
+        ) = Default::default();
+
+        let shader_program = ShaderProgram::new(
+            "res/shaders/depthOnlyVertShader.glsl",
+            None,
+            "res/shaders/depthOnlyFragShader.glsl",
+            |shader_prog| {
+                shader_prog.bind_attribute(RawModel::POS_ATTRIB, "pos");
+                shader_prog.bind_attribute(RawModel::TEX_COORD_ATTRIB, "tex_coord");
+            },
+            |shader_prog| {
+                location_transform = shader_prog.get_uniform_location("transform");
+                location_vp_matrix = shader_prog.get_uniform_location("vp_matrix");
+                location_clip_plane = shader_prog.get_uniform_location("clip_plane");
+                location_uses_alpha_test = shader_prog.get_uniform_location("uses_alpha_test");
+                location_number_of_rows = shader_prog.get_uniform_location("number_of_rows");
+                location_texture_offset = shader_prog.get_uniform_location("texture_offset");
+                location_uv_scale = shader_prog.get_uniform_location("uv_scale");
+                location_uv_offset = shader_prog.get_uniform_location("uv_offset");
+            }
+        );
+        DepthOnlyShader {
+            shader_program,
+            location_transform,
+            location_vp_matrix,
+            location_clip_plane,
+            location_uses_alpha_test,
+            location_number_of_rows,
+            location_texture_offset,
+            location_uv_scale,
+            location_uv_offset,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.shader_program.start();
+    }
+
+    pub fn stop(&mut self) {
+        self.shader_program.stop();
+    }
+
+    pub fn load_transformation_matrix(&mut self, transform: &Matrix4f) {
+        ShaderProgram::load_matrix(self.location_transform, transform);
+    }
+
+    pub fn load_vp_matrix(&mut self, vp_matrix: &Matrix4f) {
+        ShaderProgram::load_matrix(self.location_vp_matrix, vp_matrix);
+    }
+
+    pub fn load_clip_plane(&mut self, clip_plane: &Vector4f) {
+        ShaderProgram::load_vector4d(self.location_clip_plane, clip_plane);
+    }
+
+    pub fn load_uses_alpha_test(&mut self, uses_alpha_test: bool) {
+        ShaderProgram::load_bool(self.location_uses_alpha_test, uses_alpha_test);
+    }
+
+    // the alpha test has to look at the same texels the entity shader draws
+    pub fn load_atlas_number_of_rows(&mut self, number_of_rows: usize) {
+        ShaderProgram::load_float(self.location_number_of_rows, number_of_rows as f32);
+    }
+
+    pub fn load_atlas_offset(&mut self, offset: &Vector2f) {
+        ShaderProgram::load_vector2d(self.location_texture_offset, offset);
+    }
+
+    pub fn load_uv_transform(&mut self, uv_scale: &Vector2f, uv_offset: &Vector2f) {
+        ShaderProgram::load_vector2d(self.location_uv_scale, uv_scale);
+        ShaderProgram::load_vector2d(self.location_uv_offset, uv_offset);
+    }
+}
@@ -0,0 +1,25 @@
+use crate::entities::FogCutoff;
+use super::shader_program::ShaderProgram;
+
+// the fog_start and fog_end uniforms of fog.glsl, every shader including it keeps one of these
+#[derive(Default)]
+pub struct FogUniforms {
+    location_fog_start: i32,
+    location_fog_end: i32,
+}
+
+impl FogUniforms {
+    pub fn get_locations(shader_prog: &ShaderProgram) -> FogUniforms {
+        FogUniforms {
+            location_fog_start: shader_prog.get_uniform_location("fog_start"),
+            location_fog_end: shader_prog.get_uniform_location("fog_end"),
+        }
+    }
+
+    // the shader has to be started
+    pub fn load_fog_cutoff(&self, fog_cutoff: &Option<FogCutoff>) {
+        let (fog_start, fog_end) = FogCutoff::shader_values(fog_cutoff);
+        ShaderProgram::load_float(self.location_fog_start, fog_start);
+        ShaderProgram::load_float(self.location_fog_end, fog_end);
+    }
+}
@@ -1,18 +1,21 @@
 use super::shader_program::ShaderProgram;
 use crate::models::RawModel;
 use crate::math::{
+    Color,
     Matrix4f,
 };
 
 pub struct GuiShader {
     program: ShaderProgram,
     location_transformation_matrix: i32,    
+    location_tint: i32,
 }
 
 impl GuiShader {
     pub fn new() -> GuiShader {
         let (
             mut location_transformation_matrix,
+            mut location_tint,
         ) = Default::default();
      
         let shader_program = ShaderProgram::new(
@@ -24,11 +27,13 @@ impl GuiShader {
             },
             |shader_prog| {                
                 location_transformation_matrix = shader_prog.get_uniform_location("transform");
+                location_tint = shader_prog.get_uniform_location("tint");
         });
 
         GuiShader {
             program: shader_program,
             location_transformation_matrix,
+            location_tint,
         }
     }
 
@@ -43,4 +48,8 @@ impl GuiShader {
     pub fn load_transformation_matrix(&mut self, transform_matrix: &Matrix4f) {
         ShaderProgram::load_matrix(self.location_transformation_matrix, transform_matrix);
     }
+
+    pub fn load_tint(&mut self, tint: &Color) {
+        ShaderProgram::load_color_rgba(self.location_tint, tint);
+    }
 }
\ No newline at end of file
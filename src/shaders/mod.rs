@@ -14,6 +14,9 @@ pub mod post_processing;
 pub mod particle_using_geometry_shader;
 pub mod env_map_shader;
 pub mod animated_model_shader;
+pub mod outline_shader;
+pub mod depth_only_shader;
+pub mod fog_uniforms;
 
 pub use self::static_shader::StaticShader;
 pub use self::normal_map_static_shader::NormalMapStaticShader;
@@ -27,3 +30,5 @@ pub use self::shadow_shader::ShadowShader;
 pub use self::debug_shader::DebugShader;
 pub use self::env_map_shader::EnvMapShader;
 pub use self::animated_model_shader::AnimatedModelShader;
+pub use self::outline_shader::OutlineShader;
+pub use self::depth_only_shader::DepthOnlyShader;
@@ -1,13 +1,15 @@
 use super::shader_program::ShaderProgram;
+use super::fog_uniforms::FogUniforms;
 use crate::entities::{
     Camera,
+    FogCutoff,
     Light,
 };
 use crate::models::RawModel;
 use crate::math::{
+    Color,
     Matrix4f,
     Vector2f,
-    Vector3f,
     Vector4f,
 };
 
@@ -24,12 +26,16 @@ pub struct NormalMapStaticShader {
     location_reflectivity: i32,
     location_uses_fake_lighting: i32,
     location_sky_color: i32,
+    fog_uniforms: FogUniforms,
     location_number_of_rows: i32,
     location_texture_offset: i32,
+    location_uv_scale: i32,
+    location_uv_offset: i32,
     location_attenuation: [i32; NUM_LIGHTS],
     location_clip_plane: i32,
     location_texture: i32,
     location_normal_map: i32,
+    location_normal_strength: i32,
 }
 
 impl NormalMapStaticShader {
@@ -44,15 +50,19 @@ impl NormalMapStaticShader {
             mut location_reflectivity,
             mut location_uses_fake_lighting,
             mut location_sky_color,
+            mut fog_uniforms,
         ) = Default::default();
 
         let (
             mut location_number_of_rows, 
             mut location_texture_offset,
+            mut location_uv_scale,
+            mut location_uv_offset,
             mut location_attenuation,
             mut location_clip_plane,
             mut location_texture,
             mut location_normal_map,
+            mut location_normal_strength,
         ) = Default::default();
         
         let shader_program = ShaderProgram::new(
@@ -84,9 +94,12 @@ impl NormalMapStaticShader {
                 location_uses_fake_lighting = shader_prog.get_uniform_location("uses_fake_lighting");
                 // fog unfirom
                 location_sky_color = shader_prog.get_uniform_location("sky_color");
+                fog_uniforms = FogUniforms::get_locations(shader_prog);
                 // atlas uniforms
                 location_number_of_rows = shader_prog.get_uniform_location("number_of_rows");
                 location_texture_offset = shader_prog.get_uniform_location("texture_offset");
+                location_uv_scale = shader_prog.get_uniform_location("uv_scale");
+                location_uv_offset = shader_prog.get_uniform_location("uv_offset");
                 // point light attenuation
                 location_attenuation = [0i32; NUM_LIGHTS];
                 for i in 0..NUM_LIGHTS {
@@ -96,6 +109,7 @@ impl NormalMapStaticShader {
                 // setting up uniforms to bind samplers to texture units
                 location_texture = shader_prog.get_uniform_location("texture_sampler");
                 location_normal_map = shader_prog.get_uniform_location("normal_map_sampler");
+                location_normal_strength = shader_prog.get_uniform_location("normal_strength");
         });
 
         NormalMapStaticShader {
@@ -109,12 +123,16 @@ impl NormalMapStaticShader {
             location_reflectivity,
             location_uses_fake_lighting,
             location_sky_color,
+            fog_uniforms,
             location_number_of_rows,
             location_texture_offset,
+            location_uv_scale,
+            location_uv_offset,
             location_attenuation,
             location_clip_plane,
             location_texture,
             location_normal_map,
+            location_normal_strength,
         }
     }
 
@@ -134,8 +152,17 @@ impl NormalMapStaticShader {
         ShaderProgram::load_vector2d(self.location_texture_offset, offset);
     }
 
-    pub fn load_sky_color(&mut self, sky_color: &Vector3f) {
-        ShaderProgram::load_vector3d(self.location_sky_color, sky_color);
+    pub fn load_uv_transform(&mut self, uv_scale: &Vector2f, uv_offset: &Vector2f) {
+        ShaderProgram::load_vector2d(self.location_uv_scale, uv_scale);
+        ShaderProgram::load_vector2d(self.location_uv_offset, uv_offset);
+    }
+
+    pub fn load_sky_color(&mut self, sky_color: &Color) {
+        ShaderProgram::load_color(self.location_sky_color, sky_color);
+    }
+
+    pub fn load_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.fog_uniforms.load_fog_cutoff(fog_cutoff);
     }
 
     pub fn load_uses_fake_lighting(&mut self, uses_fake: bool) {
@@ -147,18 +174,16 @@ impl NormalMapStaticShader {
         ShaderProgram::load_float(self.location_reflectivity, reflectivity);
     }
 
-    pub fn load_lights(&mut self, lights: &Vec<Light>) {        
+    pub fn load_normal_strength(&mut self, normal_strength: f32) {
+        ShaderProgram::load_float(self.location_normal_strength, normal_strength);
+    }
+
+    pub fn load_lights(&mut self, lights: &Vec<Light>) {
         for i in 0..NUM_LIGHTS {
-            if i < lights.len() {
-                ShaderProgram::load_vector3d(self.location_light_pos[i], &lights[i].position);
-                ShaderProgram::load_vector3d(self.location_light_color[i], &lights[i].color);
-                ShaderProgram::load_vector3d(self.location_attenuation[i], &lights[i].attenuation);
-            } else {
-                // no light data means fewer than NUM_LIGHTS affect object
-                ShaderProgram::load_vector3d(self.location_light_pos[i], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_light_color[i], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_attenuation[i], &Vector3f::POS_X_AXIS);
-            }
+            let light = Light::for_shader_slot(lights, i);
+            ShaderProgram::load_vector4d(self.location_light_pos[i], &light.shader_position());
+            ShaderProgram::load_color(self.location_light_color[i], &light.color);
+            ShaderProgram::load_vector3d(self.location_attenuation[i], &light.attenuation);
         }
     }
 
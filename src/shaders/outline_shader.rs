@@ -0,0 +1,64 @@
+use super::shader_program::ShaderProgram;
+use crate::models::RawModel;
+use crate::math::{
+    Color,
+    Matrix4f,
+};
+
+pub struct OutlineShader {
+    shader_program: ShaderProgram,
+    location_mvp_matrix: i32,
+    location_outline_offset: i32,
+    location_outline_color: i32,
+}
+
+impl OutlineShader {
+    pub fn new() -> Self {
+        let (
+            mut location_mvp_matrix,
+            mut location_outline_offset,
+            mut location_outline_color,
+        ) = Default::default();
+
+        let shader_program = ShaderProgram::new(
+            "res/shaders/outlineVertShader.glsl",
+            None,
+            "res/shaders/outlineFragShader.glsl",
+            |shader_prog| {
+                shader_prog.bind_attribute(RawModel::POS_ATTRIB, "pos");
+                shader_prog.bind_attribute(RawModel::NORMAL_ATTRIB, "normal");
+            },
+            |shader_prog| {
+                location_mvp_matrix = shader_prog.get_uniform_location("mvp_matrix");
+                location_outline_offset = shader_prog.get_uniform_location("outline_offset");
+                location_outline_color = shader_prog.get_uniform_location("outline_color");
+            }
+        );
+        OutlineShader {
+            shader_program,
+            location_mvp_matrix,
+            location_outline_offset,
+            location_outline_color,
+        }
+    }
+
+    pub fn start(&mut self) {
+        self.shader_program.start();
+    }
+
+    pub fn stop(&mut self) {
+        self.shader_program.stop();
+    }
+
+    pub fn load_mvp_matrix(&mut self, mvp: &Matrix4f) {
+        ShaderProgram::load_matrix(self.location_mvp_matrix, mvp);
+    }
+
+    pub fn load_outline_offset(&mut self, offset: f32) {
+        ShaderProgram::load_float(self.location_outline_offset, offset);
+    }
+
+    pub fn load_outline_color(&mut self, color: &Color) {
+        ShaderProgram::load_color(self.location_outline_color, color);
+    }
+}
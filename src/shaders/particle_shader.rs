@@ -1,17 +1,30 @@
 use super::shader_program::ShaderProgram;
 use crate::math::{
     Matrix4f,
+    Vector4f,
 };
+use crate::display::DepthMode;
 use crate::models::{
     RawModel,
     ParticleModel,
     ParticleTexture,
 };
+use crate::renderers::particle_renderer::{
+    ParticleDepthTest,
+    ParticleSceneDepth,
+};
 
 pub struct ParticleShader {
     program: ShaderProgram,
     location_proj_mat: i32,
     location_number_of_rows: i32,
+    location_premultiply: i32,
+    location_additive_weight: i32,
+    location_view_clip_plane: i32,
+    location_particle_texture: i32,
+    location_scene_depth: i32,
+    location_tests_scene_depth: i32,
+    location_reversed_depth: i32,
 }
 
 impl ParticleShader {
@@ -19,6 +32,13 @@ impl ParticleShader {
         let (
             mut location_proj_mat,
             mut location_number_of_rows,
+            mut location_premultiply,
+            mut location_additive_weight,
+            mut location_view_clip_plane,
+            mut location_particle_texture,
+            mut location_scene_depth,
+            mut location_tests_scene_depth,
+            mut location_reversed_depth,
         ) = Default::default();
 
         let program = ShaderProgram::new(
@@ -34,12 +54,26 @@ impl ParticleShader {
             |shader_program| {
                 location_proj_mat = shader_program.get_uniform_location("projection_matrix");
                 location_number_of_rows = shader_program.get_uniform_location("number_of_rows");
+                location_premultiply = shader_program.get_uniform_location("premultiply");
+                location_additive_weight = shader_program.get_uniform_location("additive_weight");
+                location_view_clip_plane = shader_program.get_uniform_location("view_clip_plane");
+                location_particle_texture = shader_program.get_uniform_location("particle_texture");
+                location_scene_depth = shader_program.get_uniform_location("scene_depth");
+                location_tests_scene_depth = shader_program.get_uniform_location("tests_scene_depth");
+                location_reversed_depth = shader_program.get_uniform_location("reversed_depth");
             }
         );
         ParticleShader {
             program,
             location_proj_mat,
             location_number_of_rows,
+            location_premultiply,
+            location_additive_weight,
+            location_view_clip_plane,
+            location_particle_texture,
+            location_scene_depth,
+            location_tests_scene_depth,
+            location_reversed_depth,
         }
     }
 
@@ -55,7 +89,25 @@ impl ParticleShader {
         ShaderProgram::load_matrix(self.location_proj_mat, proj_mat);
     }
 
+    pub fn connect_texture_units(&mut self) {
+        ShaderProgram::load_int(self.location_particle_texture, 0);
+        ShaderProgram::load_int(self.location_scene_depth, 1);
+    }
+
+    pub fn load_scene_depth(&mut self, scene_depth: &ParticleSceneDepth) {
+        let tests_scene_depth = scene_depth.depth_test == ParticleDepthTest::ResolvedSceneDepth && scene_depth.depth_texture.is_some();
+        ShaderProgram::load_bool(self.location_tests_scene_depth, tests_scene_depth);
+        ShaderProgram::load_bool(self.location_reversed_depth, scene_depth.depth_mode == DepthMode::ReversedZ);
+    }
+
+    pub fn load_view_clip_plane(&mut self, view_clip_plane: &Vector4f) {
+        ShaderProgram::load_vector4d(self.location_view_clip_plane, view_clip_plane);
+    }
+
     pub fn load_particle_texture_data(&mut self, texture: &ParticleTexture) {
         ShaderProgram::load_float(self.location_number_of_rows, texture.number_of_rows_in_atlas as f32);
+        let additive_weight = texture.blend_mode.premultiplied_additive_weight();
+        ShaderProgram::load_bool(self.location_premultiply, additive_weight.is_some());
+        ShaderProgram::load_float(self.location_additive_weight, additive_weight.unwrap_or(0.0));
     }
 }
\ No newline at end of file
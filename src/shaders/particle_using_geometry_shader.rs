@@ -2,17 +2,20 @@ use super::shader_program::ShaderProgram;
 use crate::models::RawModel;
 use crate::math::{
     Matrix4f,
+    Vector4f,
 };
 
 pub struct ParticleUsingGeometryShader {
     program: ShaderProgram,
     location_projection_view_matrix: i32,    
+    location_clip_plane: i32,
 }
 
 impl ParticleUsingGeometryShader {
     pub fn new() -> Self {
         let (
             mut location_projection_view_matrix,
+            mut location_clip_plane,
         ) = Default::default();
      
         let shader_program = ShaderProgram::new(
@@ -24,11 +27,13 @@ impl ParticleUsingGeometryShader {
             },
             |shader_prog| {                
                 location_projection_view_matrix = shader_prog.get_uniform_location("projectionViewMatrix");
+                location_clip_plane = shader_prog.get_uniform_location("clip_plane");
         });
 
         Self {
             program: shader_program,
             location_projection_view_matrix,
+            location_clip_plane,
         }
     }
 
@@ -43,4 +48,8 @@ impl ParticleUsingGeometryShader {
     pub fn load_vp_matrix(&mut self, vp_matrix: &Matrix4f) {
         ShaderProgram::load_matrix(self.location_projection_view_matrix, vp_matrix);
     }
+
+    pub fn load_clip_plane(&mut self, clip_plane: &Vector4f) {
+        ShaderProgram::load_vector4d(self.location_clip_plane, clip_plane);
+    }
 }
\ No newline at end of file
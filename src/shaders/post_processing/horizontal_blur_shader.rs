@@ -32,7 +32,7 @@ impl HorizontalBlurShader {
         }
     }
 
-    fn load_viewport_dimension(&mut self, viewport_width: usize) {
+    pub fn load_viewport_dimension(&mut self, viewport_width: usize) {
         ShaderProgram::load_float(self.location_size, viewport_width as f32);
     }
 }
@@ -1,4 +1,5 @@
 use std::fs::File;
+use std::path::Path;
 // items from traits can only be used if trait is in scope
 // we need io traits which are exported in bulk in prelude
 use std::io::{
@@ -9,6 +10,7 @@ use std::io::{
 }; 
 use super::super::gl;
 use super::super::math::{
+    Color,
     Matrix4f,
     Vector2f, 
     Vector3f,
@@ -19,17 +21,35 @@ pub struct ShaderProgram {
     program_id: u32,
     vertex_shader_id: u32,
     geometry_shader_id: Option<u32>,
+    tessellation_shader_ids: Option<(u32, u32)>,
     fragment_shader_id: u32,
 }
 
+// control and evaluation shader files, both stages are needed for tessellation
+pub type TessellationFiles<'a> = (&'a str, &'a str);
+
+// a line `#include "file.glsl"` is replaced by that file, the path is relative to the including shader
+const INCLUDE_DIRECTIVE: &str = "#include";
+
 impl ShaderProgram {
 
     pub fn new<F1, F2>(vertex_file: &str, geometry_file: Option<&str>, fragment_file: &str, attrib_binder_fn: F1, uniform_loader: F2) -> ShaderProgram 
+        where F1: FnOnce(&ShaderProgram) -> (), 
+              F2: FnOnce(&ShaderProgram) -> () {
+        ShaderProgram::new_with_tessellation(vertex_file, None, geometry_file, fragment_file, attrib_binder_fn, uniform_loader)
+    }
+
+    // the caller has to make sure the context supports tessellation (gl::helper::supports_tessellation)
+    pub fn new_with_tessellation<F1, F2>(vertex_file: &str, tessellation_files: Option<TessellationFiles>, geometry_file: Option<&str>, fragment_file: &str, attrib_binder_fn: F1, uniform_loader: F2) -> ShaderProgram 
         where F1: FnOnce(&ShaderProgram) -> (), 
               F2: FnOnce(&ShaderProgram) -> () {
         let vertex_shader_id = ShaderProgram::load_shader(vertex_file, gl::VERTEX_SHADER)
             .expect("Failed to create vertex shader");
         let geometry_shader_id = geometry_file.map(|geo_file| { ShaderProgram::load_shader(geo_file, gl::GEOMETRY_SHADER).expect("Failed to create geometry shader") });
+        let tessellation_shader_ids = tessellation_files.map(|(control_file, evaluation_file)| {(
+            ShaderProgram::load_shader(control_file, gl::TESS_CONTROL_SHADER).expect("Failed to create tessellation control shader"),
+            ShaderProgram::load_shader(evaluation_file, gl::TESS_EVALUATION_SHADER).expect("Failed to create tessellation evaluation shader"),
+        )});
         let fragment_shader_id = ShaderProgram::load_shader(fragment_file, gl::FRAGMENT_SHADER)
             .expect("Failed to create fragment shader");
 
@@ -39,24 +59,30 @@ impl ShaderProgram {
         if let Some(geometry_shdr_id) = geometry_shader_id {
             gl::attach_shader(program_id, geometry_shdr_id);
         }
+        if let Some((control_shdr_id, evaluation_shdr_id)) = tessellation_shader_ids {
+            gl::attach_shader(program_id, control_shdr_id);
+            gl::attach_shader(program_id, evaluation_shdr_id);
+        }
         
         let shader_prog = ShaderProgram {
             program_id,
             vertex_shader_id,
             geometry_shader_id,
+            tessellation_shader_ids,
             fragment_shader_id,
         };
         attrib_binder_fn(&shader_prog);
         gl::link_program(program_id);
         if gl::get_program(program_id, gl::LINK_STATUS) == gl::FALSE as i32 {
             let link_log = gl::get_program_info_log(program_id).expect("Failed to get program log");
-            println!("Link log: {}", link_log);
+            log_error!("Link log of {}: {}", vertex_file, link_log);
             panic!("Program linking failed");
         }
+        gl::helper::debug_check_error(|| format!("linking shader program of {}", vertex_file));
         gl::validate_program(program_id);
         if gl::get_program(program_id, gl::VALIDATE_STATUS) == gl::FALSE as i32 {
             let validate_log = gl::get_program_info_log(program_id).expect("Failed to get program log");
-            println!("Validate log: {}", validate_log);
+            log_error!("Validate log of {}: {}", vertex_file, validate_log);
             panic!("Program linking failed");
         }
         uniform_loader(&shader_prog);
@@ -71,17 +97,45 @@ impl ShaderProgram {
         gl::use_program(0);
     }
 
-    pub fn load_shader(filename: &str, type_: u32) -> std::io::Result<u32> {
+    fn read_shader_file(filename: &Path) -> std::io::Result<String> {
         let shader_file = File::open(filename)?;
         let mut buf_reader = BufReader::new(shader_file);
         let mut contents = String::new();
         buf_reader.read_to_string(&mut contents)?;
+        Ok(contents)
+    }
+
+    // glsl has no includes so shared snippets are spliced in before compiling, included files may include others
+    fn resolve_includes<F>(source: &str, read_included: &F) -> std::io::Result<String> 
+        where F: Fn(&str) -> std::io::Result<String> {
+        let mut resolved = String::with_capacity(source.len());
+        for line in source.lines() {
+            match line.trim().strip_prefix(INCLUDE_DIRECTIVE) {
+                Some(included_file) => {
+                    let included_source = read_included(included_file.trim().trim_matches('"'))?;
+                    resolved.push_str(&ShaderProgram::resolve_includes(&included_source, read_included)?);
+                },
+                None => {
+                    resolved.push_str(line);
+                    resolved.push('\n');
+                },
+            }
+        }
+        Ok(resolved)
+    }
+
+    pub fn load_shader(filename: &str, type_: u32) -> std::io::Result<u32> {
+        let shader_path = Path::new(filename);
+        let shader_dir = shader_path.parent().unwrap_or(Path::new(""));
+        let contents = ShaderProgram::resolve_includes(&ShaderProgram::read_shader_file(shader_path)?, &|included_file| {
+            ShaderProgram::read_shader_file(&shader_dir.join(included_file))
+        })?;
         let shader_id = gl::create_shader(type_);
         gl::shader_source(shader_id, &contents)?;
         gl::compile_shader(shader_id);
         if gl::get_shader(shader_id, gl::COMPILE_STATUS) == gl::FALSE as i32 {
             let compile_log = gl::get_shader_info_log(shader_id)?;
-            println!("Could not compile shader. Log: {}", compile_log);
+            log_error!("Could not compile shader {}. Log: {}", filename, compile_log);
             Err(Error::new(ErrorKind::Other, "Failed to compile shader"))
         }
         else {
@@ -122,6 +176,15 @@ impl ShaderProgram {
         gl::uniform3f(location_id, value.x, value.y, value.z);
     }
 
+    // loads only the rgb part, the shaders don't use the alpha of colors
+    pub fn load_color(location_id: i32, value: &Color) {
+        gl::uniform3f(location_id, value.r, value.g, value.b);
+    }
+
+    pub fn load_color_rgba(location_id: i32, value: &Color) {
+        gl::uniform4f(location_id, value.r, value.g, value.b, value.a);
+    }
+
     pub fn load_vector2d(location_id: i32, value: &Vector2f) {
         gl::uniform2f(location_id, value.x, value.y);
     }
@@ -141,6 +204,31 @@ impl Drop for ShaderProgram {
         if let Some(geometry_shdr_id) = self.geometry_shader_id {
             gl::delete_shader(geometry_shdr_id);
         }
+        if let Some((control_shdr_id, evaluation_shdr_id)) = self.tessellation_shader_ids {
+            gl::detach_shader(self.program_id, control_shdr_id);
+            gl::detach_shader(self.program_id, evaluation_shdr_id);
+            gl::delete_shader(control_shdr_id);
+            gl::delete_shader(evaluation_shdr_id);
+        }
         gl::delete_program(self.program_id);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn includes_are_spliced_in_recursively() {
+        let read_included = |file: &str| -> std::io::Result<String> {
+            match file {
+                "outer.glsl" => Ok("float outer;\n#include \"inner.glsl\"".to_string()),
+                "inner.glsl" => Ok("float inner;".to_string()),
+                _ => Err(Error::new(ErrorKind::NotFound, file.to_string())),
+            }
+        };
+        let resolved = ShaderProgram::resolve_includes("#version 400 core\n#include \"outer.glsl\"\nvoid main() {}", &read_included).unwrap();
+        assert_eq!(resolved, "#version 400 core\nfloat outer;\nfloat inner;\nvoid main() {}\n");
+        assert!(ShaderProgram::resolve_includes("#include \"missing.glsl\"", &read_included).is_err());
+    }
+}
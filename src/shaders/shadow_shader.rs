@@ -1,18 +1,27 @@
 use crate::shaders::shader_program::ShaderProgram;
 use crate::math::{
     Matrix4f,
+    Vector2f,
 };
 use crate::models::RawModel;
 
 pub struct ShadowShader {
     shader_program: ShaderProgram,
     location_mvp_matrix: i32,
+    location_number_of_rows: i32,
+    location_texture_offset: i32,
+    location_uv_scale: i32,
+    location_uv_offset: i32,
 }
 
 impl ShadowShader {
     pub fn new() -> Self {
         let (
             mut location_mvp_matrix,
+            mut location_number_of_rows,
+            mut location_texture_offset,
+            mut location_uv_scale,
+            mut location_uv_offset,
         ) = Default::default();
 
         let shader_program = ShaderProgram::new(
@@ -25,11 +34,19 @@ impl ShadowShader {
             },
             |shader_prog| {
                 location_mvp_matrix = shader_prog.get_uniform_location("mvp_matrix");
+                location_number_of_rows = shader_prog.get_uniform_location("number_of_rows");
+                location_texture_offset = shader_prog.get_uniform_location("texture_offset");
+                location_uv_scale = shader_prog.get_uniform_location("uv_scale");
+                location_uv_offset = shader_prog.get_uniform_location("uv_offset");
             }
         );
         ShadowShader {
             shader_program,
             location_mvp_matrix,
+            location_number_of_rows,
+            location_texture_offset,
+            location_uv_scale,
+            location_uv_offset,
         }
     }
 
@@ -44,4 +61,18 @@ impl ShadowShader {
     pub fn load_mvp_matrix(&mut self, mvp: &Matrix4f) {
         ShaderProgram::load_matrix(self.location_mvp_matrix, mvp);
     }
+
+    // the alpha test has to look at the same texels the entity shader draws
+    pub fn load_atlas_number_of_rows(&mut self, number_of_rows: usize) {
+        ShaderProgram::load_float(self.location_number_of_rows, number_of_rows as f32);
+    }
+
+    pub fn load_atlas_offset(&mut self, offset: &Vector2f) {
+        ShaderProgram::load_vector2d(self.location_texture_offset, offset);
+    }
+
+    pub fn load_uv_transform(&mut self, uv_scale: &Vector2f, uv_offset: &Vector2f) {
+        ShaderProgram::load_vector2d(self.location_uv_scale, uv_scale);
+        ShaderProgram::load_vector2d(self.location_uv_offset, uv_offset);
+    }
 }
\ No newline at end of file
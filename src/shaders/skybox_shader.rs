@@ -3,9 +3,11 @@ use crate::models::{
     RawModel,
 };
 use crate::entities::{
+    AtmosphereParams,
     Camera,
 };
 use crate::math::{
+    Color,
     Matrix4f,
     Vector3f,
     Vector4f,
@@ -21,6 +23,13 @@ pub struct SkyboxShader {
     location_blend_factor: i32,
     location_clip_plane: i32,
     location_uses_fog: i32,
+    location_uses_atmosphere: i32,
+    location_sun_direction: i32,
+    location_sun_zenith: i32,
+    location_turbidity: i32,
+    location_exposure: i32,
+    location_projects_to_far_plane: i32,
+    location_far_plane_depth: i32,
 }
 
 impl SkyboxShader {
@@ -34,6 +43,15 @@ impl SkyboxShader {
             mut location_blend_factor,
             mut location_clip_plane,
             mut location_uses_fog,
+            mut location_uses_atmosphere,
+            mut location_sun_direction,
+            mut location_turbidity,
+            mut location_exposure,
+        ) = Default::default();
+        let (
+            mut location_projects_to_far_plane,
+            mut location_far_plane_depth,
+            mut location_sun_zenith,
         ) = Default::default();
 
         let program = ShaderProgram::new(
@@ -52,6 +70,13 @@ impl SkyboxShader {
                 location_blend_factor = shader_prog.get_uniform_location("blend_factor");
                 location_clip_plane = shader_prog.get_uniform_location("clip_plane");
                 location_uses_fog = shader_prog.get_uniform_location("uses_fog");
+                location_uses_atmosphere = shader_prog.get_uniform_location("uses_atmosphere");
+                location_sun_direction = shader_prog.get_uniform_location("sun_direction");
+                location_sun_zenith = shader_prog.get_uniform_location("sun_zenith");
+                location_turbidity = shader_prog.get_uniform_location("turbidity");
+                location_exposure = shader_prog.get_uniform_location("exposure");
+                location_projects_to_far_plane = shader_prog.get_uniform_location("projects_to_far_plane");
+                location_far_plane_depth = shader_prog.get_uniform_location("far_plane_depth");
             }
         );        
 
@@ -65,6 +90,13 @@ impl SkyboxShader {
             location_blend_factor,
             location_clip_plane,
             location_uses_fog,
+            location_uses_atmosphere,
+            location_sun_direction,
+            location_sun_zenith,
+            location_turbidity,
+            location_exposure,
+            location_projects_to_far_plane,
+            location_far_plane_depth,
         }
     }
 
@@ -85,21 +117,41 @@ impl SkyboxShader {
         ShaderProgram::load_float(self.location_blend_factor, blend_factor);
     }
 
-    pub fn load_sky_color(&mut self, sky_color: &Vector3f, uses_fog: bool) {
-        ShaderProgram::load_vector3d(self.location_sky_color, sky_color);
+    pub fn load_sky_color(&mut self, sky_color: &Color, uses_fog: bool) {
+        ShaderProgram::load_color(self.location_sky_color, sky_color);
         ShaderProgram::load_float(self.location_uses_fog, if uses_fog { 1.0 } else { 0.0 });
     }
 
+    // the sun direction is that of the directional light, None switches back to the cubemaps
+    pub fn load_atmosphere(&mut self, atmosphere: Option<&AtmosphereParams>, sun_direction: &Vector3f) {
+        match atmosphere {
+            Some(params) => {
+                ShaderProgram::load_bool(self.location_uses_atmosphere, true);
+                ShaderProgram::load_vector3d(self.location_sun_direction, &sun_direction);
+                ShaderProgram::load_float(self.location_sun_zenith, AtmosphereParams::sun_zenith_angle(sun_direction));
+                ShaderProgram::load_float(self.location_turbidity, params.turbidity);
+                ShaderProgram::load_float(self.location_exposure, params.exposure);
+            },
+            None => ShaderProgram::load_bool(self.location_uses_atmosphere, false),
+        }
+    }
+
     pub fn load_projection_matrix(&mut self, projection_matrix: &Matrix4f) {
         ShaderProgram::load_matrix(self.location_proj_matrix, projection_matrix);
     }
 
-    pub fn load_view_matrix(&mut self, camera: &Camera, skybox_rotation: f32) {
-        let view_matrix = Matrix4f::create_skybox_view_matrix(camera, skybox_rotation);
+    pub fn load_view_matrix(&mut self, camera: &Camera, skybox_rotation: f32, skybox_rotation_axis: &Vector3f) {
+        let view_matrix = Matrix4f::create_skybox_view_matrix(camera, skybox_rotation, skybox_rotation_axis);
         ShaderProgram::load_matrix(self.location_view_matrix, &view_matrix);
     }
 
     pub fn load_clip_plane(&mut self, clip_plane: &Vector4f) {
         ShaderProgram::load_vector4d(self.location_clip_plane, clip_plane);
     }
+
+    // None draws the cube where it is
+    pub fn load_far_plane_depth(&mut self, far_plane_depth: Option<f32>) {
+        ShaderProgram::load_bool(self.location_projects_to_far_plane, far_plane_depth.is_some());
+        ShaderProgram::load_float(self.location_far_plane_depth, far_plane_depth.unwrap_or(0.0));
+    }
 }
\ No newline at end of file
@@ -1,13 +1,15 @@
 use super::shader_program::ShaderProgram;
+use super::fog_uniforms::FogUniforms;
 use crate::entities::{
     Camera,
+    FogCutoff,
     Light,
 };
 use crate::models::RawModel;
 use crate::math::{
+    Color,
     Matrix4f,
     Vector2f,
-    Vector3f,
     Vector4f,
 };
 use crate::shadows::shadow_params::ShadowParams;
@@ -26,16 +28,26 @@ pub struct StaticShader {
     location_reflectivity: i32,
     location_uses_fake_lighting: i32,
     location_sky_color: i32,
+    fog_uniforms: FogUniforms,
     location_number_of_rows: i32,
     location_texture_offset: i32,
+    location_uv_scale: i32,
+    location_uv_offset: i32,
     location_attenuation: [i32; NUM_LIGHTS],
     location_clip_plane: i32,
     location_to_shadowmap_space: i32,
     location_shadowmap: i32,
     location_shadow_distance: i32,
     location_shadow_map_size: i32,
+    location_shadow_constant_bias: i32,
+    location_shadow_slope_bias: i32,
+    location_shadow_max_bias: i32,
     location_extra_info_map: i32,
     location_has_extra_info: i32,
+    location_has_vertex_color: i32,
+    location_lod_fade: i32,
+    location_lod_fade_in: i32,
+    location_is_instanced: i32,
 }
 
 impl StaticShader {
@@ -51,11 +63,14 @@ impl StaticShader {
             mut location_reflectivity,
             mut location_uses_fake_lighting,
             mut location_sky_color,
+            mut fog_uniforms,
         ) = Default::default();
 
         let (
             mut location_number_of_rows, 
             mut location_texture_offset,
+            mut location_uv_scale,
+            mut location_uv_offset,
             mut location_attenuation,
             mut location_clip_plane,
         ) = Default::default();
@@ -65,8 +80,17 @@ impl StaticShader {
             mut location_shadowmap,
             mut location_shadow_distance,
             mut location_shadow_map_size,
+            mut location_shadow_constant_bias,
+            mut location_shadow_slope_bias,
+            mut location_shadow_max_bias,
             mut location_extra_info_map,
             mut location_has_extra_info,
+            mut location_has_vertex_color,
+        ) = Default::default();
+        let (
+            mut location_lod_fade,
+            mut location_lod_fade_in,
+            mut location_is_instanced,
         ) = Default::default();
         
         let shader_program = ShaderProgram::new(
@@ -77,6 +101,9 @@ impl StaticShader {
                 shader_prog.bind_attribute(RawModel::POS_ATTRIB, "pos");
                 shader_prog.bind_attribute(RawModel::TEX_COORD_ATTRIB, "tex_coord");
                 shader_prog.bind_attribute(RawModel::NORMAL_ATTRIB, "normal");
+                shader_prog.bind_attribute(RawModel::COLOR_ATTRIB, "vertex_color");
+                shader_prog.bind_attribute(RawModel::INSTANCE_TRANSFORM_ATTRIB, "instance_transform");
+                shader_prog.bind_attribute(RawModel::INSTANCE_ATLAS_OFFSET_ATTRIB, "instance_texture_offset");
             },
             |shader_prog| {                
                 location_texture_sampler = shader_prog.get_uniform_location("texture_sampler");
@@ -98,9 +125,12 @@ impl StaticShader {
                 location_uses_fake_lighting = shader_prog.get_uniform_location("uses_fake_lighting");
                 // fog unfirom
                 location_sky_color = shader_prog.get_uniform_location("sky_color");
+                fog_uniforms = FogUniforms::get_locations(shader_prog);
                 // atlas uniforms
                 location_number_of_rows = shader_prog.get_uniform_location("number_of_rows");
                 location_texture_offset = shader_prog.get_uniform_location("texture_offset");
+                location_uv_scale = shader_prog.get_uniform_location("uv_scale");
+                location_uv_offset = shader_prog.get_uniform_location("uv_offset");
                 // point light attenuation
                 location_attenuation = [0i32; NUM_LIGHTS];
                 for i in 0..NUM_LIGHTS {
@@ -112,9 +142,16 @@ impl StaticShader {
                 location_shadowmap = shader_prog.get_uniform_location("shadow_map");
                 location_shadow_distance = shader_prog.get_uniform_location("shadow_distance");
                 location_shadow_map_size = shader_prog.get_uniform_location("shadow_map_size");
+                location_shadow_constant_bias = shader_prog.get_uniform_location("shadow_constant_bias");
+                location_shadow_slope_bias = shader_prog.get_uniform_location("shadow_slope_bias");
+                location_shadow_max_bias = shader_prog.get_uniform_location("shadow_max_bias");
 
                 location_extra_info_map = shader_prog.get_uniform_location("extra_info_map");
                 location_has_extra_info = shader_prog.get_uniform_location("has_extra_info");
+                location_has_vertex_color = shader_prog.get_uniform_location("has_vertex_color");
+                location_lod_fade = shader_prog.get_uniform_location("lod_fade");
+                location_lod_fade_in = shader_prog.get_uniform_location("lod_fade_in");
+                location_is_instanced = shader_prog.get_uniform_location("is_instanced");
         });
 
         StaticShader {            
@@ -129,16 +166,26 @@ impl StaticShader {
             location_reflectivity,
             location_uses_fake_lighting,
             location_sky_color,
+            fog_uniforms,
             location_number_of_rows,
             location_texture_offset,
+            location_uv_scale,
+            location_uv_offset,
             location_attenuation,
             location_clip_plane,
             location_to_shadowmap_space,
             location_shadowmap,
             location_shadow_distance,
             location_shadow_map_size,
+            location_shadow_constant_bias,
+            location_shadow_slope_bias,
+            location_shadow_max_bias,
             location_extra_info_map,
             location_has_extra_info,
+            location_has_vertex_color,
+            location_lod_fade,
+            location_lod_fade_in,
+            location_is_instanced,
         }
     }
 
@@ -164,8 +211,17 @@ impl StaticShader {
         ShaderProgram::load_vector2d(self.location_texture_offset, offset);
     }
 
-    pub fn load_sky_color(&mut self, sky_color: &Vector3f) {
-        ShaderProgram::load_vector3d(self.location_sky_color, sky_color);
+    pub fn load_uv_transform(&mut self, uv_scale: &Vector2f, uv_offset: &Vector2f) {
+        ShaderProgram::load_vector2d(self.location_uv_scale, uv_scale);
+        ShaderProgram::load_vector2d(self.location_uv_offset, uv_offset);
+    }
+
+    pub fn load_sky_color(&mut self, sky_color: &Color) {
+        ShaderProgram::load_color(self.location_sky_color, sky_color);
+    }
+
+    pub fn load_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.fog_uniforms.load_fog_cutoff(fog_cutoff);
     }
 
     pub fn load_uses_fake_lighting(&mut self, uses_fake: bool) {
@@ -177,18 +233,12 @@ impl StaticShader {
         ShaderProgram::load_float(self.location_reflectivity, reflectivity);
     }
 
-    pub fn load_lights(&mut self, lights: &Vec<Light>) {        
+    pub fn load_lights(&mut self, lights: &Vec<Light>) {
         for i in 0..NUM_LIGHTS {
-            if i < lights.len() {
-                ShaderProgram::load_vector3d(self.location_light_pos[i], &lights[i].position);
-                ShaderProgram::load_vector3d(self.location_light_color[i], &lights[i].color);
-                ShaderProgram::load_vector3d(self.location_attenuation[i], &lights[i].attenuation);
-            } else {
-                // no light data means fewer than NUM_LIGHTS affect object
-                ShaderProgram::load_vector3d(self.location_light_pos[i], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_light_color[i], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_attenuation[i], &Vector3f::POS_X_AXIS);
-            }
+            let light = Light::for_shader_slot(lights, i);
+            ShaderProgram::load_vector4d(self.location_light_pos[i], &light.shader_position());
+            ShaderProgram::load_color(self.location_light_color[i], &light.color);
+            ShaderProgram::load_vector3d(self.location_attenuation[i], &light.attenuation);
         }
     }
 
@@ -216,9 +266,26 @@ impl StaticShader {
     pub fn load_shadow_params(&mut self, shadow_params: &ShadowParams) {
         ShaderProgram::load_float(self.location_shadow_distance, shadow_params.shadow_distance);
         ShaderProgram::load_float(self.location_shadow_map_size, shadow_params.shadow_map_size as f32);
+        ShaderProgram::load_float(self.location_shadow_constant_bias, shadow_params.constant_bias);
+        ShaderProgram::load_float(self.location_shadow_slope_bias, shadow_params.slope_bias);
+        ShaderProgram::load_float(self.location_shadow_max_bias, shadow_params.max_bias);
     }
 
     pub fn load_extra_info(&mut self, has_extra_info: bool) {        
         ShaderProgram::load_float(self.location_has_extra_info, if has_extra_info { 1.0 } else { 0.0 });
     }
+
+    pub fn load_has_vertex_color(&mut self, has_vertex_color: bool) {
+        ShaderProgram::load_bool(self.location_has_vertex_color, has_vertex_color);
+    }
+
+    // instanced draws read the transform and atlas offset from the instance attributes
+    pub fn load_is_instanced(&mut self, is_instanced: bool) {
+        ShaderProgram::load_bool(self.location_is_instanced, is_instanced);
+    }
+
+    pub fn load_lod_fade(&mut self, fade: f32, is_fading_in: bool) {
+        ShaderProgram::load_float(self.location_lod_fade, fade);
+        ShaderProgram::load_bool(self.location_lod_fade_in, is_fading_in);
+    }
 }
\ No newline at end of file
@@ -1,18 +1,25 @@
-use super::shader_program::ShaderProgram;
+use super::shader_program::{
+    ShaderProgram,
+    TessellationFiles,
+};
+use super::fog_uniforms::FogUniforms;
 use crate::entities::{
     Camera,
+    FogCutoff,
     Light,
 };
 use crate::models::RawModel;
 use crate::math::{
+    Color,
     Matrix4f,
-    Vector3f,
     Vector4f,
 };
 use crate::shadows::shadow_params::ShadowParams;
 
 const NUM_LIGHTS: usize = 4;
 
+const TESSELLATION_FILES: TessellationFiles = ("res/shaders/terrainTessControlShader.glsl", "res/shaders/terrainTessEvalShader.glsl");
+
 pub struct TerrainShader {
     program: ShaderProgram,
     location_transformation_matrix: i32,
@@ -23,6 +30,7 @@ pub struct TerrainShader {
     location_shine_damper: i32,
     location_reflectivity: i32,
     location_sky_color: i32,
+    fog_uniforms: FogUniforms,
     location_background_sampler: i32,
     location_r_sampler: i32,
     location_g_sampler: i32,
@@ -34,10 +42,28 @@ pub struct TerrainShader {
     location_shadowmap: i32,
     location_shadow_distance: i32,
     location_shadow_map_size: i32,
+    location_shadow_constant_bias: i32,
+    location_shadow_slope_bias: i32,
+    location_shadow_max_bias: i32,
+    location_tess_min_level: i32,
+    location_tess_max_level: i32,
+    location_tess_near_distance: i32,
+    location_tess_far_distance: i32,
+    location_detail_amplitude: i32,
+    location_detail_frequency: i32,
 }
 
 impl TerrainShader {
     pub fn new() -> TerrainShader {
+        TerrainShader::create("res/shaders/terrainVertexShader.glsl", None)
+    }
+
+    // needs gl 4.0, patches have to be drawn with gl::PATCHES
+    pub fn new_tessellated() -> TerrainShader {
+        TerrainShader::create("res/shaders/terrainTessVertexShader.glsl", Some(TESSELLATION_FILES))
+    }
+
+    fn create(vertex_file: &str, tessellation_files: Option<TessellationFiles>) -> TerrainShader {
         let (
             mut location_transformation_matrix, 
             mut location_projection_matrix,
@@ -47,6 +73,7 @@ impl TerrainShader {
             mut location_shine_damper,
             mut location_reflectivity,
             mut location_sky_color,
+            mut fog_uniforms,
         ) = Default::default();
 
         let (
@@ -64,10 +91,24 @@ impl TerrainShader {
             mut location_shadowmap,
             mut location_shadow_distance,
             mut location_shadow_map_size,
+            mut location_shadow_constant_bias,
+            mut location_shadow_slope_bias,
+            mut location_shadow_max_bias,
+        ) = Default::default();
+
+        // the untessellated program doesn't have these so their locations stay -1 and loading them is ignored
+        let (
+            mut location_tess_min_level,
+            mut location_tess_max_level,
+            mut location_tess_near_distance,
+            mut location_tess_far_distance,
+            mut location_detail_amplitude,
+            mut location_detail_frequency,
         ) = Default::default();
         
-        let shader_program = ShaderProgram::new(
-            "res/shaders/terrainVertexShader.glsl",
+        let shader_program = ShaderProgram::new_with_tessellation(
+            vertex_file,
+            tessellation_files,
             None,
             "res/shaders/terrainFragShader.glsl",
             |shader_prog| {
@@ -91,6 +132,7 @@ impl TerrainShader {
                 location_reflectivity = shader_prog.get_uniform_location("reflectivity");
                 // fog unfirom
                 location_sky_color = shader_prog.get_uniform_location("sky_color");
+                fog_uniforms = FogUniforms::get_locations(shader_prog);
                 // texture samplers
                 location_background_sampler = shader_prog.get_uniform_location("background_sampler");
                 location_r_sampler = shader_prog.get_uniform_location("r_sampler");
@@ -107,6 +149,15 @@ impl TerrainShader {
                 location_shadowmap = shader_prog.get_uniform_location("shadow_map");
                 location_shadow_distance = shader_prog.get_uniform_location("shadow_distance");
                 location_shadow_map_size = shader_prog.get_uniform_location("shadow_map_size");
+                location_shadow_constant_bias = shader_prog.get_uniform_location("shadow_constant_bias");
+                location_shadow_slope_bias = shader_prog.get_uniform_location("shadow_slope_bias");
+                location_shadow_max_bias = shader_prog.get_uniform_location("shadow_max_bias");
+                location_tess_min_level = shader_prog.get_uniform_location("tess_min_level");
+                location_tess_max_level = shader_prog.get_uniform_location("tess_max_level");
+                location_tess_near_distance = shader_prog.get_uniform_location("tess_near_distance");
+                location_tess_far_distance = shader_prog.get_uniform_location("tess_far_distance");
+                location_detail_amplitude = shader_prog.get_uniform_location("detail_amplitude");
+                location_detail_frequency = shader_prog.get_uniform_location("detail_frequency");
         });
 
         TerrainShader {
@@ -119,6 +170,7 @@ impl TerrainShader {
             location_shine_damper,
             location_reflectivity,
             location_sky_color,
+            fog_uniforms,
             location_background_sampler,
             location_r_sampler,
             location_g_sampler,
@@ -130,6 +182,15 @@ impl TerrainShader {
             location_shadowmap,
             location_shadow_distance,
             location_shadow_map_size,
+            location_shadow_constant_bias,
+            location_shadow_slope_bias,
+            location_shadow_max_bias,
+            location_tess_min_level,
+            location_tess_max_level,
+            location_tess_near_distance,
+            location_tess_far_distance,
+            location_detail_amplitude,
+            location_detail_frequency,
         }
     }
 
@@ -150,8 +211,12 @@ impl TerrainShader {
         ShaderProgram::load_int(self.location_shadowmap, 5);
     }
 
-    pub fn load_sky_color(&mut self, sky_color: &Vector3f) {
-        ShaderProgram::load_vector3d(self.location_sky_color, sky_color);
+    pub fn load_sky_color(&mut self, sky_color: &Color) {
+        ShaderProgram::load_color(self.location_sky_color, sky_color);
+    }
+
+    pub fn load_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.fog_uniforms.load_fog_cutoff(fog_cutoff);
     }
 
     pub fn load_shine_variables(&mut self, shine_damper: f32, reflectivity: f32) {
@@ -159,19 +224,13 @@ impl TerrainShader {
         ShaderProgram::load_float(self.location_reflectivity, reflectivity);
     }
 
-    pub fn load_lights(&mut self, lights: &Vec<Light>) {        
+    pub fn load_lights(&mut self, lights: &Vec<Light>) {
         for i in 0..NUM_LIGHTS {
-            if i < lights.len() {
-                ShaderProgram::load_vector3d(self.location_light_pos[i], &lights[i].position);
-                ShaderProgram::load_vector3d(self.location_light_color[i], &lights[i].color);
-                ShaderProgram::load_vector3d(self.location_attenuation[i], &lights[i].attenuation);
-            } else {
-                // no light data means fewer than NUM_LIGHTS affect object
-                ShaderProgram::load_vector3d(self.location_light_pos[i], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_light_color[i], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_attenuation[i], &Vector3f::POS_X_AXIS);
-            }
-        } 
+            let light = Light::for_shader_slot(lights, i);
+            ShaderProgram::load_vector4d(self.location_light_pos[i], &light.shader_position());
+            ShaderProgram::load_color(self.location_light_color[i], &light.color);
+            ShaderProgram::load_vector3d(self.location_attenuation[i], &light.attenuation);
+        }
     }
 
     pub fn load_transformation_matrix(&mut self, transform_matrix: &Matrix4f) {
@@ -198,5 +257,20 @@ impl TerrainShader {
     pub fn load_shadow_params(&mut self, shadow_params: &ShadowParams) {
         ShaderProgram::load_float(self.location_shadow_distance, shadow_params.shadow_distance);
         ShaderProgram::load_float(self.location_shadow_map_size, shadow_params.shadow_map_size as f32);
+        ShaderProgram::load_float(self.location_shadow_constant_bias, shadow_params.constant_bias);
+        ShaderProgram::load_float(self.location_shadow_slope_bias, shadow_params.slope_bias);
+        ShaderProgram::load_float(self.location_shadow_max_bias, shadow_params.max_bias);
+    }
+
+    pub fn load_tessellation_levels(&mut self, min_level: f32, max_level: f32, near_distance: f32, far_distance: f32) {
+        ShaderProgram::load_float(self.location_tess_min_level, min_level);
+        ShaderProgram::load_float(self.location_tess_max_level, max_level);
+        ShaderProgram::load_float(self.location_tess_near_distance, near_distance);
+        ShaderProgram::load_float(self.location_tess_far_distance, far_distance);
+    }
+
+    pub fn load_detail_relief(&mut self, amplitude: f32, frequency: f32) {
+        ShaderProgram::load_float(self.location_detail_amplitude, amplitude);
+        ShaderProgram::load_float(self.location_detail_frequency, frequency);
     }
 }
\ No newline at end of file
@@ -80,12 +80,12 @@ impl TextShader {
     }
 
     pub fn load_text_material(&mut self, material: &TextMaterial) {
-        ShaderProgram::load_vector3d(self.location_color, &material.color);
+        ShaderProgram::load_color(self.location_color, &material.color);
         ShaderProgram::load_float(self.location_width, material.width);
         ShaderProgram::load_float(self.location_edge, material.edge);
         ShaderProgram::load_float(self.location_outline_width, material.outline_width);
         ShaderProgram::load_float(self.location_outline_edge, material.outline_edge);
-        ShaderProgram::load_vector3d(self.location_outline_color, &material.outline_color);
+        ShaderProgram::load_color(self.location_outline_color, &material.outline_color);
         ShaderProgram::load_vector2d(self.location_outline_offset, &material.offset);
     }
 }
@@ -1,15 +1,19 @@
+use crate::display::DepthMode;
 use crate::entities::{
     Camera,
+    FogCutoff,
     Light,
 };
 use crate::models::{
     RawModel,
+    WaterProps,
 };
 use crate::math::{
+    Color,
     Matrix4f,
-    Vector3f,
 };
 use crate::shaders::shader_program::ShaderProgram;
+use crate::shaders::fog_uniforms::FogUniforms;
 
 const LIGHT_NUM: usize = 4;
 
@@ -30,7 +34,10 @@ pub struct WaterShader {
     location_depth_map_unit: i32,
     location_depth_a: i32,
     location_depth_b: i32,
+    location_zero_to_one_depth: i32,
     location_sky_color: i32,
+    fog_uniforms: FogUniforms,
+    location_distortion_falloff_depth: i32,
 }
 
 impl WaterShader {
@@ -53,7 +60,10 @@ impl WaterShader {
             mut location_attenuation,
             mut location_depth_a,
             mut location_depth_b,
+            mut location_zero_to_one_depth,
             mut location_sky_color,
+            mut fog_uniforms,
+            mut location_distortion_falloff_depth,
         ) = Default::default();
 
         let program = ShaderProgram::new(
@@ -86,7 +96,10 @@ impl WaterShader {
 
                 location_depth_a = shader_prog.get_uniform_location("depth_calc_A");
                 location_depth_b = shader_prog.get_uniform_location("depth_calc_B");
+                location_zero_to_one_depth = shader_prog.get_uniform_location("zero_to_one_depth");
                 location_sky_color = shader_prog.get_uniform_location("sky_color");
+                fog_uniforms = FogUniforms::get_locations(shader_prog);
+                location_distortion_falloff_depth = shader_prog.get_uniform_location("distortion_falloff_depth");
             },
         );
         WaterShader {
@@ -106,7 +119,10 @@ impl WaterShader {
             location_depth_map_unit,
             location_depth_a,
             location_depth_b,
+            location_zero_to_one_depth,
             location_sky_color,
+            fog_uniforms,
+            location_distortion_falloff_depth,
         }
     }
 
@@ -118,8 +134,16 @@ impl WaterShader {
         self.program.stop();
     }
 
-    pub fn load_sky_color(&mut self, color: &Vector3f) {
-        ShaderProgram::load_vector3d(self.location_sky_color, color);
+    pub fn load_sky_color(&mut self, color: &Color) {
+        ShaderProgram::load_color(self.location_sky_color, color);
+    }
+
+    pub fn load_fog_cutoff(&mut self, fog_cutoff: &Option<FogCutoff>) {
+        self.fog_uniforms.load_fog_cutoff(fog_cutoff);
+    }
+
+    pub fn load_water_props(&mut self, water_props: &WaterProps) {
+        ShaderProgram::load_float(self.location_distortion_falloff_depth, water_props.shader_falloff_depth());
     }
 
     pub fn load_projection_matrix(&mut self, proj_mat: &Matrix4f) {
@@ -128,6 +152,10 @@ impl WaterShader {
         ShaderProgram::load_float(self.location_depth_b, proj_mat[2][3]);
     }
 
+    pub fn load_depth_mode(&mut self, depth_mode: DepthMode) {
+        ShaderProgram::load_bool(self.location_zero_to_one_depth, depth_mode.is_zero_to_one());
+    }
+
     pub fn load_camera(&mut self, camera: &Camera) {
         let view_matrix = Matrix4f::create_view_matrix(camera);
         ShaderProgram::load_matrix(self.location_view_mat, &view_matrix);
@@ -152,15 +180,10 @@ impl WaterShader {
 
     pub fn load_lights(&mut self, lights: &Vec<Light>) {
         for li in 0..LIGHT_NUM {
-            if li < lights.len() {
-                ShaderProgram::load_vector3d(self.location_light_color[li], &lights[li].color);
-                ShaderProgram::load_vector3d(self.location_light_pos[li], &lights[li].position);
-                ShaderProgram::load_vector3d(self.location_attenuation[li], &lights[li].attenuation);
-            } else {
-                ShaderProgram::load_vector3d(self.location_light_color[li], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_light_pos[li], &Vector3f::ZERO);
-                ShaderProgram::load_vector3d(self.location_attenuation[li], &Vector3f::POS_X_AXIS);
-            }
+            let light = Light::for_shader_slot(lights, li);
+            ShaderProgram::load_color(self.location_light_color[li], &light.color);
+            ShaderProgram::load_vector4d(self.location_light_pos[li], &light.shader_position());
+            ShaderProgram::load_vector3d(self.location_attenuation[li], &light.attenuation);
         }
     }
 }
\ No newline at end of file
@@ -1,2 +1,3 @@
 pub mod shadow_box;
 pub mod shadow_params;
+pub mod shadow_map_freeze;
@@ -1,5 +1,6 @@
 use crate::entities::{
     Camera,
+    Entity,
 };
 use crate::math::{
     Matrix3f,
@@ -24,6 +25,8 @@ pub struct ShadowBox {
     nearplane_height: f32,
     near_plane: f32,
     far_plane: f32,
+    aspect_ratio: f32,
+    fov_deg: f32,
     pub width: f32,
     pub height: f32,
     pub length: f32,
@@ -31,6 +34,10 @@ pub struct ShadowBox {
     pub obb_corners: [Vector3f; 8],
     pub frustum_corners: [Vector3f; 8],
     pub ortho_proj_mat: Matrix4f,
+    // the light space the box is axis aligned in and the box bounds in it, used to cull shadow casters
+    light_basis: Matrix4f,
+    lightspace_min: Vector3f,
+    lightspace_max: Vector3f,
 }
 
 impl ShadowBox {
@@ -47,6 +54,8 @@ impl ShadowBox {
             nearplane_height,
             near_plane: near,
             far_plane: far,
+            aspect_ratio,
+            fov_deg,
             width: 0.0,
             height: 0.0,
             length: 0.0,
@@ -54,6 +63,9 @@ impl ShadowBox {
             obb_corners: Default::default(),
             frustum_corners: Default::default(),
             ortho_proj_mat: Matrix4f::identity(),
+            light_basis: Matrix4f::identity(),
+            lightspace_min: Vector3f::zero(),
+            lightspace_max: Vector3f::zero(),
         }
     }
 
@@ -72,6 +84,71 @@ impl ShadowBox {
         self.ortho_proj_mat[2][2] = -2.0 / self.length;
     }
 
+    // the ortho projection clips everything outside the box so a sphere that doesn't touch it can't cast a shadow into the map
+    pub fn intersects_sphere(&self, center: &Vector3f, radius: f32) -> bool {
+        let center_ls = self.light_basis.transform(&Vector4f::new(center.x, center.y, center.z, 1.0));
+        center_ls.x + radius >= self.lightspace_min.x && center_ls.x - radius <= self.lightspace_max.x
+            && center_ls.y + radius >= self.lightspace_min.y && center_ls.y - radius <= self.lightspace_max.y
+            && center_ls.z + radius >= self.lightspace_min.z && center_ls.z - radius <= self.lightspace_max.z
+    }
+
+    // the box is axis aligned in light space so in world space it is rotated with the light
+    // corners are in the same order as DebugRenderer::CUBE_VERTS (-0.5 maps to the min and 0.5 to the max bound)
+    pub fn world_space_corners(&self) -> [Vector3f; 8] {
+        const UNIT_CUBE: [(f32, f32, f32); 8] = [
+            (0.0, 0.0, 1.0), (1.0, 0.0, 1.0), (1.0, 1.0, 1.0), (0.0, 1.0, 1.0),
+            (0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (1.0, 1.0, 0.0), (0.0, 1.0, 0.0),
+        ];
+        let lightspace_to_world = self.light_basis.inverse();
+        let mut corners: [Vector3f; 8] = Default::default();
+        for (corner, (fx, fy, fz)) in corners.iter_mut().zip(UNIT_CUBE.iter()) {
+            let x = self.lightspace_min.x + fx * self.width;
+            let y = self.lightspace_min.y + fy * self.height;
+            let z = self.lightspace_min.z + fz * self.length;
+            *corner = lightspace_to_world.transform(&Vector4f::new(x, y, z, 1.0)).xyz();
+        }
+        corners
+    }
+
+    // world space corners relative to world_space_center, drawn by the DebugRenderer around a debug entity placed at the center
+    pub fn corners_around_center(&self) -> [Vector3f; 8] {
+        let mut corners = self.world_space_corners();
+        for corner in corners.iter_mut() {
+            *corner = &*corner - &self.world_space_center;
+        }
+        corners
+    }
+
+    pub fn lightspace_bounds(&self) -> (&Vector3f, &Vector3f) {
+        (&self.lightspace_min, &self.lightspace_max)
+    }
+
+    // entities without a known bounding radius are always kept, hidden ones never
+    pub fn shadow_casters<'a>(&self, entities: &'a [Entity]) -> Vec<&'a Entity> {
+        entities.iter()
+            .filter(|entity| entity.visible)
+            .filter(|entity| entity.bounding_radius().map_or(true, |radius| self.intersects_sphere(&entity.position, radius)))
+            .collect()
+    }
+
+    pub fn set_shadow_distance(&mut self, shadow_distance: f32) {
+        self.far_plane = -shadow_distance;
+        self.recompute_frustum_sizes();
+    }
+
+    pub fn set_aspect_ratio(&mut self, aspect_ratio: f32) {
+        self.aspect_ratio = aspect_ratio;
+        self.recompute_frustum_sizes();
+    }
+
+    fn recompute_frustum_sizes(&mut self) {
+        let (farplane_width, farplane_height, nearplane_width, nearplane_height) = ShadowBox::compute_frustum_sizes(self.aspect_ratio, self.fov_deg, self.near_plane.abs(), self.far_plane.abs());
+        self.farplane_width = farplane_width;
+        self.farplane_height = farplane_height;
+        self.nearplane_width = nearplane_width;
+        self.nearplane_height = nearplane_height;
+    }
+
     fn compute_frustum_sizes(aspect_ratio: f32, fov_deg: f32, near_dist: f32, far_dist: f32) -> (f32, f32, f32, f32)  {
         let tan_fov_half = (fov_deg / 2.0).to_radians().tan();
         let near_width = 2.0 * near_dist * tan_fov_half;
@@ -164,6 +241,9 @@ impl ShadowBox {
         self.width = max_v.x - min_v.x;
         self.height = max_v.y - min_v.y;
         self.length = max_v.z - min_v.z;
+        self.light_basis = light_basis_mat.clone();
+        self.lightspace_min = min_v.clone();
+        self.lightspace_max = max_v.clone();
         // self.width = mm;
         // self.height = mm;
         // self.length = mm;
@@ -251,4 +331,78 @@ impl ShadowBox {
         let result = imat.transform(&d_vec);
         result
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frustum_sizes_follow_fov() {
+        let (far_width_wide, _, near_width_wide, _) = ShadowBox::compute_frustum_sizes(1.5, 70.0, 0.1, 100.0);
+        let (far_width_zoomed, _, near_width_zoomed, _) = ShadowBox::compute_frustum_sizes(1.5, 30.0, 0.1, 100.0);
+        assert!(far_width_zoomed < far_width_wide);
+        assert!(near_width_zoomed < near_width_wide);
+
+        let shadow_box = ShadowBox::new(1.5, 70.0, -0.1, -100.0);
+        assert_eq!(shadow_box.farplane_width, far_width_wide);
+        let shadow_box = ShadowBox::new(1.5, 30.0, -0.1, -100.0);
+        assert_eq!(shadow_box.farplane_width, far_width_zoomed);
+        assert_eq!(shadow_box.nearplane_width, near_width_zoomed);
+        assert_eq!(shadow_box.farplane_height, far_width_zoomed / 1.5);
+    }
+
+    #[test]
+    fn entities_outside_the_shadow_box_are_not_shadow_casters() {
+        let mut shadow_box = ShadowBox::new(1.5, 70.0, -0.1, -100.0);
+        let corner = |x, y, z| Vector3f::new(x, y, z);
+        shadow_box.frustum_corners = [
+            corner(-10.0, -10.0, -10.0), corner(10.0, -10.0, -10.0), corner(10.0, 10.0, -10.0), corner(-10.0, 10.0, -10.0),
+            corner(-10.0, -10.0, 10.0), corner(10.0, -10.0, 10.0), corner(10.0, 10.0, 10.0), corner(-10.0, 10.0, 10.0),
+        ];
+        shadow_box.update_shadow_box_size(&Matrix4f::identity());
+
+        let mut model = crate::models::TexturedModel {
+            raw_model: Default::default(), texture: Default::default(), normal_map_tex_id: None, extra_info_tex_id: None, bounding_radius: Some(1.0), bounds: None,
+        };
+        let entities = Entity::spawn_many(&model, &[
+            (Vector3f::new(0.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            (Vector3f::new(50.0, 0.0, 0.0), Vector3f::zero(), 1.0),
+            // the center is outside but the scaled bounding sphere reaches into the box
+            (Vector3f::new(0.0, 12.0, 0.0), Vector3f::zero(), 3.0),
+        ]);
+        let casters: Vec<f32> = shadow_box.shadow_casters(&entities).iter().map(|entity| entity.position.x + entity.position.y).collect();
+        assert_eq!(casters, vec![0.0, 12.0]);
+
+        model.bounding_radius = None;
+        let unbounded = Entity::spawn_many(&model, &[(Vector3f::new(50.0, 0.0, 0.0), Vector3f::zero(), 1.0)]);
+        assert_eq!(shadow_box.shadow_casters(&unbounded).len(), 1);
+    }
+
+    #[test]
+    fn debug_corners_match_shadow_box_bounds() {
+        let mut shadow_box = ShadowBox::new(1.5, 70.0, -0.1, -100.0);
+        let corner = |x, y, z| Vector3f::new(x, y, z);
+        shadow_box.frustum_corners = [
+            corner(-2.0, -1.0, -30.0), corner(2.0, -1.0, -30.0), corner(2.0, 1.0, -30.0), corner(-2.0, 1.0, -30.0),
+            corner(-20.0, -10.0, -40.0), corner(20.0, -10.0, -40.0), corner(20.0, 10.0, -40.0), corner(-20.0, 10.0, -40.0),
+        ];
+        let mut light_basis = Matrix4f::identity();
+        light_basis.rotate(&Vector3f::new(0.0, 30.0, 0.0));
+        shadow_box.update_shadow_box_size(&light_basis);
+
+        let center = &shadow_box.world_space_center;
+        let (min, max) = shadow_box.lightspace_bounds();
+        let (min, max) = (min.clone(), max.clone());
+        let offsets = shadow_box.corners_around_center();
+        // the debug entity sits at the center and the offsets put the corners back onto the box bounds in light space
+        let near = light_basis.transform(&Vector4f::new(center.x + offsets[4].x, center.y + offsets[4].y, center.z + offsets[4].z, 1.0));
+        let far = light_basis.transform(&Vector4f::new(center.x + offsets[2].x, center.y + offsets[2].y, center.z + offsets[2].z, 1.0));
+        for (actual, expected) in [(near.x, min.x), (near.y, min.y), (near.z, min.z), (far.x, max.x), (far.y, max.y), (far.z, max.z)].iter() {
+            assert!((actual - expected).abs() < 1e-3, "Corner at {} expected at {}", actual, expected);
+        }
+        let diagonal = &offsets[2] - &offsets[4];
+        let size = (shadow_box.width * shadow_box.width + shadow_box.height * shadow_box.height + shadow_box.length * shadow_box.length).sqrt();
+        assert!((diagonal.length() - size).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,81 @@
+use crate::math::Vector3f;
+
+// how far the camera and sun may move before a frozen shadow map has to be rendered again
+#[derive(Debug, Clone)]
+pub struct ShadowMapFreezeParams {
+    // distance in world units the camera may move
+    pub camera_move_threshold: f32,
+    // change in camera pitch or yaw in degrees, rotating the camera moves the shadow box as well
+    pub camera_rotation_threshold_deg: f32,
+    // change in sun pitch or yaw in degrees
+    pub sun_rotation_threshold_deg: f32,
+}
+
+impl Default for ShadowMapFreezeParams {
+    fn default() -> Self {
+        ShadowMapFreezeParams {
+            camera_move_threshold: 1.0,
+            camera_rotation_threshold_deg: 2.0,
+            sun_rotation_threshold_deg: 0.5,
+        }
+    }
+}
+
+// the state the shadow map was last rendered with
+#[derive(Debug, Clone)]
+pub struct ShadowMapSnapshot {
+    pub camera_position: Vector3f,
+    pub camera_pitch: f32,
+    pub camera_yaw: f32,
+    pub sun_pitch: f32,
+    pub sun_yaw: f32,
+}
+
+impl ShadowMapSnapshot {
+    pub fn needs_rerender(&self, current: &ShadowMapSnapshot, params: &ShadowMapFreezeParams) -> bool {
+        let camera_moved = (&current.camera_position - &self.camera_position).length() > params.camera_move_threshold;
+        let camera_rotated = Self::angle_delta_deg(current.camera_pitch, self.camera_pitch) > params.camera_rotation_threshold_deg
+            || Self::angle_delta_deg(current.camera_yaw, self.camera_yaw) > params.camera_rotation_threshold_deg;
+        let sun_rotated = Self::angle_delta_deg(current.sun_pitch, self.sun_pitch) > params.sun_rotation_threshold_deg
+            || Self::angle_delta_deg(current.sun_yaw, self.sun_yaw) > params.sun_rotation_threshold_deg;
+        camera_moved || camera_rotated || sun_rotated
+    }
+
+    // smallest difference between two angles so that going from 359 to 1 degrees is a change of 2 degrees
+    fn angle_delta_deg(a: f32, b: f32) -> f32 {
+        let delta = (a - b).rem_euclid(360.0);
+        delta.min(360.0 - delta)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(camera_position: Vector3f, camera_yaw: f32, sun_pitch: f32) -> ShadowMapSnapshot {
+        ShadowMapSnapshot { camera_position, camera_pitch: 20.0, camera_yaw, sun_pitch, sun_yaw: 45.0 }
+    }
+
+    #[test]
+    fn small_deltas_reuse_the_shadow_map() {
+        let params = ShadowMapFreezeParams::default();
+        let last = snapshot(Vector3f::new(10.0, 5.0, 10.0), 359.5, -30.0);
+        let current = snapshot(Vector3f::new(10.5, 5.0, 10.2), 0.5, -30.2);
+        assert!(!last.needs_rerender(&current, &params));
+    }
+
+    #[test]
+    fn large_deltas_rerender_the_shadow_map() {
+        let params = ShadowMapFreezeParams::default();
+        let last = snapshot(Vector3f::new(10.0, 5.0, 10.0), 90.0, -30.0);
+
+        let camera_moved = snapshot(Vector3f::new(12.0, 5.0, 10.0), 90.0, -30.0);
+        assert!(last.needs_rerender(&camera_moved, &params));
+
+        let camera_rotated = snapshot(Vector3f::new(10.0, 5.0, 10.0), 95.0, -30.0);
+        assert!(last.needs_rerender(&camera_rotated, &params));
+
+        let sun_moved = snapshot(Vector3f::new(10.0, 5.0, 10.0), 90.0, -31.0);
+        assert!(last.needs_rerender(&sun_moved, &params));
+    }
+}
@@ -1,6 +1,195 @@
+use crate::entities::Entity;
+use crate::math::{
+    distance,
+    Vector3f,
+};
+
+// side length of the square shadow map, lower resolutions are faster but give blockier shadow edges
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShadowMapResolution {
+    Low,
+    Medium,
+    High,
+    Ultra,
+}
+
+impl ShadowMapResolution {
+    pub fn size(&self) -> usize {
+        match self {
+            ShadowMapResolution::Low => 1024,
+            ShadowMapResolution::Medium => 2048,
+            ShadowMapResolution::High => 4096,
+            ShadowMapResolution::Ultra => 8192,
+        }
+    }
+}
+
+impl Default for ShadowMapResolution {
+    fn default() -> Self {
+        ShadowMapResolution::High
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ShadowParams {
     pub shadow_map_texture: u32,
+    // bound together with the texture so the shaders get the depth comparison
+    pub shadow_map_sampler: u32,
     pub shadow_distance: f32,
     pub shadow_map_size: usize,    
-}
\ No newline at end of file
+    // depth bias added to the shadow map depth before comparing, in shadow map depth units
+    pub constant_bias: f32,
+    // scaled by the tangent of the angle between surface normal and sun so grazing surfaces get more bias
+    pub slope_bias: f32,
+    // too much bias detaches the shadows from their casters (peter panning)
+    pub max_bias: f32,
+    // move the shadow box only in whole shadow map texels so shadow edges don't shimmer when the camera moves
+    pub snaps_to_texels: bool,
+    // only entities whose bounding sphere touches the shadow box are drawn into the shadow map
+    pub culls_to_shadow_box: bool,
+    // entities further than this from the camera are left out of the shadow map even inside the shadow box, None keeps them all
+    // Entity::max_shadow_cast_distance overrides it per entity
+    pub max_caster_distance: Option<f32>,
+}
+
+impl ShadowParams {
+    pub const DEFAULT_CONSTANT_BIAS: f32 = 0.01;
+    pub const DEFAULT_SLOPE_BIAS: f32 = 0.005;
+    pub const DEFAULT_MAX_BIAS: f32 = 0.02;
+
+    pub fn set_bias(&mut self, constant_bias: f32, slope_bias: f32, max_bias: f32) {
+        self.constant_bias = constant_bias;
+        self.slope_bias = slope_bias;
+        self.max_bias = max_bias;
+    }
+
+    // same as shadow_bias in shadowPcf.glsl
+    pub fn slope_scaled_bias(&self, unit_normal: &Vector3f, unit_to_sun: &Vector3f) -> f32 {
+        let cos_angle = unit_normal.dot_product(unit_to_sun).max(0.001).min(1.0);
+        let tan_angle = (1.0 - cos_angle * cos_angle).sqrt() / cos_angle;
+        (self.constant_bias + self.slope_bias * tan_angle).min(self.max_bias)
+    }
+
+    // the shaders and the texel snapping read the size from here so it has to match the shadow map fbo
+    pub fn set_resolution(&mut self, resolution: ShadowMapResolution) {
+        self.shadow_map_size = resolution.size();
+    }
+
+    // the size the shadow map fbo has to be recreated with, None if it already matches
+    pub fn shadow_map_rebuild_size(&self, fbo_width: usize, fbo_height: usize) -> Option<usize> {
+        if (fbo_width, fbo_height) == (self.shadow_map_size, self.shadow_map_size) {
+            None
+        } else {
+            Some(self.shadow_map_size)
+        }
+    }
+
+    pub fn casts_shadow_from(&self, entity: &Entity, camera_position: &Vector3f) -> bool {
+        match entity.max_shadow_cast_distance.or(self.max_caster_distance) {
+            Some(max_distance) => distance(&entity.position, camera_position) <= max_distance,
+            None => true,
+        }
+    }
+
+    pub fn cull_distant_casters<'a>(&self, casters: Vec<&'a Entity>, camera_position: &Vector3f) -> Vec<&'a Entity> {
+        casters.into_iter().filter(|entity| self.casts_shadow_from(entity, camera_position)).collect()
+    }
+
+    // rounds a lightspace coordinate to the nearest multiple of the world size of one shadow map texel
+    pub fn snap_to_texel(&self, lightspace_coord: f32, ortho_size: f32) -> f32 {
+        if ortho_size <= 0.0 || self.shadow_map_size == 0 {
+            return lightspace_coord;
+        }
+        let texel_size = ortho_size / self.shadow_map_size as f32;
+        (lightspace_coord / texel_size).round() * texel_size
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params() -> ShadowParams {
+        ShadowParams {
+            shadow_map_texture: 0,
+            shadow_map_sampler: 0,
+            shadow_distance: 100.0,
+            shadow_map_size: 4096,
+            constant_bias: ShadowParams::DEFAULT_CONSTANT_BIAS,
+            slope_bias: ShadowParams::DEFAULT_SLOPE_BIAS,
+            max_bias: ShadowParams::DEFAULT_MAX_BIAS,
+            snaps_to_texels: true,
+            culls_to_shadow_box: true,
+            max_caster_distance: None,
+        }
+    }
+
+    #[test]
+    fn bias_grows_at_grazing_angles() {
+        let params = params();
+        let up = Vector3f::POS_Y_AXIS;
+        let facing_sun = params.slope_scaled_bias(&up, &Vector3f::POS_Y_AXIS);
+        let mut sun_at_45 = Vector3f::new(1.0, 1.0, 0.0);
+        sun_at_45.normalize();
+        let at_45 = params.slope_scaled_bias(&up, &sun_at_45);
+        let mut low_sun = Vector3f::new(1.0, 0.05, 0.0);
+        low_sun.normalize();
+        let grazing = params.slope_scaled_bias(&up, &low_sun);
+
+        assert!((facing_sun - ShadowParams::DEFAULT_CONSTANT_BIAS).abs() < 1e-6);
+        assert!((at_45 - (ShadowParams::DEFAULT_CONSTANT_BIAS + ShadowParams::DEFAULT_SLOPE_BIAS)).abs() < 1e-5);
+        assert!(facing_sun < at_45 && at_45 < grazing);
+        assert_eq!(grazing, ShadowParams::DEFAULT_MAX_BIAS);
+        // surfaces facing away from the sun are clamped instead of producing inf or nan
+        assert_eq!(params.slope_scaled_bias(&up, &Vector3f::new(0.0, -1.0, 0.0)), ShadowParams::DEFAULT_MAX_BIAS);
+    }
+
+    #[test]
+    fn snaps_to_whole_texels_of_the_ortho_box() {
+        let params = params();
+        let ortho_width = 200.0;
+        let texel_size = ortho_width / 4096.0;
+
+        let snapped = params.snap_to_texel(10.03, ortho_width);
+        assert!((snapped - 205.0 * texel_size).abs() < 1e-5, "snapped to {}", snapped);
+        assert!((params.snap_to_texel(-10.03, ortho_width) + snapped).abs() < 1e-5);
+        // moving by less than half a texel keeps the same snapped position
+        assert_eq!(params.snap_to_texel(10.03 - texel_size * 0.2, ortho_width), snapped);
+        assert_eq!(params.snap_to_texel(3.5, 0.0), 3.5);
+    }
+
+    #[test]
+    fn entities_beyond_cast_distance_are_not_shadow_casters() {
+        use crate::models::TexturedModel;
+        let model = TexturedModel::default();
+        let near = Entity::new(model.clone(), Vector3f::new(10.0, 0.0, 0.0), Vector3f::zero(), 1.0);
+        let far = Entity::new(model.clone(), Vector3f::new(80.0, 0.0, 0.0), Vector3f::zero(), 1.0);
+        let mut landmark = Entity::new(model, Vector3f::new(0.0, 0.0, 90.0), Vector3f::zero(), 1.0);
+        landmark.max_shadow_cast_distance = Some(100.0);
+        let entities = vec![near, far, landmark];
+
+        let mut params = params();
+        let camera_position = Vector3f::zero();
+        assert_eq!(params.cull_distant_casters(entities.iter().collect(), &camera_position).len(), 3);
+
+        params.max_caster_distance = Some(50.0);
+        let casters: Vec<f32> = params.cull_distant_casters(entities.iter().collect(), &camera_position).iter()
+            .map(|entity| entity.position.x + entity.position.z)
+            .collect();
+        assert_eq!(casters, vec![10.0, 90.0]);
+    }
+
+    #[test]
+    fn new_resolution_rebuilds_the_shadow_map_at_that_size() {
+        let mut params = params();
+        assert_eq!(params.shadow_map_rebuild_size(4096, 4096), None);
+
+        params.set_resolution(ShadowMapResolution::Low);
+        assert_eq!(params.shadow_map_size, 1024);
+        assert_eq!(params.shadow_map_rebuild_size(4096, 4096), Some(1024));
+        assert_eq!(params.shadow_map_rebuild_size(1024, 1024), None);
+        // the texels get 4 times larger
+        let texel_size = 200.0 / 1024.0;
+        assert!((params.snap_to_texel(10.03, 200.0) - 51.0 * texel_size).abs() < 1e-5);
+    }
+}
@@ -1,6 +1,25 @@
 use std::fmt;
 use std::fs::File;
 use std::io::Error;
+use std::sync::RwLock;
+
+// this crate can't use the logging of the crates built on it so they can hand it a handler for the load diagnostics
+// textures are loaded on worker threads so it has to be a plain fn, without a handler the messages are printed
+pub type LoadInfoHandler = fn(&str);
+
+static LOAD_INFO_HANDLER: RwLock<Option<LoadInfoHandler>> = RwLock::new(None);
+
+pub fn set_load_info_handler(handler: Option<LoadInfoHandler>) {
+    *LOAD_INFO_HANDLER.write().unwrap_or_else(|poisoned| poisoned.into_inner()) = handler;
+}
+
+fn report_load_info(message: &str) {
+    let handler = *LOAD_INFO_HANDLER.read().unwrap_or_else(|poisoned| poisoned.into_inner());
+    match handler {
+        Some(handler) => handler(message),
+        None => println!("{}", message),
+    }
+}
 
 pub struct Texture<PixelType> {
     pub width: usize,
@@ -29,6 +48,15 @@ pub struct RGBA<T> {
 
 pub type Texture2DRGBA = Texture<RGBA<u8>>;
 
+fn bytes_per_color(color_type: &png::ColorType) -> usize {
+    match color_type {
+        png::ColorType::RGBA => 4,
+        png::ColorType::Grayscale => 1,
+        png::ColorType::GrayscaleAlpha => 2,
+        _ => 3,
+    }
+}
+
 fn create_color_type(buf: &[u8], color_type: &png::ColorType, i: usize) -> RGBA<u8> {
     if *color_type == png::ColorType::RGBA {
         RGBA {
@@ -37,6 +65,16 @@ fn create_color_type(buf: &[u8], color_type: &png::ColorType, i: usize) -> RGBA<
             b: buf[4*i+2], 
             a: buf[4*i+3], 
         }   
+    } else if *color_type == png::ColorType::Grayscale || *color_type == png::ColorType::GrayscaleAlpha {
+        // gray images (e.g. height maps) get the gray value in all the color channels
+        let stride = bytes_per_color(color_type);
+        let gray = buf[stride*i];
+        RGBA {
+            r: gray,
+            g: gray,
+            b: gray,
+            a: if stride == 2 { buf[stride*i+1] } else { 255 },
+        }
     } else {
         RGBA {
             r: buf[3*i],
@@ -54,13 +92,12 @@ pub fn load_rgba_2d_texture(file_name: &str, reverse: bool) -> Result<Texture2DR
     reader.next_frame(&mut buf)?;
 
     let mut result = Vec::new();
-    let bytes_per_color = if info.color_type == png::ColorType::RGBA { 4 } else { 3 };
+    let bytes_per_color = bytes_per_color(&info.color_type);
     let rbga_count = info.buffer_size() / bytes_per_color;
 
-    println!("filename: {}. buffer size: {}. width: {}. height: {}. colorType: {:?}. bit_depth: {:?}. line_size: {}", 
+    report_load_info(&format!("filename: {}. buffer size: {}. width: {}. height: {}. colorType: {:?}. bit_depth: {:?}. line_size: {}. bytes_per_color: {}. rgba_count: {}", 
         file_name, info.buffer_size(), info.width, info.height,
-        info.color_type, info.bit_depth, info.line_size);
-    println!("bytes_per_color: {}. rgba_count: {}", bytes_per_color, rbga_count);
+        info.color_type, info.bit_depth, info.line_size, bytes_per_color, rbga_count));
 
     for i in 0..rbga_count {
         result.push(create_color_type(&buf, &info.color_type, i));